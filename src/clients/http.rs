@@ -1,83 +1,180 @@
 use rquest::{Client, Response, RequestBuilder};
 use rquest_util::Emulation;
-use http::header::{HeaderMap, HeaderName, HeaderValue};
+use http::header::{HeaderMap, HeaderName, HeaderValue, RETRY_AFTER};
 use http::StatusCode;
-use crate::error::Result;
+use chrono::{DateTime, Utc};
+use crate::error::{Error, Result};
 use crate::config::Settings;
+use crate::metrics::{CallRecord, Metrics};
+use crate::stats::{Endpoint, StatsRegistry};
+use crate::utils::pacer::AdaptivePacer;
+use crate::utils::time::sleep_with_jitter;
 use tracing::{error, debug};
+use std::collections::HashMap;
+use std::sync::Arc;
 use std::time::Duration;
-use tokio::time::sleep;
+use tokio::time::{sleep, Instant};
+
+/// Jitter added on top of a server-requested `Retry-After` wait.
+const RETRY_AFTER_JITTER_MS: u64 = 250;
 
 pub struct HttpClient {
     client: Client,
-    headers: HeaderMap,  // Store headers at struct level
+    headers: HashMap<Endpoint, HeaderMap>,
+    stats: Arc<StatsRegistry>,
+    metrics: Arc<Metrics>,
+    max_retry_after: Duration,
+    /// Sent as `x-client-request-id` so requests can be tied to a run.
+    run_id: Option<HeaderValue>,
+    /// Told every response status, retries included.
+    pacer: Option<Arc<AdaptivePacer>>,
+}
+
+/// Parses `Retry-After` as either delay-seconds or an HTTP-date.
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = DateTime::parse_from_rfc2822(value).ok()?;
+    let wait = date.with_timezone(&Utc) - Utc::now();
+    Some(wait.to_std().unwrap_or(Duration::ZERO))
 }
 
 impl HttpClient {
-    pub fn new(settings: Settings, emulation: Emulation) -> Result<Self> {
+    pub fn new(
+        settings: Settings,
+        emulation: Emulation,
+        stats: Arc<StatsRegistry>,
+        metrics: Arc<Metrics>,
+    ) -> Result<Self> {
+        let max_retry_after = Duration::from_millis(settings.throttling.max_retry_after_ms);
+        let headers = Endpoint::ALL.iter()
+            .map(|endpoint| {
+                let configured = settings.api.headers.for_endpoint(*endpoint);
+                (*endpoint, Self::header_map(*endpoint, &configured))
+            })
+            .collect();
+
+        debug!(
+            emulation = ?emulation,
+            "Creating client with emulation"
+        );
+
+        let client = Client::builder()
+            .emulation(emulation)
+            .timeout(Duration::from_secs(30))
+            .build()?;
+
+        Ok(Self { 
+            client,
+            headers,
+            stats,
+            metrics,
+            max_retry_after,
+            run_id: None,
+            pacer: None,
+        })
+    }
+
+    fn header_map(endpoint: Endpoint, configured: &HashMap<String, String>) -> HeaderMap {
         let mut headers = HeaderMap::new();
-        
-        // Add configured headers
-        for (key, value) in settings.api.headers.iter() {
+        for (key, value) in configured.iter() {
             if let (Ok(header_name), Ok(header_value)) = (
                 HeaderName::from_bytes(key.as_bytes()),
                 HeaderValue::from_str(value)
             ) {
                 headers.insert(header_name, header_value);
                 debug!(
+                    endpoint = endpoint.as_str(),
                     header_key = key,
                     header_value = value,
                     "Adding header"
                 );
             } else {
                 error!(
+                    endpoint = endpoint.as_str(),
                     header_key = key,
                     header_value = value,
                     "Invalid header value"
                 );
             }
         }
+        headers
+    }
 
-        debug!(
-            emulation = ?emulation,
-            "Creating client with emulation"
-        );
-
-        let client = Client::builder()
-            .emulation(emulation)
-            .timeout(Duration::from_secs(30))
-            .build()?;
+    pub(crate) fn set_run_id(&mut self, run_id: &str) {
+        self.run_id = HeaderValue::from_str(run_id).ok();
+    }
 
-        Ok(Self { 
-            client,
-            headers,
-        })
+    pub(crate) fn set_pacer(&mut self, pacer: Arc<AdaptivePacer>) {
+        self.pacer = Some(pacer);
     }
 
-    pub fn get(&self, url: &str) -> RequestBuilder {
+    /// Builds a GET request carrying the header set configured for `endpoint`.
+    pub fn get(&self, url: &str, endpoint: Endpoint) -> RequestBuilder {
         let mut request = self.client.get(url);
+        let headers = &self.headers[&endpoint];
         
         // Apply headers to each request
-        for (key, value) in self.headers.iter() {
+        for (key, value) in headers.iter() {
             request = request.header(key, value);
         }
+        if let Some(run_id) = &self.run_id {
+            request = request.header("x-client-request-id", run_id);
+        }
 
         debug!(
             url = url,
-            headers = ?self.headers,
+            endpoint = endpoint.as_str(),
+            headers = ?headers,
             "Creating GET request with headers"
         );
 
         request
     }
 
-    pub async fn send(&self, request: RequestBuilder) -> Result<Response> {
+    /// Reads and counts the body of a response that won't be returned to the
+    /// caller; bandwidth of retried and rejected requests is still billed.
+    async fn discard(&self, endpoint: Endpoint, response: Response) {
+        let bytes = response.bytes().await.map(|b| b.len() as u64).unwrap_or(0);
+        self.stats.record_bytes(endpoint, bytes);
+    }
+
+    /// Sends the request, retrying rate limits, gateway timeouts and connection
+    /// errors, and records the outcome in the pool's [`Metrics`].
+    pub async fn send(&self, request: RequestBuilder, endpoint: Endpoint) -> Result<Response> {
+        let started = Instant::now();
+        let mut attempts = 0;
+        let result = self.send_with_retries(request, endpoint, &mut attempts).await;
+
+        let status = match &result {
+            Ok(response) => Some(response.status().as_u16()),
+            Err(Error::Http(e)) => e.status().map(|status| status.as_u16()),
+            Err(Error::Forbidden) => Some(StatusCode::FORBIDDEN.as_u16()),
+            Err(Error::RateLimit) => Some(StatusCode::TOO_MANY_REQUESTS.as_u16()),
+            Err(_) => None,
+        };
+        self.metrics.record(endpoint, CallRecord {
+            status,
+            latency: started.elapsed(),
+            retries: attempts.saturating_sub(1),
+        });
+        result
+    }
+
+    async fn send_with_retries(
+        &self,
+        request: RequestBuilder,
+        endpoint: Endpoint,
+        attempts: &mut u32,
+    ) -> Result<Response> {
         const MAX_RETRIES: u32 = 3;
         const BASE_DELAY_MS: u64 = 2000;
         
-        let mut attempts = 0;
         loop {
-            attempts += 1;
+            *attempts += 1;
+            self.stats.record_request(endpoint);
             
             let built_request = request.try_clone()
                 .expect("Failed to clone request")
@@ -85,7 +182,7 @@ impl HttpClient {
             
             debug!(
                 url = %built_request.url(),
-                attempt = attempts,
+                attempt = *attempts,
                 headers = ?built_request.headers().iter()
                     .map(|(k, v)| (k.as_str(), v.to_str().unwrap_or("invalid")))
                     .collect::<Vec<_>>(),
@@ -106,13 +203,38 @@ impl HttpClient {
                             .collect::<Vec<_>>(),
                         "Response received"
                     );
+                    if let Some(pacer) = &self.pacer {
+                        pacer.observe(response.status().as_u16());
+                    }
                 
                     match response.status() {
                         StatusCode::TOO_MANY_REQUESTS => {
-                            if attempts >= MAX_RETRIES {
-                                return Err(crate::error::Error::RateLimit);
+                            let requested = retry_after(response.headers());
+                            self.discard(endpoint, response).await;
+                            if *attempts >= MAX_RETRIES {
+                                return Err(Error::RateLimit);
+                            }
+                            match requested {
+                                Some(wait) => {
+                                    let wait = wait.min(self.max_retry_after);
+                                    debug!(
+                                        attempt = *attempts,
+                                        retry_after_ms = wait.as_millis() as u64,
+                                        capped = requested.is_some_and(|r| r > self.max_retry_after),
+                                        "Rate limited, honoring Retry-After"
+                                    );
+                                    sleep_with_jitter(wait.as_millis() as u64, RETRY_AFTER_JITTER_MS).await;
+                                }
+                                None => {
+                                    let wait_ms = BASE_DELAY_MS * 2u64.pow(*attempts - 1);
+                                    debug!(
+                                        attempt = *attempts,
+                                        backoff_ms = wait_ms,
+                                        "Rate limited without Retry-After, backing off"
+                                    );
+                                    sleep(Duration::from_millis(wait_ms)).await;
+                                }
                             }
-                            sleep(Duration::from_millis(BASE_DELAY_MS * 2u64.pow(attempts - 1))).await;
                             continue;
                         },
                         StatusCode::FORBIDDEN => {
@@ -120,35 +242,39 @@ impl HttpClient {
                                 url = %response.url(),
                                 "Received 403 Forbidden"
                             );
-                            return Err(crate::error::Error::Forbidden);
+                            self.discard(endpoint, response).await;
+                            return Err(Error::Forbidden);
                         },
                         StatusCode::GATEWAY_TIMEOUT => {
-                            if attempts >= MAX_RETRIES {
-                                return Err(crate::error::Error::Http(response.error_for_status().unwrap_err()));
+                            if *attempts >= MAX_RETRIES {
+                                let error = response.error_for_status_ref().unwrap_err();
+                                self.discard(endpoint, response).await;
+                                return Err(Error::Http(error));
                             }
                             debug!(
                                 url = %response.url(),
-                                attempt = attempts,
+                                attempt = *attempts,
                                 "Gateway timeout, retrying after delay"
                             );
-                            sleep(Duration::from_millis(BASE_DELAY_MS * 2u64.pow(attempts - 1))).await;
+                            self.discard(endpoint, response).await;
+                            sleep(Duration::from_millis(BASE_DELAY_MS * 2u64.pow(*attempts - 1))).await;
                             continue;
                         },
                         _ => return Ok(response)  // Return successful responses immediately
                     }
                 },
                 Err(e) => {
-                    if attempts >= MAX_RETRIES {
+                    if *attempts >= MAX_RETRIES {
                         return Err(e.into());
                     }
                     
                     debug!(
                         error = %e,
-                        attempt = attempts,
+                        attempt = *attempts,
                         "Connection error, retrying after delay"
                     );
                     
-                    sleep(Duration::from_millis(BASE_DELAY_MS * 2u64.pow(attempts - 1))).await;
+                    sleep(Duration::from_millis(BASE_DELAY_MS * 2u64.pow(*attempts - 1))).await;
                     continue;
                 }
             }
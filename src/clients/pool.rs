@@ -2,11 +2,17 @@ use rquest_util::Emulation;
 use crate::config::Settings;
 use crate::clients::http::HttpClient;
 use crate::error::Result;
+use crate::metrics::Metrics;
+use crate::utils::pacer::AdaptivePacer;
+use crate::stats::StatsRegistry;
+use std::sync::Arc;
 use tracing::debug;
 
 pub struct ClientPool {
     clients: Vec<HttpClient>,
     current: std::sync::atomic::AtomicUsize,
+    stats: Arc<StatsRegistry>,
+    metrics: Arc<Metrics>,
 }
 
 impl ClientPool {
@@ -20,19 +26,49 @@ impl ClientPool {
 
         debug!("Creating client pool with {} emulations", emulations.len());
 
+        let stats = Arc::new(StatsRegistry::new());
+        let metrics = Arc::new(Metrics::new());
+
         let clients = emulations.into_iter()
             .map(|emulation| {
                 debug!("Creating client with emulation: {:?}", emulation);
-                HttpClient::new(settings.clone(), emulation)
+                HttpClient::new(settings.clone(), emulation, stats.clone(), metrics.clone())
             })
             .collect::<Result<Vec<_>>>()?;
 
         Ok(Self {
             clients,
             current: std::sync::atomic::AtomicUsize::new(0),
+            stats,
+            metrics,
         })
     }
 
+    /// Tags every request with the run's correlation id.
+    pub fn with_run_id(mut self, run_id: &str) -> Self {
+        for client in &mut self.clients {
+            client.set_run_id(run_id);
+        }
+        self
+    }
+
+    /// Feeds every response status to the pacer `VendorService` reads its
+    /// delay from.
+    pub fn with_pacer(mut self, pacer: Arc<AdaptivePacer>) -> Self {
+        for client in &mut self.clients {
+            client.set_pacer(pacer.clone());
+        }
+        self
+    }
+
+    pub fn stats(&self) -> Arc<StatsRegistry> {
+        self.stats.clone()
+    }
+
+    pub fn metrics(&self) -> Arc<Metrics> {
+        self.metrics.clone()
+    }
+
     pub fn next_client(&self) -> &HttpClient {
         let current = self.current.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
         &self.clients[current % self.clients.len()]
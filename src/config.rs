@@ -1,37 +1,951 @@
-use serde::Deserialize;
-use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use serde::de::DeserializeOwned;
+use std::collections::{BTreeMap, HashMap};
+use std::net::SocketAddr;
+use std::path::PathBuf;
 use config::{Config, ConfigError};
+use chrono_tz::Tz;
+use regex::Regex;
 use tracing::debug;
+use crate::stats::Endpoint;
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Deserialize, Clone, PartialEq)]
 pub struct Settings {
-    pub cities: Vec<String>,
+    pub cities: CitiesConfig,
+    /// When non-empty, only these city ids/keys are extracted.
+    #[serde(default)]
+    pub city_allowlist: Vec<String>,
+    #[serde(default)]
+    pub city_denylist: Vec<String>,
     pub minio: MinioConfig,
     pub api: ApiConfig,
+    #[serde(default)]
+    pub quality: QualityConfig,
+    #[serde(default)]
+    pub throttling: ThrottlingConfig,
+    #[serde(default)]
+    pub limits: LimitsConfig,
+    #[serde(default)]
+    pub output: OutputConfig,
+    #[serde(default)]
+    pub parquet: ParquetConfig,
+    #[serde(default)]
+    pub extraction: ExtractionConfig,
+    #[serde(default)]
+    pub storage: StorageConfig,
+    #[serde(default)]
+    pub country: CountryConfig,
+    #[serde(default)]
+    pub debug: DebugConfig,
+    #[serde(default)]
+    pub concurrency: ConcurrencyConfig,
+    #[serde(default)]
+    pub incremental: IncrementalConfig,
+    #[serde(default)]
+    pub vendor_filter: VendorFilterConfig,
+    #[serde(default)]
+    pub pacing: PacingConfig,
+    #[serde(default)]
+    pub health: HealthConfig,
+    /// Log the requests a run would make and return empty responses instead;
+    /// outputs go to `<output_dir>/dry_run` and nothing is uploaded.
+    #[serde(default)]
+    pub dry_run: bool,
+    /// Directory the JSON files, markers, state and checkpoints are written
+    /// to; `OUTPUT_DIR` (or `APP_OUTPUT_DIR`) overrides it.
+    #[serde(default = "default_output_dir")]
+    pub output_dir: PathBuf,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+/// Either an explicit list of listings or `auto`, which discovers every city of
+/// the configured country at startup.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum CitiesConfig {
+    Auto(AutoCities),
+    List(Vec<ListingQuery>),
+}
+
+/// The `auto` keyword of [`CitiesConfig`].
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum AutoCities {
+    Auto,
+}
+
+impl CitiesConfig {
+    pub fn is_auto(&self) -> bool {
+        matches!(self, Self::Auto(_))
+    }
+
+    /// Configured listings; empty until `auto` has been resolved.
+    pub fn queries(&self) -> &[ListingQuery] {
+        match self {
+            Self::Auto(_) => &[],
+            Self::List(queries) => queries,
+        }
+    }
+}
+
+/// Where a listing is searched: a foodpanda `city_id`, or a point for areas a
+/// single city doesn't cover well. The `cities` list can mix both.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum ListingQuery {
+    City(String),
+    Geo { lat: f64, lng: f64 },
+}
+
+impl ListingQuery {
+    /// Identifier used for file names, phase markers and state: the city id,
+    /// or `lat_lng` rounded to 3 decimals.
+    pub fn key(&self) -> String {
+        match self {
+            Self::City(city_id) => city_id.clone(),
+            Self::Geo { lat, lng } => format!("{:.3}_{:.3}", lat, lng),
+        }
+    }
+
+    /// Hive partition segment of the query's output, e.g. `city_id=17` or
+    /// `area=24.861_67.010`.
+    pub fn partition_segment(&self) -> String {
+        match self {
+            Self::City(city_id) => format!("city_id={}", city_id),
+            Self::Geo { .. } => format!("area={}", self.key()),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, PartialEq)]
 pub struct MinioConfig {
     pub endpoint: String,
     pub access_key: String,
     pub secret_key: String,
     pub bucket: String,
     pub region: String,
+    /// Create the bucket (in `region`) at startup when it doesn't exist,
+    /// instead of failing.
+    #[serde(default)]
+    pub create_bucket_if_missing: bool,
+    /// Retries of an upload request failing with a timeout, dropped
+    /// connection or 5xx; other errors fail at once.
+    #[serde(default = "default_upload_retries")]
+    pub upload_retries: u32,
+    /// Parts of a multipart upload sent at once, each holding a chunk in memory.
+    #[serde(default = "default_multipart_concurrency")]
+    pub multipart_concurrency: usize,
+    /// Size in MiB of a multipart upload's parts; at least 5.
+    #[serde(default = "default_multipart_chunk_mb")]
+    pub multipart_chunk_mb: usize,
+    /// Parquet files larger than this many MiB are uploaded in parts; at
+    /// least `multipart_chunk_mb`.
+    #[serde(default = "default_multipart_chunk_mb")]
+    pub multipart_threshold_mb: usize,
+    /// Multipart uploads started this many hours ago are aborted at startup.
+    #[serde(default = "default_stale_upload_hours")]
+    pub stale_upload_hours: u64,
+}
+
+fn default_upload_retries() -> u32 {
+    crate::storage::minio::DEFAULT_UPLOAD_RETRIES
+}
+
+fn default_multipart_concurrency() -> usize {
+    crate::storage::minio::DEFAULT_MULTIPART_CONCURRENCY
+}
+
+fn default_multipart_chunk_mb() -> usize {
+    crate::storage::minio::DEFAULT_MULTIPART_CHUNK_MB
+}
+
+fn default_stale_upload_hours() -> u64 {
+    24
+}
+
+impl MinioConfig {
+    fn validate(&self) -> Result<(), ConfigError> {
+        let min_chunk_mb = crate::storage::minio::MIN_MULTIPART_CHUNK_MB;
+        if self.multipart_chunk_mb < min_chunk_mb {
+            return Err(ConfigError::Message(format!(
+                "minio.multipart_chunk_mb must be at least {}, got {}",
+                min_chunk_mb, self.multipart_chunk_mb
+            )));
+        }
+        if self.multipart_threshold_mb < self.multipart_chunk_mb {
+            return Err(ConfigError::Message(format!(
+                "minio.multipart_threshold_mb ({}) must not be below minio.multipart_chunk_mb ({})",
+                self.multipart_threshold_mb, self.multipart_chunk_mb
+            )));
+        }
+        Ok(())
+    }
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Deserialize, Clone, PartialEq)]
 pub struct ApiConfig {
-    pub headers: HashMap<String, String>,
+    pub headers: HeadersConfig,
+    /// Overrides `https://disco.deliveryhero.io`, e.g. to point at a mock server.
+    #[serde(default)]
+    pub listing_base_url: Option<String>,
+    /// Overrides `https://{country.api_host}`.
+    #[serde(default)]
+    pub vendor_base_url: Option<String>,
+    /// Overrides `https://{country.reviews_host}`.
+    #[serde(default)]
+    pub reviews_base_url: Option<String>,
+    #[serde(default)]
+    pub reviews: ReviewsQueryConfig,
+    /// Sections requested with vendor details via `include=`; empty omits the
+    /// parameter for a metadata-only run.
+    #[serde(default = "default_detail_includes")]
+    pub detail_includes: Vec<String>,
+}
+
+/// Request headers: `default` goes on every request and the per-API sets are
+/// merged over it. A flat map is read as `default`.
+#[derive(Debug, Deserialize, Clone, Default, PartialEq)]
+#[serde(from = "HeadersRepr")]
+pub struct HeadersConfig {
+    pub default: HashMap<String, String>,
+    /// Disco listing API.
+    pub listing: HashMap<String, String>,
+    /// fd-api vendor details (and delivery fees).
+    pub details: HashMap<String, String>,
+    /// Reviews API, including ratings.
+    pub reviews: HashMap<String, String>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum HeadersRepr {
+    Sections {
+        default: HashMap<String, String>,
+        #[serde(default)]
+        listing: HashMap<String, String>,
+        #[serde(default)]
+        details: HashMap<String, String>,
+        #[serde(default)]
+        reviews: HashMap<String, String>,
+    },
+    Flat(HashMap<String, String>),
+}
+
+impl From<HeadersRepr> for HeadersConfig {
+    fn from(repr: HeadersRepr) -> Self {
+        match repr {
+            HeadersRepr::Sections { default, listing, details, reviews } => Self {
+                default,
+                listing,
+                details,
+                reviews,
+            },
+            HeadersRepr::Flat(default) => Self {
+                default,
+                ..Self::default()
+            },
+        }
+    }
+}
+
+impl HeadersConfig {
+    /// The default headers with the endpoint's overrides applied.
+    pub fn for_endpoint(&self, endpoint: Endpoint) -> HashMap<String, String> {
+        let overrides = match endpoint {
+            Endpoint::Listing => &self.listing,
+            Endpoint::Details | Endpoint::Delivery => &self.details,
+            Endpoint::Ratings | Endpoint::Reviews => &self.reviews,
+        };
+        let mut headers = self.default.clone();
+        headers.extend(overrides.iter().map(|(k, v)| (k.clone(), v.clone())));
+        headers
+    }
+}
+
+fn default_output_dir() -> PathBuf {
+    PathBuf::from("data")
+}
+
+fn default_detail_includes() -> Vec<String> {
+    ["menus", "bundles", "multiple_discounts"].map(String::from).to_vec()
+}
+
+/// Query parameters of the reviews endpoint.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct ReviewsQueryConfig {
+    /// Reviews per page.
+    pub limit: i32,
+    /// Order by creation time: `desc` or `asc`.
+    pub sort: String,
+    /// Only request reviews that mention a dish.
+    pub has_dish: bool,
+}
+
+impl Default for ReviewsQueryConfig {
+    fn default() -> Self {
+        Self {
+            limit: 30,
+            sort: "desc".to_string(),
+            has_dish: true,
+        }
+    }
+}
+
+impl ReviewsQueryConfig {
+    fn validate(&self) -> Result<(), ConfigError> {
+        if self.limit <= 0 {
+            return Err(ConfigError::Message(format!(
+                "api.reviews.limit must be positive, got {}",
+                self.limit
+            )));
+        }
+        if self.sort != "desc" && self.sort != "asc" {
+            return Err(ConfigError::Message(format!(
+                "api.reviews.sort must be \"desc\" or \"asc\", got {:?}",
+                self.sort
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, Default, PartialEq)]
+pub struct QualityConfig {
+    /// Maximum tolerated share of nulls per Parquet column, e.g. `details: 0.5`.
+    #[serde(default)]
+    pub max_null_rate: HashMap<String, f64>,
+    #[serde(default)]
+    pub on_breach: BreachAction,
+    /// Share (in percent) of yesterday's vendors that may vanish before the
+    /// run is flagged suspect.
+    #[serde(default)]
+    pub max_missing_pct: Option<f64>,
+    /// Share (in percent) of a city's vendors whose details may fail before
+    /// the city is failed. Unset, failures are only logged.
+    #[serde(default)]
+    pub max_vendor_failure_pct: Option<f64>,
+    /// Fail on response fields the models don't cover instead of warning,
+    /// for canary runs.
+    #[serde(default)]
+    pub strict_schema: bool,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum BreachAction {
+    /// Fail the city without uploading.
+    #[default]
+    Fail,
+    /// Upload anyway, tagging the object `quality=degraded`.
+    Tag,
+}
+
+/// How often a JSON writer flushes its records to disk.
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FlushPolicy {
+    #[default]
+    EveryRecord,
+    EveryNRecords(usize),
+    /// Seconds since the last flush, checked as records are written.
+    EveryDuration(u64),
+}
+
+/// Where the stubs of vendors whose details came back 400/404/410 go.
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SkippedVendorOutput {
+    /// In the vendors file, with `name: "Unknown"` and no details.
+    #[default]
+    Inline,
+    /// In their own `skipped_vendors` file, uploaded as JSON.
+    Separate,
+    /// Nowhere; they are only counted.
+    Drop,
+}
+
+/// Delays (base + random jitter, in milliseconds) between API calls.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct ThrottlingConfig {
+    pub inter_vendor_delay_ms: u64,
+    pub inter_vendor_jitter_ms: u64,
+    pub sub_fetch_delay_ms: u64,
+    pub sub_fetch_jitter_ms: u64,
+    pub page_delay_ms: u64,
+    pub page_jitter_ms: u64,
+    /// Upper bound on a server-requested `Retry-After` wait after a 429.
+    /// Read when the HTTP clients are built, so SIGHUP doesn't change it.
+    pub max_retry_after_ms: u64,
+    /// Delay before each vendor of the end-of-city retry pass over failed vendors.
+    pub dead_letter_delay_ms: u64,
+    pub dead_letter_jitter_ms: u64,
+}
+
+impl Default for ThrottlingConfig {
+    fn default() -> Self {
+        Self {
+            inter_vendor_delay_ms: 1500,
+            inter_vendor_jitter_ms: 1000,
+            sub_fetch_delay_ms: 800,
+            sub_fetch_jitter_ms: 400,
+            page_delay_ms: 2000,
+            page_jitter_ms: 1000,
+            max_retry_after_ms: 60_000,
+            dead_letter_delay_ms: 10_000,
+            dead_letter_jitter_ms: 5_000,
+        }
+    }
+}
+
+/// Adaptive inter-vendor delay driven by the recent share of 403/429
+/// responses. When enabled it replaces `throttling.inter_vendor_delay_ms`;
+/// the jitter still applies. Read once at startup.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct PacingConfig {
+    pub adaptive: bool,
+    /// Delay while no request is being blocked.
+    pub floor_ms: u64,
+    pub ceiling_ms: u64,
+    /// Number of most recent responses the blocked rate is taken over.
+    pub window: usize,
+    /// Blocked rate at which the delay reaches the ceiling.
+    pub saturation_rate: f64,
+}
+
+impl Default for PacingConfig {
+    fn default() -> Self {
+        Self {
+            adaptive: false,
+            floor_ms: 500,
+            ceiling_ms: 8000,
+            window: 50,
+            saturation_rate: 0.2,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct LimitsConfig {
+    /// Wall-clock budget for the whole run; enables the preflight feasibility check.
+    pub run_budget_minutes: Option<u64>,
+    /// Fail at preflight instead of warning when the budget can't be met.
+    pub enforce_feasibility: bool,
+    /// Average request latency assumed by the feasibility estimate.
+    pub assumed_request_ms: u64,
+    /// Stop fetching (and upload what was collected) once this many response
+    /// bytes have been downloaded across all endpoints.
+    pub max_bytes_downloaded: Option<u64>,
+}
+
+impl Default for LimitsConfig {
+    fn default() -> Self {
+        Self {
+            run_budget_minutes: None,
+            enforce_feasibility: false,
+            assumed_request_ms: 500,
+            max_bytes_downloaded: None,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct OutputConfig {
+    /// Serialize records with sorted keys for byte-identical artifacts.
+    pub canonical_json: bool,
+    /// Gzip the vendor, menu item and review JSON files (`.json.gz`).
+    pub compress_json: bool,
+    /// Rotate the JSON files into `_partNNNN` parts of at most this many
+    /// records; each part is converted and uploaded as soon as it is finished.
+    pub max_records_per_file: Option<usize>,
+    /// Same, by uncompressed size in bytes.
+    pub max_bytes_per_file: Option<u64>,
+    /// When uncompressed JSON files are flushed to disk; gzipped files are
+    /// only flushed when finished, as flushing costs their compression.
+    pub flush_policy: FlushPolicy,
+    /// Count only flushed records in the writers' counts.
+    pub durable_count: bool,
+    pub skipped_vendor_output: SkippedVendorOutput,
+    /// Per-dataset toggles and key prefixes; datasets not listed use their defaults.
+    pub datasets: BTreeMap<String, DatasetConfig>,
+}
+
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct DatasetConfig {
+    pub enabled: bool,
+    /// Prepended to the partitioned object key, e.g. `vendors/`. Defaults per dataset.
+    pub prefix: Option<String>,
+}
+
+impl Default for DatasetConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            prefix: None,
+        }
+    }
+}
+
+/// A dataset's effective output settings.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Dataset {
+    pub name: &'static str,
+    pub enabled: bool,
+    pub prefix: String,
+}
+
+impl OutputConfig {
+    /// Datasets the pipeline knows how to produce, with their default key prefix.
+    pub const DATASETS: &'static [(&'static str, &'static str)] = &[
+        ("vendors", ""),
+        ("missing_vendors", "missing_vendors/"),
+        ("failed_vendors", "failed_vendors/"),
+        ("skipped_vendors", "skipped_vendors/"),
+        ("menu_items", "table=menu_items/"),
+        ("reviews", "table=reviews/"),
+        ("reports", "reports/"),
+    ];
+
+    pub fn dataset(&self, name: &str) -> Dataset {
+        let (name, default_prefix) = Self::DATASETS.iter()
+            .find(|(known, _)| *known == name)
+            .copied()
+            .unwrap_or_else(|| panic!("unknown dataset {}", name));
+        let config = self.datasets.get(name).cloned().unwrap_or_default();
+        Dataset {
+            name,
+            enabled: config.enabled,
+            prefix: config.prefix.unwrap_or_else(|| default_prefix.to_string()),
+        }
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        for name in self.datasets.keys() {
+            if !Self::DATASETS.iter().any(|(known, _)| known == name) {
+                let known: Vec<&str> = Self::DATASETS.iter().map(|(known, _)| *known).collect();
+                return Err(ConfigError::Message(format!(
+                    "output.datasets.{} is not a known dataset (expected one of: {})",
+                    name,
+                    known.join(", ")
+                )));
+            }
+        }
+
+        // Enabled datasets must not share a key prefix
+        let enabled: Vec<Dataset> = Self::DATASETS.iter()
+            .map(|(name, _)| self.dataset(name))
+            .filter(|dataset| dataset.enabled)
+            .collect();
+        for (i, dataset) in enabled.iter().enumerate() {
+            for other in &enabled[i + 1..] {
+                if dataset.prefix == other.prefix {
+                    return Err(ConfigError::Message(format!(
+                        "output.datasets.{} and output.datasets.{} share the prefix {:?}",
+                        dataset.name, other.name, dataset.prefix
+                    )));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct ParquetConfig {
+    /// Merge a city's part files into one Parquet file before upload.
+    pub compact_before_upload: bool,
+    /// Parts are only merged while their combined size stays below this.
+    pub compact_max_mb: u64,
+    /// Parquet codec; zstd uses `zstd_level`.
+    pub compression: ParquetCompression,
+    /// 1 (fastest) to 22 (smallest).
+    pub zstd_level: i32,
+    /// Rows per row group; vendor conversion holds one group in memory at a time.
+    #[serde(alias = "row_group_size")]
+    pub rows_per_group: usize,
+    pub dictionary_enabled: bool,
+    /// Columns dictionary-encoded even with `dictionary_enabled` off.
+    pub dictionary_columns: Vec<String>,
+    /// Write a bloom filter on `code`, so point lookups by vendor code can
+    /// skip row groups.
+    pub code_bloom_filter: bool,
+    /// False-positive probability of the bloom filter, between 0 and 1.
+    pub bloom_filter_fpp: f64,
+    /// Write page-level min/max statistics, used by readers to skip pages.
+    pub statistics_enabled: bool,
+    /// Write `extraction_started_at`/`extraction_completed_at` as Int64 epoch
+    /// seconds, as before they became UTC millisecond timestamps.
+    pub legacy_int64_timestamps: bool,
+    /// Build vendor Parquet in memory and upload it without writing a file.
+    pub in_memory: bool,
+    /// Vendor JSON at or above this size is converted through a file even
+    /// with `in_memory`.
+    pub in_memory_max_mb: u64,
+}
+
+impl Default for ParquetConfig {
+    fn default() -> Self {
+        Self {
+            compact_before_upload: false,
+            compact_max_mb: 512,
+            compression: ParquetCompression::Zstd,
+            zstd_level: 3,
+            rows_per_group: 1000,
+            dictionary_enabled: true,
+            dictionary_columns: vec!["code".to_string(), "name".to_string(), "status".to_string()],
+            code_bloom_filter: true,
+            bloom_filter_fpp: 0.01,
+            statistics_enabled: true,
+            legacy_int64_timestamps: false,
+            in_memory: false,
+            in_memory_max_mb: 64,
+        }
+    }
+}
+
+impl ParquetConfig {
+    fn validate(&self) -> Result<(), ConfigError> {
+        if self.compression == ParquetCompression::Zstd && !(1..=22).contains(&self.zstd_level) {
+            return Err(ConfigError::Message(format!(
+                "parquet.zstd_level must be between 1 and 22, got {}",
+                self.zstd_level
+            )));
+        }
+        if !(self.bloom_filter_fpp > 0.0 && self.bloom_filter_fpp < 1.0) {
+            return Err(ConfigError::Message(format!(
+                "parquet.bloom_filter_fpp must be between 0 and 1, got {}",
+                self.bloom_filter_fpp
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ParquetCompression {
+    #[default]
+    Zstd,
+    Snappy,
+    Gzip,
+    None,
+}
+
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct ExtractionConfig {
+    /// Cap on listing pages per city, for testing. Unset fetches everything.
+    pub max_pages: Option<i32>,
+    /// Fetch page N of every city before page N+1 of any, so a cut-short run
+    /// leaves each city partially covered instead of some empty.
+    pub interleave_cities: bool,
+    /// Stop paging a vendor's reviews once this many are collected. Unset fetches all.
+    pub max_reviews_per_vendor: Option<usize>,
+    /// Skip cities whose last upload finished less than this many hours ago,
+    /// unless the run is started with `--force`.
+    pub skip_if_fresh_hours: Option<u64>,
+    /// Quote each vendor's delivery fee and time, at one extra request per vendor.
+    pub fetch_delivery_info: bool,
+    /// Reviews are paged, so they are usually most of a vendor's requests.
+    pub fetch_reviews: bool,
+    /// Also keep the raw reviews inside each vendor record, as before they
+    /// got their own table.
+    pub nest_reviews: bool,
+    pub fetch_ratings: bool,
+    /// Process only this many vendors per city, picked uniformly from the
+    /// whole listing; outputs are named `vendors_sample`.
+    pub sample_size: Option<usize>,
+    /// Seed for a reproducible sample; unset picks a new one each run.
+    pub sample_seed: Option<u64>,
+    /// Process a city's vendors in an order shuffled by the run id, once its
+    /// whole listing has been collected, instead of listing order.
+    pub shuffle_vendors: bool,
+    /// Give up on a vendor whose fetches take longer than this, counting it
+    /// as failed. 0 disables the deadline.
+    pub vendor_deadline_secs: u64,
+    /// Pick up a city a killed run was extracting from its checkpoint
+    /// instead of from the first page (sequential, unsampled runs only).
+    pub resume: bool,
+}
+
+impl Default for ExtractionConfig {
+    fn default() -> Self {
+        Self {
+            max_pages: None,
+            interleave_cities: false,
+            max_reviews_per_vendor: None,
+            skip_if_fresh_hours: None,
+            fetch_delivery_info: false,
+            fetch_reviews: true,
+            nest_reviews: false,
+            fetch_ratings: true,
+            sample_size: None,
+            sample_seed: None,
+            shuffle_vendors: false,
+            vendor_deadline_secs: 120,
+            resume: false,
+        }
+    }
+}
+
+impl ExtractionConfig {
+    /// Stem of the vendor output files and object keys; sampled extracts are
+    /// kept apart from full ones.
+    pub fn vendors_file_stem(&self) -> &'static str {
+        if self.sample_size.is_some() {
+            "vendors_sample"
+        } else {
+            "vendors"
+        }
+    }
+
+    /// The kinds of vendor data the run collects, for the run summary.
+    pub fn data_classes(&self) -> Vec<&'static str> {
+        let mut classes = vec!["details"];
+        if self.fetch_reviews {
+            classes.push("reviews");
+        }
+        if self.fetch_ratings {
+            classes.push("ratings");
+        }
+        if self.fetch_delivery_info {
+            classes.push("delivery_info");
+        }
+        classes
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct StorageConfig {
+    /// IANA timezone whose calendar date names the run's partitions.
+    pub partition_timezone: String,
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        Self {
+            partition_timezone: "UTC".to_string(),
+        }
+    }
+}
+
+impl StorageConfig {
+    pub fn partition_tz(&self) -> Result<Tz, ConfigError> {
+        self.partition_timezone.parse::<Tz>().map_err(|e| ConfigError::Message(format!(
+            "storage.partition_timezone {:?} is not a valid timezone: {}",
+            self.partition_timezone, e
+        )))
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct ConcurrencyConfig {
+    /// Vendors fetched at once within a listing page; 1 keeps the polite
+    /// one-at-a-time pace.
+    pub vendor_workers: usize,
+    /// Most `vendor_workers` can be raised to by a `SIGHUP` reload; defaults
+    /// to the starting `vendor_workers`.
+    pub max_vendor_workers: Option<usize>,
+    /// Listing pages fetched ahead of the one the vendor workers are on.
+    pub listing_prefetch_pages: usize,
+    /// Records queued for a city's writer task before the workers wait.
+    pub write_queue: usize,
+}
+
+impl ConcurrencyConfig {
+    pub fn max_vendor_workers(&self) -> usize {
+        self.max_vendor_workers.unwrap_or(self.vendor_workers).max(self.vendor_workers)
+    }
+}
+
+impl Default for ConcurrencyConfig {
+    fn default() -> Self {
+        Self {
+            vendor_workers: 1,
+            max_vendor_workers: None,
+            listing_prefetch_pages: 1,
+            write_queue: 256,
+        }
+    }
+}
+
+/// Skipping vendors whose details a previous run fetched recently. Enabled by
+/// setting `manifest_path`, `manifest_s3_key` or both.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct IncrementalConfig {
+    /// Local manifest read at startup and rewritten at the end of the run.
+    /// Defaults to `<output_dir>/incremental_manifest.json` when only the S3
+    /// key is set.
+    pub manifest_path: Option<PathBuf>,
+    /// Object key the manifest is downloaded from and uploaded back to.
+    pub manifest_s3_key: Option<String>,
+    /// Vendors extracted more recently than this are not fetched again.
+    pub max_age_hours: u64,
+    pub unchanged: UnchangedVendors,
+    /// Detail keys left out of the content hash because they change without
+    /// the vendor changing.
+    pub volatile_fields: Vec<String>,
+    /// Leave out vendors whose content hash matches the manifest's, so the
+    /// output only holds what changed.
+    pub delta: bool,
+}
+
+impl Default for IncrementalConfig {
+    fn default() -> Self {
+        Self {
+            manifest_path: None,
+            manifest_s3_key: None,
+            max_age_hours: 24,
+            unchanged: UnchangedVendors::default(),
+            volatile_fields: ["available_in", "close_reasons", "next_opening", "timestamp", "updated_at"]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+            delta: false,
+        }
+    }
+}
+
+impl IncrementalConfig {
+    pub fn is_enabled(&self) -> bool {
+        self.manifest_path.is_some() || self.manifest_s3_key.is_some()
+    }
+}
+
+/// What an incremental run writes for a vendor it didn't fetch again.
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum UnchangedVendors {
+    /// A record with only the code and `unchanged: true`.
+    #[default]
+    Stub,
+    /// Nothing.
+    Omit,
+}
+
+/// Listing-level criteria a vendor must meet before its details are fetched.
+/// Unset criteria are ignored; with none set every vendor is fetched.
+#[derive(Debug, Deserialize, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct VendorFilterConfig {
+    /// Vendors without a rating don't meet it.
+    pub min_rating: Option<f64>,
+    /// Cuisine ids, any of which qualifies the vendor.
+    pub cuisines: Vec<i64>,
+    /// Regular expression matched against the listed name.
+    pub name_pattern: Option<String>,
+    /// Accept vendors meeting any set criterion instead of all of them.
+    pub match_any: bool,
+    /// Write filtered-out vendors as stubs flagged `filtered` instead of
+    /// leaving them out.
+    pub write_filtered_stub: bool,
+}
+
+impl VendorFilterConfig {
+    pub fn name_regex(&self) -> Result<Option<Regex>, ConfigError> {
+        self.name_pattern.as_deref()
+            .map(Regex::new)
+            .transpose()
+            .map_err(|e| ConfigError::Message(format!("vendor_filter.name_pattern is invalid: {}", e)))
+    }
+}
+
+/// Troubleshooting aids, all off by default.
+#[derive(Debug, Deserialize, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct DebugConfig {
+    /// Directory unparseable API responses are written to instead of logged.
+    pub capture_dir: Option<PathBuf>,
+}
+
+/// `/health` and `/status` HTTP endpoints, served while a run is in
+/// progress; off unless `listen` is set.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct HealthConfig {
+    /// Address to serve them on, e.g. `0.0.0.0:8080`.
+    pub listen: Option<String>,
+    /// Seconds without progress after which `/health` answers 503.
+    pub stale_after_secs: u64,
+}
+
+impl Default for HealthConfig {
+    fn default() -> Self {
+        Self {
+            listen: None,
+            stale_after_secs: 300,
+        }
+    }
+}
+
+impl HealthConfig {
+    pub fn listen_addr(&self) -> Result<Option<SocketAddr>, ConfigError> {
+        self.listen.as_deref()
+            .map(|listen| listen.parse().map_err(|e| ConfigError::Message(format!(
+                "health.listen must be an address like 0.0.0.0:8080, got {:?}: {}",
+                listen, e
+            ))))
+            .transpose()
+    }
+}
+
+/// Market the pipeline extracts; defaults to Pakistan.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct CountryConfig {
+    /// Lowercase country code used by the listing API and partition keys, e.g. `pk`.
+    pub code: String,
+    pub global_entity_id: String,
+    /// Host of the vendor details API, e.g. `pk.fd-api.com`.
+    pub api_host: String,
+    /// Host of the reviews and ratings API, e.g. `reviews-api-pk.fd-api.com`.
+    pub reviews_host: String,
+    pub currency: String,
+    pub language_id: i32,
+    /// Further language ids to fetch vendor details in, e.g. `[1, 2]` for
+    /// English and Urdu. Details in `language_id` are always fetched.
+    pub languages: Vec<i32>,
+}
+
+impl Default for CountryConfig {
+    fn default() -> Self {
+        Self {
+            code: "pk".to_string(),
+            global_entity_id: "FP_PK".to_string(),
+            api_host: "pk.fd-api.com".to_string(),
+            reviews_host: "reviews-api-pk.fd-api.com".to_string(),
+            currency: "PKR".to_string(),
+            language_id: 1,
+            languages: Vec::new(),
+        }
+    }
+}
+
+impl CountryConfig {
+    /// Languages vendor details are fetched in, `language_id` first.
+    pub fn detail_languages(&self) -> Vec<i32> {
+        let mut languages = vec![self.language_id];
+        for id in &self.languages {
+            if !languages.contains(id) {
+                languages.push(*id);
+            }
+        }
+        languages
+    }
 }
 
 impl Settings {
     pub fn new() -> Result<Self, ConfigError> {
-        let builder = Config::builder()
-            .add_source(config::File::with_name("config/default.yaml"))
-            .add_source(config::Environment::with_prefix("APP"));
-
-        // Build the configuration
-        let config = builder.build()?;
+        let config = Self::load(true)?;
 
         // Debug log the raw configuration
         if let Ok(headers) = config.get_table("api.headers") {
@@ -50,6 +964,127 @@ impl Settings {
             "Parsed API headers"
         );
 
+        settings.validate()?;
+
         Ok(settings)
     }
-}
\ No newline at end of file
+
+    /// The configuration as loaded, with the MinIO credentials redacted, for
+    /// the run manifest.
+    pub fn snapshot() -> Result<serde_json::Value, ConfigError> {
+        let mut snapshot: serde_json::Value = Self::load(true)?.try_deserialize()?;
+        if let Some(minio) = snapshot.get_mut("minio").and_then(serde_json::Value::as_object_mut) {
+            for key in ["access_key", "secret_key"] {
+                if let Some(value) = minio.get_mut(key) {
+                    *value = "<redacted>".into();
+                }
+            }
+        }
+        Ok(snapshot)
+    }
+
+    /// One section of the configuration, read from the same sources as `new`
+    /// but without requiring (or validating) the rest, for commands that only
+    /// need part of it. `None` when the section isn't set.
+    pub fn section<T: DeserializeOwned>(key: &str) -> Result<Option<T>, ConfigError> {
+        match Self::load(false)?.get(key) {
+            Ok(section) => Ok(Some(section)),
+            Err(ConfigError::NotFound(_)) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn load(require_file: bool) -> Result<Config, ConfigError> {
+        Config::builder()
+            .add_source(config::File::with_name("config/default.yaml").required(require_file))
+            .add_source(config::Environment::with_prefix("APP"))
+            .set_override_option("output_dir", std::env::var("OUTPUT_DIR").ok())?
+            .build()
+    }
+
+    /// Applies `city_allowlist` and `city_denylist` to a list of listings.
+    pub fn filter_cities(&self, queries: Vec<ListingQuery>) -> Vec<ListingQuery> {
+        queries.into_iter()
+            .filter(|query| {
+                let key = query.key();
+                (self.city_allowlist.is_empty() || self.city_allowlist.contains(&key))
+                    && !self.city_denylist.contains(&key)
+            })
+            .collect()
+    }
+
+    /// Partition segment for a listing key; keys no longer in `cities` (e.g.
+    /// from a resumed marker) are assumed to be city ids.
+    pub fn partition_segment(&self, key: &str) -> String {
+        self.cities.queries().iter()
+            .find(|query| query.key() == key)
+            .map(ListingQuery::partition_segment)
+            .unwrap_or_else(|| format!("city_id={}", key))
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        self.minio.validate()?;
+        self.api.reviews.validate()?;
+        self.output.validate()?;
+        self.parquet.validate()?;
+        self.storage.partition_tz()?;
+        self.vendor_filter.name_regex()?;
+        self.health.listen_addr()?;
+        Ok(())
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use crate::services::testing;
+
+    fn city(id: &str) -> ListingQuery {
+        ListingQuery::City(id.to_string())
+    }
+
+    #[test]
+    fn auto_cities_parse_as_auto() {
+        let cities: CitiesConfig = serde_json::from_value(json!("auto")).unwrap();
+
+        assert_eq!(cities, CitiesConfig::Auto(AutoCities::Auto));
+        assert!(cities.is_auto());
+        assert!(cities.queries().is_empty());
+    }
+
+    #[test]
+    fn city_list_mixes_ids_and_points() {
+        let cities: CitiesConfig = serde_json::from_value(json!(["17", { "lat": 24.8607, "lng": 67.0011 }])).unwrap();
+
+        assert!(!cities.is_auto());
+        assert_eq!(cities.queries(), [city("17"), ListingQuery::Geo { lat: 24.8607, lng: 67.0011 }]);
+        assert_eq!(cities.queries()[1].key(), "24.861_67.001");
+    }
+
+    #[test]
+    fn other_keywords_are_rejected() {
+        assert!(serde_json::from_value::<CitiesConfig>(json!("all")).is_err());
+    }
+
+    #[test]
+    fn filter_cities_applies_the_allow_and_deny_lists() {
+        let queries = || vec![city("17"), city("18"), city("19"), ListingQuery::Geo { lat: 24.8607, lng: 67.0011 }];
+        // (allowlist, denylist, kept keys)
+        let cases: [(&[&str], &[&str], &[&str]); 4] = [
+            (&[], &[], &["17", "18", "19", "24.861_67.001"]),
+            (&["17", "24.861_67.001"], &[], &["17", "24.861_67.001"]),
+            (&[], &["18"], &["17", "19", "24.861_67.001"]),
+            // The denylist wins over the allowlist
+            (&["17", "18"], &["18"], &["17"]),
+        ];
+        for (allowlist, denylist, kept) in cases {
+            let mut settings = testing::settings();
+            settings.city_allowlist = allowlist.iter().map(|key| key.to_string()).collect();
+            settings.city_denylist = denylist.iter().map(|key| key.to_string()).collect();
+
+            let keys: Vec<String> = settings.filter_cities(queries()).iter().map(ListingQuery::key).collect();
+
+            assert_eq!(keys, kept, "allow {:?}, deny {:?}", allowlist, denylist);
+        }
+    }
+}
@@ -37,6 +37,18 @@ pub enum Error {
     #[error("Maximum retries exceeded")]
     MaxRetriesExceeded,
 
+    #[error("Gave up after {attempts} attempts, statuses seen: {statuses:?}")]
+    RetriesExhausted { attempts: u32, statuses: Vec<u16> },
+
+    #[error("Vendor not extracted within its {0}s deadline")]
+    Deadline(u64),
+
+    #[error("Invalid URL: {0}")]
+    Url(#[from] url::ParseError),
+
+    #[error("No recorded response for {0}")]
+    ReplayMiss(String),
+
     #[error("Lock error: {0}")]
     Lock(#[from] tokio::sync::TryLockError),
     
@@ -46,6 +58,16 @@ pub enum Error {
     #[error("Storage error: {0}")]
     Storage(String),
 
+    /// A storage request that may succeed when repeated (timeout, 5xx, ...).
+    #[error("Transient storage error: {0}")]
+    TransientStorage(String),
+
+    #[error("Malformed record at byte {offset}: {message}")]
+    MalformedRecord { offset: u64, message: String },
+
+    #[error("Bucket not found: {0}")]
+    BucketNotFound(String),
+
     #[error("ByteStream error: {0}")]
     ByteStream(#[from] ByteStreamError),
 
@@ -54,6 +76,18 @@ pub enum Error {
 
     #[error("Arrow error: {0}")]
     Arrow(#[from] ArrowError),
+
+    #[error("Unmodeled fields in {0}")]
+    SchemaDrift(String),
+
+    #[error("Data quality check failed: {0}")]
+    Quality(String),
+
+    #[error("Parquet read-back failed: {0}")]
+    Validation(String),
+
+    #[error("Run cannot finish within budget: {0}")]
+    Infeasible(String),
 }
 
 // Implement From for various SdkError types
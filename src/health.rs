@@ -0,0 +1,466 @@
+//! `/health` and `/status` over HTTP, so whatever supervises a run (a
+//! container orchestrator, a cron wrapper) can ask whether it is alive and
+//! what it is doing without scraping the logs. The listener is up from the
+//! start of the run until it ends, fed by the same progress events as the
+//! periodic progress log line and by the phase each city enters.
+//!
+//! A run extracts the configured cities once and exits: there is no idle
+//! state between runs and no next scheduled run to report.
+
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+use crate::services::{ProgressEvent, VendorStatus};
+use crate::storage::CityManifest;
+
+/// Longest request head read before answering 400.
+const MAX_REQUEST_BYTES: usize = 8 * 1024;
+/// Time a client gets to send its request.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// What the run is doing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RunState {
+    /// Connecting to the bucket, recovering leftover files, preflight.
+    Starting,
+    Extracting,
+    Converting,
+    Uploading,
+    /// Every city is done; the manifests are being stored.
+    Finishing,
+}
+
+/// Listing pages of one city, as in the progress log line.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct CityPages {
+    pub done: i32,
+    /// Current estimate of the city's pages.
+    pub planned: i32,
+}
+
+/// The last city the run was done with, uploaded or given up on.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CitySummary {
+    pub city_id: String,
+    pub finished_at: DateTime<Utc>,
+    /// Rows uploaded per dataset.
+    pub records: BTreeMap<String, usize>,
+    pub listing_incomplete: bool,
+    /// Why the city failed, when it did.
+    pub failed: Option<String>,
+}
+
+/// Answer of `/status`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StatusReport {
+    pub run_id: Uuid,
+    pub started_at: DateTime<Utc>,
+    pub uptime_secs: u64,
+    pub state: RunState,
+    /// City being extracted, converted or uploaded; `None` while starting,
+    /// finishing or extracting cities interleaved.
+    pub city_id: Option<String>,
+    pub healthy: bool,
+    /// Seconds since the run last showed progress.
+    pub heartbeat_age_secs: u64,
+    pub pages: BTreeMap<String, CityPages>,
+    pub vendors_done: usize,
+    pub vendors_failed: usize,
+    pub cities_done: usize,
+    pub cities_failed: usize,
+    pub last_city: Option<CitySummary>,
+}
+
+/// Answer of `/health`: 200 while the heartbeat is fresh, 503 once it isn't.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct HealthReport {
+    pub healthy: bool,
+    pub state: RunState,
+    pub heartbeat_age_secs: u64,
+}
+
+/// Live state of the run, shared between the orchestration, the progress
+/// event consumer and the HTTP listener. Every progress event and phase
+/// change counts as a heartbeat.
+#[derive(Debug, Clone)]
+pub struct RunStatus {
+    inner: Arc<Mutex<Tracked>>,
+    stale_after: Duration,
+}
+
+#[derive(Debug)]
+struct Tracked {
+    run_id: Uuid,
+    started_at: DateTime<Utc>,
+    started: Instant,
+    heartbeat: Instant,
+    state: RunState,
+    city_id: Option<String>,
+    pages: BTreeMap<String, CityPages>,
+    vendors_done: usize,
+    vendors_failed: usize,
+    cities_done: usize,
+    cities_failed: usize,
+    last_city: Option<CitySummary>,
+}
+
+impl RunStatus {
+    /// A run just started; `/health` turns unhealthy after `stale_after`
+    /// without progress.
+    pub fn new(run_id: Uuid, stale_after: Duration) -> Self {
+        let now = Instant::now();
+        Self {
+            inner: Arc::new(Mutex::new(Tracked {
+                run_id,
+                started_at: Utc::now(),
+                started: now,
+                heartbeat: now,
+                state: RunState::Starting,
+                city_id: None,
+                pages: BTreeMap::new(),
+                vendors_done: 0,
+                vendors_failed: 0,
+                cities_done: 0,
+                cities_failed: 0,
+                last_city: None,
+            })),
+            stale_after,
+        }
+    }
+
+    fn tracked(&self) -> MutexGuard<'_, Tracked> {
+        self.inner.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// The run moved on to `state`, for `city_id` when it works on one city.
+    pub fn enter(&self, state: RunState, city_id: Option<&str>) {
+        let mut tracked = self.tracked();
+        tracked.state = state;
+        tracked.city_id = city_id.map(str::to_string);
+        tracked.heartbeat = Instant::now();
+    }
+
+    pub fn record(&self, event: &ProgressEvent) {
+        let mut tracked = self.tracked();
+        match event {
+            ProgressEvent::PageFetched { city_id, of, .. } => {
+                tracked.pages.entry(city_id.clone()).or_default().planned = *of;
+            }
+            ProgressEvent::VendorDone { status, .. } => {
+                tracked.vendors_done += 1;
+                if *status == VendorStatus::Failed {
+                    tracked.vendors_failed += 1;
+                }
+            }
+            ProgressEvent::BatchDone { city_id, batch, .. } => {
+                tracked.pages.entry(city_id.clone()).or_default().done = *batch;
+            }
+        }
+        tracked.heartbeat = Instant::now();
+    }
+
+    /// A city was recorded in the run manifest, uploaded or failed.
+    pub fn city_finished(&self, city: &CityManifest) {
+        let mut tracked = self.tracked();
+        if city.failed.is_some() {
+            tracked.cities_failed += 1;
+        } else {
+            tracked.cities_done += 1;
+        }
+        tracked.last_city = Some(CitySummary {
+            city_id: city.city_id.clone(),
+            finished_at: Utc::now(),
+            records: city.records.clone(),
+            listing_incomplete: city.listing_incomplete,
+            failed: city.failed.clone(),
+        });
+        tracked.heartbeat = Instant::now();
+    }
+
+    /// Records progress events until their sender is dropped.
+    pub fn track(&self, mut events: mpsc::Receiver<ProgressEvent>) {
+        let status = self.clone();
+        tokio::spawn(async move {
+            while let Some(event) = events.recv().await {
+                status.record(&event);
+            }
+        });
+    }
+
+    pub fn health(&self) -> HealthReport {
+        let tracked = self.tracked();
+        let heartbeat_age = tracked.heartbeat.elapsed();
+        HealthReport {
+            healthy: heartbeat_age <= self.stale_after,
+            state: tracked.state,
+            heartbeat_age_secs: heartbeat_age.as_secs(),
+        }
+    }
+
+    pub fn report(&self) -> StatusReport {
+        let tracked = self.tracked();
+        let heartbeat_age = tracked.heartbeat.elapsed();
+        StatusReport {
+            run_id: tracked.run_id,
+            started_at: tracked.started_at,
+            uptime_secs: tracked.started.elapsed().as_secs(),
+            state: tracked.state,
+            city_id: tracked.city_id.clone(),
+            healthy: heartbeat_age <= self.stale_after,
+            heartbeat_age_secs: heartbeat_age.as_secs(),
+            pages: tracked.pages.clone(),
+            vendors_done: tracked.vendors_done,
+            vendors_failed: tracked.vendors_failed,
+            cities_done: tracked.cities_done,
+            cities_failed: tracked.cities_failed,
+            last_city: tracked.last_city.clone(),
+        }
+    }
+}
+
+/// The running listener. [`shutdown`](Self::shutdown) closes it once the run
+/// is done; dropping it, on any other exit from the run, does the same
+/// without waiting.
+#[derive(Debug)]
+pub struct HealthServer {
+    local_addr: SocketAddr,
+    shutdown: CancellationToken,
+    task: JoinHandle<()>,
+}
+
+impl HealthServer {
+    /// Binds `addr` and answers `/health` and `/status` from `status`.
+    pub async fn start(addr: SocketAddr, status: RunStatus) -> std::io::Result<Self> {
+        let listener = TcpListener::bind(addr).await?;
+        let local_addr = listener.local_addr()?;
+        let shutdown = CancellationToken::new();
+        let task = tokio::spawn(serve(listener, status, shutdown.clone()));
+        info!(addr = %local_addr, "Serving /health and /status");
+        Ok(Self { local_addr, shutdown, task })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// Stops accepting connections and waits for the listener to close.
+    pub async fn shutdown(mut self) {
+        self.shutdown.cancel();
+        let _ = (&mut self.task).await;
+        info!(addr = %self.local_addr, "Health endpoint closed");
+    }
+}
+
+impl Drop for HealthServer {
+    fn drop(&mut self) {
+        self.shutdown.cancel();
+    }
+}
+
+async fn serve(listener: TcpListener, status: RunStatus, shutdown: CancellationToken) {
+    loop {
+        tokio::select! {
+            _ = shutdown.cancelled() => break,
+            accepted = listener.accept() => match accepted {
+                Ok((stream, peer)) => {
+                    let status = status.clone();
+                    tokio::spawn(async move {
+                        if let Err(e) = respond(stream, &status).await {
+                            debug!(peer = %peer, error = %e, "Failed to answer health request");
+                        }
+                    });
+                }
+                Err(e) => warn!(error = %e, "Failed to accept health connection"),
+            },
+        }
+    }
+}
+
+/// Answers one request and closes the connection.
+async fn respond(mut stream: TcpStream, status: &RunStatus) -> std::io::Result<()> {
+    let request_line = match tokio::time::timeout(REQUEST_TIMEOUT, read_request_line(&mut stream)).await {
+        Ok(request_line) => request_line?,
+        Err(_) => None,
+    };
+    let (code, body) = route(request_line.as_deref(), status)?;
+    let head = format!(
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        code,
+        body.len()
+    );
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(&body).await?;
+    stream.shutdown().await
+}
+
+/// The request line, or `None` when the head is longer than
+/// `MAX_REQUEST_BYTES` or the client stopped sending before its end.
+async fn read_request_line(stream: &mut TcpStream) -> std::io::Result<Option<String>> {
+    let mut head = Vec::new();
+    let mut buffer = [0u8; 1024];
+    while !head.windows(4).any(|window| window == b"\r\n\r\n") {
+        if head.len() > MAX_REQUEST_BYTES {
+            return Ok(None);
+        }
+        let read = stream.read(&mut buffer).await?;
+        if read == 0 {
+            return Ok(None);
+        }
+        head.extend_from_slice(&buffer[..read]);
+    }
+    Ok(String::from_utf8_lossy(&head).lines().next().map(str::to_string))
+}
+
+/// Status line and JSON body for a request line; query strings are ignored.
+fn route(request_line: Option<&str>, status: &RunStatus) -> serde_json::Result<(&'static str, Vec<u8>)> {
+    let Some(request_line) = request_line else {
+        return Ok(("400 Bad Request", serde_json::to_vec(&serde_json::json!({ "error": "bad request" }))?));
+    };
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next();
+    let path = parts.next().map(|target| target.split('?').next().unwrap_or(target));
+    match (method, path) {
+        (Some("GET"), Some("/health")) => {
+            let health = status.health();
+            let code = if health.healthy { "200 OK" } else { "503 Service Unavailable" };
+            Ok((code, serde_json::to_vec(&health)?))
+        }
+        (Some("GET"), Some("/status")) => Ok(("200 OK", serde_json::to_vec(&status.report())?)),
+        (Some(_), Some("/health" | "/status")) => {
+            Ok(("405 Method Not Allowed", serde_json::to_vec(&serde_json::json!({ "error": "method not allowed" }))?))
+        }
+        _ => Ok(("404 Not Found", serde_json::to_vec(&serde_json::json!({ "error": "not found" }))?)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+    use crate::storage::CityStatus;
+
+    fn city(city_id: &str, failed: Option<&str>) -> CityManifest {
+        CityManifest {
+            city_id: city_id.to_string(),
+            status: if failed.is_some() { CityStatus::Failed } else { CityStatus::Uploaded },
+            records: BTreeMap::from([("vendors".to_string(), 3)]),
+            listing_incomplete: false,
+            files: Vec::new(),
+            report: None,
+            null_rates: BTreeMap::new(),
+            failed: failed.map(str::to_string),
+            fresh: None,
+        }
+    }
+
+    /// Sends `request_line` and returns the response's status line and body.
+    async fn request(addr: SocketAddr, request_line: &str) -> (String, Value) {
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream.write_all(format!("{}\r\nHost: localhost\r\n\r\n", request_line).as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        (head.lines().next().unwrap().to_string(), serde_json::from_str(body).unwrap())
+    }
+
+    async fn server(status: &RunStatus) -> HealthServer {
+        HealthServer::start("127.0.0.1:0".parse().unwrap(), status.clone()).await.unwrap()
+    }
+
+    #[test]
+    fn progress_and_cities_are_counted() {
+        let status = RunStatus::new(Uuid::nil(), Duration::from_secs(60));
+        status.enter(RunState::Extracting, Some("1"));
+        status.record(&ProgressEvent::PageFetched { city_id: "1".to_string(), page: 1, of: 4 });
+        status.record(&ProgressEvent::VendorDone { code: "a".to_string(), status: VendorStatus::Ok });
+        status.record(&ProgressEvent::VendorDone { code: "b".to_string(), status: VendorStatus::Failed });
+        status.record(&ProgressEvent::BatchDone { city_id: "1".to_string(), batch: 1, failed: 1 });
+        status.city_finished(&city("1", None));
+        status.city_finished(&city("2", Some("too many vendors failed")));
+
+        let report = status.report();
+
+        assert_eq!(report.state, RunState::Extracting);
+        assert_eq!(report.city_id.as_deref(), Some("1"));
+        assert_eq!(report.pages, BTreeMap::from([("1".to_string(), CityPages { done: 1, planned: 4 })]));
+        assert_eq!((report.vendors_done, report.vendors_failed), (2, 1));
+        assert_eq!((report.cities_done, report.cities_failed), (1, 1));
+        let last_city = report.last_city.unwrap();
+        assert_eq!(last_city.city_id, "2");
+        assert_eq!(last_city.failed.as_deref(), Some("too many vendors failed"));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn heartbeat_goes_stale_without_progress() {
+        let status = RunStatus::new(Uuid::nil(), Duration::from_secs(60));
+
+        tokio::time::advance(Duration::from_secs(60)).await;
+        assert!(status.health().healthy);
+        tokio::time::advance(Duration::from_secs(1)).await;
+        let health = status.health();
+        assert!(!health.healthy);
+        assert_eq!(health.heartbeat_age_secs, 61);
+        assert_eq!(status.report().uptime_secs, 61);
+
+        status.enter(RunState::Uploading, Some("1"));
+        assert!(status.health().healthy);
+    }
+
+    #[tokio::test]
+    async fn serves_health_and_status() {
+        let status = RunStatus::new(Uuid::nil(), Duration::from_secs(60));
+        status.enter(RunState::Converting, Some("1"));
+        let server = server(&status).await;
+
+        let (code, health) = request(server.local_addr(), "GET /health HTTP/1.1").await;
+        assert_eq!(code, "HTTP/1.1 200 OK");
+        assert_eq!(health, json!({ "healthy": true, "state": "converting", "heartbeat_age_secs": 0 }));
+
+        let (code, report) = request(server.local_addr(), "GET /status?verbose=1 HTTP/1.1").await;
+        assert_eq!(code, "HTTP/1.1 200 OK");
+        assert_eq!(report["run_id"], json!(Uuid::nil()));
+        assert_eq!(report["state"], "converting");
+        assert_eq!(report["city_id"], "1");
+        assert_eq!(report["last_city"], Value::Null);
+
+        assert_eq!(request(server.local_addr(), "POST /health HTTP/1.1").await.0, "HTTP/1.1 405 Method Not Allowed");
+        assert_eq!(request(server.local_addr(), "GET /metrics HTTP/1.1").await.0, "HTTP/1.1 404 Not Found");
+    }
+
+    #[tokio::test]
+    async fn stale_heartbeat_answers_503() {
+        let status = RunStatus::new(Uuid::nil(), Duration::ZERO);
+        let server = server(&status).await;
+        tokio::time::sleep(Duration::from_millis(5)).await;
+
+        let (code, health) = request(server.local_addr(), "GET /health HTTP/1.1").await;
+
+        assert_eq!(code, "HTTP/1.1 503 Service Unavailable");
+        assert_eq!(health["healthy"], false);
+        // `/status` stays informational
+        assert_eq!(request(server.local_addr(), "GET /status HTTP/1.1").await.0, "HTTP/1.1 200 OK");
+    }
+
+    #[tokio::test]
+    async fn shutdown_closes_the_listener() {
+        let status = RunStatus::new(Uuid::nil(), Duration::from_secs(60));
+        let server = server(&status).await;
+        let addr = server.local_addr();
+
+        server.shutdown().await;
+
+        assert!(TcpStream::connect(addr).await.is_err());
+    }
+}
@@ -5,8 +5,14 @@ pub mod utils;
 pub mod storage;
 pub mod config;
 pub mod error;
+pub mod stats;
+pub mod metrics;
+pub mod health;
+#[cfg(feature = "tui")]
+pub mod tui;
 
 pub use models::{Vendor, VendorListResponse};
 pub use clients::pool::ClientPool;
 pub use error::{Error, Result};
 pub use config::Settings;
+pub use metrics::Metrics;
@@ -1,9 +1,10 @@
 use std::sync::Arc;
-use tokio::sync::Mutex;
-use chrono::{Datelike, Utc};
+use tokio::sync::mpsc;
+use chrono::{DateTime, Datelike, NaiveDate, Utc};
 use anyhow::Result;
 use std::fs::{self, File};
-use tracing::{info, error};
+use tracing::{info, info_span, warn, error, Instrument};
+use uuid::Uuid;
 use tracing_subscriber::{
     fmt::{self, time::UtcTime},
     layer::SubscriberExt,
@@ -11,32 +12,2111 @@ use tracing_subscriber::{
     filter::{EnvFilter, LevelFilter},
     Layer,
 };
-use tempfile::NamedTempFile;
 use std::io::BufReader;
-use std::path::Path;
-
-use foodpanda_etl::config::Settings;
-use foodpanda_etl::models::Vendor;
-use foodpanda_etl::storage::ParquetConverter;
-use foodpanda_etl::services::api::ApiService;
-use foodpanda_etl::services::vendor::VendorService;
-use foodpanda_etl::storage::JsonWriter;
-use foodpanda_etl::storage::minio::MinioUploader;
+use std::path::{Path, PathBuf};
+
+use foodpanda_etl::config::{Settings, CitiesConfig, CountryConfig, ListingQuery, MinioConfig, OutputConfig, ParquetConfig, SkippedVendorOutput};
+use foodpanda_etl::error::Error;
+use foodpanda_etl::models::{City, MenuItem, Review, VendorListResponse};
+use foodpanda_etl::storage::{ConversionStats, ParquetConverter, ParquetMeta};
+use foodpanda_etl::storage::parquet::bloom_filter_bytes;
+use foodpanda_etl::services::api::{ApiService, LISTING_PAGE_SIZE};
+use foodpanda_etl::services::{tee, Cassette, ProgressEvent, ProgressSender, VendorStatus};
+use foodpanda_etl::services::vendor::{CityExtraction, ExtractionReport, FailedVendor, VendorService};
+use foodpanda_etl::storage::{replace_json_extension, CityOutput, CityWriters, CompletedPart, JsonWriter, JsonWriterOptions, RecordStream, VendorStream};
+use foodpanda_etl::storage::minio::{content_type_for, MinioStorage};
+use foodpanda_etl::storage::{CityCheckpoint, CityPhase, Freshness, IncrementalManifest, LastUpload, NullRates, ObservedVendor, PendingUpload, PhaseMarker, QualityVerdict, ReloadableSettings, RunManifest, SettingsReload, ValidationReport, VendorSample, VendorState};
 use foodpanda_etl::clients::ClientPool;
-use foodpanda_etl::utils::time::sleep_with_jitter;
+use foodpanda_etl::stats::Endpoint;
+use foodpanda_etl::metrics::EndpointSummary;
+use foodpanda_etl::health::{HealthServer, RunState, RunStatus};
+use foodpanda_etl::utils::{file_safe_name, AdaptivePacer, CityEstimate, RunEstimate, SharedThrottling};
+use foodpanda_etl::utils::time::{file_safe_timestamp, logical_date};
+#[cfg(feature = "tui")]
+use foodpanda_etl::tui::{self, Dashboard, DashboardSources};
+use std::collections::{BTreeMap, HashMap};
+use std::time::Duration;
+
+fn get_log_filename(timestamp: &str, user_login: &str) -> String {
+    format!("logs/foodpanda_etl_{}_{}_.log", timestamp, user_login)
+}
+
+/// Fetches each city's first listing page to estimate whether the configured
+/// throttling can finish within `limits.run_budget_minutes`. The responses are
+/// returned so extraction doesn't have to request them again.
+async fn preflight(
+    settings: &Settings,
+    api_service: &ApiService,
+    run: &RunContext,
+) -> Result<HashMap<String, VendorListResponse>> {
+    let mut initial_responses = HashMap::new();
+    let Some(budget_minutes) = settings.limits.run_budget_minutes else {
+        return Ok(initial_responses);
+    };
+
+    let mut estimates = Vec::new();
+    for query in settings.cities.queries() {
+        let response = fetch_initial_page(api_service, run, query).await?;
+        estimates.push(CityEstimate {
+            city_id: query.key(),
+            vendors: response.data.available_count.max(0) as u64,
+            page_size: response.data.returned_count.max(0) as u64,
+        });
+        initial_responses.insert(query.key(), response);
+    }
+
+    let budget = Duration::from_secs(budget_minutes * 60);
+    let estimate = RunEstimate::new(
+        &settings.throttling,
+        &settings.limits,
+        &settings.concurrency,
+        &settings.extraction,
+        &estimates,
+    );
+
+    info!(
+        cities = estimate.cities,
+        vendors = estimate.vendors,
+        pages = estimate.pages,
+        vendors_per_second = estimate.vendors_per_second(),
+        min_minutes = estimate.min_duration.as_secs_f64() / 60.0,
+        budget_minutes = budget_minutes,
+        "Run feasibility estimate"
+    );
+
+    if !estimate.fits(budget) {
+        let message = format!(
+            "{}; {}",
+            estimate.describe(budget),
+            estimate.suggestions(&settings.throttling, budget).join("; ")
+        );
+        if settings.limits.enforce_feasibility {
+            return Err(Error::Infeasible(message).into());
+        }
+        warn!(message = message, "Configured delays cannot finish within the run budget");
+    }
+
+    Ok(initial_responses)
+}
+
+/// Fetches a city's first listing page and keeps the body as received under
+/// `output_dir/snapshots/` for debugging listing drift.
+async fn fetch_initial_page(
+    api_service: &ApiService,
+    run: &RunContext,
+    query: &ListingQuery,
+) -> Result<VendorListResponse> {
+    let (response, body) = api_service.fetch_vendor_page_raw(query, 0, LISTING_PAGE_SIZE).await?;
+    let city_id = query.key();
+
+    let snapshot_dir = run.output_dir.join("snapshots");
+    fs::create_dir_all(&snapshot_dir)?;
+    let snapshot_path = snapshot_dir.join(format!(
+        "listing_city_{}_{}_.json",
+        file_safe_name(&city_id),
+        run.timestamp
+    ));
+    fs::write(&snapshot_path, &body)?;
+    info!(
+        city_id = city_id,
+        available_count = response.data.available_count,
+        snapshot_file = %snapshot_path.display(),
+        "Saved page-0 listing snapshot"
+    );
+
+    Ok(response)
+}
+
+/// The cities a run extracts, and what `cities: auto` discovered for them.
+struct ResolvedCities {
+    /// Every city discovered, before the allow/deny lists; `None` for a
+    /// configured list.
+    discovered: Option<Vec<City>>,
+    selected: Vec<ListingQuery>,
+}
+
+/// Discovers the country's cities when configured as `auto`, then applies the
+/// allow/deny lists. Both lists are logged and go into the run manifest for
+/// auditing.
+async fn resolve_cities(settings: &Settings, api_service: &ApiService) -> Result<ResolvedCities> {
+    let (discovered, queries) = if settings.cities.is_auto() {
+        let discovered = api_service.fetch_cities().await?;
+        info!(
+            country = settings.country.code,
+            discovered = ?discovered.iter().map(|city| (city.id, city.name.as_str())).collect::<Vec<_>>(),
+            "Discovered cities"
+        );
+        let queries = discovered.iter().map(|city| ListingQuery::City(city.id.to_string())).collect();
+        (Some(discovered), queries)
+    } else {
+        (None, settings.cities.queries().to_vec())
+    };
+
+    let selected = settings.filter_cities(queries);
+    info!(
+        cities = ?selected.iter().map(ListingQuery::key).collect::<Vec<_>>(),
+        "Cities selected for this run"
+    );
+    Ok(ResolvedCities { discovered, selected })
+}
+
+/// Values shared by every city of one run.
+struct RunContext {
+    /// Correlation id carried by the run's logs, requests, records and keys.
+    run_id: Uuid,
+    /// Run start as a `file_safe_timestamp`, shared by the run's file names.
+    timestamp: String,
+    user_login: String,
+    output_dir: PathBuf,
+    /// Logical date shared by every partition this run writes.
+    partition_date: NaiveDate,
+    /// `--force`: extract cities even when `skip_if_fresh_hours` says they're fresh.
+    force: bool,
+}
+
+/// A city that can't be uploaded. The run records it in the manifest and
+/// carries on with the next city.
+#[derive(Debug, thiserror::Error)]
+#[error("{reason}")]
+struct CityFailed {
+    reason: String,
+    /// Vendor column null rates, when the city got as far as converting.
+    null_rates: BTreeMap<String, f64>,
+    /// Leave the marker in its phase, so the next run resumes the city
+    /// instead of extracting it again.
+    resumable: bool,
+}
+
+impl CityFailed {
+    /// A city failed by `e`: a quality failure as it was raised, any other
+    /// error with its message.
+    fn from_error(e: anyhow::Error) -> Self {
+        e.downcast::<CityFailed>().unwrap_or_else(|e| CityFailed {
+            reason: format!("{:#}", e),
+            null_rates: BTreeMap::new(),
+            resumable: false,
+        })
+    }
+
+    /// A converted city whose upload couldn't finish, even after
+    /// `minio.upload_retries`; the next run resumes the upload.
+    fn upload(marker: &PhaseMarker, e: anyhow::Error) -> Self {
+        let mut null_rates = NullRates::new();
+        for rates in marker.uploads.iter().filter_map(|upload| upload.null_rates.as_ref()) {
+            null_rates.merge(rates);
+        }
+        CityFailed {
+            reason: format!("upload failed: {:#}", e),
+            null_rates: null_rates.rates(),
+            resumable: true,
+        }
+    }
+}
+
+/// Gives up on a city: its marker is set to `failed`, so the next run
+/// extracts it again (or, for a resumable failure, left as it is so the next
+/// run resumes it), and the manifest and `/status` record why.
+fn fail_city(
+    run: &RunContext,
+    manifest: &mut RunManifest,
+    run_status: &RunStatus,
+    marker: &mut PhaseMarker,
+    failed: CityFailed,
+) -> Result<()> {
+    error!(
+        city_id = marker.city_id,
+        reason = failed.reason,
+        phase = ?marker.phase,
+        resumable = failed.resumable,
+        "City failed, continuing with the next city"
+    );
+    if !failed.resumable {
+        marker.advance(&run.output_dir, CityPhase::Failed)?;
+    }
+    manifest.record_failed_city(marker, load_report(marker)?, &failed.reason, failed.null_rates);
+    manifest.store(&run.output_dir)?;
+    if let Some(city) = manifest.cities.last() {
+        run_status.city_finished(city);
+    }
+    Ok(())
+}
+
+/// Applies reloaded settings: throttling values and `concurrency.vendor_workers`
+/// are swapped in place, anything else is not reloadable and only triggers a
+/// warning. Returns what changed, for the run manifest.
+#[cfg(unix)]
+fn apply_reload(throttling: &SharedThrottling, current: &mut Settings, reloaded: Settings) -> Option<SettingsReload> {
+    let mut ignored = reloaded.clone();
+    ignored.throttling = current.throttling.clone();
+    ignored.concurrency.vendor_workers = current.concurrency.vendor_workers;
+    if ignored != *current {
+        warn!("Only throttling and concurrency.vendor_workers are reloadable; other changes are ignored until restart");
+    }
+
+    let requested = ReloadableSettings {
+        throttling: reloaded.throttling,
+        vendor_workers: reloaded.concurrency.vendor_workers,
+    };
+    let unchanged = ReloadableSettings {
+        throttling: current.throttling.clone(),
+        vendor_workers: throttling.vendor_workers(),
+    };
+    if requested == unchanged {
+        info!("Settings reloaded, throttling unchanged");
+        return None;
+    }
+
+    let old = ReloadableSettings {
+        throttling: throttling.replace(requested.throttling.clone()),
+        vendor_workers: throttling.set_vendor_workers(requested.vendor_workers),
+    };
+    let new = ReloadableSettings {
+        throttling: requested.throttling,
+        vendor_workers: throttling.vendor_workers(),
+    };
+    if new.vendor_workers != requested.vendor_workers {
+        warn!(
+            requested = requested.vendor_workers,
+            max_vendor_workers = throttling.max_vendor_workers(),
+            "Reloaded vendor_workers is outside 1..=max_vendor_workers, clamped"
+        );
+    }
+    info!(old = ?old, new = ?new, "Reloaded throttling settings");
+    current.throttling = new.throttling.clone();
+    current.concurrency.vendor_workers = new.vendor_workers;
+    Some(SettingsReload { reloaded_at: Utc::now(), old, new })
+}
+
+/// Re-reads the configuration on SIGHUP so delays and the vendor worker cap
+/// can be tuned mid-run. Each applied reload is appended to `reloads`.
+#[cfg(unix)]
+fn spawn_reload_listener(
+    throttling: SharedThrottling,
+    mut current: Settings,
+    reloads: Arc<std::sync::Mutex<Vec<SettingsReload>>>,
+) -> std::io::Result<()> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangups = signal(SignalKind::hangup())?;
+    tokio::spawn(async move {
+        while hangups.recv().await.is_some() {
+            info!("SIGHUP received, reloading throttling settings");
+            match Settings::new() {
+                Ok(reloaded) => {
+                    if let Some(reload) = apply_reload(&throttling, &mut current, reloaded) {
+                        reloads.lock()
+                            .unwrap_or_else(|poisoned| poisoned.into_inner())
+                            .push(reload);
+                    }
+                }
+                Err(e) => error!(
+                    error = %e,
+                    "Failed to reload settings, keeping current values"
+                ),
+            }
+        }
+    });
+    Ok(())
+}
+
+/// With `--tui`, draws the dashboard from a copy of the progress events and
+/// hands the other copy back for the logger. Dropping the dashboard gives the
+/// terminal back.
+#[cfg(feature = "tui")]
+fn start_dashboard(
+    args: &[String],
+    events: mpsc::Receiver<ProgressEvent>,
+    client_pool: &ClientPool,
+    pacer: Option<Arc<AdaptivePacer>>,
+) -> Result<(mpsc::Receiver<ProgressEvent>, Option<Dashboard>)> {
+    if !args.iter().any(|arg| arg == "--tui") {
+        return Ok((events, None));
+    }
+    let (logged, shown) = tee(events);
+    let sources = DashboardSources {
+        stats: client_pool.stats(),
+        metrics: client_pool.metrics(),
+        pacer,
+    };
+    Ok((logged, Some(Dashboard::start(shown, sources)?)))
+}
+
+/// With `health.listen` set, serves `/health` and `/status` from a copy of
+/// the progress events and hands the other copy back for the logger.
+async fn start_health_server(
+    settings: &Settings,
+    run_status: &RunStatus,
+    events: mpsc::Receiver<ProgressEvent>,
+) -> Result<(mpsc::Receiver<ProgressEvent>, Option<HealthServer>)> {
+    let Some(addr) = settings.health.listen_addr()? else {
+        return Ok((events, None));
+    };
+    let (logged, tracked) = tee(events);
+    run_status.track(tracked);
+    Ok((logged, Some(HealthServer::start(addr, run_status.clone()).await?)))
+}
+
+const PROGRESS_LOG_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Logs a progress line every interval: vendors and pages done so far, and an
+/// ETA over the planned pages of the cities started so far.
+fn spawn_progress_logger(mut events: mpsc::Receiver<ProgressEvent>) {
+    tokio::spawn(async move {
+        let started = std::time::Instant::now();
+        // Per city: pages done, pages planned
+        let mut pages: HashMap<String, (i32, i32)> = HashMap::new();
+        let (mut vendors_done, mut vendors_failed) = (0usize, 0usize);
+        let mut ticker = tokio::time::interval(PROGRESS_LOG_INTERVAL);
+        ticker.tick().await;
+        loop {
+            tokio::select! {
+                event = events.recv() => match event {
+                    Some(ProgressEvent::PageFetched { city_id, of, .. }) => {
+                        pages.entry(city_id).or_default().1 = of;
+                    }
+                    Some(ProgressEvent::VendorDone { status, .. }) => {
+                        vendors_done += 1;
+                        if status == VendorStatus::Failed {
+                            vendors_failed += 1;
+                        }
+                    }
+                    Some(ProgressEvent::BatchDone { city_id, batch, .. }) => {
+                        pages.entry(city_id).or_default().0 = batch;
+                    }
+                    None => break,
+                },
+                _ = ticker.tick() => {
+                    let pages_done: i32 = pages.values().map(|(done, _)| *done).sum();
+                    let pages_planned: i32 = pages.values().map(|(done, planned)| (*planned).max(*done)).sum();
+                    let eta_minutes = (pages_done > 0).then(|| {
+                        let remaining = (pages_planned - pages_done) as f64 / pages_done as f64;
+                        (started.elapsed().as_secs_f64() * remaining / 60.0).round() as u64
+                    });
+                    info!(
+                        vendors_done = vendors_done,
+                        vendors_failed = vendors_failed,
+                        pages_done = pages_done,
+                        pages_planned = pages_planned,
+                        eta_minutes = ?eta_minutes,
+                        "Extraction progress"
+                    );
+                }
+            }
+        }
+    });
+}
+
+fn menu_items_filename(run: &RunContext, city_id: &str) -> String {
+    format!("menu_items_city_{}_{}.json", file_safe_name(city_id), run.timestamp)
+}
+
+fn reviews_filename(run: &RunContext, city_id: &str) -> String {
+    format!("reviews_city_{}_{}.json", file_safe_name(city_id), run.timestamp)
+}
+
+fn skipped_vendors_filename(run: &RunContext, city_id: &str) -> String {
+    format!("skipped_vendors_city_{}_{}.json", file_safe_name(city_id), run.timestamp)
+}
+
+fn json_writer_options(settings: &Settings) -> JsonWriterOptions {
+    JsonWriterOptions {
+        canonical_json: settings.output.canonical_json,
+        compress: settings.output.compress_json,
+        max_records_per_file: settings.output.max_records_per_file,
+        max_bytes_per_file: settings.output.max_bytes_per_file,
+        flush_policy: settings.output.flush_policy,
+        durable_count: settings.output.durable_count,
+    }
+}
+
+/// Creates the JSON files a city's vendors, menu items and reviews (and, with
+/// `output.skipped_vendor_output: separate`, skipped vendor stubs) are
+/// streamed into. Parts finished by rotation are sent to `parts`.
+async fn open_city_writer(
+    settings: &Settings,
+    run: &RunContext,
+    city_id: &str,
+    parts: mpsc::UnboundedSender<CompletedPart>,
+) -> Result<CityWriters> {
+    let filename = format!(
+        "{}_city_{}_{}_.json",
+        settings.extraction.vendors_file_stem(),
+        file_safe_name(city_id),
+        run.timestamp
+    );
+    let options = json_writer_options(settings);
+    let dir = run.output_dir.as_path();
+    let vendors = JsonWriter::with_options(dir, &filename, options.clone()).await?;
+    let menu_items = JsonWriter::with_options(dir, &menu_items_filename(run, city_id), options.clone()).await?;
+    let reviews = JsonWriter::with_options(dir, &reviews_filename(run, city_id), options.clone()).await?;
+    let skipped = match settings.output.skipped_vendor_output {
+        SkippedVendorOutput::Separate => Some(
+            JsonWriter::with_options(dir, &skipped_vendors_filename(run, city_id), unrotated(options)).await?,
+        ),
+        _ => None,
+    };
+    Ok(CityWriters::new(city_id, vendors, menu_items, reviews, skipped, settings.concurrency.write_queue, parts))
+}
+
+/// The skipped vendors file stays whole; it is small and uploaded with the city.
+fn unrotated(options: JsonWriterOptions) -> JsonWriterOptions {
+    JsonWriterOptions {
+        max_records_per_file: None,
+        max_bytes_per_file: None,
+        ..options
+    }
+}
+
+/// Reopens a city's JSON files where a killed run's checkpoint left them.
+async fn reopen_city_writer(
+    settings: &Settings,
+    checkpoint: &CityCheckpoint,
+    parts: mpsc::UnboundedSender<CompletedPart>,
+) -> Result<CityWriters> {
+    let options = json_writer_options(settings);
+    let files = &checkpoint.files;
+    let vendors = JsonWriter::open_append(&files.vendors, options.clone()).await?;
+    let menu_items = JsonWriter::open_append(&files.menu_items, options.clone()).await?;
+    let reviews = JsonWriter::open_append(&files.reviews, options.clone()).await?;
+    let skipped = match &files.skipped_vendors {
+        Some(skipped) => Some(JsonWriter::open_append(skipped, unrotated(options)).await?),
+        None => None,
+    };
+    Ok(CityWriters::new(&checkpoint.city_id, vendors, menu_items, reviews, skipped, settings.concurrency.write_queue, parts))
+}
+
+/// Closes a city's JSON files and returns what was written.
+async fn finish_city_writer(writers: &CityWriters) -> Result<CityOutput> {
+    Ok(writers.finish().await?)
+}
+
+/// Logs one line per endpoint: request count, latency percentiles and error rate.
+fn log_endpoint_metrics(scope: &str, summaries: &[EndpointSummary]) {
+    for summary in summaries {
+        info!(
+            scope = scope,
+            endpoint = summary.endpoint.as_str(),
+            requests = summary.count,
+            p50_ms = summary.p50_ms,
+            p95_ms = summary.p95_ms,
+            error_rate = summary.error_rate,
+            retries = summary.retries,
+            statuses = ?summary.statuses,
+            "Endpoint metrics"
+        );
+    }
+}
+
+/// Extracts a city into its JSON files, uploading the parts finished by
+/// rotation meanwhile. Returns the written output, the extraction, the
+/// report's path and the parts already handled.
+async fn extract_city(
+    api_service: &ApiService,
+    vendor_service: &VendorService,
+    settings: &Settings,
+    run: &RunContext,
+    minio_uploader: &MinioStorage,
+    query: &ListingQuery,
+    initial_response: Option<VendorListResponse>,
+) -> Result<(CityOutput, CityExtraction, PathBuf, CityParts)> {
+    let city_id = query.key();
+    let checkpoint = if settings.extraction.resume && vendor_service.resumable() {
+        CityCheckpoint::latest(&run.output_dir, &city_id)?
+    } else {
+        None
+    };
+    let (parts_sender, parts) = mpsc::unbounded_channel();
+    let writers = match &checkpoint {
+        Some(checkpoint) => reopen_city_writer(settings, checkpoint, parts_sender).await?,
+        None => open_city_writer(settings, run, &city_id, parts_sender).await?,
+    };
+    
+    // Start timer
+    let start_time = std::time::Instant::now();
+    let traffic_before = api_service.stats().snapshot();
+    let metrics_mark = api_service.metrics().mark();
+
+    // Owns `writers`, so the part uploads below end with it even on error
+    let extracting = {
+        let city_id = city_id.clone();
+        async move {
+            let initial_response = match initial_response {
+                Some(response) => response,
+                None => fetch_initial_page(api_service, run, query).await?,
+            };
+            let extraction = match vendor_service.extract_city(query, Some(initial_response), &writers, checkpoint.as_ref()).await {
+                Ok(extraction) => extraction,
+                Err(e) => {
+                    if let Err(suspend_error) = writers.suspend().await {
+                        warn!(city_id = city_id, error = %suspend_error, "Failed to close unfinished city files");
+                    }
+                    return Err(e.into());
+                }
+            };
+
+            // Finish writing for this city
+            let output = finish_city_writer(&writers).await?;
+            Ok::<_, anyhow::Error>((extraction, output))
+        }
+    };
+    let (extracted, parts) = tokio::join!(extracting, upload_parts(settings, run, minio_uploader, parts));
+    let (extraction, output) = extracted?;
+    record_unwritten(&run.output_dir, &extraction)?;
+    let mut parts = parts?.remove(&city_id).unwrap_or_default();
+    if let Some(checkpoint) = &checkpoint {
+        // Parts finished before the run was killed were handled by it
+        let mut vendor_parts = checkpoint.files.vendors.finished.clone();
+        vendor_parts.append(&mut parts.vendor_parts);
+        parts.vendor_parts = vendor_parts;
+    }
+    let final_count = output.vendors;
+
+    let total_time = start_time.elapsed();
+    let minutes = total_time.as_secs_f64() / 60.0;
+    let vendors_per_second = final_count as f64 / total_time.as_secs_f64();
+    let traffic = api_service.stats().snapshot().since(&traffic_before);
+
+    info!(
+        city_id = city_id,
+        timestamp = run.timestamp,
+        user = run.user_login,
+        total_vendors = final_count,
+        initial_available_count = extraction.initial_available_count,
+        final_available_count = extraction.final_available_count,
+        pages_fetched = extraction.pages,
+        page_size = extraction.page_size,
+        total_minutes = minutes,
+        vendors_per_second = vendors_per_second,
+        bytes_downloaded = traffic.total_bytes(),
+        bytes_per_vendor = traffic.bytes_per_vendor(final_count),
+        bytes_by_endpoint = ?traffic.by_endpoint(),
+        succeeded_vendors = extraction.succeeded,
+        skipped_vendors = extraction.skipped,
+        failed_vendors = extraction.failed.len(),
+        duplicate_vendors = extraction.duplicates,
+        unchanged_vendors = extraction.unchanged,
+        filtered_vendors = extraction.filtered,
+        vendor_statuses = ?extraction.status_counts(),
+        output_file = %output.vendors_path.display(),
+        file_parts = parts.vendor_parts.len() + 1,
+        "Extraction completed"
+    );
+    log_endpoint_metrics(&city_id, &api_service.metrics().summary_since(&metrics_mark));
+    let report_path = write_report(run, &ExtractionReport::new(&extraction, Some(run.run_id), output.bytes_written))?;
+
+    Ok((output, extraction, report_path, parts))
+}
+
+/// Extracts several cities round-robin, one listing page each per round, with
+/// a JSON file per city. Each city's marker is stored as soon as it finishes,
+/// so resuming stays per-city; parts finished by rotation are added to it once
+/// every city is done, with the failure of a city one of its parts failed.
+/// Traffic is shared, so it's only logged per run.
+async fn extract_cities_interleaved(
+    api_service: &ApiService,
+    vendor_service: &VendorService,
+    settings: &Settings,
+    run: &RunContext,
+    minio_uploader: &MinioStorage,
+    queries: &[ListingQuery],
+    initial_responses: &mut HashMap<String, VendorListResponse>,
+) -> Result<Vec<(PhaseMarker, Option<CityFailed>)>> {
+    let (parts_sender, parts) = mpsc::unbounded_channel();
+    let mut cities = Vec::new();
+    for query in queries {
+        let city_id = query.key();
+        let initial_response = match initial_responses.remove(&city_id) {
+            Some(response) => response,
+            None => fetch_initial_page(api_service, run, query).await?,
+        };
+        let cursor = vendor_service.start_city(query, Some(initial_response)).await?;
+        let writers = open_city_writer(settings, run, &city_id, parts_sender.clone()).await?;
+        cities.push((cursor, writers));
+    }
+    // The part uploads end once every city's writer has stopped
+    drop(parts_sender);
+
+    let mut markers = Vec::new();
+    let extracting = async {
+        let rounds = async {
+            while cities.iter().any(|(cursor, _)| !cursor.is_done()) {
+                for (cursor, writers) in cities.iter_mut() {
+                    if cursor.is_done() {
+                        continue;
+                    }
+                    vendor_service.extract_page(cursor, writers).await?;
+                    if !cursor.is_done() {
+                        continue;
+                    }
+
+                    vendor_service.finish_city(cursor, writers).await?;
+                    let extraction = &cursor.extraction;
+                    record_unwritten(&run.output_dir, extraction)?;
+                    let output = finish_city_writer(writers).await?;
+                    let final_count = output.vendors;
+                    info!(
+                        city_id = extraction.city_id,
+                        timestamp = run.timestamp,
+                        user = run.user_login,
+                        total_vendors = final_count,
+                        initial_available_count = extraction.initial_available_count,
+                        final_available_count = extraction.final_available_count,
+                        pages_fetched = extraction.pages,
+                        page_size = extraction.page_size,
+                        succeeded_vendors = extraction.succeeded,
+                        skipped_vendors = extraction.skipped,
+                        failed_vendors = extraction.failed.len(),
+                        duplicate_vendors = extraction.duplicates,
+                        unchanged_vendors = extraction.unchanged,
+                        filtered_vendors = extraction.filtered,
+                        vendor_statuses = ?extraction.status_counts(),
+                        output_file = %output.vendors_path.display(),
+                        "Extraction completed"
+                    );
+                    let report_path = write_report(run, &ExtractionReport::new(extraction, Some(run.run_id), output.bytes_written))?;
+                    let dead_letter_path = write_dead_letter(run, extraction)?;
+                    let failed = check_vendor_failures(settings, extraction);
+
+                    let mut marker = PhaseMarker::extracted(
+                        &extraction.city_id,
+                        output.vendors_path,
+                        run.partition_date,
+                        !extraction.is_complete(),
+                    );
+                    marker.dead_letter_path = dead_letter_path;
+                    marker.report_path = Some(report_path);
+                    marker.menu_items_path = Some(output.menu_items_path);
+                    marker.reviews_path = Some(output.reviews_path);
+                    marker.skipped_vendors_path = output.skipped_vendors_path;
+                    marker.store(&run.output_dir)?;
+                    markers.push((marker, failed));
+                }
+            }
+            Ok::<_, anyhow::Error>(())
+        };
+        if let Err(e) = rounds.await {
+            // Leave the unfinished cities' files for repair; finished ones just refuse
+            for (_, writers) in &cities {
+                let _ = writers.suspend().await;
+            }
+            return Err(e);
+        }
+        Ok(())
+    };
+    let (extracted, parts) = tokio::join!(extracting, upload_parts(settings, run, minio_uploader, parts));
+    extracted?;
+
+    let mut parts = parts?;
+    let mut cities = Vec::new();
+    for (mut marker, failed) in markers {
+        let parts_failed = match parts.remove(&marker.city_id) {
+            Some(city_parts) => {
+                let failed = city_parts.record(&mut marker);
+                marker.store(&run.output_dir)?;
+                failed
+            }
+            None => None,
+        };
+        cities.push((marker, failed.or(parts_failed)));
+    }
+    Ok(cities)
+}
+
+/// When each city was last uploaded according to the run manifests in the
+/// bucket from the last `extraction.skip_if_fresh_hours`, read once per run.
+/// Empty when freshness isn't checked, in dry runs, and when the bucket can't
+/// be listed, leaving `is_fresh` to the phase markers.
+async fn recent_uploads(settings: &Settings, minio_uploader: &MinioStorage) -> BTreeMap<String, LastUpload> {
+    let Some(max_hours) = settings.extraction.skip_if_fresh_hours.filter(|_| !settings.dry_run) else {
+        return BTreeMap::new();
+    };
+    let since = Utc::now() - chrono::Duration::hours(max_hours as i64);
+    match RunManifest::recent_uploads(minio_uploader, since).await {
+        Ok(uploads) => uploads,
+        Err(e) => {
+            warn!(error = %e, "Failed to read recent run manifests, checking freshness from phase markers");
+            BTreeMap::new()
+        }
+    }
+}
+
+/// Whether the city was uploaded within `extraction.skip_if_fresh_hours` and
+/// can be skipped this run, and if so the upload that makes it fresh. The
+/// last upload is the newer of the last run manifest that uploaded the city
+/// (from `recent_uploads`) and its phase marker. Logs the decision, the
+/// record consulted and its age.
+fn is_fresh(
+    settings: &Settings,
+    run: &RunContext,
+    city_id: &str,
+    recent_uploads: &BTreeMap<String, LastUpload>,
+) -> Result<Option<Freshness>> {
+    let Some(max_hours) = settings.extraction.skip_if_fresh_hours else {
+        return Ok(None);
+    };
+    let marker_upload = PhaseMarker::load(&run.output_dir, city_id)?
+        .and_then(|marker| marker.uploaded_at())
+        .map(|uploaded_at| LastUpload {
+            source: PhaseMarker::path(&run.output_dir, city_id).display().to_string(),
+            uploaded_at,
+        });
+    let last_upload = recent_uploads.get(city_id)
+        .cloned()
+        .into_iter()
+        .chain(marker_upload)
+        .max_by_key(|upload| upload.uploaded_at);
+    let Some(last_upload) = last_upload else {
+        info!(city_id = city_id, skip_if_fresh_hours = max_hours, skip = false, "No earlier upload of the city found");
+        return Ok(None);
+    };
+
+    let age = Utc::now() - last_upload.uploaded_at;
+    let fresh = age < chrono::Duration::hours(max_hours as i64);
+    let skip = fresh && !run.force;
+    info!(
+        city_id = city_id,
+        source = last_upload.source,
+        last_upload_age_minutes = age.num_minutes(),
+        skip_if_fresh_hours = max_hours,
+        fresh = fresh,
+        forced = run.force,
+        skip = skip,
+        "Checked city freshness"
+    );
+    Ok(skip.then(|| Freshness { last_upload, age_minutes: age.num_minutes() }))
+}
+
+/// Footer provenance for the Parquet files of a city's partition.
+fn parquet_meta(settings: &Settings, run_id: Uuid, city_id: &str, partition_date: NaiveDate) -> ParquetMeta {
+    ParquetMeta {
+        run_id: run_id.to_string(),
+        city_id: city_id.to_string(),
+        country: settings.country.code.clone(),
+        extraction_date: partition_date,
+    }
+}
+
+/// Partitioned object key, without extension, for one of a city's output
+/// files; `location` is the listing's partition segment (`city_id=..` or `area=..`).
+fn partition_key(
+    prefix: &str,
+    country: &str,
+    location: &str,
+    partition_date: NaiveDate,
+    now: DateTime<Utc>,
+    file_stem: &str,
+    run_id: Uuid,
+) -> String {
+    format!(
+        "{}country={}/{}/year={}/month={:02}/day={:02}/{}_{}_{}",
+        prefix,
+        country,
+        location,
+        partition_date.year(),
+        partition_date.month(),
+        partition_date.day(),
+        file_stem,
+        file_safe_timestamp(now),
+        run_id
+    )
+}
+
+/// Writes the vendors still failing after the retry pass to
+/// `failed_vendors_{city}_{timestamp}.json`, so they are uploaded with the
+/// city's output rather than only logged.
+fn write_dead_letter(run: &RunContext, extraction: &CityExtraction) -> Result<Option<PathBuf>> {
+    if extraction.failed.is_empty() {
+        return Ok(None);
+    }
+    let path = run.output_dir.join(format!(
+        "failed_vendors_{}_{}.json",
+        file_safe_name(&extraction.city_id),
+        run.timestamp
+    ));
+    serde_json::to_writer_pretty(File::create(&path)?, &extraction.failed)?;
+    info!(
+        city_id = extraction.city_id,
+        failed_vendors = extraction.failed.len(),
+        dead_letter_file = %path.display(),
+        "Wrote dead-letter file"
+    );
+    Ok(Some(path))
+}
+
+/// Reads the city's extraction report back for the run manifest, unless a
+/// previous run already uploaded and removed it.
+fn load_report(marker: &PhaseMarker) -> Result<Option<ExtractionReport>> {
+    match &marker.report_path {
+        Some(path) if path.exists() => Ok(Some(serde_json::from_reader(BufReader::new(File::open(path)?))?)),
+        _ => Ok(None),
+    }
+}
+
+/// Writes the city's extraction report, the file the orchestrator reads to
+/// judge the run.
+fn write_report(run: &RunContext, report: &ExtractionReport) -> Result<PathBuf> {
+    let path = run.output_dir.join(format!("report_{}_{}.json", file_safe_name(&report.city_id), run.run_id));
+    serde_json::to_writer_pretty(File::create(&path)?, report)?;
+    info!(
+        city_id = report.city_id,
+        pages = report.pages,
+        complete = report.complete,
+        vendors_ok = report.vendors_ok,
+        vendors_skipped = report.vendors_skipped,
+        vendors_failed = report.vendors_failed,
+        bytes_written = report.bytes_written,
+        duration_secs = (report.finished_at - report.started_at).num_seconds(),
+        report_file = %path.display(),
+        "Wrote extraction report"
+    );
+    Ok(path)
+}
+
+fn incremental_manifest_path(settings: &Settings, run: &RunContext) -> PathBuf {
+    settings.incremental.manifest_path.clone()
+        .unwrap_or_else(|| run.output_dir.join("incremental_manifest.json"))
+}
+
+/// Loads the previous run's incremental manifest, downloading it first when
+/// `incremental.manifest_s3_key` is set. Without one every vendor is fetched
+/// and the run starts a new manifest.
+async fn load_incremental_manifest(
+    settings: &Settings,
+    run: &RunContext,
+    minio_uploader: &MinioStorage,
+) -> Result<IncrementalManifest> {
+    let path = incremental_manifest_path(settings, run);
+    if let Some(key) = &settings.incremental.manifest_s3_key {
+        if !minio_uploader.download_to_file(key, &path).await? {
+            info!(s3_key = key, "No incremental manifest in the bucket yet");
+        }
+    }
+    let manifest = IncrementalManifest::load(&path)?.unwrap_or_default();
+    info!(
+        manifest = %path.display(),
+        vendors = manifest.vendors.len(),
+        max_age_hours = settings.incremental.max_age_hours,
+        "Incremental run: skipping recently extracted vendors"
+    );
+    Ok(manifest)
+}
+
+/// Writes the updated incremental manifest for the next run, uploading it
+/// when `incremental.manifest_s3_key` is set.
+async fn store_incremental_manifest(
+    settings: &Settings,
+    run: &RunContext,
+    minio_uploader: &MinioStorage,
+    manifest: &IncrementalManifest,
+) -> Result<()> {
+    let path = incremental_manifest_path(settings, run);
+    manifest.store(&path)?;
+    if let Some(key) = &settings.incremental.manifest_s3_key {
+        minio_uploader.upload_file(&path, key, None).await?;
+    }
+    info!(
+        manifest = %path.display(),
+        vendors = manifest.vendors.len(),
+        "Stored incremental manifest"
+    );
+    Ok(())
+}
+
+/// Logs the city's failed vendors and returns why the city fails when they
+/// exceed `quality.max_vendor_failure_pct`; the run records that and carries
+/// on with the other cities.
+fn check_vendor_failures(settings: &Settings, extraction: &CityExtraction) -> Option<CityFailed> {
+    if extraction.failed.is_empty() {
+        return None;
+    }
+    let failure_pct = extraction.failure_pct();
+    let sample: Vec<String> = extraction.failed.iter()
+        .take(5)
+        .map(|failed| format!("{} ({})", failed.code, failed.error))
+        .collect();
+    warn!(
+        city_id = extraction.city_id,
+        failed_vendors = extraction.failed.len(),
+        failure_pct = failure_pct,
+        sample = ?sample,
+        "Some vendors failed and were left out"
+    );
+
+    match settings.quality.max_vendor_failure_pct {
+        Some(max) if failure_pct > max => Some(CityFailed {
+            reason: Error::Quality(format!(
+                "city {} has {:.1}% failed vendors (max {:.1}%)",
+                extraction.city_id, failure_pct, max
+            )).to_string(),
+            null_rates: BTreeMap::new(),
+            resumable: false,
+        }),
+        _ => None,
+    }
+}
+
+/// Keeps the codes of the vendors an extraction processed without writing
+/// them in the city's state, so missing vendor detection counts them as seen.
+fn record_unwritten(output_dir: &Path, extraction: &CityExtraction) -> Result<()> {
+    if extraction.unwritten.is_empty() {
+        return Ok(());
+    }
+    let mut state = VendorState::load(output_dir, &extraction.city_id)?
+        .unwrap_or_else(|| VendorState::new(&extraction.city_id));
+    state.record_unwritten(&extraction.unwritten);
+    state.store(output_dir)?;
+    Ok(())
+}
+
+/// Compares today's vendors with the previous run's and converts the ones that
+/// disappeared into their own Parquet file. Skipped for incomplete listings,
+/// which would report every vendor they didn't reach as missing.
+fn detect_missing_vendors(
+    settings: &Settings,
+    output_dir: &Path,
+    marker: &PhaseMarker,
+    vendors: &[ObservedVendor],
+    partition_date: NaiveDate,
+    now: DateTime<Utc>,
+    run_id: Uuid,
+) -> Result<Option<PendingUpload>> {
+    let city_id = &marker.city_id;
+    let mut state = VendorState::load(output_dir, city_id)?
+        .unwrap_or_else(|| VendorState::new(city_id));
+    let previous_count = state.vendors.len();
+
+    let mut upload = None;
+    if marker.listing_incomplete {
+        info!(
+            city_id = city_id,
+            "Listing incomplete, skipping missing vendor detection"
+        );
+    } else if previous_count > 0 {
+        let missing = state.missing_from(vendors);
+        let missing_pct = missing.len() as f64 * 100.0 / previous_count as f64;
+
+        info!(
+            city_id = city_id,
+            missing_vendors = missing.len(),
+            previous_vendors = previous_count,
+            missing_pct = missing_pct,
+            "Detected vendors missing from today's listing"
+        );
+
+        if settings.quality.max_missing_pct.is_some_and(|max| missing_pct > max) {
+            warn!(
+                city_id = city_id,
+                missing_pct = missing_pct,
+                max_missing_pct = ?settings.quality.max_missing_pct,
+                "Missing vendor share exceeds threshold, run is suspect"
+            );
+        }
+
+        let dataset = settings.output.dataset("missing_vendors");
+        if dataset.enabled && !missing.is_empty() {
+            let base = replace_json_extension(&marker.json_path, "");
+            let stem = base.file_name().unwrap_or_default().to_string_lossy();
+            let path = marker.json_path.with_file_name(format!("missing_{}.parquet", stem));
+            ParquetConverter::convert_missing_vendors_to_parquet(
+                &missing,
+                &path,
+                &parquet_meta(settings, run_id, city_id, partition_date),
+                &settings.parquet,
+            )?;
+            upload = Some(PendingUpload::new(
+                dataset.name,
+                path,
+                format!("{}.parquet", partition_key(
+                    &dataset.prefix,
+                    &settings.country.code,
+                    &settings.partition_segment(city_id),
+                    partition_date,
+                    now,
+                    "missing_vendors",
+                    run_id,
+                )),
+                missing.len(),
+            ));
+        }
+    }
+
+    state.observe(vendors, partition_date, !marker.listing_incomplete);
+    state.store(output_dir)?;
+
+    Ok(upload)
+}
+
+/// Streams a vendor JSON file into Parquet next to it, or into memory with
+/// `parquet.in_memory` when the JSON is under `parquet.in_memory_max_mb`,
+/// checking its null rates. Returns the file's upload, or `None` when the
+/// dataset is disabled.
+fn convert_vendors(
+    settings: &Settings,
+    run: &RunContext,
+    city_id: &str,
+    json_path: &Path,
+    partition_date: NaiveDate,
+    now: DateTime<Utc>,
+) -> Result<Option<PendingUpload>> {
+    let dataset = settings.output.dataset("vendors");
+    if !dataset.enabled {
+        info!(
+            city_id = city_id,
+            dataset = dataset.name,
+            json_file = %json_path.display(),
+            "Dataset disabled, keeping JSON locally and skipping conversion"
+        );
+        return Ok(None);
+    }
+
+    let parquet_path = replace_json_extension(json_path, "parquet");
+    let json_bytes = fs::metadata(json_path)?.len();
+    let in_memory = settings.parquet.in_memory
+        && json_bytes < settings.parquet.in_memory_max_mb * 1024 * 1024;
+    if settings.parquet.in_memory && !in_memory {
+        info!(
+            city_id = city_id,
+            json_bytes = json_bytes,
+            in_memory_max_mb = settings.parquet.in_memory_max_mb,
+            "Vendor JSON too large to convert in memory, writing a Parquet file"
+        );
+    }
+    let mut sample = VendorSample::default();
+    let vendors = VendorStream::open(json_path)?.inspect(|vendor| {
+        if let Ok(vendor) = vendor {
+            sample.observe(vendor);
+        }
+    });
+    let meta = parquet_meta(settings, run.run_id, city_id, partition_date);
+    let (contents, null_rates) = if in_memory {
+        let (contents, null_rates) = ParquetConverter::convert_vendors_to_bytes(vendors, &meta, &settings.parquet)?;
+        (Some(contents), null_rates)
+    } else {
+        let null_rates = ParquetConverter::convert_vendors_to_parquet(
+            vendors,
+            &parquet_path,
+            &meta,
+            &settings.parquet,
+        )?;
+        (None, null_rates)
+    };
+
+    // Read the output back before it can be uploaded, keeping it on failure
+    let verified = match &contents {
+        Some(contents) => sample.verify(contents.clone()),
+        None => sample.verify(File::open(&parquet_path)?),
+    };
+    if let Err(e) = verified {
+        if let Some(contents) = &contents {
+            fs::write(&parquet_path, contents)?;
+        }
+        error!(
+            city_id = city_id,
+            parquet_file = %parquet_path.display(),
+            error = %e,
+            "Parquet read-back failed, keeping the file for inspection"
+        );
+        return Err(e.into());
+    }
+
+    info!(
+        city_id = city_id,
+        null_rates = ?null_rates.rates(),
+        "Computed column null rates"
+    );
+
+    // Refuse (or tag) garbage before it reaches downstream consumers
+    let verdict = null_rates.verdict(&settings.quality);
+    if let QualityVerdict::Tagged(breaches) | QualityVerdict::Failed(breaches) = &verdict {
+        for breach in breaches {
+            warn!(
+                city_id = city_id,
+                column = breach.column,
+                null_rate = breach.null_rate,
+                max_null_rate = breach.max_null_rate,
+                "Column null rate exceeds threshold"
+            );
+        }
+    }
+    let tagging = match verdict {
+        QualityVerdict::Passed => None,
+        QualityVerdict::Tagged(_) => Some("quality=degraded".to_string()),
+        QualityVerdict::Failed(breaches) => {
+            let columns: Vec<&str> = breaches.iter().map(|b| b.column.as_str()).collect();
+            return Err(CityFailed {
+                reason: Error::Quality(format!(
+                    "city {} exceeds null-rate thresholds for columns: {}",
+                    city_id,
+                    columns.join(", ")
+                )).to_string(),
+                null_rates: null_rates.rates(),
+                resumable: false,
+            }.into());
+        }
+    };
+
+    info!(
+        city_id = city_id,
+        vendors_count = null_rates.rows(),
+        parquet_file = %parquet_path.display(),
+        in_memory = in_memory,
+        "Converted JSON to Parquet"
+    );
+    let parquet_bytes = match &contents {
+        Some(contents) => contents.len() as u64,
+        None => fs::metadata(&parquet_path)?.len(),
+    };
+    log_compression(settings, city_id, dataset.name, json_bytes, parquet_bytes, null_rates.rows());
+    if settings.parquet.code_bloom_filter {
+        // What the filter costs, to judge per environment whether it pays off
+        let bloom_bytes = match &contents {
+            Some(contents) => bloom_filter_bytes(contents.clone())?,
+            None => bloom_filter_bytes(File::open(&parquet_path)?)?,
+        };
+        info!(
+            city_id = city_id,
+            bloom_filter_bytes = bloom_bytes,
+            parquet_bytes = parquet_bytes,
+            bloom_filter_share = bloom_bytes as f64 / parquet_bytes.max(1) as f64,
+            "Bloom filter size"
+        );
+    }
+
+    let mut upload = PendingUpload::new(
+        dataset.name,
+        parquet_path,
+        format!("{}.parquet", partition_key(
+            &dataset.prefix,
+            &settings.country.code,
+            &settings.partition_segment(city_id),
+            partition_date,
+            now,
+            &object_stem(settings.extraction.vendors_file_stem(), json_path),
+            run.run_id,
+        )),
+        null_rates.rows(),
+    );
+    upload.tagging = tagging;
+    upload.null_rates = Some(null_rates);
+    upload.source = contents.is_some().then(|| json_path.to_path_buf());
+    upload.contents = contents;
+    Ok(Some(upload))
+}
+
+/// Logs the Parquet writer settings a conversion used and the size of the
+/// file against the JSON it was converted from.
+fn log_compression(
+    settings: &Settings,
+    city_id: &str,
+    dataset: &str,
+    json_bytes: u64,
+    parquet_bytes: u64,
+    rows: usize,
+) {
+    info!(
+        city_id = city_id,
+        dataset = dataset,
+        rows = rows,
+        compression = ?settings.parquet.compression,
+        zstd_level = settings.parquet.zstd_level,
+        row_group_size = settings.parquet.rows_per_group,
+        dictionary_enabled = settings.parquet.dictionary_enabled,
+        statistics_enabled = settings.parquet.statistics_enabled,
+        // Gzipped JSON is compared at its compressed size
+        json_bytes = json_bytes,
+        parquet_bytes = parquet_bytes,
+        compression_ratio = json_bytes as f64 / parquet_bytes.max(1) as f64,
+        "Parquet file written"
+    );
+}
+
+/// Streams a city's menu item JSON file into Parquet next to it.
+fn convert_menu_items(
+    settings: &Settings,
+    run: &RunContext,
+    city_id: &str,
+    path: &Path,
+    partition_date: NaiveDate,
+    now: DateTime<Utc>,
+) -> Result<Option<PendingUpload>> {
+    let dataset = settings.output.dataset("menu_items");
+    if !dataset.enabled {
+        return Ok(None);
+    }
+    let parquet_path = replace_json_extension(path, "parquet");
+    let menu_items = ParquetConverter::convert_menu_items_to_parquet(
+        RecordStream::<MenuItem>::open(path)?,
+        &parquet_path,
+        &parquet_meta(settings, run.run_id, city_id, partition_date),
+        &settings.parquet,
+    )?;
+    info!(
+        city_id = city_id,
+        menu_items = menu_items,
+        parquet_file = %parquet_path.display(),
+        "Converted menu items to Parquet"
+    );
+    log_compression(
+        settings,
+        city_id,
+        dataset.name,
+        fs::metadata(path)?.len(),
+        fs::metadata(&parquet_path)?.len(),
+        menu_items,
+    );
+    Ok(Some(PendingUpload::new(
+        dataset.name,
+        parquet_path,
+        format!("{}.parquet", partition_key(
+            &dataset.prefix,
+            &settings.country.code,
+            &settings.partition_segment(city_id),
+            partition_date,
+            now,
+            &object_stem("menu_items", path),
+            run.run_id,
+        )),
+        menu_items,
+    )))
+}
+
+/// Streams a city's review JSON file into Parquet next to it.
+fn convert_reviews(
+    settings: &Settings,
+    run: &RunContext,
+    city_id: &str,
+    path: &Path,
+    partition_date: NaiveDate,
+    now: DateTime<Utc>,
+) -> Result<Option<PendingUpload>> {
+    let dataset = settings.output.dataset("reviews");
+    if !dataset.enabled {
+        return Ok(None);
+    }
+    let parquet_path = replace_json_extension(path, "parquet");
+    let reviews = ParquetConverter::convert_reviews_to_parquet(
+        RecordStream::<Review>::open(path)?,
+        &parquet_path,
+        &parquet_meta(settings, run.run_id, city_id, partition_date),
+        &settings.parquet,
+    )?;
+    info!(
+        city_id = city_id,
+        reviews = reviews,
+        parquet_file = %parquet_path.display(),
+        "Converted reviews to Parquet"
+    );
+    log_compression(
+        settings,
+        city_id,
+        dataset.name,
+        fs::metadata(path)?.len(),
+        fs::metadata(&parquet_path)?.len(),
+        reviews,
+    );
+    Ok(Some(PendingUpload::new(
+        dataset.name,
+        parquet_path,
+        format!("{}.parquet", partition_key(
+            &dataset.prefix,
+            &settings.country.code,
+            &settings.partition_segment(city_id),
+            partition_date,
+            now,
+            &object_stem("reviews", path),
+            run.run_id,
+        )),
+        reviews,
+    )))
+}
+
+/// Object key file stem for a dataset's JSON file, carrying the file's part
+/// number (`vendors_part0002`) when it was rotated.
+fn object_stem(name: &str, json_path: &Path) -> String {
+    let base = replace_json_extension(json_path, "");
+    let part = base.file_name()
+        .and_then(|stem| stem.to_str())
+        .and_then(|stem| stem.rsplit_once("_part"))
+        .map(|(_, number)| number)
+        .filter(|number| !number.is_empty() && number.bytes().all(|byte| byte.is_ascii_digit()));
+    match part {
+        Some(number) => format!("{}_part{}", name, number),
+        None => name.to_string(),
+    }
+}
+
+/// Converts an in-memory upload's JSON again when its contents went with
+/// the run that converted it.
+fn restore_contents(settings: &Settings, meta: &ParquetMeta, upload: &mut PendingUpload) -> Result<()> {
+    if let (Some(source), None) = (&upload.source, &upload.contents) {
+        let (contents, _) = ParquetConverter::convert_vendors_to_bytes(
+            VendorStream::open(source)?,
+            meta,
+            &settings.parquet,
+        )?;
+        upload.contents = Some(contents);
+    }
+    Ok(())
+}
+
+/// Uploads a converted file from memory or disk, JSON datasets untagged.
+async fn upload_pending(minio_uploader: &MinioStorage, upload: &PendingUpload) -> Result<()> {
+    match &upload.contents {
+        Some(contents) => {
+            minio_uploader
+                .upload_bytes(contents.clone(), &upload.s3_key, content_type_for(&upload.path), upload.tagging.as_deref())
+                .await?;
+        }
+        None if upload.path.extension().is_some_and(|ext| ext == "json" || ext == "gz") => {
+            minio_uploader.upload_file(&upload.path, &upload.s3_key, None).await?;
+        }
+        None => {
+            minio_uploader.upload_parquet_file(&upload.path, &upload.s3_key, upload.tagging.as_deref()).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Parts of a city's files converted and uploaded during its extraction.
+#[derive(Debug, Default)]
+struct CityParts {
+    uploads: Vec<PendingUpload>,
+    /// Vendor JSON parts, kept for missing vendor detection.
+    vendor_parts: Vec<PathBuf>,
+    /// Set when a part failed the city; its later parts are left alone.
+    failed: Option<CityFailed>,
+}
+
+impl CityParts {
+    /// Adds the parts to the city's marker, before it is converted, and
+    /// hands back the city's failure if a part failed it.
+    fn record(self, marker: &mut PhaseMarker) -> Option<CityFailed> {
+        marker.uploads.extend(self.uploads);
+        marker.vendor_parts.extend(self.vendor_parts);
+        self.failed
+    }
+}
+
+/// Converts and uploads the parts the city writers finish by rotation until
+/// every writer has stopped, so a city's output leaves the disk while it is
+/// still being extracted. A failed upload is left pending for `upload_city`.
+async fn upload_parts(
+    settings: &Settings,
+    run: &RunContext,
+    minio_uploader: &MinioStorage,
+    mut parts: mpsc::UnboundedReceiver<CompletedPart>,
+) -> Result<HashMap<String, CityParts>> {
+    let mut cities: HashMap<String, CityParts> = HashMap::new();
+    while let Some(part) = parts.recv().await {
+        let city = cities.entry(part.city_id.clone()).or_default();
+        if part.dataset == "vendors" {
+            city.vendor_parts.push(part.path.clone());
+        }
+        if city.failed.is_some() {
+            continue;
+        }
+        let now = Utc::now();
+        let converted = match part.dataset {
+            "vendors" => convert_vendors(settings, run, &part.city_id, &part.path, run.partition_date, now),
+            "menu_items" => convert_menu_items(settings, run, &part.city_id, &part.path, run.partition_date, now),
+            _ => convert_reviews(settings, run, &part.city_id, &part.path, run.partition_date, now),
+        };
+        let upload = match converted {
+            Ok(upload) => upload,
+            Err(e) => {
+                city.failed = Some(CityFailed::from_error(e));
+                continue;
+            }
+        };
+        // A disabled dataset's JSON is its only copy
+        let Some(mut upload) = upload else {
+            continue;
+        };
+        if part.dataset != "vendors" {
+            fs::remove_file(&part.path)?;
+        }
+        upload.record_checksum()?;
+        // Parts wait for the city to be merged, on disk since they are merged from files
+        if settings.parquet.compact_before_upload {
+            if let Some(contents) = upload.contents.take() {
+                fs::write(&upload.path, contents)?;
+                upload.source = None;
+            }
+            city.uploads.push(upload);
+            continue;
+        }
+
+        match upload_pending(minio_uploader, &upload).await {
+            Ok(()) => {
+                info!(
+                    city_id = part.city_id,
+                    dataset = upload.dataset,
+                    s3_key = upload.s3_key,
+                    rows = upload.rows,
+                    "Uploaded file part"
+                );
+                if upload.contents.take().is_none() {
+                    fs::remove_file(&upload.path)?;
+                }
+                upload.uploaded = true;
+            }
+            Err(e) => warn!(
+                city_id = part.city_id,
+                s3_key = upload.s3_key,
+                error = %e,
+                "Failed to upload file part, retrying with the city"
+            ),
+        }
+        city.uploads.push(upload);
+    }
+    Ok(cities)
+}
+
+/// Converts the city's JSON into Parquet files next to it and records their
+/// target keys (and quality tagging) in the marker before advancing it.
+fn convert_city(settings: &Settings, run: &RunContext, marker: &mut PhaseMarker) -> Result<()> {
+    let city_id = marker.city_id.clone();
+    let output_dir = run.output_dir.as_path();
+    // A resumed city keeps the partition date of the run that extracted it
+    let partition_date = marker.partition_date.unwrap_or(run.partition_date);
+
+    info!(
+        city_id = city_id,
+        json_file = %marker.json_path.display(),
+        "Converting JSON to Parquet"
+    );
+
+    let now = Utc::now();
+
+    let mut uploads = Vec::new();
+    uploads.extend(convert_vendors(settings, run, &city_id, &marker.json_path, partition_date, now)?);
+
+    // Missing vendor detection needs the whole city, earlier parts and skipped vendors included
+    let mut observed = Vec::new();
+    for path in marker.vendor_parts.iter().chain([&marker.json_path]) {
+        for vendor in VendorStream::open(path)? {
+            observed.push(ObservedVendor::from(&vendor?));
+        }
+    }
+    let mut skipped_vendors = 0;
+    if let Some(path) = &marker.skipped_vendors_path {
+        for vendor in VendorStream::open(path)? {
+            observed.push(ObservedVendor::from(&vendor?));
+            skipped_vendors += 1;
+        }
+    }
+    uploads.extend(detect_missing_vendors(settings, output_dir, marker, &observed, partition_date, now, run.run_id)?);
+
+    if let Some(path) = &marker.menu_items_path {
+        uploads.extend(convert_menu_items(settings, run, &city_id, path, partition_date, now)?);
+    }
+    if let Some(path) = &marker.reviews_path {
+        uploads.extend(convert_reviews(settings, run, &city_id, path, partition_date, now)?);
+    }
+
+    let dataset = settings.output.dataset("reports");
+    if let (true, Some(path)) = (dataset.enabled, &marker.report_path) {
+        uploads.push(PendingUpload::new(
+            dataset.name,
+            path.clone(),
+            format!("{}.json", partition_key(
+                &dataset.prefix,
+                &settings.country.code,
+                &settings.partition_segment(&city_id),
+                partition_date,
+                now,
+                "report",
+                run.run_id,
+            )),
+            1,
+        ));
+    }
+
+    let dataset = settings.output.dataset("failed_vendors");
+    if let (true, Some(path)) = (dataset.enabled, &marker.dead_letter_path) {
+        let failed: Vec<FailedVendor> = serde_json::from_reader(BufReader::new(File::open(path)?))?;
+        uploads.push(PendingUpload::new(
+            dataset.name,
+            path.clone(),
+            format!("{}.json", partition_key(
+                &dataset.prefix,
+                &settings.country.code,
+                &settings.partition_segment(&city_id),
+                partition_date,
+                now,
+                "failed_vendors",
+                run.run_id,
+            )),
+            failed.len(),
+        ));
+    }
 
-fn get_log_filename(timestamp: &str, user_login: &str) -> String {
-    format!("logs/foodpanda_etl_{}_{}_.log", 
-        timestamp.replace(" ", "_"),
-        user_login
-    )
+    let dataset = settings.output.dataset("skipped_vendors");
+    if let (true, Some(path)) = (dataset.enabled, &marker.skipped_vendors_path) {
+        let extension = if path.extension().is_some_and(|ext| ext == "gz") { "json.gz" } else { "json" };
+        uploads.push(PendingUpload::new(
+            dataset.name,
+            path.clone(),
+            format!("{}.{}", partition_key(
+                &dataset.prefix,
+                &settings.country.code,
+                &settings.partition_segment(&city_id),
+                partition_date,
+                now,
+                "skipped_vendors",
+                run.run_id,
+            ), extension),
+            skipped_vendors,
+        ));
+    }
+
+    // Parts converted during extraction are already listed
+    marker.uploads.extend(uploads);
+    if settings.parquet.compact_before_upload {
+        let meta = parquet_meta(settings, run.run_id, &city_id, partition_date);
+        for dataset in PARQUET_DATASETS {
+            let (mut parts, rest): (Vec<PendingUpload>, Vec<PendingUpload>) = std::mem::take(&mut marker.uploads)
+                .into_iter()
+                .partition(|upload| upload.dataset == dataset && !upload.uploaded);
+            marker.uploads = rest;
+            match compact_parts(settings, &meta, &city_id, &mut parts)? {
+                Some(compacted) => marker.uploads.push(compacted),
+                None => marker.uploads.extend(parts),
+            }
+        }
+    }
+    marker.advance(output_dir, CityPhase::Converted)?;
+
+    Ok(())
+}
+
+/// Datasets converted to Parquet, whose parts `parquet.compact_before_upload` merges.
+const PARQUET_DATASETS: [&str; 3] = ["vendors", "menu_items", "reviews"];
+
+/// Merges a dataset's pending Parquet parts into one file next to the last
+/// part when there are several and they stay under `parquet.compact_max_mb`
+/// together. The merged upload takes the last part's key without its part
+/// number and lists the parts, which are removed.
+fn compact_parts(
+    settings: &Settings,
+    meta: &ParquetMeta,
+    city_id: &str,
+    parts: &mut [PendingUpload],
+) -> Result<Option<PendingUpload>> {
+    if parts.len() < 2 {
+        return Ok(None);
+    }
+    let last = &parts[parts.len() - 1];
+    let dataset = last.dataset.clone();
+    let output = last.path.with_file_name(format!(
+        "{}_compacted.parquet",
+        last.path.file_stem().and_then(|stem| stem.to_str()).unwrap_or(&dataset),
+    ));
+    let s3_key = unparted_key(&last.s3_key);
+    // Parts built in memory are merged from files like the rest
+    for part in parts.iter_mut() {
+        restore_contents(settings, meta, part)?;
+        if let Some(contents) = part.contents.take() {
+            fs::write(&part.path, contents)?;
+            part.source = None;
+        }
+    }
+    let paths: Vec<PathBuf> = parts.iter().map(|part| part.path.clone()).collect();
+    if !ParquetConverter::should_compact(&paths, settings.parquet.compact_max_mb * 1024 * 1024)? {
+        info!(
+            city_id = city_id,
+            dataset = dataset,
+            parts = paths.len(),
+            compact_max_mb = settings.parquet.compact_max_mb,
+            "Parts too large to compact, uploading them separately"
+        );
+        return Ok(None);
+    }
+
+    let rows = ParquetConverter::compact_files(&paths, &output, &settings.parquet)?;
+    info!(
+        city_id = city_id,
+        dataset = dataset,
+        parts = paths.len(),
+        rows = rows,
+        parquet_file = %output.display(),
+        "Compacted Parquet parts"
+    );
+    let mut compacted = PendingUpload::new(&dataset, output, s3_key, rows);
+    compacted.tagging = parts.iter().find_map(|part| part.tagging.clone());
+    compacted.null_rates = parts.iter()
+        .filter_map(|part| part.null_rates.as_ref())
+        .fold(None, |merged: Option<NullRates>, rates| {
+            let mut merged = merged.unwrap_or_default();
+            merged.merge(rates);
+            Some(merged)
+        });
+    compacted.compacted_from = paths.iter()
+        .filter_map(|path| path.file_name())
+        .map(|name| name.to_string_lossy().into_owned())
+        .collect();
+    for path in &paths {
+        fs::remove_file(path)?;
+    }
+    Ok(Some(compacted))
+}
+
+/// An object key with the `_partNNNN` of its file name removed.
+fn unparted_key(key: &str) -> String {
+    let name_start = key.rfind('/').map_or(0, |slash| slash + 1);
+    if let Some(offset) = key[name_start..].find("_part") {
+        let start = name_start + offset;
+        let digits = key[start + 5..].bytes().take_while(u8::is_ascii_digit).count();
+        if digits > 0 {
+            return format!("{}{}", &key[..start], &key[start + 5 + digits..]);
+        }
+    }
+    key.to_string()
+}
+
+/// Splits a leftover vendor temp file name
+/// (`<stem>_city_<key>_<timestamp>_[_partNNNN].json[.gz].tmp`) into the
+/// city key and the file timestamp its menu item and review files share.
+/// Files of runs before file-safe timestamps have `<date>_<time>` instead.
+fn parse_leftover_name(name: &str) -> Option<(&str, &str)> {
+    let stem = name.strip_suffix(".json.tmp").or_else(|| name.strip_suffix(".json.gz.tmp"))?;
+    let stem = match stem.rsplit_once("_part") {
+        Some((first, number)) if !number.is_empty() && number.bytes().all(|byte| byte.is_ascii_digit()) => first,
+        _ => stem,
+    };
+    let stem = stem.strip_suffix('_')?;
+    let (mut rest, timestamp) = stem.rsplit_once('_')?;
+    let mut timestamp_len = timestamp.len();
+    if !is_file_safe_timestamp(timestamp) {
+        let (before, date) = rest.rsplit_once('_')?;
+        rest = before;
+        timestamp_len += date.len() + 1;
+    }
+    let (prefix, city_id) = rest.split_once("_city_")?;
+    if !matches!(prefix, "vendors" | "vendors_sample") {
+        return None;
+    }
+    Some((city_id, &stem[stem.len() - timestamp_len..]))
+}
+
+/// Whether `value` has the `YYYYMMDDTHHMMSSZ` shape of `file_safe_timestamp`.
+fn is_file_safe_timestamp(value: &str) -> bool {
+    let bytes = value.as_bytes();
+    bytes.len() == 16
+        && bytes[8] == b'T'
+        && bytes[15] == b'Z'
+        && bytes[..8].iter().chain(&bytes[9..15]).all(u8::is_ascii_digit)
+}
+
+/// Repairs the JSON files of cities a previous run was killed while
+/// extracting, then converts and uploads what was recovered. The cities'
+/// phase markers are put back afterwards so the recovery doesn't count as a
+/// fresh extraction. Returns the uploaded keys.
+async fn recover_leftover_files(
+    settings: &Settings,
+    run: &RunContext,
+    minio_uploader: &MinioStorage,
+) -> Result<Vec<String>> {
+    let Ok(entries) = fs::read_dir(&run.output_dir) else {
+        return Ok(Vec::new());
+    };
+    let names: Vec<String> = entries
+        .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+        .collect();
+    // Menu items and reviews rotate on their own, so their unfinished part may have any number
+    let leftover = |prefix: String| {
+        names.iter()
+            .find(|name| name.starts_with(&prefix) && name.ends_with(".tmp"))
+            .map(|name| run.output_dir.join(name))
+    };
+    let partition_tz = settings.storage.partition_tz()?;
+    let mut uploaded_keys = Vec::new();
+    for name in &names {
+        let Some((city_id, file_timestamp)) = parse_leftover_name(name) else {
+            continue;
+        };
+        if settings.extraction.resume && CityCheckpoint::latest(&run.output_dir, city_id)?.is_some() {
+            info!(city_id = city_id, file = name.as_str(), "Leaving unfinished JSON file to resume from its checkpoint");
+            continue;
+        }
+        let temp_path = run.output_dir.join(name);
+        let city_id = city_id.to_string();
+        let menu_items_temp = leftover(format!("menu_items_city_{}_{}", city_id, file_timestamp));
+        let reviews_temp = leftover(format!("reviews_city_{}_{}", city_id, file_timestamp));
+        let skipped_temp = leftover(format!("skipped_vendors_city_{}_{}", city_id, file_timestamp));
+        let extracted_at = DateTime::<Utc>::from(fs::metadata(&temp_path)?.modified()?);
+
+        let (json_path, vendors) = match JsonWriter::repair(&temp_path) {
+            Ok(repaired) => repaired,
+            Err(e) => {
+                warn!(city_id = city_id, file = %temp_path.display(), error = %e, "Failed to repair leftover JSON file");
+                continue;
+            }
+        };
+        info!(
+            city_id = city_id,
+            json_file = %json_path.display(),
+            vendors_count = vendors,
+            "Repaired JSON file left by a previous run"
+        );
+
+        let mut marker = PhaseMarker::extracted(&city_id, json_path, logical_date(extracted_at, partition_tz), true);
+        let leftovers = [
+            (menu_items_temp, &mut marker.menu_items_path),
+            (reviews_temp, &mut marker.reviews_path),
+            (skipped_temp, &mut marker.skipped_vendors_path),
+        ];
+        for (temp_path, path) in leftovers {
+            let Some(temp_path) = temp_path else {
+                continue;
+            };
+            match JsonWriter::repair(&temp_path) {
+                Ok((repaired, _)) => *path = Some(repaired),
+                Err(e) => warn!(city_id = city_id, file = %temp_path.display(), error = %e, "Failed to repair leftover JSON file"),
+            }
+        }
+
+        // Converting and uploading advance the marker; keep the previous one
+        let marker_path = PhaseMarker::path(&run.output_dir, &city_id);
+        let previous_marker = fs::read(&marker_path).ok();
+        let recovered = async {
+            convert_city(settings, run, &mut marker)?;
+            upload_city(settings, run, minio_uploader, &mut marker).await
+        }.await;
+        match previous_marker {
+            Some(bytes) => fs::write(&marker_path, bytes)?,
+            None if marker_path.exists() => fs::remove_file(&marker_path)?,
+            None => {}
+        }
+        match recovered {
+            Ok(()) => {
+                info!(city_id = city_id, uploads = marker.uploads.len(), "Uploaded recovered city files");
+                uploaded_keys.extend(marker.uploads.iter().map(|upload| upload.s3_key.clone()));
+            }
+            Err(e) => warn!(city_id = city_id, error = %e, "Failed to upload recovered city files"),
+        }
+    }
+    Ok(uploaded_keys)
+}
+
+async fn upload_city(
+    settings: &Settings,
+    run: &RunContext,
+    minio_uploader: &MinioStorage,
+    marker: &mut PhaseMarker,
+) -> Result<()> {
+    let output_dir = run.output_dir.as_path();
+    let meta = parquet_meta(
+        settings,
+        run.run_id,
+        &marker.city_id,
+        marker.partition_date.unwrap_or(run.partition_date),
+    );
+    while let Some(index) = marker.uploads.iter().position(|u| !u.uploaded) {
+        restore_contents(settings, &meta, &mut marker.uploads[index])?;
+        marker.uploads[index].record_checksum()?;
+        let upload = marker.uploads[index].clone();
+        let file_size = upload.bytes;
+
+        info!(
+            dataset = upload.dataset,
+            s3_key = &upload.s3_key,
+            file_size_mb = file_size / (1024 * 1024),
+            "Uploading file to S3"
+        );
+
+        upload_pending(minio_uploader, &upload).await?;
+
+        // Record each finished upload so a resume doesn't repeat it
+        marker.uploads[index].uploaded = true;
+        marker.uploads[index].contents = None;
+        marker.store(output_dir)?;
+
+        info!(
+            dataset = upload.dataset,
+            s3_key = upload.s3_key,
+            rows = upload.rows,
+            file_size_bytes = file_size,
+            file_size_mb = file_size / (1024 * 1024),
+            "Successfully uploaded file to S3"
+        );
+    }
+
+    marker.advance(output_dir, CityPhase::Uploaded)?;
+
+    // Local artifacts are only removed once the upload is recorded. The JSON
+    // is the only copy of a disabled dataset, so it is kept in that case.
+    let mut local_files: Vec<&Path> = marker.uploads.iter().map(|u| u.path.as_path()).collect();
+    if marker.uploads.iter().any(|u| u.dataset == "vendors") {
+        local_files.push(&marker.json_path);
+        local_files.extend(marker.vendor_parts.iter().map(PathBuf::as_path));
+    }
+    if let (true, Some(path)) = (
+        marker.uploads.iter().any(|u| u.dataset == "menu_items"),
+        &marker.menu_items_path,
+    ) {
+        local_files.push(path);
+    }
+    if let (true, Some(path)) = (
+        marker.uploads.iter().any(|u| u.dataset == "reviews"),
+        &marker.reviews_path,
+    ) {
+        local_files.push(path);
+    }
+    for path in local_files {
+        // Parts uploaded during extraction were removed then
+        if !path.exists() {
+            continue;
+        }
+        if let Err(e) = fs::remove_file(path) {
+            error!(
+                error = %e,
+                filename = path.to_string_lossy().to_string(),
+                "Failed to remove local file"
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// `foodpanda_etl validate --input <parquet-or-json> [--strict] [--max-failure-pct <pct>]`
+///
+/// Runs stored records through the current typed models and fails when the
+/// share of unparseable records exceeds `--max-failure-pct` (default 0).
+fn run_validate(args: &[String]) -> Result<()> {
+    let mut input = None;
+    let mut strict = false;
+    let mut max_failure_pct = 0.0;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--input" => input = args.next().map(PathBuf::from),
+            "--strict" => strict = true,
+            "--max-failure-pct" => {
+                max_failure_pct = args
+                    .next()
+                    .ok_or_else(|| anyhow::anyhow!("--max-failure-pct needs a value"))?
+                    .parse()?;
+            }
+            other => anyhow::bail!("unknown validate argument: {}", other),
+        }
+    }
+    let input = input.ok_or_else(|| anyhow::anyhow!("validate needs --input <path>"))?;
+
+    let report = ValidationReport::for_file(&input, strict)?;
+    print!("{}", report);
+    if report.failure_pct() > max_failure_pct {
+        anyhow::bail!(
+            "{:.2}% of records failed validation (threshold {:.2}%)",
+            report.failure_pct(),
+            max_failure_pct
+        );
+    }
+    Ok(())
+}
+
+/// `foodpanda_etl convert --input <path-or-glob> --output-dir <dir> [--upload <key-template>]
+/// [--city-id <id>] [--date <YYYY-MM-DD>]`
+///
+/// Converts existing vendor JSON into Parquet without scraping, e.g. to redo
+/// a day after a schema change. With `--upload` each file is uploaded under
+/// the key template. Only the `parquet`, `country` and, with `--upload`,
+/// `minio` settings are read.
+async fn run_convert(args: &[String]) -> Result<()> {
+    let mut input = None;
+    let mut output_dir = None;
+    let mut key_template = None;
+    let mut city_id = None;
+    let mut date = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().cloned().ok_or_else(|| anyhow::anyhow!("{} needs a value", arg));
+        match arg.as_str() {
+            "--input" => input = Some(value()?),
+            "--output-dir" => output_dir = Some(PathBuf::from(value()?)),
+            "--upload" => key_template = Some(value()?),
+            "--city-id" => city_id = Some(value()?),
+            "--date" => date = Some(value()?.parse::<NaiveDate>()?),
+            other => anyhow::bail!("unknown convert argument: {}", other),
+        }
+    }
+    let input = input.ok_or_else(|| anyhow::anyhow!("convert needs --input <path-or-glob>"))?;
+    let output_dir = output_dir.ok_or_else(|| anyhow::anyhow!("convert needs --output-dir <dir>"))?;
+    let inputs = expand_input(&input)?;
+    if inputs.is_empty() {
+        anyhow::bail!("no files match {}", input);
+    }
+    fs::create_dir_all(&output_dir)?;
+
+    let parquet: ParquetConfig = Settings::section("parquet")?.unwrap_or_default();
+    let country = Settings::section::<CountryConfig>("country")?.unwrap_or_default().code;
+    let uploader = match &key_template {
+        Some(_) => {
+            let minio: MinioConfig = Settings::section("minio")?
+                .ok_or_else(|| anyhow::anyhow!("--upload needs the minio settings"))?;
+            Some(MinioStorage::new(
+                &minio.endpoint,
+                &minio.access_key,
+                &minio.secret_key,
+                &minio.bucket,
+                &minio.region,
+                minio.create_bucket_if_missing,
+            ).await?
+                .with_upload_retries(minio.upload_retries)
+                .with_multipart_concurrency(minio.multipart_concurrency)
+                .with_multipart_chunk_mb(minio.multipart_chunk_mb)
+                .with_multipart_threshold_mb(minio.multipart_threshold_mb))
+        }
+        None => None,
+    };
+    let date = date.unwrap_or_else(|| Utc::now().date_naive());
+    let run_id = Uuid::new_v4();
+
+    println!("file                                                   rows  skipped        bytes  key");
+    let mut failed = 0;
+    for path in &inputs {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        let city_id = city_id.clone()
+            .or_else(|| name.strip_prefix("vendors_city_")?.split('_').next().map(String::from))
+            .unwrap_or_else(|| "unknown".to_string());
+        let meta = ParquetMeta {
+            run_id: run_id.to_string(),
+            city_id,
+            country: country.clone(),
+            extraction_date: date,
+        };
+        let output = output_dir.join(replace_json_extension(path, "parquet").file_name().unwrap_or_default());
+        let converted = convert_file(path, &output, &meta, &parquet);
+        let uploaded = match (&converted, &uploader, &key_template) {
+            (Ok(_), Some(uploader), Some(template)) => {
+                let key = expand_key_template(template, &meta, &output, run_id);
+                uploader.upload_parquet_file(&output, &key, None).await.map(|()| Some(key))
+            }
+            _ => Ok(None),
+        };
+        match (converted, uploaded) {
+            (Ok(stats), Ok(key)) => {
+                for skipped in &stats.skipped {
+                    eprintln!("{}:{}: {}", path.display(), skipped.line, skipped.error);
+                }
+                println!(
+                    "{:<48} {:>10} {:>8} {:>12}  {}",
+                    name,
+                    stats.rows_converted,
+                    stats.rows_skipped,
+                    stats.output_bytes,
+                    key.as_deref().unwrap_or("-")
+                );
+            }
+            (Err(e), _) | (_, Err(e)) => {
+                failed += 1;
+                println!("{:<48} failed: {}", name, e);
+            }
+        }
+    }
+    if failed > 0 {
+        anyhow::bail!("{} of {} files failed", failed, inputs.len());
+    }
+    Ok(())
+}
+
+/// Converts one vendor JSON file: NDJSON (`.ndjson`/`.jsonl`, optionally
+/// gzipped) skipping unparseable lines, anything else as a `JsonWriter` file.
+fn convert_file(
+    input: &Path,
+    output: &Path,
+    meta: &ParquetMeta,
+    config: &ParquetConfig,
+) -> foodpanda_etl::error::Result<ConversionStats> {
+    let name = input.to_string_lossy();
+    let name = name.strip_suffix(".gz").unwrap_or(&name);
+    if name.ends_with(".ndjson") || name.ends_with(".jsonl") {
+        return ParquetConverter::convert_ndjson_file(input, output, meta, config);
+    }
+    let null_rates = ParquetConverter::convert_vendors_to_parquet(
+        VendorStream::open(input)?,
+        output,
+        meta,
+        config,
+    )?;
+    Ok(ConversionStats {
+        rows_converted: null_rates.rows(),
+        output_bytes: fs::metadata(output)?.len(),
+        ..Default::default()
+    })
+}
+
+/// Fills `{country}`, `{city_id}`, `{date}`, `{year}`, `{month}`, `{day}`,
+/// `{stem}` (the Parquet file name without extension) and `{run_id}` in a
+/// `--upload` key template.
+fn expand_key_template(template: &str, meta: &ParquetMeta, output: &Path, run_id: Uuid) -> String {
+    let date = meta.extraction_date;
+    let stem = output.file_stem().unwrap_or_default().to_string_lossy();
+    template
+        .replace("{country}", &meta.country)
+        .replace("{city_id}", &meta.city_id)
+        .replace("{date}", &date.to_string())
+        .replace("{year}", &date.year().to_string())
+        .replace("{month}", &format!("{:02}", date.month()))
+        .replace("{day}", &format!("{:02}", date.day()))
+        .replace("{stem}", &stem)
+        .replace("{run_id}", &run_id.to_string())
+}
+
+/// The files a `--input` names: the path itself, or the sorted matches of
+/// `*` and `?` in its file name (the directory must be literal).
+fn expand_input(input: &str) -> Result<Vec<PathBuf>> {
+    let path = Path::new(input);
+    let pattern = path.file_name().unwrap_or_default().to_string_lossy();
+    if !pattern.contains(['*', '?']) {
+        return Ok(vec![path.to_path_buf()]);
+    }
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    if dir.to_string_lossy().contains(['*', '?']) {
+        anyhow::bail!("only the file name of --input may contain wildcards: {}", input);
+    }
+    let mut matches = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        if wildcard_match(pattern.as_bytes(), entry.file_name().to_string_lossy().as_bytes()) {
+            matches.push(entry.path());
+        }
+    }
+    matches.sort();
+    Ok(matches)
+}
+
+fn wildcard_match(pattern: &[u8], name: &[u8]) -> bool {
+    match (pattern.split_first(), name.split_first()) {
+        (None, _) => name.is_empty(),
+        (Some((b'*', rest)), _) => {
+            wildcard_match(rest, name) || (!name.is_empty() && wildcard_match(pattern, &name[1..]))
+        }
+        (Some((b'?', rest)), Some((_, name_rest))) => wildcard_match(rest, name_rest),
+        (Some((expected, rest)), Some((actual, name_rest))) => expected == actual && wildcard_match(rest, name_rest),
+        (Some(_), None) => false,
+    }
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
-
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().is_some_and(|command| command == "validate") {
+        return run_validate(&args[1..]);
+    }
+    if args.first().is_some_and(|command| command == "convert") {
+        return run_convert(&args[1..]).await;
+    }
+    #[cfg(not(feature = "tui"))]
+    if args.iter().any(|arg| arg == "--tui") {
+        anyhow::bail!("--tui needs a build with the `tui` feature");
+    }
     
-    let timestamp = Utc::now().format("%Y-%m-%d %H:%M:%S").to_string();
+    let timestamp = file_safe_timestamp(Utc::now());
     let user_login = std::env::var("USER_LOGIN").unwrap_or_else(|_| "default_user".to_string());
 
      // Create logs directory if it doesn't exist
@@ -85,192 +2165,541 @@ async fn main() -> Result<()> {
          .json()
          .with_current_span(true)
          .with_filter(stdout_filter);
+    // Quiet while the dashboard owns the terminal
+    #[cfg(feature = "tui")]
+    let stdout_layer = stdout_layer.with_filter(tui::console_filter());
  
      // Initialize both layers
-     tracing_subscriber::registry()
+     let registry = tracing_subscriber::registry()
          .with(file_layer)
-         .with(stdout_layer)
-         .init();
+         .with(stdout_layer);
+    #[cfg(feature = "tui")]
+    let registry = registry.with(tui::RecentWarnings);
+    registry.init();
+
+    // Every log line of the run carries its id through the span
+    let run_id = Uuid::new_v4();
+    run(args, timestamp, user_login, run_id)
+        .instrument(info_span!("run", run_id = %run_id))
+        .await
+}
 
+async fn run(args: Vec<String>, timestamp: String, user_login: String, run_id: Uuid) -> Result<()> {
+    let started_at = Utc::now();
     info!(
         timestamp = timestamp,
         user = user_login,
+        run_id = %run_id,
         "Starting extraction"
     );
 
-    let settings = Settings::new()?;
-    let client_pool = Arc::new(ClientPool::new(settings.clone())?);
-    let api_service = ApiService::new(client_pool.clone());
-    let vendor_service = VendorService::new(api_service.clone());
+    let mut settings = Settings::new()?;
+    if args.iter().any(|arg| arg == "--dry-run") {
+        settings.dry_run = true;
+    }
+    let pacer = settings.pacing.adaptive.then(|| Arc::new(AdaptivePacer::new(&settings.pacing)));
+    let mut client_pool = ClientPool::new(settings.clone())?.with_run_id(&run_id.to_string());
+    if let Some(pacer) = &pacer {
+        client_pool = client_pool.with_pacer(pacer.clone());
+    }
+    let client_pool = Arc::new(client_pool);
+    let throttling = SharedThrottling::new(settings.throttling.clone(), &settings.concurrency);
+    let api_service = ApiService::new(client_pool.clone(), &settings, throttling.clone())
+        .with_cassette(Cassette::from_env()?);
+    let (progress_sender, progress_events) = mpsc::channel(1024);
+    #[cfg(feature = "tui")]
+    let (progress_events, _dashboard) = start_dashboard(&args, progress_events, &client_pool, pacer.clone())?;
+    let run_status = RunStatus::new(run_id, Duration::from_secs(settings.health.stale_after_secs));
+    let (progress_events, health_server) = start_health_server(&settings, &run_status, progress_events).await?;
+    spawn_progress_logger(progress_events);
+    let vendor_service = VendorService::new(Arc::new(api_service.clone()), &settings, throttling.clone())
+        .with_run_id(run_id)
+        .with_progress(ProgressSender::new(progress_sender));
+    let vendor_service = match pacer {
+        Some(pacer) => vendor_service.with_pacer(pacer),
+        None => vendor_service,
+    };
 
-    // Process each city from the configuration
-    for city_id in &settings.cities {
-        info!(city_id = city_id, "Processing city");
+    let reloads = Arc::new(std::sync::Mutex::new(Vec::new()));
+    #[cfg(unix)]
+    spawn_reload_listener(throttling.clone(), settings.clone(), reloads.clone())?;
 
-        // Get output directory from environment variable or use a default
-        let output_dir = std::env::var("OUTPUT_DIR").unwrap_or_else(|_| "data".to_string());
-
-        let filename = format!("vendors_city_{}_{}_.json", city_id, timestamp.replace(" ", "_"));
-        // Create temporary Parquet file
-        let temp_parquet = NamedTempFile::new()?;
-        let json_writer = JsonWriter::new(&filename).await?;
-        let json_writer = Arc::new(Mutex::new(json_writer));
-        
-        // Get initial page to determine total count and page size
-        let initial_response = api_service.fetch_vendor_page(city_id, 0, 48).await?;
-        let total_vendors = initial_response.data.available_count;
-        let page_size = initial_response.data.returned_count;
-        let total_pages = (total_vendors as f32 / page_size as f32).ceil() as i32;
+    // Resolve `cities: auto` after the reload listener took its copy, so a
+    // reload compares against the configured value rather than the discovered list
+    let cities = resolve_cities(&settings, &api_service).await?;
+    settings.cities = CitiesConfig::List(cities.selected);
 
-        info!(
-            total_vendors = total_vendors,
-            total_pages = total_pages,
-            page_size = page_size,
-            "Vendor pagination details"
-        );
+    let mut output_dir = settings.output_dir.clone();
+    if settings.dry_run {
+        // Keep dry-run markers and state away from those of real runs
+        output_dir = output_dir.join("dry_run");
+        info!(output_dir = %output_dir.display(), "Dry run: no API requests or uploads will be made");
+    }
+    // Computed once so a run crossing midnight doesn't split its partitions
+    let partition_tz = settings.storage.partition_tz()?;
+    let partition_date = logical_date(Utc::now(), partition_tz);
+    info!(
+        partition_date = %partition_date,
+        partition_timezone = settings.storage.partition_timezone,
+        "Using logical partition date for this run"
+    );
+
+    let run = RunContext {
+        run_id,
+        timestamp,
+        user_login,
+        output_dir,
+        partition_date,
+        force: args.iter().any(|arg| arg == "--force"),
+    };
+    // Filled in as cities finish and uploaded last, marking the run complete
+    let mut manifest = RunManifest::new(run_id, partition_date, settings.dry_run, Settings::snapshot()?, started_at);
+    // Taken just before `started_at`, so it can be a second behind it
+    manifest.timestamp = run.timestamp.clone();
+    manifest.discovered_cities = cities.discovered;
+    manifest.selected_cities = settings.cities.queries().iter().map(ListingQuery::key).collect();
+
+    // Verified once up front so an unreachable bucket fails before any extraction
+    let minio_uploader = if settings.dry_run {
+        MinioStorage::dry_run(
+            &settings.minio.endpoint,
+            &settings.minio.access_key,
+            &settings.minio.secret_key,
+            &settings.minio.bucket,
+            &settings.minio.region,
+        )
+    } else {
+        MinioStorage::new(
+            &settings.minio.endpoint,
+            &settings.minio.access_key,
+            &settings.minio.secret_key,
+            &settings.minio.bucket,
+            &settings.minio.region,
+            settings.minio.create_bucket_if_missing,
+        ).await?
+            .with_upload_retries(settings.minio.upload_retries)
+            .with_multipart_concurrency(settings.minio.multipart_concurrency)
+            .with_multipart_chunk_mb(settings.minio.multipart_chunk_mb)
+            .with_multipart_threshold_mb(settings.minio.multipart_threshold_mb)
+    };
+    // Parts of uploads a killed run left behind count against quota
+    let stale_after = Duration::from_secs(settings.minio.stale_upload_hours * 3600);
+    for (name, _) in OutputConfig::DATASETS {
+        let dataset = settings.output.dataset(name);
+        if !dataset.enabled {
+            continue;
+        }
+        let prefix = format!("{}country={}/", dataset.prefix, settings.country.code);
+        match minio_uploader.cleanup_stale_uploads(&prefix, stale_after).await {
+            Ok(0) => {}
+            Ok(aborted) => info!(prefix = prefix, aborted = aborted, "Aborted stale multipart uploads"),
+            Err(e) => warn!(prefix = prefix, error = %e, "Failed to clean up stale multipart uploads"),
+        }
+    }
+    let vendor_service = if settings.incremental.is_enabled() {
+        vendor_service.with_incremental(load_incremental_manifest(&settings, &run, &minio_uploader).await?)
+    } else {
+        vendor_service
+    };
+    let vendor_service = vendor_service.with_checkpoints(&run.output_dir);
+    let recent_uploads = recent_uploads(&settings, &minio_uploader).await;
+    // Salvage whatever a killed run had fetched before starting over
+    let mut uploaded_keys = recover_leftover_files(&settings, &run, &minio_uploader).await?;
 
-        // Start timer
-        let start_time = std::time::Instant::now();
+    let mut initial_responses = preflight(&settings, &api_service, &run).await?;
 
-        // Process all pages
-        for page in 0..total_pages {
-            let offset = page * page_size;
-            
-            if page > 0 {
-                sleep_with_jitter(2000, 1000).await;
+    // Interleaved runs extract every city up front, then convert and upload below
+    let mut interleaved = HashMap::new();
+    if vendor_service.interleave_cities() {
+        let mut to_extract = Vec::new();
+        for query in settings.cities.queries() {
+            let city_id = query.key();
+            let pending = PhaseMarker::load(&run.output_dir, &city_id)?
+                .is_some_and(|marker| marker.is_pending());
+            if !pending && is_fresh(&settings, &run, &city_id, &recent_uploads)?.is_none() {
+                to_extract.push(query.clone());
             }
-            
-            let response = api_service.fetch_vendor_page(city_id, offset, page_size).await?;
-            let vendor_codes: Vec<String> = response.data.items
-                .into_iter()
-                .map(|item| item.code)
-                .collect();
-
-            info!(
-                page = page + 1,
-                total_pages = total_pages,
-                vendors_count = vendor_codes.len(),
-                "Processing vendor batch"
+        }
+        info!(
+            cities = ?to_extract.iter().map(ListingQuery::key).collect::<Vec<_>>(),
+            "Extracting cities interleaved"
+        );
+        run_status.enter(RunState::Extracting, None);
+        for city in extract_cities_interleaved(
+            &api_service,
+            &vendor_service,
+            &settings,
+            &run,
+            &minio_uploader,
+            &to_extract,
+            &mut initial_responses,
+        ).await? {
+            interleaved.insert(city.0.city_id.clone(), city);
+        }
+    }
+
+    // Process each city from the configuration
+    for query in settings.cities.queries() {
+        let city_id = &query.key();
+        if !vendor_service.interleave_cities() && vendor_service.budget_exhausted() {
+            warn!(
+                city_id = city_id,
+                bytes_downloaded = api_service.stats().total_bytes(),
+                max_bytes_downloaded = ?settings.limits.max_bytes_downloaded,
+                "Download budget exhausted, skipping remaining cities"
             );
+            break;
+        }
+        info!(city_id = city_id, "Processing city");
 
-            match vendor_service.process_vendor_batch(
-                vendor_codes,
-                &json_writer,
-                page + 1,
-                total_pages,
-            ).await {
-                Ok(_) => info!(
-                    page = page + 1,
-                    total_pages = total_pages,
-                    "Batch processed successfully"
-                ),
-                Err(e) => {
-                    error!(
-                        error = %e,
-                        page = page + 1,
-                        total_pages = total_pages,
-                        "Failed to process batch"
+        let (mut marker, failed) = match interleaved.remove(city_id) {
+            Some(city) => city,
+            // Resume a city whose previous run was killed between phases
+            None => match PhaseMarker::load(&run.output_dir, city_id)? {
+                Some(marker) if marker.is_pending() => {
+                    info!(
+                        city_id = city_id,
+                        phase = ?marker.phase,
+                        json_file = %marker.json_path.display(),
+                        "Resuming city from phase marker"
                     );
-                    return Err(e.into());
+                    (marker, None)
                 }
-            }
+                _ => {
+                    if let Some(freshness) = is_fresh(&settings, &run, city_id, &recent_uploads)? {
+                        info!(city_id = city_id, status = "fresh", "Skipping recently uploaded city");
+                        manifest.record_fresh_city(city_id, freshness);
+                        manifest.store(&run.output_dir)?;
+                        continue;
+                    }
+                    run_status.enter(RunState::Extracting, Some(city_id.as_str()));
+                    let (output, extraction, report_path, parts) = extract_city(
+                        &api_service,
+                        &vendor_service,
+                        &settings,
+                        &run,
+                        &minio_uploader,
+                        query,
+                        initial_responses.remove(city_id),
+                    ).await?;
+                    let dead_letter_path = write_dead_letter(&run, &extraction)?;
+                    let too_many_failed = check_vendor_failures(&settings, &extraction);
+                    let mut marker = PhaseMarker::extracted(
+                        city_id,
+                        output.vendors_path,
+                        run.partition_date,
+                        !extraction.is_complete(),
+                    );
+                    marker.dead_letter_path = dead_letter_path;
+                    marker.report_path = Some(report_path);
+                    marker.menu_items_path = Some(output.menu_items_path);
+                    marker.reviews_path = Some(output.reviews_path);
+                    marker.skipped_vendors_path = output.skipped_vendors_path;
+                    let parts_failed = parts.record(&mut marker);
+                    marker.store(&run.output_dir)?;
+                    (marker, too_many_failed.or(parts_failed))
+                }
+            },
+        };
+        if let Some(failed) = failed {
+            fail_city(&run, &mut manifest, &run_status, &mut marker, failed)?;
+            continue;
         }
 
-        // Finish writing and upload for this city
-        let final_count = {
-            let mut writer = json_writer.lock().await;  
-            writer.finish().await?;
-            writer.get_count()
-        };
+        if marker.phase == CityPhase::Extracted {
+            run_status.enter(RunState::Converting, Some(city_id.as_str()));
+            if let Err(e) = convert_city(&settings, &run, &mut marker) {
+                fail_city(&run, &mut manifest, &run_status, &mut marker, CityFailed::from_error(e))?;
+                continue;
+            }
+        }
 
-        let total_time = start_time.elapsed();
-        let minutes = total_time.as_secs_f64() / 60.0;
-        let vendors_per_second = final_count as f64 / total_time.as_secs_f64();
+        if marker.phase == CityPhase::Converted {
+            // Upload to MinIO
+            info!(city_id = city_id, "Starting MinIO upload");
+            run_status.enter(RunState::Uploading, Some(city_id.as_str()));
+            let report = load_report(&marker)?;
+            if let Err(e) = upload_city(&settings, &run, &minio_uploader, &mut marker).await {
+                let failed = CityFailed::upload(&marker, e);
+                fail_city(&run, &mut manifest, &run_status, &mut marker, failed)?;
+                continue;
+            }
+            uploaded_keys.extend(marker.uploads.iter().map(|upload| upload.s3_key.clone()));
+            CityCheckpoint::remove_all(&run.output_dir, city_id)?;
+            manifest.record_city(&marker, report);
+            manifest.store(&run.output_dir)?;
+            if let Some(city) = manifest.cities.last() {
+                run_status.city_finished(city);
+            }
+        }
+    }
+    run_status.enter(RunState::Finishing, None);
 
+    let traffic = api_service.stats().snapshot();
+    info!(
+        bytes_downloaded = traffic.total_bytes(),
+        bytes_by_endpoint = ?traffic.by_endpoint(),
+        "Run traffic totals"
+    );
+    log_endpoint_metrics("run", &api_service.metrics().summary());
+    // A configured manifest path is shared with real runs; leave it alone
+    if let (Some(incremental), false) = (vendor_service.incremental_manifest(), settings.dry_run) {
+        store_incremental_manifest(&settings, &run, &minio_uploader, &incremental).await?;
+        uploaded_keys.extend(settings.incremental.manifest_s3_key.clone());
+    }
+    if settings.dry_run {
+        let stats = api_service.stats();
         info!(
-            city_id = city_id,
-            timestamp = timestamp,
-            user = user_login,
-            total_vendors = final_count,
-            total_pages = total_pages,
-            page_size = page_size,
-            total_minutes = minutes,
-            vendors_per_second = vendors_per_second,
-            output_file = filename,
-            "Extraction completed"
+            listing_pages = stats.requests(Endpoint::Listing),
+            vendors = stats.requests(Endpoint::Details),
+            s3_keys = ?uploaded_keys,
+            "Dry run summary: would have fetched these pages and vendors and uploaded to these keys"
         );
+    }
 
-        // Upload to MinIO
-        info!(city_id = city_id, "Starting MinIO upload");
+    manifest.uploaded_keys = uploaded_keys;
+    manifest.reloads = reloads.lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .clone();
+    manifest.finished_at = Some(Utc::now());
+    manifest.store(&run.output_dir)?;
+    let manifest_key = manifest.s3_key();
+    minio_uploader.upload_file(&RunManifest::path(&run.output_dir, run_id), &manifest_key, None).await?;
+    info!(
+        s3_key = manifest_key,
+        cities = manifest.cities.len(),
+        uploaded_keys = manifest.uploaded_keys.len(),
+        "Uploaded run manifest"
+    );
+    if let Some(health_server) = health_server {
+        health_server.shutdown().await;
+    }
+    // Every other city was still processed; the exit status reports the failed ones
+    let failed_cities: Vec<&str> = manifest.cities.iter()
+        .filter(|city| city.failed.is_some())
+        .map(|city| city.city_id.as_str())
+        .collect();
+    if !failed_cities.is_empty() {
+        anyhow::bail!("cities failed: {}", failed_cities.join(", "));
+    }
+    info!(
+        data_classes = ?settings.extraction.data_classes(),
+        "All cities processed successfully"
+    );
+    Ok(())
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use foodpanda_etl::models::Vendor;
 
-        
+    fn settings() -> Settings {
+        serde_json::from_value(serde_json::json!({
+            "cities": ["1"],
+            "minio": {
+                "endpoint": "http://127.0.0.1:9000",
+                "access_key": "test",
+                "secret_key": "test",
+                "bucket": "test",
+                "region": "us-east-1",
+            },
+            "api": { "headers": {} },
+        }))
+        .expect("test settings deserialize")
+    }
 
-        // Initialize MinIO uploader once
-        let minio_uploader = MinioUploader::new(
-            &settings.minio.endpoint,
-            &settings.minio.access_key,
-            &settings.minio.secret_key,
-            &settings.minio.bucket,
-            &settings.minio.region,
-        ).await?;
-        
-        info!(
-            city_id = city_id,
-            json_file = &filename,
-            "Converting JSON to Parquet"
-        );
-    
-        // Read JSON and convert to Parquet
-        let file_path = Path::new(&output_dir).join(&filename);
-        let json_file = File::open(&file_path)?;
-        let reader = BufReader::new(json_file);
-        let vendors: Vec<Vendor> = serde_json::from_reader(reader)?;
-    
-        // Convert to Parquet
-        ParquetConverter::convert_vendors_to_parquet(
-            &vendors,
-            temp_parquet.path().to_str().unwrap()
-        )?;
-    
-        // Generate partitioned S3 key
-        let now = Utc::now();
-        let s3_key = format!(
-            "city_id={}/year={}/month={:02}/day={:02}/vendors_{}.parquet",
-            city_id,
-            now.year(),
-            now.month(),
-            now.day(),
-            now.timestamp()
-        );
+    fn run_context(output_dir: &Path) -> RunContext {
+        RunContext {
+            run_id: Uuid::new_v4(),
+            timestamp: "20250314T093000Z".to_string(),
+            user_login: "test".to_string(),
+            output_dir: output_dir.to_path_buf(),
+            partition_date: NaiveDate::from_ymd_opt(2025, 3, 14).unwrap(),
+            force: false,
+        }
+    }
+
+    /// Logs uploads instead of sending them.
+    fn uploader() -> MinioStorage {
+        MinioStorage::dry_run("http://127.0.0.1:9000", "test", "test", "test", "us-east-1")
+    }
 
-        // Get file size before upload
-        let file_size = temp_parquet.as_file().metadata()?.len();
+    /// What a run killed right after extracting city `1` leaves behind: its
+    /// vendor JSON and an `extracted` marker.
+    fn extracted_city(run: &RunContext) -> PhaseMarker {
+        let json_path = run.output_dir.join("vendors_city_1_20250314T093000Z_.json");
+        let lines: Vec<String> = (0..3)
+            .map(|index| serde_json::to_string(&Vendor::stub(&format!("v{}", index), 1, Some(run.run_id), "ok")).unwrap())
+            .collect();
+        fs::write(&json_path, format!("[\n{}\n]\n", lines.join(",\n"))).unwrap();
+        let mut marker = PhaseMarker::extracted("1", json_path, run.partition_date, false);
+        marker.store(&run.output_dir).unwrap();
+        marker
+    }
 
-        info!(
-            s3_key = &s3_key,
-            file_size_mb = file_size / (1024 * 1024),
-            "Uploading Parquet file to S3"
-        );
+    /// The marker a restarted run resumes city `1` from, once leftover
+    /// recovery has run.
+    async fn restart(settings: &Settings, run: &RunContext) -> PhaseMarker {
+        let recovered = recover_leftover_files(settings, run, &uploader()).await.unwrap();
+        assert!(recovered.is_empty());
+        let marker = PhaseMarker::load(&run.output_dir, "1").unwrap().unwrap();
+        assert!(marker.is_pending());
+        marker
+    }
 
-        // Upload file
-        minio_uploader.upload_parquet_file(temp_parquet.path(), &s3_key).await?;
+    #[tokio::test]
+    async fn extracted_city_resumes_at_conversion() {
+        let dir = tempfile::tempdir().unwrap();
+        let settings = settings();
+        let run = run_context(dir.path());
+        let extracted = fs::read(&extracted_city(&run).json_path).unwrap();
 
-        info!(
-            s3_key = s3_key,
-            vendors_count = vendors.len(),
-            file_size_mb = file_size / (1024 * 1024),
-            "Successfully uploaded Parquet file to S3"
-        );
-    
-        // Optionally cleanup the JSON file
-        if let Err(e) = std::fs::remove_file(&file_path) {
-            error!(
-                error = %e,
-                filename = file_path.to_string_lossy().to_string(),
-                "Failed to remove JSON file"
-            );
-        }
+        let mut marker = restart(&settings, &run).await;
+
+        assert_eq!(marker.phase, CityPhase::Extracted);
+        // The killed run's JSON is converted as it was left
+        assert_eq!(fs::read(&marker.json_path).unwrap(), extracted);
+        convert_city(&settings, &run, &mut marker).unwrap();
+        assert_eq!(marker.phase, CityPhase::Converted);
+        let vendors = marker.uploads.iter().find(|upload| upload.dataset == "vendors").unwrap();
+        assert_eq!(vendors.rows, 3);
+        assert!(vendors.path.exists());
+
+        upload_city(&settings, &run, &uploader(), &mut marker).await.unwrap();
+        let stored = PhaseMarker::load(&run.output_dir, "1").unwrap().unwrap();
+        assert_eq!(stored.phase, CityPhase::Uploaded);
     }
-    info!("All cities processed successfully");
-    Ok(())
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn converted_city_resumes_at_upload() {
+        let dir = tempfile::tempdir().unwrap();
+        let settings = settings();
+        let run = run_context(dir.path());
+        let mut marker = extracted_city(&run);
+        convert_city(&settings, &run, &mut marker).unwrap();
+        let parquet = marker.uploads.iter().find(|upload| upload.dataset == "vendors").unwrap().path.clone();
+        // Without its JSON the city can't be extracted or converted again, only uploaded
+        fs::remove_file(&marker.json_path).unwrap();
+
+        let mut marker = restart(&settings, &run).await;
+
+        assert_eq!(marker.phase, CityPhase::Converted);
+        assert!(marker.uploads.iter().all(|upload| !upload.uploaded));
+        upload_city(&settings, &run, &uploader(), &mut marker).await.unwrap();
+        let stored = PhaseMarker::load(&run.output_dir, "1").unwrap().unwrap();
+        assert_eq!(stored.phase, CityPhase::Uploaded);
+        assert!(stored.uploads.iter().all(|upload| upload.uploaded));
+        assert!(!parquet.exists());
+    }
+
+    /// Settings that skip cities uploaded within the last 12 hours.
+    fn freshness_settings() -> Settings {
+        let mut settings = settings();
+        settings.extraction.skip_if_fresh_hours = Some(12);
+        settings
+    }
+
+    /// Uploads of city `1` recorded by a run manifest `hours` ago.
+    fn manifest_upload(hours: i64) -> BTreeMap<String, LastUpload> {
+        BTreeMap::from([("1".to_string(), LastUpload {
+            source: "runs/2025-03-13/run/manifest.json".to_string(),
+            uploaded_at: Utc::now() - chrono::Duration::hours(hours),
+        })])
+    }
+
+    /// Marks city `1` uploaded just now.
+    fn uploaded_city(run: &RunContext) {
+        let mut marker = PhaseMarker::extracted("1", run.output_dir.join("vendors.json"), run.partition_date, false);
+        marker.advance(&run.output_dir, CityPhase::Uploaded).unwrap();
+    }
+
+    #[test]
+    fn city_uploaded_recently_is_fresh() {
+        let dir = tempfile::tempdir().unwrap();
+        let run = run_context(dir.path());
+        uploaded_city(&run);
+
+        let freshness = is_fresh(&freshness_settings(), &run, "1", &BTreeMap::new()).unwrap().unwrap();
+
+        assert_eq!(freshness.last_upload.source, PhaseMarker::path(dir.path(), "1").display().to_string());
+        assert!(freshness.age_minutes < 1);
+        // The run manifest is consulted without a marker, as on a new container
+        let other_dir = tempfile::tempdir().unwrap();
+        let freshness = is_fresh(&freshness_settings(), &run_context(other_dir.path()), "1", &manifest_upload(2))
+            .unwrap()
+            .unwrap();
+        assert_eq!(freshness.last_upload.source, "runs/2025-03-13/run/manifest.json");
+        assert_eq!(freshness.age_minutes, 120);
+    }
+
+    #[test]
+    fn city_uploaded_long_ago_is_stale() {
+        let dir = tempfile::tempdir().unwrap();
+        let run = run_context(dir.path());
+
+        assert_eq!(is_fresh(&freshness_settings(), &run, "1", &manifest_upload(30)).unwrap(), None);
+        // A newer marker wins over an older manifest
+        uploaded_city(&run);
+        assert!(is_fresh(&freshness_settings(), &run, "1", &manifest_upload(30)).unwrap().is_some());
+    }
+
+    #[test]
+    fn city_without_marker_or_manifest_is_not_fresh() {
+        let dir = tempfile::tempdir().unwrap();
+        let run = run_context(dir.path());
+
+        assert_eq!(is_fresh(&freshness_settings(), &run, "1", &BTreeMap::new()).unwrap(), None);
+        // Nor is an extracted city that was never uploaded
+        extracted_city(&run);
+        assert_eq!(is_fresh(&freshness_settings(), &run, "1", &BTreeMap::new()).unwrap(), None);
+    }
+
+    #[test]
+    fn force_extracts_fresh_cities() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut run = run_context(dir.path());
+        uploaded_city(&run);
+        run.force = true;
+
+        assert_eq!(is_fresh(&freshness_settings(), &run, "1", &manifest_upload(1)).unwrap(), None);
+        // Freshness isn't checked at all without skip_if_fresh_hours
+        run.force = false;
+        assert_eq!(is_fresh(&settings(), &run, "1", &manifest_upload(1)).unwrap(), None);
+    }
+
+    #[test]
+    fn failed_upload_fails_only_the_city_and_stays_resumable() {
+        let dir = tempfile::tempdir().unwrap();
+        let settings = settings();
+        let run = run_context(dir.path());
+        let mut marker = extracted_city(&run);
+        convert_city(&settings, &run, &mut marker).unwrap();
+        let mut manifest = RunManifest::new(run.run_id, run.partition_date, false, serde_json::Value::Null, Utc::now());
+        let run_status = RunStatus::new(run.run_id, Duration::from_secs(60));
+
+        let failed = CityFailed::upload(&marker, anyhow::anyhow!("MinIO error: service unavailable"));
+        fail_city(&run, &mut manifest, &run_status, &mut marker, failed).unwrap();
+
+        assert_eq!(manifest.cities[0].failed.as_deref(), Some("upload failed: MinIO error: service unavailable"));
+        // The null rates computed while converting are kept
+        assert!(!manifest.cities[0].null_rates.is_empty());
+        let stored = PhaseMarker::load(&run.output_dir, "1").unwrap().unwrap();
+        assert_eq!(stored.phase, CityPhase::Converted);
+        assert!(stored.is_pending());
+    }
+
+    #[test]
+    fn conversion_errors_fail_the_city_for_extraction() {
+        let quality = CityFailed::from_error(CityFailed {
+            reason: "too many nulls".to_string(),
+            null_rates: BTreeMap::from([("details".to_string(), 1.0)]),
+            resumable: false,
+        }.into());
+        assert_eq!(quality.reason, "too many nulls");
+        assert_eq!(quality.null_rates.len(), 1);
+
+        let other = CityFailed::from_error(anyhow::Error::from(std::io::Error::other("disk full")).context("converting vendors"));
+        assert_eq!(other.reason, "converting vendors: disk full");
+        assert!(!other.resumable);
+    }
+}
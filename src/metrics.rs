@@ -0,0 +1,108 @@
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::time::Duration;
+use serde::Serialize;
+use crate::stats::Endpoint;
+
+/// One request as seen by the caller: the final status after the client's own
+/// retries, and the time spent including them.
+#[derive(Debug, Clone, Copy)]
+pub struct CallRecord {
+    /// `None` when no response was received (connection error, timeout).
+    pub status: Option<u16>,
+    pub latency: Duration,
+    pub retries: u32,
+}
+
+impl CallRecord {
+    pub fn is_error(&self) -> bool {
+        self.status.is_none_or(|status| status >= 400)
+    }
+}
+
+/// Per-endpoint request latency and status metrics, shared by every client in
+/// the pool. Records are kept for the whole run; summaries can cover the run or
+/// everything since a [`MetricsMark`].
+#[derive(Debug, Default)]
+pub struct Metrics {
+    calls: Mutex<BTreeMap<Endpoint, Vec<CallRecord>>>,
+}
+
+/// Position in the records, for summaries of a later stretch (e.g. one city).
+#[derive(Debug, Clone, Default)]
+pub struct MetricsMark(BTreeMap<Endpoint, usize>);
+
+#[derive(Debug, Clone, Serialize)]
+pub struct EndpointSummary {
+    pub endpoint: Endpoint,
+    pub count: usize,
+    pub p50_ms: u64,
+    pub p95_ms: u64,
+    pub error_rate: f64,
+    pub retries: u64,
+    pub statuses: BTreeMap<String, usize>,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&self, endpoint: Endpoint, call: CallRecord) {
+        let mut calls = self.calls.lock().unwrap_or_else(|e| e.into_inner());
+        calls.entry(endpoint).or_default().push(call);
+    }
+
+    pub fn mark(&self) -> MetricsMark {
+        let calls = self.calls.lock().unwrap_or_else(|e| e.into_inner());
+        MetricsMark(calls.iter().map(|(endpoint, records)| (*endpoint, records.len())).collect())
+    }
+
+    /// Summary of every request so far, per endpoint that saw any.
+    pub fn summary(&self) -> Vec<EndpointSummary> {
+        self.summary_since(&MetricsMark::default())
+    }
+
+    pub fn summary_since(&self, mark: &MetricsMark) -> Vec<EndpointSummary> {
+        let calls = self.calls.lock().unwrap_or_else(|e| e.into_inner());
+        calls.iter()
+            .filter_map(|(endpoint, records)| {
+                let start = mark.0.get(endpoint).copied().unwrap_or(0);
+                summarize(*endpoint, records.get(start..)?)
+            })
+            .collect()
+    }
+}
+
+fn summarize(endpoint: Endpoint, records: &[CallRecord]) -> Option<EndpointSummary> {
+    if records.is_empty() {
+        return None;
+    }
+    let mut latencies: Vec<u64> = records.iter()
+        .map(|record| record.latency.as_millis() as u64)
+        .collect();
+    latencies.sort_unstable();
+
+    let mut statuses = BTreeMap::new();
+    for record in records {
+        let status = record.status.map_or_else(|| "none".to_string(), |s| s.to_string());
+        *statuses.entry(status).or_insert(0) += 1;
+    }
+
+    let errors = records.iter().filter(|record| record.is_error()).count();
+    Some(EndpointSummary {
+        endpoint,
+        count: records.len(),
+        p50_ms: percentile(&latencies, 50),
+        p95_ms: percentile(&latencies, 95),
+        error_rate: errors as f64 / records.len() as f64,
+        retries: records.iter().map(|record| record.retries as u64).sum(),
+        statuses,
+    })
+}
+
+/// Nearest-rank percentile of sorted, non-empty values.
+fn percentile(sorted: &[u64], pct: usize) -> u64 {
+    let rank = (sorted.len() * pct).div_ceil(100).max(1);
+    sorted[rank - 1]
+}
@@ -0,0 +1,16 @@
+use serde::{Deserialize, Serialize};
+
+/// A city from the country's city configuration endpoint.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct City {
+    pub id: i64,
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub url_key: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CitiesResponse {
+    pub data: Vec<City>,
+}
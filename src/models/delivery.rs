@@ -0,0 +1,12 @@
+use serde::{Deserialize, Serialize};
+
+/// Delivery fee and time quoted by the fee-calculation endpoint for a location.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DeliveryInfo {
+    #[serde(alias = "delivery_fee")]
+    pub fee: Option<f64>,
+    pub currency: Option<String>,
+    #[serde(alias = "delivery_time")]
+    pub eta_minutes: Option<i32>,
+}
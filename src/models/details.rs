@@ -0,0 +1,58 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+/// Vendor details from the v5 vendors API. Only the fields we use are typed;
+/// every other field is kept in `extra`, so serializing writes the full object.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VendorDetails {
+    pub name: String,
+    pub rating: Option<f64>,
+    #[serde(rename = "review_number")]
+    pub review_count: Option<i64>,
+    pub cuisines: Vec<Cuisine>,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    pub address: Option<String>,
+    #[serde(rename = "minimum_order_amount")]
+    pub minimum_order: Option<f64>,
+    #[serde(rename = "minimum_delivery_fee")]
+    pub delivery_fee: Option<f64>,
+    pub chain: Option<Chain>,
+    pub is_active: Option<bool>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Cuisine {
+    pub id: Option<i64>,
+    pub name: String,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Chain {
+    pub code: Option<String>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+impl VendorDetails {
+    pub fn chain_code(&self) -> Option<&str> {
+        self.chain.as_ref()?.code.as_deref()
+    }
+
+    /// Name of the vendor's city, from the untyped `city` field (an object
+    /// with a `name`, or a plain string). `None` for any other shape.
+    pub fn address_city(&self) -> Option<&str> {
+        match self.extra.get("city")? {
+            Value::String(name) => Some(name.as_str()),
+            Value::Object(city) => city.get("name")?.as_str(),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,15 @@
+use serde::{Deserialize, Serialize};
+
+/// A deal from the `discounts` section of vendor details.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Discount {
+    #[serde(alias = "type")]
+    pub discount_type: Option<String>,
+    #[serde(alias = "discount_amount", alias = "value")]
+    pub amount: Option<f64>,
+    #[serde(alias = "minimum_order_value", alias = "minimum_order_amount")]
+    pub min_order_value: Option<f64>,
+    pub start_date: Option<String>,
+    pub end_date: Option<String>,
+}
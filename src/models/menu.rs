@@ -0,0 +1,55 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use super::details::VendorDetails;
+
+/// One product of a vendor's menu, flattened out of the `menus` section of
+/// the details response (requested with `include=menus`).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MenuItem {
+    pub vendor_code: String,
+    pub menu_id: Option<i64>,
+    pub category: Option<String>,
+    pub product_id: Option<i64>,
+    pub name: String,
+    pub description: Option<String>,
+    /// Price of the first variation; products are priced per variation.
+    pub price: Option<f64>,
+    pub is_available: bool,
+}
+
+impl MenuItem {
+    /// Every product under `menus[].menu_categories[].products[]`; empty when
+    /// the details carry no menus.
+    pub fn from_details(vendor_code: &str, details: &VendorDetails) -> Vec<MenuItem> {
+        let mut items = Vec::new();
+        for menu in array(details.extra.get("menus")) {
+            let menu_id = menu.get("id").and_then(Value::as_i64);
+            for category in array(menu.get("menu_categories")) {
+                let category_name = category.get("name").and_then(Value::as_str);
+                for product in array(category.get("products")) {
+                    items.push(MenuItem {
+                        vendor_code: vendor_code.to_string(),
+                        menu_id,
+                        category: category_name.map(str::to_string),
+                        product_id: product.get("id").and_then(Value::as_i64),
+                        name: product.get("name").and_then(Value::as_str).unwrap_or_default().to_string(),
+                        description: product.get("description")
+                            .and_then(Value::as_str)
+                            .filter(|description| !description.is_empty())
+                            .map(str::to_string),
+                        price: array(product.get("product_variations"))
+                            .first()
+                            .and_then(|variation| variation.get("price"))
+                            .and_then(Value::as_f64),
+                        is_available: !product.get("is_sold_out").and_then(Value::as_bool).unwrap_or(false),
+                    });
+                }
+            }
+        }
+        items
+    }
+}
+
+fn array(value: Option<&Value>) -> &[Value] {
+    value.and_then(Value::as_array).map_or(&[], Vec::as_slice)
+}
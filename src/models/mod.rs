@@ -1,7 +1,19 @@
 mod vendor;
+mod city;
+mod delivery;
+mod details;
+mod discount;
+mod menu;
 mod ratings;
 mod response;
+mod review;
 
 pub use vendor::Vendor;
-pub use ratings::RatingsDistribution;
-pub use response::{VendorListResponse, VendorDetailResponse, ReviewsResponse, VendorData, VendorItem};
+pub use city::{CitiesResponse, City};
+pub use delivery::DeliveryInfo;
+pub use details::{Chain, Cuisine, VendorDetails};
+pub use discount::Discount;
+pub use menu::MenuItem;
+pub use ratings::{RatingScore, RatingsDistribution};
+pub use review::Review;
+pub use response::{pages_for, VendorListResponse, VendorDetailResponse, DeliveryInfoResponse, ReviewsResponse, VendorData, VendorItem};
@@ -1,28 +1,106 @@
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct VendorListResponse {
     pub data: VendorData,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct VendorData {
     pub items: Vec<VendorItem>,
     pub returned_count: i32,
     pub available_count: i32,
 }
 
-#[derive(Debug, Deserialize)]
+impl VendorData {
+    /// Whether the listing has nothing to page through, e.g. a temporarily
+    /// disabled city reporting zero returned or available vendors.
+    pub fn is_empty_listing(&self) -> bool {
+        self.returned_count <= 0 || self.available_count <= 0
+    }
+
+    /// Pages needed for the advertised vendors at this response's page size.
+    pub fn total_pages(&self) -> i32 {
+        pages_for(self.available_count, self.returned_count)
+    }
+}
+
+/// Number of listing pages needed for `available` vendors at `page_size` per
+/// page; zero rather than a division by zero when either is not positive.
+pub fn pages_for(available: i32, page_size: i32) -> i32 {
+    if page_size <= 0 || available <= 0 {
+        return 0;
+    }
+    // Rounds up without overflowing next to i32::MAX
+    (available - 1) / page_size + 1
+}
+
+/// A listing entry; only what the vendor filter needs besides the code.
+#[derive(Debug, Serialize, Deserialize)]
 pub struct VendorItem {
     pub code: String,
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub rating: Option<f64>,
+    #[serde(default)]
+    pub cuisines: Vec<super::details::Cuisine>,
+    /// Price level, 1 (cheapest) to 3.
+    #[serde(default)]
+    pub budget: Option<i32>,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct VendorDetailResponse {
-    pub data: serde_json::Value,
+    pub data: super::details::VendorDetails,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DeliveryInfoResponse {
+    pub data: super::delivery::DeliveryInfo,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct ReviewsResponse {
     pub data: Vec<serde_json::Value>,
-}
\ No newline at end of file
+    /// Cursor for the next page; absent or empty on the last page.
+    #[serde(default, rename = "nextPageKey")]
+    pub next_page_key: Option<String>,
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn listing(available_count: i32, returned_count: i32) -> VendorData {
+        VendorData { items: Vec::new(), returned_count, available_count }
+    }
+
+    #[test]
+    fn pages_round_up_to_whole_pages() {
+        // (available, page size, pages)
+        let cases = [
+            (0, 48, 0),
+            (1, 48, 1),
+            (47, 48, 1),
+            (48, 48, 1),
+            (96, 48, 2),
+            (97, 48, 3),
+            (48, 0, 0),
+            (48, -1, 0),
+            (-5, 48, 0),
+            (i32::MAX, 48, 44_739_243),
+            (i32::MAX, 1, i32::MAX),
+        ];
+        for (available, page_size, pages) in cases {
+            assert_eq!(pages_for(available, page_size), pages, "{} vendors at {} per page", available, page_size);
+        }
+    }
+
+    #[test]
+    fn total_pages_uses_the_returned_count_as_page_size() {
+        assert_eq!(listing(0, 48).total_pages(), 0);
+        assert_eq!(listing(96, 48).total_pages(), 2);
+        assert_eq!(listing(97, 48).total_pages(), 3);
+        assert_eq!(listing(97, 0).total_pages(), 0);
+    }
+}
@@ -0,0 +1,62 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+/// One customer review, flattened out of the reviews API's `data` entries.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Review {
+    pub uuid: String,
+    pub vendor_code: String,
+    /// Score of the `overall` rating topic.
+    pub overall_rating: Option<f64>,
+    pub text: Option<String>,
+    #[serde(default, with = "chrono::serde::ts_milliseconds_option")]
+    pub created_at: Option<DateTime<Utc>>,
+    /// Titles of the ordered products the review is attached to.
+    #[serde(default)]
+    pub dish_names: Vec<String>,
+    /// SHA-256 of the reviewer id, so reviewers can be grouped without being
+    /// identified.
+    pub reviewer_id_hash: Option<String>,
+}
+
+impl Review {
+    pub fn from_value(vendor_code: &str, review: &Value) -> Review {
+        let overall_rating = array(review.get("ratings"))
+            .iter()
+            .find(|rating| rating.get("topic").and_then(Value::as_str) == Some("overall"))
+            .and_then(|rating| rating.get("score"))
+            .and_then(Value::as_f64);
+
+        Review {
+            uuid: review.get("uuid").and_then(Value::as_str).unwrap_or_default().to_string(),
+            vendor_code: vendor_code.to_string(),
+            overall_rating,
+            text: review.get("text")
+                .and_then(Value::as_str)
+                .filter(|text| !text.is_empty())
+                .map(str::to_string),
+            created_at: review.get("createdAt")
+                .and_then(Value::as_str)
+                .and_then(|created_at| DateTime::parse_from_rfc3339(created_at).ok())
+                .map(|created_at| created_at.with_timezone(&Utc)),
+            dish_names: array(review.get("productVariations"))
+                .iter()
+                .filter_map(|product| product.get("defaultTitle").and_then(Value::as_str))
+                .map(str::to_string)
+                .collect(),
+            reviewer_id_hash: review.get("reviewerId")
+                .and_then(|id| match id {
+                    Value::String(id) => Some(id.clone()),
+                    Value::Number(id) => Some(id.to_string()),
+                    _ => None,
+                })
+                .map(|id| format!("{:x}", Sha256::digest(id.as_bytes()))),
+        }
+    }
+}
+
+fn array(value: Option<&Value>) -> &[Value] {
+    value.and_then(Value::as_array).map_or(&[], Vec::as_slice)
+}
@@ -1,16 +1,125 @@
+use std::collections::BTreeMap;
 use serde::{Deserialize, Serialize};
+use uuid::Uuid;
 use chrono::{DateTime, Utc};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Vendor {
     pub code: String,
     pub name: String,
-    pub details: Option<serde_json::Value>,
+    pub details: Option<super::details::VendorDetails>,
+    /// Details per language id when `country.languages` asks for more than
+    /// one; `details` then holds the default language.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub localized_details: BTreeMap<i32, super::details::VendorDetails>,
     pub batch_number: i32,
+    /// Run that extracted the vendor.
+    #[serde(default)]
+    pub run_id: Option<Uuid>,
     pub reviews: Option<Vec<serde_json::Value>>,
     pub ratings: Option<super::ratings::RatingsDistribution>,
-    #[serde(with = "chrono::serde::ts_seconds")]
+    #[serde(default)]
+    pub discounts: Vec<super::discount::Discount>,
+    /// Only fetched when `extraction.fetch_delivery_info` is set.
+    #[serde(default)]
+    pub delivery_fee: Option<f64>,
+    #[serde(default)]
+    pub delivery_eta_minutes: Option<i32>,
+    /// Not fetched again because a recent incremental run already extracted it.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub unchanged: bool,
+    /// Rejected by `vendor_filter`; only the listing's code and name are set.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub filtered: bool,
+    /// How extraction ended: `ok`, `skipped_<status>` (400/404/410, or just
+    /// `skipped` in a dry run), `unchanged` or `filtered`. Vendors that failed
+    /// are left out and go to the dead-letter file instead.
+    #[serde(default)]
+    pub status: String,
+    /// Details requests sent for the vendor, retries included.
+    #[serde(default)]
+    pub attempts: i32,
+    /// SHA-256 of the details with `incremental.volatile_fields` stripped;
+    /// only set for vendors written with details.
+    #[serde(default)]
+    pub content_hash: Option<String>,
+    /// The content hash differs from the incremental manifest's (or the
+    /// vendor is new to it).
+    #[serde(default)]
+    pub changed: bool,
+    /// Epoch milliseconds; files from before that hold epoch seconds.
+    #[serde(with = "crate::utils::serde_helpers::epoch_millis")]
     pub extraction_started_at: DateTime<Utc>,
-    #[serde(with = "chrono::serde::ts_seconds")]
+    #[serde(with = "crate::utils::serde_helpers::epoch_millis")]
     pub extraction_completed_at: DateTime<Utc>,
-}
\ No newline at end of file
+    /// Time spent fetching the vendor, from before its details request to
+    /// after its last sub-fetch.
+    #[serde(default)]
+    pub extraction_duration_ms: i64,
+}
+impl Vendor {
+    /// A vendor without details: its code, the "Unknown" placeholder name and
+    /// `status`, with both extraction timestamps set to now. Callers fill in
+    /// whatever else they know about it.
+    pub fn stub(code: &str, batch_number: i32, run_id: Option<Uuid>, status: &str) -> Self {
+        let now = Utc::now();
+        Self {
+            code: code.to_string(),
+            name: "Unknown".to_string(),
+            details: None,
+            localized_details: BTreeMap::new(),
+            batch_number,
+            run_id,
+            reviews: None,
+            ratings: None,
+            discounts: Vec::new(),
+            delivery_fee: None,
+            delivery_eta_minutes: None,
+            unchanged: false,
+            filtered: false,
+            status: status.to_string(),
+            attempts: 0,
+            content_hash: None,
+            changed: false,
+            extraction_started_at: now,
+            extraction_completed_at: now,
+            extraction_duration_ms: 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn vendor() -> Vendor {
+        let mut vendor = Vendor::stub("v1", 1, None, "ok");
+        vendor.extraction_started_at = Utc.timestamp_millis_opt(1_741_944_600_123).unwrap();
+        vendor.extraction_completed_at = Utc.timestamp_millis_opt(1_741_944_601_987).unwrap();
+        vendor
+    }
+
+    #[test]
+    fn extraction_timestamps_round_trip_to_the_millisecond() {
+        let vendor = vendor();
+        let json = serde_json::to_value(&vendor).unwrap();
+        assert_eq!(json["extraction_started_at"], 1_741_944_600_123_i64);
+
+        let read: Vendor = serde_json::from_value(json).unwrap();
+
+        assert_eq!(read.extraction_started_at, vendor.extraction_started_at);
+        assert_eq!(read.extraction_completed_at, vendor.extraction_completed_at);
+        assert_eq!(read.extraction_completed_at.timestamp_subsec_millis(), 987);
+    }
+
+    #[test]
+    fn epoch_seconds_from_older_files_still_read() {
+        let mut json = serde_json::to_value(vendor()).unwrap();
+        json["extraction_started_at"] = 1_741_944_600_i64.into();
+
+        let read: Vendor = serde_json::from_value(json).unwrap();
+
+        assert_eq!(read.extraction_started_at, Utc.timestamp_opt(1_741_944_600, 0).unwrap());
+    }
+}
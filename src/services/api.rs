@@ -1,38 +1,360 @@
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use tracing::{error, debug};
+use async_trait::async_trait;
+use tracing::{error, debug, info, warn};
 use http::StatusCode;
+use bytes::Bytes;
+use rquest::Response;
+use serde::Deserialize;
+use url::Url;
 use crate::clients::ClientPool;
+use crate::config::{CountryConfig, ListingQuery, ReviewsQueryConfig, Settings};
 use crate::error::{Result, Error};
-use crate::models::{VendorListResponse, VendorDetailResponse, ReviewsResponse, RatingsDistribution};
-use crate::utils::retry_with_backoff;
+use crate::models::{CitiesResponse, City, DeliveryInfo, DeliveryInfoResponse, VendorListResponse, VendorDetailResponse, VendorDetails, ReviewsResponse, RatingsDistribution};
+use crate::utils::{file_safe_name, retry_with_backoff};
 use crate::utils::time::sleep_with_jitter;
+use crate::utils::throttle::SharedThrottling;
+use crate::metrics::Metrics;
+use crate::stats::{Endpoint, StatsRegistry};
+use crate::services::{Cassette, SchemaWatch};
 
 const MAX_RETRIES: u32 = 3;
 const BASE_DELAY_MS: u64 = 1000;
 
+/// Vendors requested per listing page.
+pub const LISTING_PAGE_SIZE: i32 = 48;
+
+/// Server errors worth retrying on another client.
+fn is_transient(status: StatusCode) -> bool {
+    matches!(
+        status,
+        StatusCode::BAD_GATEWAY | StatusCode::SERVICE_UNAVAILABLE | StatusCode::GATEWAY_TIMEOUT
+    )
+}
+
+/// Listing page returned instead of a response in a dry run.
+const DRY_RUN_LISTING: &[u8] = br#"{"data":{"items":[],"returned_count":0,"available_count":0}}"#;
+
+const DEFAULT_LISTING_BASE_URL: &str = "https://disco.deliveryhero.io";
+
+/// Configured base URL without a trailing slash, or the default when unset.
+fn base_url(configured: Option<&str>, default: String) -> String {
+    configured
+        .map(|url| url.trim_end_matches('/').to_string())
+        .unwrap_or(default)
+}
+
+/// Outcome of a vendor details request.
+#[derive(Debug, Clone, Default)]
+pub struct DetailsFetch {
+    /// `None` when the vendor is unavailable or the request was skipped.
+    pub details: Option<VendorDetails>,
+    /// The 400/404/410 that made the vendor unavailable.
+    pub unavailable_status: Option<u16>,
+    /// Requests sent, retries included; 0 in a dry run.
+    pub attempts: u32,
+}
+
+/// The vendor endpoints `VendorService` extracts from, so it can run against
+/// something other than the live API.
+#[async_trait]
+pub trait VendorApi: Send + Sync {
+    fn stats(&self) -> &Arc<StatsRegistry>;
+
+    async fn fetch_vendor_page(&self, query: &ListingQuery, offset: i32, limit: i32) -> Result<VendorListResponse>;
+
+    async fn fetch_vendor_details(&self, code: &str) -> Result<DetailsFetch>;
+
+    async fn fetch_vendor_details_in(&self, code: &str, language_id: i32) -> Result<Option<VendorDetails>>;
+
+    async fn fetch_vendor_ratings(&self, vendor_code: &str) -> Result<Option<RatingsDistribution>>;
+
+    async fn fetch_vendor_reviews(&self, vendor_code: &str) -> Result<Vec<serde_json::Value>>;
+
+    async fn fetch_vendor_delivery_info(&self, vendor_code: &str, lat: f64, lng: f64) -> Result<Option<DeliveryInfo>>;
+}
+
+/// Query of the disco listing endpoint.
+struct ListingParams<'a> {
+    query: &'a ListingQuery,
+    offset: i32,
+    limit: i32,
+    country: &'a str,
+    language_id: i32,
+}
+
+impl ListingParams<'_> {
+    fn pairs(&self) -> Vec<(&'static str, String)> {
+        let mut pairs = match self.query {
+            ListingQuery::City(city_id) => vec![("city_id", city_id.clone())],
+            ListingQuery::Geo { lat, lng } => vec![
+                ("latitude", lat.to_string()),
+                ("longitude", lng.to_string()),
+            ],
+        };
+        pairs.extend([
+            ("offset", self.offset.to_string()),
+            ("limit", self.limit.to_string()),
+            // Sent empty, as the web client does
+            ("configuration", String::new()),
+            ("country", self.country.to_string()),
+            ("language_id", self.language_id.to_string()),
+            ("sort", String::new()),
+            ("vertical", "restaurants".to_string()),
+        ]);
+        pairs
+    }
+}
+
+/// `base` with the query pairs form-encoded onto it.
+fn with_query(base: &str, pairs: &[(&str, String)]) -> Result<String> {
+    Ok(Url::parse_with_params(base, pairs)?.to_string())
+}
+
+/// Writes an unparseable body verbatim for later replay. The subject goes
+/// through [`file_safe_name`] so it can't leave the endpoint's directory.
+fn capture_body(dir: &Path, endpoint: Endpoint, subject: &str, body: &[u8]) -> Result<PathBuf> {
+    let dir = dir.join(endpoint.as_str());
+    std::fs::create_dir_all(&dir)?;
+    let timestamp = chrono::Utc::now().format("%Y%m%dT%H%M%S%.3f");
+    let path = dir.join(format!("{}_{}.json", file_safe_name(subject), timestamp));
+    std::fs::write(&path, body)?;
+    Ok(path)
+}
+
 #[derive(Clone)]
 pub struct ApiService {
     client_pool: Arc<ClientPool>,
+    stats: Arc<StatsRegistry>,
+    metrics: Arc<Metrics>,
+    country: CountryConfig,
+    throttling: SharedThrottling,
+    max_reviews_per_vendor: Option<usize>,
+    reviews_query: ReviewsQueryConfig,
+    detail_includes: Vec<String>,
+    listing_base_url: String,
+    vendor_base_url: String,
+    reviews_base_url: String,
+    dry_run: bool,
+    cassette: Cassette,
+    schema: Arc<SchemaWatch>,
+    capture_dir: Option<PathBuf>,
 }
 
 impl ApiService {
-    pub fn new(client_pool: Arc<ClientPool>) -> Self {
-        Self { client_pool }
+    pub fn new(client_pool: Arc<ClientPool>, settings: &Settings, throttling: SharedThrottling) -> Self {
+        let stats = client_pool.stats();
+        let metrics = client_pool.metrics();
+        let country = settings.country.clone();
+        let api = &settings.api;
+        Self {
+            client_pool,
+            stats,
+            metrics,
+            listing_base_url: base_url(api.listing_base_url.as_deref(), DEFAULT_LISTING_BASE_URL.to_string()),
+            vendor_base_url: base_url(api.vendor_base_url.as_deref(), format!("https://{}", country.api_host)),
+            reviews_base_url: base_url(api.reviews_base_url.as_deref(), format!("https://{}", country.reviews_host)),
+            country,
+            throttling,
+            max_reviews_per_vendor: settings.extraction.max_reviews_per_vendor,
+            reviews_query: api.reviews.clone(),
+            detail_includes: api.detail_includes.clone(),
+            dry_run: settings.dry_run,
+            cassette: Cassette::Off,
+            schema: Arc::new(SchemaWatch::new(settings.quality.strict_schema)),
+            capture_dir: settings.debug.capture_dir.clone(),
+        }
+    }
+
+    /// Records responses to, or replays them from, disk (see [`Cassette`]).
+    pub fn with_cassette(mut self, cassette: Cassette) -> Self {
+        self.cassette = cassette;
+        self
+    }
+
+    pub fn stats(&self) -> &Arc<StatsRegistry> {
+        &self.stats
+    }
+
+    /// Latency and status-code metrics of every request made so far.
+    pub fn metrics(&self) -> &Arc<Metrics> {
+        &self.metrics
+    }
+
+    /// Shared response handler: reads the body and counts its bytes against the endpoint.
+    async fn read_body(&self, endpoint: Endpoint, response: Response) -> Result<Bytes> {
+        let body = response.bytes().await?;
+        self.stats.record_bytes(endpoint, body.len() as u64);
+        Ok(body)
+    }
+
+    /// Turns an unexpected status into an error, still counting the advertised body size.
+    fn status_error(&self, endpoint: Endpoint, response: Response) -> Error {
+        self.stats.record_bytes(endpoint, response.content_length().unwrap_or(0));
+        Error::Http(response.error_for_status().unwrap_err())
+    }
+
+    /// Logs a response body that failed to parse. With `debug.capture_dir` set
+    /// the body is written to `{capture_dir}/{endpoint}/{subject}_{timestamp}.json`
+    /// and only its path is logged.
+    fn parse_failure(&self, endpoint: Endpoint, subject: &str, body: &[u8], e: serde_json::Error) -> Error {
+        let captured = self.capture_dir.as_deref()
+            .and_then(|dir| match capture_body(dir, endpoint, subject, body) {
+                Ok(path) => Some(path),
+                Err(capture_error) => {
+                    warn!(error = %capture_error, "Failed to capture unparseable response");
+                    None
+                }
+            });
+        match captured {
+            Some(path) => error!(
+                error = %e,
+                endpoint = endpoint.as_str(),
+                subject = subject,
+                line = e.line(),
+                column = e.column(),
+                captured = %path.display(),
+                "Failed to parse API response"
+            ),
+            None => error!(
+                error = %e,
+                endpoint = endpoint.as_str(),
+                subject = subject,
+                body = %String::from_utf8_lossy(body),
+                "Failed to parse API response"
+            ),
+        }
+        Error::from(e)
+    }
+
+    /// Checks the listing's `data` object against [`VendorData`](crate::models::VendorData).
+    fn check_listing_schema(&self, raw: &serde_json::Value, page: &VendorListResponse) -> Result<()> {
+        let Some(raw_data) = raw.get("data") else {
+            return Ok(());
+        };
+        let modeled = serde_json::to_value(&page.data)?;
+        self.schema.report("vendor listing", &SchemaWatch::unknown_keys(raw_data, &modeled))
+    }
+
+    /// Details keep untyped fields in `extra`; those are what the models don't
+    /// cover, apart from the sections requested through `include=`.
+    fn check_details_schema(&self, details: &VendorDetails) -> Result<()> {
+        let unknown = details.extra.keys().filter(|key| !self.detail_includes.contains(key));
+        self.schema.report("vendor details", unknown)
+    }
+
+    /// Empty bodies and bodies without a non-empty `ratings` array mean no ratings.
+    fn parse_ratings(&self, vendor_code: &str, body: &[u8]) -> Result<Option<RatingsDistribution>> {
+        if body.iter().all(u8::is_ascii_whitespace) {
+            return Ok(None);
+        }
+        let value: serde_json::Value = serde_json::from_slice(body)?;
+        let has_ratings = value.get("ratings")
+            .and_then(|ratings| ratings.as_array())
+            .is_some_and(|ratings| !ratings.is_empty());
+        if !has_ratings {
+            return Ok(None);
+        }
+        let ratings = RatingsDistribution::deserialize(&value)
+            .map_err(|e| self.parse_failure(Endpoint::Ratings, vendor_code, body, e))?;
+        let modeled = serde_json::to_value(&ratings)?;
+        self.schema.report("ratings distribution", &SchemaWatch::unknown_keys(&value, &modeled))?;
+        Ok(Some(ratings))
     }
 
-    pub async fn fetch_vendor_page(&self, city_id: &str, offset: i32, limit: i32) -> Result<VendorListResponse> {
-        let url = format!(
-            "https://disco.deliveryhero.io/listing/api/v1/pandora/vendors?\
-             city_id={}&offset={}&limit={}&\
-             configuration=&country=pk&language_id=1&sort=&vertical=restaurants",
-            city_id, offset, limit
+    /// In a dry run, logs and counts the request that would have been made;
+    /// callers then return an empty response instead of sending it.
+    fn skip_request(&self, endpoint: Endpoint, url: &str) -> bool {
+        if !self.dry_run {
+            return false;
+        }
+        self.stats.record_request(endpoint);
+        info!(
+            endpoint = endpoint.as_str(),
+            url = url,
+            "Dry run, skipping request"
         );
+        true
+    }
+
+    /// Lists every city of the configured country. The cities live on the
+    /// fd-api host, so the request goes out with the details headers.
+    pub async fn fetch_cities(&self) -> Result<Vec<City>> {
+        let url = with_query(
+            &format!("{}/api/v5/cities", self.vendor_base_url),
+            &[("language_id", self.country.language_id.to_string())],
+        )?;
+        if self.skip_request(Endpoint::Details, &url) {
+            return Ok(Vec::new());
+        }
+        if let Some(body) = self.cassette.replay(&url)? {
+            return Ok(serde_json::from_slice::<CitiesResponse>(&body)?.data);
+        }
+
+        let client = self.client_pool.next_client();
+
+        retry_with_backoff(MAX_RETRIES, BASE_DELAY_MS, || async {
+            let request = client.get(&url, Endpoint::Details);
+            let response = client.send(request, Endpoint::Details).await?;
+
+            debug!(
+                status = response.status().as_u16(),
+                url = url,
+                "API response received"
+            );
+
+            if response.status() == StatusCode::OK {
+                let body = self.read_body(Endpoint::Details, response).await?;
+                self.cassette.record(&url, &body)?;
+                let cities: CitiesResponse = serde_json::from_slice(&body)
+                    .map_err(|e| self.parse_failure(Endpoint::Details, "cities", &body, e))?;
+                return Ok(cities.data);
+            }
+
+            Err(self.status_error(Endpoint::Details, response))
+        }).await
+    }
+
+    pub async fn fetch_vendor_page(&self, query: &ListingQuery, offset: i32, limit: i32) -> Result<VendorListResponse> {
+        let (page, _) = self.fetch_vendor_page_raw(query, offset, limit).await?;
+        Ok(page)
+    }
+
+    /// Like [`fetch_vendor_page`](Self::fetch_vendor_page), also returning the
+    /// response body as received.
+    pub async fn fetch_vendor_page_raw(
+        &self,
+        query: &ListingQuery,
+        offset: i32,
+        limit: i32,
+    ) -> Result<(VendorListResponse, Bytes)> {
+        let params = ListingParams {
+            query,
+            offset,
+            limit,
+            country: &self.country.code,
+            language_id: self.country.language_id,
+        };
+        let city_key = format!("{}_offset_{}", query.key(), offset);
+        let url = with_query(
+            &format!("{}/listing/api/v1/pandora/vendors", self.listing_base_url),
+            &params.pairs(),
+        )?;
+        if self.skip_request(Endpoint::Listing, &url) {
+            let body = Bytes::from_static(DRY_RUN_LISTING);
+            return Ok((serde_json::from_slice(&body)?, body));
+        }
+        if let Some(body) = self.cassette.replay(&url)? {
+            let page = serde_json::from_slice(&body)?;
+            self.check_listing_schema(&serde_json::from_slice(&body)?, &page)?;
+            return Ok((page, body));
+        }
 
         let client = self.client_pool.next_client();
         
         retry_with_backoff(MAX_RETRIES, BASE_DELAY_MS, || async {
-            let request = client.get(&url);
-            let response = client.send(request).await?;
+            let request = client.get(&url, Endpoint::Listing);
+            let response = client.send(request, Endpoint::Listing).await?;
             
             debug!(
                 status = response.status().as_u16(),
@@ -41,50 +363,71 @@ impl ApiService {
             );
 
             if response.status() == StatusCode::OK {
-                let body = response.bytes().await?;
+                let body = self.read_body(Endpoint::Listing, response).await?;
+                self.cassette.record(&url, &body)?;
                 
-                if let Err(e) = serde_json::from_slice::<serde_json::Value>(&body) {
-                    let body_str = String::from_utf8_lossy(&body);
-                    error!(
-                        error = %e,
-                        body = %body_str,
-                        "Invalid JSON response"
-                    );
-                    return Err(Error::Json(e));
-                }
+                let raw = match serde_json::from_slice::<serde_json::Value>(&body) {
+                    Ok(raw) => raw,
+                    Err(e) => return Err(self.parse_failure(Endpoint::Listing, &city_key, &body, e)),
+                };
 
-                return serde_json::from_slice(&body).map_err(|e| {
-                    let body_str = String::from_utf8_lossy(&body);
-                    error!(
-                        error = %e,
-                        body = %body_str,
-                        "Failed to parse vendor page response"
-                    );
-                    Error::from(e)
-                });
+                let page = serde_json::from_slice(&body)
+                    .map_err(|e| self.parse_failure(Endpoint::Listing, &city_key, &body, e))?;
+                self.check_listing_schema(&raw, &page)?;
+                return Ok((page, body));
             }
             
-            Err(Error::Http(response.error_for_status().unwrap_err()))
+            Err(self.status_error(Endpoint::Listing, response))
         }).await
     }
 
 
     
-    pub async fn fetch_vendor_details(&self, code: &str) -> Result<Option<serde_json::Value>> {
-        let url = format!(
-            "https://pk.fd-api.com/api/v5/vendors/{}?\
-             include=menus,bundles,multiple_discounts&language_id=1&\
-             opening_type=delivery&basket_currency=PKR",
-            code
-        );
+    /// Returns `None` for vendors the API has no details for (400, 404 or 410);
+    /// the status is logged so the reasons can be told apart.
+    pub async fn fetch_vendor_details(&self, code: &str) -> Result<DetailsFetch> {
+        self.fetch_details(code, self.country.language_id).await
+    }
+
+    /// [`fetch_vendor_details`](Self::fetch_vendor_details) in another language.
+    pub async fn fetch_vendor_details_in(&self, code: &str, language_id: i32) -> Result<Option<VendorDetails>> {
+        Ok(self.fetch_details(code, language_id).await?.details)
+    }
+
+    async fn fetch_details(&self, code: &str, language_id: i32) -> Result<DetailsFetch> {
+        let mut params = vec![
+            ("language_id", language_id.to_string()),
+            ("opening_type", "delivery".to_string()),
+            ("basket_currency", self.country.currency.clone()),
+        ];
+        if !self.detail_includes.is_empty() {
+            params.push(("include", self.detail_includes.join(",")));
+        }
+        let url = with_query(&format!("{}/api/v5/vendors/{}", self.vendor_base_url, code), &params)?;
+        if self.skip_request(Endpoint::Details, &url) {
+            return Ok(DetailsFetch::default());
+        }
+        if let Some(body) = self.cassette.replay(&url)? {
+            let detail = serde_json::from_slice::<VendorDetailResponse>(&body)?;
+            self.check_details_schema(&detail.data)?;
+            return Ok(DetailsFetch {
+                details: Some(detail.data),
+                unavailable_status: None,
+                attempts: 1,
+            });
+        }
 
         let mut attempt = 0;
         let max_retries = MAX_RETRIES;
         let base_delay = BASE_DELAY_MS;
+        let mut statuses_seen = Vec::new();
         
         loop {
             if attempt >= max_retries {
-                return Err(Error::MaxRetriesExceeded);
+                return Err(Error::RetriesExhausted {
+                    attempts: attempt,
+                    statuses: statuses_seen,
+                });
             }
 
             let client_index = (self.client_pool.current_index() + attempt as usize) % self.client_pool.len();
@@ -98,30 +441,50 @@ impl ApiService {
                 "Attempting to fetch vendor details"
             );
 
-            let request = client.get(&url);
-            match client.send(request).await {
+            let request = client.get(&url, Endpoint::Details);
+            match client.send(request, Endpoint::Details).await {
                 Ok(response) => {
                     match response.status() {
                         StatusCode::OK => {
-                            let body = response.bytes().await?;
+                            let body = self.read_body(Endpoint::Details, response).await?;
+                            self.cassette.record(&url, &body)?;
                             let detail: VendorDetailResponse = serde_json::from_slice(&body)
-                                .map_err(|e| {
-                                    let body_str = String::from_utf8_lossy(&body);
-                                    error!(
-                                        error = %e,
-                                        body = %body_str,
-                                        "Failed to parse vendor details response"
-                                    );
-                                    Error::from(e)
-                                })?;
-                            return Ok(Some(detail.data));
+                                .map_err(|e| self.parse_failure(Endpoint::Details, code, &body, e))?;
+                            self.check_details_schema(&detail.data)?;
+                            return Ok(DetailsFetch {
+                                details: Some(detail.data),
+                                unavailable_status: None,
+                                attempts: attempt + 1,
+                            });
+                        },
+                        // 404/410: delisted between the listing and the details call
+                        status @ (StatusCode::BAD_REQUEST | StatusCode::NOT_FOUND | StatusCode::GONE) => {
+                            info!(
+                                vendor_code = code,
+                                status = status.as_u16(),
+                                "Vendor details unavailable, skipping"
+                            );
+                            let _ = self.read_body(Endpoint::Details, response).await;
+                            return Ok(DetailsFetch {
+                                details: None,
+                                unavailable_status: Some(status.as_u16()),
+                                attempts: attempt + 1,
+                            });
                         },
-                        StatusCode::BAD_REQUEST => {
+                        status if is_transient(status) => {
                             debug!(
                                 vendor_code = code,
-                                "Received 400 Bad Request for vendor details, skipping"
+                                status = status.as_u16(),
+                                client_index = client_index,
+                                "Transient server error, will retry with different client"
                             );
-                            return Ok(None);
+                            statuses_seen.push(status.as_u16());
+                            let _ = self.read_body(Endpoint::Details, response).await;
+                            attempt += 1;
+                            if attempt < max_retries {
+                                sleep_with_jitter(base_delay * 2u64.pow(attempt), 1000).await;
+                            }
+                            continue;
                         },
                         status => {
                             error!(
@@ -129,7 +492,7 @@ impl ApiService {
                                 vendor_code = code,
                                 "Unexpected status code"
                             );
-                            return Err(Error::Http(response.error_for_status().unwrap_err()));
+                            return Err(self.status_error(Endpoint::Details, response));
                         }
                     }
                 },
@@ -140,8 +503,26 @@ impl ApiService {
                         client_index = client_index,
                         "Received 403, will try with different client"
                     );
+                    statuses_seen.push(StatusCode::FORBIDDEN.as_u16());
                     attempt += 1;
-                    sleep_with_jitter(base_delay * 2u64.pow(attempt as u32), 1000).await;
+                    if attempt < max_retries {
+                        sleep_with_jitter(base_delay * 2u64.pow(attempt), 1000).await;
+                    }
+                    continue;
+                },
+                // The client already retried 504s on its own before giving up
+                Err(Error::Http(e)) if e.status().is_some_and(is_transient) => {
+                    debug!(
+                        vendor_code = code,
+                        error = %e,
+                        client_index = client_index,
+                        "Transient server error, will retry with different client"
+                    );
+                    statuses_seen.extend(e.status().map(|status| status.as_u16()));
+                    attempt += 1;
+                    if attempt < max_retries {
+                        sleep_with_jitter(base_delay * 2u64.pow(attempt), 1000).await;
+                    }
                     continue;
                 },
                 Err(e) => return Err(e),
@@ -149,18 +530,25 @@ impl ApiService {
         }
     }
 
-    pub async fn fetch_vendor_ratings(&self, vendor_code: &str) -> Result<RatingsDistribution> {
-        let url = format!(
-            "https://reviews-api-pk.fd-api.com/ratings-distribution/vendor/{}?\
-             global_entity_id=FP_PK",
-            vendor_code
-        );
+    /// Returns `None` without retrying for vendors that have no ratings yet:
+    /// a 404, an empty body, or a body without a non-empty `ratings` array.
+    pub async fn fetch_vendor_ratings(&self, vendor_code: &str) -> Result<Option<RatingsDistribution>> {
+        let url = with_query(
+            &format!("{}/ratings-distribution/vendor/{}", self.reviews_base_url, vendor_code),
+            &[("global_entity_id", self.country.global_entity_id.clone())],
+        )?;
+        if self.skip_request(Endpoint::Ratings, &url) {
+            return Ok(None);
+        }
+        if let Some(body) = self.cassette.replay(&url)? {
+            return self.parse_ratings(vendor_code, &body);
+        }
 
         let client = self.client_pool.next_client();
         
         retry_with_backoff(MAX_RETRIES, BASE_DELAY_MS, || async {
-            let request = client.get(&url);
-            let response = client.send(request).await?;
+            let request = client.get(&url, Endpoint::Ratings);
+            let response = client.send(request, Endpoint::Ratings).await?;
             
             debug!(
                 status = response.status().as_u16(),
@@ -168,57 +556,384 @@ impl ApiService {
                 "API response received"
             );
 
+            if response.status() == StatusCode::NOT_FOUND {
+                let _ = self.read_body(Endpoint::Ratings, response).await;
+                return Ok(None);
+            }
+
             if response.status() == StatusCode::OK {
-                let body = response.bytes().await?;
-                return serde_json::from_slice(&body).map_err(|e| {
-                    let body_str = String::from_utf8_lossy(&body);
-                    error!(
-                        error = %e,
-                        body = %body_str,
-                        "Failed to parse vendor ratings response"
-                    );
-                    Error::from(e)
-                });
+                let body = self.read_body(Endpoint::Ratings, response).await?;
+                self.cassette.record(&url, &body)?;
+                return self.parse_ratings(vendor_code, &body);
             }
             
-            Err(Error::Http(response.error_for_status().unwrap_err()))
+            Err(self.status_error(Endpoint::Ratings, response))
+        }).await
+    }
+
+    /// Quotes the delivery fee and time for delivering to `lat`/`lng`. Returns
+    /// `None` when the vendor does not deliver there (404).
+    pub async fn fetch_vendor_delivery_info(
+        &self,
+        vendor_code: &str,
+        lat: f64,
+        lng: f64,
+    ) -> Result<Option<DeliveryInfo>> {
+        let url = with_query(
+            &format!("{}/api/v5/vendors/{}/delivery-fee", self.vendor_base_url, vendor_code),
+            &[
+                ("latitude", lat.to_string()),
+                ("longitude", lng.to_string()),
+                ("basket_currency", self.country.currency.clone()),
+            ],
+        )?;
+        if self.skip_request(Endpoint::Delivery, &url) {
+            return Ok(None);
+        }
+        if let Some(body) = self.cassette.replay(&url)? {
+            return Ok(Some(serde_json::from_slice::<DeliveryInfoResponse>(&body)?.data));
+        }
+
+        let client = self.client_pool.next_client();
+
+        retry_with_backoff(MAX_RETRIES, BASE_DELAY_MS, || async {
+            let request = client.get(&url, Endpoint::Delivery);
+            let response = client.send(request, Endpoint::Delivery).await?;
+
+            debug!(
+                status = response.status().as_u16(),
+                url = url,
+                "API response received"
+            );
+
+            if response.status() == StatusCode::NOT_FOUND {
+                let _ = self.read_body(Endpoint::Delivery, response).await;
+                return Ok(None);
+            }
+
+            if response.status() == StatusCode::OK {
+                let body = self.read_body(Endpoint::Delivery, response).await?;
+                self.cassette.record(&url, &body)?;
+                let info: DeliveryInfoResponse = serde_json::from_slice(&body)
+                    .map_err(|e| self.parse_failure(Endpoint::Delivery, vendor_code, &body, e))?;
+                return Ok(Some(info.data));
+            }
+
+            Err(self.status_error(Endpoint::Delivery, response))
         }).await
     }
 
+    /// Pages through a vendor's reviews until the cursor runs out or
+    /// `extraction.max_reviews_per_vendor` is reached. A failure after the first
+    /// page returns the reviews collected so far.
     pub async fn fetch_vendor_reviews(&self, vendor_code: &str) -> Result<Vec<serde_json::Value>> {
-        let url = format!(
-            "https://reviews-api-pk.fd-api.com/reviews/vendor/{}?\
-             global_entity_id=FP_PK&limit=30&created_at=desc&has_dish=true",
-            vendor_code
+        let mut page = self.fetch_vendor_reviews_page(vendor_code, None).await?;
+        let mut reviews = Vec::new();
+        let mut pages = 1;
+
+        loop {
+            reviews.extend(page.data);
+            if let Some(max) = self.max_reviews_per_vendor.filter(|&max| reviews.len() >= max) {
+                reviews.truncate(max);
+                break;
+            }
+            let Some(page_key) = page.next_page_key.filter(|key| !key.is_empty()) else {
+                break;
+            };
+
+            let throttling = self.throttling.get();
+            sleep_with_jitter(throttling.sub_fetch_delay_ms, throttling.sub_fetch_jitter_ms).await;
+
+            page = match self.fetch_vendor_reviews_page(vendor_code, Some(&page_key)).await {
+                Ok(page) => page,
+                Err(e) => {
+                    warn!(
+                        error = %e,
+                        vendor_code = vendor_code,
+                        pages_fetched = pages,
+                        reviews_collected = reviews.len(),
+                        "Failed to fetch reviews page, keeping reviews collected so far"
+                    );
+                    break;
+                }
+            };
+            pages += 1;
+        }
+
+        debug!(
+            vendor_code = vendor_code,
+            pages_fetched = pages,
+            reviews_count = reviews.len(),
+            "Fetched vendor reviews"
         );
+        Ok(reviews)
+    }
+
+    async fn fetch_vendor_reviews_page(
+        &self,
+        vendor_code: &str,
+        page_key: Option<&str>,
+    ) -> Result<ReviewsResponse> {
+        let query = &self.reviews_query;
+        let mut params = vec![
+            ("global_entity_id", self.country.global_entity_id.clone()),
+            ("limit", query.limit.to_string()),
+            ("created_at", query.sort.clone()),
+        ];
+        if query.has_dish {
+            params.push(("has_dish", "true".to_string()));
+        }
+        if let Some(page_key) = page_key {
+            params.push(("nextPageKey", page_key.to_string()));
+        }
+        let url = with_query(&format!("{}/reviews/vendor/{}", self.reviews_base_url, vendor_code), &params)?;
+        if self.skip_request(Endpoint::Reviews, &url) {
+            return Ok(ReviewsResponse { data: Vec::new(), next_page_key: None });
+        }
+        if let Some(body) = self.cassette.replay(&url)? {
+            return Ok(serde_json::from_slice(&body)?);
+        }
 
         let client = self.client_pool.next_client();
         
         retry_with_backoff(MAX_RETRIES, BASE_DELAY_MS, || async {
-            let request = client.get(&url);
-            let response = client.send(request).await?;
+            let request = client.get(&url, Endpoint::Reviews);
+            let response = client.send(request, Endpoint::Reviews).await?;
             
             debug!(
                 status = response.status().as_u16(),
                 url = url,
+                page_key = page_key,
                 "API response received"
             );
 
             if response.status() == StatusCode::OK {
-                let body = response.bytes().await?;
-                let reviews: ReviewsResponse = serde_json::from_slice(&body).map_err(|e| {
-                    let body_str = String::from_utf8_lossy(&body);
-                    error!(
-                        error = %e,
-                        body = %body_str,
-                        "Failed to parse vendor reviews response"
-                    );
-                    Error::from(e)
-                })?;
-                return Ok(reviews.data);
+                let body = self.read_body(Endpoint::Reviews, response).await?;
+                self.cassette.record(&url, &body)?;
+                return serde_json::from_slice(&body)
+                    .map_err(|e| self.parse_failure(Endpoint::Reviews, vendor_code, &body, e));
             }
             
-            Err(Error::Http(response.error_for_status().unwrap_err()))
+            Err(self.status_error(Endpoint::Reviews, response))
         }).await
     }
-}
\ No newline at end of file
+}
+
+#[async_trait]
+impl VendorApi for ApiService {
+    fn stats(&self) -> &Arc<StatsRegistry> {
+        ApiService::stats(self)
+    }
+
+    async fn fetch_vendor_page(&self, query: &ListingQuery, offset: i32, limit: i32) -> Result<VendorListResponse> {
+        ApiService::fetch_vendor_page(self, query, offset, limit).await
+    }
+
+    async fn fetch_vendor_details(&self, code: &str) -> Result<DetailsFetch> {
+        ApiService::fetch_vendor_details(self, code).await
+    }
+
+    async fn fetch_vendor_details_in(&self, code: &str, language_id: i32) -> Result<Option<VendorDetails>> {
+        ApiService::fetch_vendor_details_in(self, code, language_id).await
+    }
+
+    async fn fetch_vendor_ratings(&self, vendor_code: &str) -> Result<Option<RatingsDistribution>> {
+        ApiService::fetch_vendor_ratings(self, vendor_code).await
+    }
+
+    async fn fetch_vendor_reviews(&self, vendor_code: &str) -> Result<Vec<serde_json::Value>> {
+        ApiService::fetch_vendor_reviews(self, vendor_code).await
+    }
+
+    async fn fetch_vendor_delivery_info(&self, vendor_code: &str, lat: f64, lng: f64) -> Result<Option<DeliveryInfo>> {
+        ApiService::fetch_vendor_delivery_info(self, vendor_code, lat, lng).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+    use std::time::Duration;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+    use crate::services::testing;
+
+    const DETAILS: &str = r#"{"data":{"name":"Pizza Place"}}"#;
+
+    /// A canned HTTP/1.1 response: status line, extra headers and body.
+    struct Reply {
+        status: &'static str,
+        headers: &'static [&'static str],
+        body: &'static str,
+    }
+
+    fn reply(status: &'static str, headers: &'static [&'static str], body: &'static str) -> Reply {
+        Reply { status, headers, body }
+    }
+
+    /// Serves `replies` in order, one per connection, on a localhost port,
+    /// and records the path of each request it answered.
+    async fn serve(replies: Vec<Reply>) -> (String, Arc<Mutex<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        let paths = Arc::new(Mutex::new(Vec::new()));
+        let seen = paths.clone();
+        tokio::spawn(async move {
+            for reply in replies {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut request = Vec::new();
+                let mut buffer = [0; 1024];
+                while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+                    let read = stream.read(&mut buffer).await.unwrap();
+                    if read == 0 {
+                        break;
+                    }
+                    request.extend_from_slice(&buffer[..read]);
+                }
+                let request_line = String::from_utf8_lossy(&request).lines().next().unwrap_or_default().to_string();
+                seen.lock().unwrap().push(request_line.split(' ').nth(1).unwrap_or_default().to_string());
+
+                let mut response = format!("HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n", reply.status, reply.body.len());
+                for header in reply.headers {
+                    response.push_str(header);
+                    response.push_str("\r\n");
+                }
+                response.push_str("\r\n");
+                response.push_str(reply.body);
+                stream.write_all(response.as_bytes()).await.unwrap();
+                stream.shutdown().await.unwrap();
+            }
+        });
+        (base_url, paths)
+    }
+
+    /// An `ApiService` sending its details requests to `base_url`.
+    fn api(base_url: &str, max_retry_after_ms: u64) -> ApiService {
+        let mut settings = testing::settings();
+        settings.api.vendor_base_url = Some(base_url.to_string());
+        settings.throttling.max_retry_after_ms = max_retry_after_ms;
+        let pool = Arc::new(ClientPool::new(settings.clone()).unwrap());
+        ApiService::new(pool, &settings, SharedThrottling::new(settings.throttling.clone(), &settings.concurrency))
+    }
+
+    const LISTING: &str = "https://disco.deliveryhero.io/listing/api/v1/pandora/vendors";
+
+    fn listing_url(query: ListingQuery) -> String {
+        let params = ListingParams { query: &query, offset: 48, limit: 48, country: "pk", language_id: 1 };
+        with_query(LISTING, &params.pairs()).unwrap()
+    }
+
+    #[test]
+    fn city_listing_query_is_form_encoded() {
+        assert_eq!(
+            listing_url(ListingQuery::City("17".to_string())),
+            format!("{}?city_id=17&offset=48&limit=48&configuration=&country=pk&language_id=1&sort=&vertical=restaurants", LISTING),
+        );
+    }
+
+    #[test]
+    fn geo_listing_query_is_form_encoded() {
+        assert_eq!(
+            listing_url(ListingQuery::Geo { lat: 24.8607, lng: -67.0011 }),
+            format!("{}?latitude=24.8607&longitude=-67.0011&offset=48&limit=48&configuration=&country=pk&language_id=1&sort=&vertical=restaurants", LISTING),
+        );
+    }
+
+    #[test]
+    fn listing_values_are_percent_encoded() {
+        let cases = [
+            ("Karāchi", "Kar%C4%81chi"),
+            ("لاہور", "%D9%84%D8%A7%DB%81%D9%88%D8%B1"),
+            ("a&b=c", "a%26b%3Dc"),
+            ("north side/2", "north+side%2F2"),
+            ("50%+off", "50%25%2Boff"),
+        ];
+        for (city_id, encoded) in cases {
+            assert_eq!(
+                listing_url(ListingQuery::City(city_id.to_string())),
+                format!("{}?city_id={}&offset=48&limit=48&configuration=&country=pk&language_id=1&sort=&vertical=restaurants", LISTING, encoded),
+                "{}",
+                city_id,
+            );
+        }
+    }
+
+    #[test]
+    fn captured_subject_stays_in_the_endpoint_dir() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let path = capture_body(dir.path(), Endpoint::Details, "../../etc/v1", b"{").unwrap();
+
+        assert_eq!(path.parent().unwrap(), dir.path().join("details"));
+        let name = path.file_name().unwrap().to_str().unwrap();
+        assert!(name.starts_with(".._.._etc_v1_"), "{}", name);
+        assert_eq!(std::fs::read(&path).unwrap(), b"{");
+    }
+
+    #[tokio::test]
+    async fn ok_details_are_parsed() {
+        let (base_url, paths) = serve(vec![reply("200 OK", &[], DETAILS)]).await;
+
+        let fetch = api(&base_url, 60_000).fetch_vendor_details("v1").await.unwrap();
+
+        assert_eq!(fetch.details.unwrap().name, "Pizza Place");
+        assert_eq!(fetch.unavailable_status, None);
+        assert_eq!(fetch.attempts, 1);
+        let paths = paths.lock().unwrap();
+        assert_eq!(paths.len(), 1);
+        assert!(paths[0].starts_with("/api/v5/vendors/v1?"));
+    }
+
+    #[tokio::test]
+    async fn not_found_marks_the_vendor_unavailable() {
+        let (base_url, paths) = serve(vec![reply("404 Not Found", &[], "{}")]).await;
+
+        let fetch = api(&base_url, 60_000).fetch_vendor_details("v1").await.unwrap();
+
+        assert!(fetch.details.is_none());
+        assert_eq!(fetch.unavailable_status, Some(404));
+        assert_eq!(fetch.attempts, 1);
+        assert_eq!(paths.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn rate_limit_waits_for_retry_after() {
+        let (base_url, paths) = serve(vec![
+            reply("429 Too Many Requests", &["Retry-After: 1"], "{}"),
+            reply("200 OK", &[], DETAILS),
+        ]).await;
+        let started = Instant::now();
+
+        let fetch = api(&base_url, 60_000).fetch_vendor_details("v1").await.unwrap();
+
+        assert!(fetch.details.is_some());
+        assert!(started.elapsed() >= Duration::from_secs(1));
+        assert_eq!(paths.lock().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn retry_after_is_capped_by_max_retry_after() {
+        let (base_url, _) = serve(vec![
+            reply("429 Too Many Requests", &["Retry-After: 3600"], "{}"),
+            reply("200 OK", &[], DETAILS),
+        ]).await;
+        let started = Instant::now();
+
+        let fetch = api(&base_url, 100).fetch_vendor_details("v1").await.unwrap();
+
+        assert!(fetch.details.is_some());
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[tokio::test]
+    async fn persistent_rate_limit_gives_up() {
+        let limited = || reply("429 Too Many Requests", &["Retry-After: 0"], "{}");
+        let (base_url, paths) = serve(vec![limited(), limited(), limited()]).await;
+
+        let error = api(&base_url, 60_000).fetch_vendor_details("v1").await.unwrap_err();
+
+        assert!(matches!(error, Error::RateLimit), "{:?}", error);
+        assert_eq!(paths.lock().unwrap().len(), 3);
+    }
+}
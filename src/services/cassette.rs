@@ -0,0 +1,83 @@
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use bytes::Bytes;
+use sha2::{Digest, Sha256};
+use tracing::{debug, info};
+use crate::error::{Error, Result};
+
+/// Records API response bodies to disk, or serves them back in place of HTTP.
+///
+/// Set `APP_RECORD_DIR` to save every successful body under the hash of its URL,
+/// or `APP_REPLAY_DIR` to read bodies from a directory recorded earlier. Replay
+/// never touches the network: a URL that was not recorded is an error.
+#[derive(Debug, Clone, Default)]
+pub enum Cassette {
+    #[default]
+    Off,
+    Record(PathBuf),
+    Replay(PathBuf),
+}
+
+impl Cassette {
+    /// Replay takes precedence when both variables are set.
+    pub fn from_env() -> Result<Self> {
+        if let Ok(dir) = std::env::var("APP_REPLAY_DIR") {
+            info!(dir = dir, "Replaying API responses from disk");
+            return Ok(Self::Replay(PathBuf::from(dir)));
+        }
+        if let Ok(dir) = std::env::var("APP_RECORD_DIR") {
+            fs::create_dir_all(&dir)?;
+            info!(dir = dir, "Recording API responses to disk");
+            return Ok(Self::Record(PathBuf::from(dir)));
+        }
+        Ok(Self::Off)
+    }
+
+    pub fn is_replay(&self) -> bool {
+        matches!(self, Self::Replay(_))
+    }
+
+    /// Saves a body in record mode, appending the URL to `index.tsv` so the
+    /// files can be told apart.
+    pub fn record(&self, url: &str, body: &[u8]) -> Result<()> {
+        let Self::Record(dir) = self else {
+            return Ok(());
+        };
+        let path = body_path(dir, url);
+        fs::write(&path, body)?;
+        let mut index = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(dir.join("index.tsv"))?;
+        writeln!(index, "{}\t{}", hash(url), url)?;
+        debug!(url = url, path = %path.display(), "Recorded response");
+        Ok(())
+    }
+
+    /// In replay mode, the recorded body for `url`; `None` in the other modes.
+    pub fn replay(&self, url: &str) -> Result<Option<Bytes>> {
+        let Self::Replay(dir) = self else {
+            return Ok(None);
+        };
+        let path = body_path(dir, url);
+        match fs::read(&path) {
+            Ok(body) => {
+                debug!(url = url, path = %path.display(), "Replayed response");
+                Ok(Some(Bytes::from(body)))
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                Err(Error::ReplayMiss(url.to_string()))
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+fn hash(url: &str) -> String {
+    format!("{:x}", Sha256::digest(url.as_bytes()))
+}
+
+fn body_path(dir: &Path, url: &str) -> PathBuf {
+    dir.join(format!("{}.json", hash(url)))
+}
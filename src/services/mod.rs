@@ -1,5 +1,13 @@
 pub mod api;
+pub mod cassette;
+pub mod progress;
+pub mod schema;
+#[cfg(test)]
+pub mod testing;
 pub mod vendor;
 
-pub use api::ApiService;
+pub use api::{ApiService, DetailsFetch, VendorApi};
+pub use cassette::Cassette;
+pub use progress::{tee, ProgressEvent, ProgressSender, VendorStatus};
+pub use schema::SchemaWatch;
 pub use vendor::VendorService;
@@ -0,0 +1,57 @@
+use tokio::sync::mpsc;
+
+/// How a vendor's processing ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VendorStatus {
+    /// Written with details.
+    Ok,
+    /// Written without details (400/404/410).
+    Skipped,
+    /// Extracted recently by a previous incremental run.
+    Unchanged,
+    /// Fetched, but left out of a delta run's output as unchanged.
+    ContentUnchanged,
+    /// Not fetched because the download budget ran out.
+    OverBudget,
+    /// Details failed; left out of the output.
+    Failed,
+}
+
+/// Extraction progress, for callers that want more than the logs.
+#[derive(Debug, Clone)]
+pub enum ProgressEvent {
+    /// A listing page is about to be processed; `of` is the current estimate.
+    PageFetched { city_id: String, page: i32, of: i32 },
+    VendorDone { code: String, status: VendorStatus },
+    BatchDone { city_id: String, batch: i32, failed: usize },
+}
+
+/// Sending half handed to `VendorService`. Events are dropped rather than
+/// waited on when the receiver falls behind, so extraction never stalls.
+#[derive(Debug, Clone)]
+pub struct ProgressSender(mpsc::Sender<ProgressEvent>);
+
+impl ProgressSender {
+    pub fn new(sender: mpsc::Sender<ProgressEvent>) -> Self {
+        Self(sender)
+    }
+
+    pub fn send(&self, event: ProgressEvent) {
+        let _ = self.0.try_send(event);
+    }
+}
+
+/// Copies each progress event to a second receiver, so e.g. the dashboard
+/// and the progress log line both see them. Like the sender, drops events a
+/// receiver is too slow for.
+pub fn tee(mut events: mpsc::Receiver<ProgressEvent>) -> (mpsc::Receiver<ProgressEvent>, mpsc::Receiver<ProgressEvent>) {
+    let (first, first_events) = mpsc::channel(1024);
+    let (second, second_events) = mpsc::channel(1024);
+    tokio::spawn(async move {
+        while let Some(event) = events.recv().await {
+            let _ = first.try_send(event.clone());
+            let _ = second.try_send(event);
+        }
+    });
+    (first_events, second_events)
+}
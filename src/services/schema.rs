@@ -0,0 +1,60 @@
+use std::collections::HashSet;
+use std::sync::Mutex;
+use serde_json::Value;
+use tracing::warn;
+use crate::error::{Error, Result};
+
+/// Watches API responses for top-level fields the typed models don't cover,
+/// so schema drift shows up in the logs. Each field is reported once per run;
+/// in strict mode (`quality.strict_schema`) any such field is an error.
+#[derive(Debug, Default)]
+pub struct SchemaWatch {
+    strict: bool,
+    reported: Mutex<HashSet<String>>,
+}
+
+impl SchemaWatch {
+    pub fn new(strict: bool) -> Self {
+        Self {
+            strict,
+            reported: Mutex::new(HashSet::new()),
+        }
+    }
+
+    /// Keys of the `raw` object that `modeled` (the parsed value serialized
+    /// back) lacks.
+    pub fn unknown_keys(raw: &Value, modeled: &Value) -> Vec<String> {
+        let (Some(raw), Some(modeled)) = (raw.as_object(), modeled.as_object()) else {
+            return Vec::new();
+        };
+        raw.keys()
+            .filter(|key| !modeled.contains_key(*key))
+            .cloned()
+            .collect()
+    }
+
+    pub fn report<'a>(&self, model: &str, unknown: impl IntoIterator<Item = &'a String>) -> Result<()> {
+        let mut unknown: Vec<&String> = unknown.into_iter().collect();
+        if unknown.is_empty() {
+            return Ok(());
+        }
+        unknown.sort();
+        if self.strict {
+            let keys: Vec<&str> = unknown.iter().map(|key| key.as_str()).collect();
+            return Err(Error::SchemaDrift(format!("{}: {}", model, keys.join(", "))));
+        }
+
+        let mut reported = self.reported.lock().unwrap_or_else(|e| e.into_inner());
+        let new: Vec<&String> = unknown.into_iter()
+            .filter(|key| reported.insert(format!("{}.{}", model, key)))
+            .collect();
+        if !new.is_empty() {
+            warn!(
+                model = model,
+                unknown_fields = ?new,
+                "Response has fields the model does not cover"
+            );
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,216 @@
+//! In-memory stand-ins for the live API, for `VendorService` tests.
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use async_trait::async_trait;
+use tokio::sync::mpsc;
+use crate::config::{ListingQuery, Settings};
+use crate::error::{Error, Result};
+use crate::models::{DeliveryInfo, RatingsDistribution, VendorData, VendorDetails, VendorItem, VendorListResponse};
+use crate::services::api::{DetailsFetch, VendorApi};
+use crate::stats::StatsRegistry;
+use crate::storage::json::{CityWriters, JsonWriter, VendorStream};
+
+/// Attempts reported by a details request the fake fails.
+pub const FAILED_ATTEMPTS: u32 = 4;
+
+/// How the fake answers a vendor's details request.
+#[derive(Debug, Clone)]
+pub enum FakeDetails {
+    Found(VendorDetails),
+    /// A 400/404/410: the vendor has no details.
+    Unavailable(u16),
+    /// Retries exhausted.
+    Fails,
+    /// The request never answers.
+    Hangs,
+}
+
+/// Serves a fixed listing and per-vendor details, and records the listing
+/// offsets and details requests made. Vendors without configured details are found, named
+/// after their code; they have no reviews, ratings or delivery info.
+/// Reviews answer at once unless given a delay.
+pub struct FakeVendorApi {
+    stats: Arc<StatsRegistry>,
+    listing: Vec<String>,
+    /// Count the listing advertises, when not the listing's length.
+    available_count: Option<i32>,
+    details: HashMap<String, FakeDetails>,
+    listing_offsets: Mutex<Vec<i32>>,
+    details_requests: Mutex<HashMap<String, u32>>,
+    reviews_delays: HashMap<String, Duration>,
+}
+
+impl FakeVendorApi {
+    pub fn new(listing: &[&str]) -> Self {
+        Self {
+            stats: Arc::new(StatsRegistry::new()),
+            listing: listing.iter().map(|code| code.to_string()).collect(),
+            available_count: None,
+            details: HashMap::new(),
+            listing_offsets: Mutex::new(Vec::new()),
+            details_requests: Mutex::new(HashMap::new()),
+            reviews_delays: HashMap::new(),
+        }
+    }
+
+    /// A listing of `count` vendors coded `v0`, `v1`, ...
+    pub fn with_listing_of(count: usize) -> Self {
+        let codes: Vec<String> = (0..count).map(|index| format!("v{}", index)).collect();
+        let codes: Vec<&str> = codes.iter().map(String::as_str).collect();
+        Self::new(&codes)
+    }
+
+    /// Advertises `count` vendors, so a listing shorter than that ends with
+    /// an empty page.
+    pub fn with_available_count(mut self, count: i32) -> Self {
+        self.available_count = Some(count);
+        self
+    }
+
+    pub fn with_details(mut self, code: &str, details: FakeDetails) -> Self {
+        self.details.insert(code.to_string(), details);
+        self
+    }
+
+    /// Answers `code`'s reviews request only after `delay`.
+    pub fn with_reviews_delay(mut self, code: &str, delay: Duration) -> Self {
+        self.reviews_delays.insert(code.to_string(), delay);
+        self
+    }
+
+    /// Offsets of the listing requests made so far, in order.
+    pub fn listing_offsets(&self) -> Vec<i32> {
+        self.listing_offsets.lock().unwrap().clone()
+    }
+
+    /// Details requests made for `code` so far.
+    pub fn details_requests(&self, code: &str) -> u32 {
+        self.details_requests.lock().unwrap().get(code).copied().unwrap_or(0)
+    }
+}
+
+#[async_trait]
+impl VendorApi for FakeVendorApi {
+    fn stats(&self) -> &Arc<StatsRegistry> {
+        &self.stats
+    }
+
+    async fn fetch_vendor_page(&self, _query: &ListingQuery, offset: i32, limit: i32) -> Result<VendorListResponse> {
+        self.listing_offsets.lock().unwrap().push(offset);
+        let start = (offset.max(0) as usize).min(self.listing.len());
+        let end = (start + limit.max(0) as usize).min(self.listing.len());
+        let items: Vec<VendorItem> = self.listing[start..end].iter()
+            .map(|code| VendorItem {
+                code: code.clone(),
+                name: format!("Vendor {}", code),
+                rating: None,
+                cuisines: Vec::new(),
+                budget: None,
+            })
+            .collect();
+        Ok(VendorListResponse {
+            data: VendorData {
+                returned_count: items.len() as i32,
+                available_count: self.available_count.unwrap_or(self.listing.len() as i32),
+                items,
+            },
+        })
+    }
+
+    async fn fetch_vendor_details(&self, code: &str) -> Result<DetailsFetch> {
+        *self.details_requests.lock().unwrap().entry(code.to_string()).or_default() += 1;
+        let details = self.details.get(code).cloned().unwrap_or_else(|| FakeDetails::Found(VendorDetails {
+            name: format!("Vendor {}", code),
+            ..VendorDetails::default()
+        }));
+        match details {
+            FakeDetails::Found(details) => Ok(DetailsFetch {
+                details: Some(details),
+                unavailable_status: None,
+                attempts: 1,
+            }),
+            FakeDetails::Unavailable(status) => Ok(DetailsFetch {
+                details: None,
+                unavailable_status: Some(status),
+                attempts: 1,
+            }),
+            FakeDetails::Fails => Err(Error::RetriesExhausted {
+                attempts: FAILED_ATTEMPTS,
+                statuses: vec![503; FAILED_ATTEMPTS as usize],
+            }),
+            FakeDetails::Hangs => std::future::pending().await,
+        }
+    }
+
+    async fn fetch_vendor_details_in(&self, _code: &str, _language_id: i32) -> Result<Option<VendorDetails>> {
+        Ok(None)
+    }
+
+    async fn fetch_vendor_ratings(&self, _vendor_code: &str) -> Result<Option<RatingsDistribution>> {
+        Ok(None)
+    }
+
+    async fn fetch_vendor_reviews(&self, vendor_code: &str) -> Result<Vec<serde_json::Value>> {
+        if let Some(delay) = self.reviews_delays.get(vendor_code) {
+            tokio::time::sleep(*delay).await;
+        }
+        Ok(Vec::new())
+    }
+
+    async fn fetch_vendor_delivery_info(&self, _vendor_code: &str, _lat: f64, _lng: f64) -> Result<Option<DeliveryInfo>> {
+        Ok(None)
+    }
+}
+
+/// Settings for a single test city, with every delay turned off.
+pub fn settings() -> Settings {
+    serde_json::from_value(serde_json::json!({
+        "cities": ["1"],
+        "minio": {
+            "endpoint": "http://127.0.0.1:9000",
+            "access_key": "test",
+            "secret_key": "test",
+            "bucket": "test",
+            "region": "us-east-1",
+        },
+        "api": { "headers": {} },
+        "throttling": {
+            "inter_vendor_delay_ms": 0,
+            "inter_vendor_jitter_ms": 0,
+            "sub_fetch_delay_ms": 0,
+            "sub_fetch_jitter_ms": 0,
+            "page_delay_ms": 0,
+            "page_jitter_ms": 0,
+            "dead_letter_delay_ms": 0,
+            "dead_letter_jitter_ms": 0,
+        },
+    }))
+    .expect("test settings deserialize")
+}
+
+/// Writers for city `1` with their files in `dir`; finished parts are dropped.
+pub async fn city_writers(dir: &Path) -> CityWriters {
+    city_writers_failing_vendors(dir, 0).await
+}
+
+/// Like [`city_writers`], but the first `failed_writes` vendor records fail
+/// to write.
+pub async fn city_writers_failing_vendors(dir: &Path, failed_writes: usize) -> CityWriters {
+    let mut vendors = JsonWriter::new(dir, "vendors_city_1.json").await.unwrap();
+    vendors.fail_next_writes(failed_writes);
+    let menu_items = JsonWriter::new(dir, "menu_items_city_1.json").await.unwrap();
+    let reviews = JsonWriter::new(dir, "reviews_city_1.json").await.unwrap();
+    let (parts, _) = mpsc::unbounded_channel();
+    CityWriters::new("1", vendors, menu_items, reviews, None, 16, parts)
+}
+
+/// Codes of the vendors in a finished JSON file, in file order.
+pub fn vendor_codes(path: &Path) -> Vec<String> {
+    VendorStream::open(path)
+        .unwrap()
+        .map(|vendor| vendor.unwrap().code)
+        .collect()
+}
@@ -1,133 +1,1714 @@
-use std::sync::Arc;
-use tokio::sync::Mutex;
-use tracing::{info, error};
-use crate::error::Result;
-use crate::models::Vendor;
-use crate::services::api::ApiService;
-use crate::storage::json::JsonWriter;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use std::path::{Path, PathBuf};
+use chrono::{DateTime, Utc};
+use futures::stream::{self, StreamExt};
+use tokio::sync::mpsc;
+use tokio::time::timeout;
+use tokio_util::sync::CancellationToken;
+use rand::SeedableRng;
+use rand::seq::SliceRandom;
+use rand::rngs::StdRng;
+use uuid::Uuid;
+use tracing::{debug, info, warn, error};
+use regex::Regex;
+use crate::config::{ExtractionConfig, IncrementalConfig, ListingQuery, Settings, SkippedVendorOutput, UnchangedVendors, VendorFilterConfig};
+use serde::{Deserialize, Serialize};
+use crate::error::{Error, Result};
+use crate::models::{pages_for, DeliveryInfo, Discount, MenuItem, RatingsDistribution, Review, Vendor, VendorDetails, VendorItem, VendorListResponse};
+use crate::services::api::{DetailsFetch, VendorApi, LISTING_PAGE_SIZE};
+use crate::services::progress::{ProgressEvent, ProgressSender, VendorStatus};
+use crate::storage::checkpoint::CityCheckpoint;
+use crate::storage::incremental::IncrementalManifest;
+use crate::storage::json::CityWriters;
+use crate::utils::bulk::{fetch_all_with_retries, BulkResult};
+use crate::utils::serde_helpers::content_hash;
 use crate::utils::time::sleep_with_jitter;
+use crate::utils::pacer::AdaptivePacer;
+use crate::utils::throttle::SharedThrottling;
 
 #[derive(Clone)]
 pub struct VendorService {
-    api_service: ApiService,
+    api_service: Arc<dyn VendorApi>,
+    throttling: SharedThrottling,
+    extraction: ExtractionConfig,
+    max_bytes_downloaded: Option<u64>,
+    /// Languages details are fetched in, the default first.
+    languages: Vec<i32>,
+    run_id: Option<Uuid>,
+    /// Listing pages fetched ahead of the page being processed.
+    listing_prefetch_pages: usize,
+    incremental: IncrementalConfig,
+    /// Previous run's manifest, updated as vendors are extracted.
+    manifest: Option<Arc<Mutex<IncrementalManifest>>>,
+    vendor_filter: VendorFilterConfig,
+    name_pattern: Option<Regex>,
+    progress: Option<ProgressSender>,
+    /// Replaces `inter_vendor_delay_ms` when adaptive pacing is on.
+    pacer: Option<Arc<AdaptivePacer>>,
+    /// Output directory city checkpoints are stored under.
+    checkpoints: Option<PathBuf>,
+    skipped_vendor_output: SkippedVendorOutput,
+}
+
+/// Pagination outcome of one city's listing.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CityExtraction {
+    pub city_id: String,
+    pub page_size: i32,
+    pub pages: i32,
+    pub initial_available_count: i32,
+    pub final_available_count: i32,
+    /// Stopped early because of the download budget or `max_pages`.
+    pub truncated: bool,
+    /// A page came back empty before the advertised vendor count was reached.
+    pub partial_listing: bool,
+    /// Only a random sample of the listed vendors was processed.
+    pub sampled: bool,
+    /// Vendors written with details.
+    pub succeeded: usize,
+    /// Vendors written without details (400/404/410).
+    pub skipped: usize,
+    /// `skipped` broken down by vendor status (`skipped_404`, ...).
+    pub skipped_by_status: BTreeMap<String, usize>,
+    pub failed: Vec<FailedVendor>,
+    /// Listed codes dropped because an earlier page already had them.
+    pub duplicates: usize,
+    /// Vendors an incremental run didn't fetch again.
+    pub unchanged: usize,
+    /// Vendors a delta run fetched but didn't write, their content hash
+    /// matching the manifest's.
+    #[serde(default)]
+    pub content_unchanged: usize,
+    /// Codes of processed vendors that weren't written, so missing vendor
+    /// detection can still count them as seen.
+    #[serde(default)]
+    pub unwritten: BTreeSet<String>,
+    /// Vendors rejected by `vendor_filter` before their details were fetched.
+    pub filtered: usize,
+    pub started_at: DateTime<Utc>,
+    /// Set once the retry pass is over.
+    pub finished_at: Option<DateTime<Utc>>,
+}
+
+/// Outcome of one city's extraction, written next to its data for the
+/// orchestrator to decide whether the run succeeded.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExtractionReport {
+    pub city_id: String,
+    pub run_id: Option<Uuid>,
+    pub pages: i32,
+    /// The listing covered the whole catalog (see `CityExtraction::is_complete`).
+    pub complete: bool,
+    pub vendors_ok: usize,
+    pub vendors_skipped: usize,
+    pub vendors_failed: usize,
+    /// Across the city's JSON files.
+    pub bytes_written: u64,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: DateTime<Utc>,
+    pub failures: Vec<FailedVendor>,
+}
+
+impl ExtractionReport {
+    pub fn new(extraction: &CityExtraction, run_id: Option<Uuid>, bytes_written: u64) -> Self {
+        Self {
+            city_id: extraction.city_id.clone(),
+            run_id,
+            pages: extraction.pages,
+            complete: extraction.is_complete(),
+            vendors_ok: extraction.succeeded,
+            vendors_skipped: extraction.skipped,
+            vendors_failed: extraction.failed.len(),
+            bytes_written,
+            started_at: extraction.started_at,
+            finished_at: extraction.finished_at.unwrap_or_else(Utc::now),
+            failures: extraction.failed.clone(),
+        }
+    }
+}
+
+/// A vendor whose details could not be fetched; it is left out of the output
+/// and written to the city's dead-letter file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FailedVendor {
+    pub code: String,
+    /// Listing page the vendor came from.
+    pub batch_number: i32,
+    /// The most recent error.
+    pub error: String,
+    pub attempts: u32,
+    pub first_failed_at: DateTime<Utc>,
+    pub last_failed_at: DateTime<Utc>,
+}
+
+impl FailedVendor {
+    fn new(code: &str, batch_number: i32, error: &Error) -> Self {
+        let now = Utc::now();
+        Self {
+            code: code.to_string(),
+            batch_number,
+            error: error.to_string(),
+            attempts: attempts_of(error),
+            first_failed_at: now,
+            last_failed_at: now,
+        }
+    }
+}
+
+fn attempts_of(error: &Error) -> u32 {
+    match error {
+        Error::RetriesExhausted { attempts, .. } => *attempts,
+        _ => 1,
+    }
+}
+
+/// Per-vendor outcomes of one listing page.
+#[derive(Debug, Clone, Default)]
+pub struct BatchResult {
+    pub succeeded: usize,
+    pub skipped: usize,
+    pub skipped_by_status: BTreeMap<String, usize>,
+    /// Vendors not fetched because the download budget ran out.
+    pub over_budget: usize,
+    pub unchanged: usize,
+    pub content_unchanged: usize,
+    pub failed: Vec<FailedVendor>,
+    /// Codes of the vendors processed without being written.
+    pub unwritten: Vec<String>,
+}
+
+/// What became of one vendor of a batch.
+enum VendorOutcome {
+    Written,
+    /// No details (400/404/410); written with minimal information and the
+    /// given vendor status, unless dropped (`written` false).
+    Skipped { status: String, written: bool },
+    /// Not fetched because the download budget ran out.
+    OverBudget,
+    /// Extracted recently by a previous run; not fetched again, and only
+    /// written as a stub when `written`.
+    Unchanged { written: bool },
+    /// Fetched, but its content hash matches the manifest's, so a delta run
+    /// leaves it out.
+    ContentUnchanged,
+}
+
+/// What was fetched for one vendor, before it is written.
+enum FetchedVendor {
+    Found {
+        details: VendorDetails,
+        /// Details requests sent, retries included.
+        attempts: u32,
+        localized_details: BTreeMap<i32, VendorDetails>,
+        reviews: Result<Option<Vec<serde_json::Value>>>,
+        ratings: Result<Option<RatingsDistribution>>,
+        delivery: Result<Option<DeliveryInfo>>,
+    },
+    /// No details (400/404/410 status, or none in a dry run).
+    Unavailable { status: Option<u16>, attempts: u32 },
+}
+
+/// Codes of one listing page, ready to be processed as a batch.
+struct ListedPage {
+    vendor_codes: Vec<String>,
+    page: i32,
+    total_pages: i32,
+    /// Listing offset of the next page.
+    next_offset: i32,
+    /// The listing's counters once the page was listed; the listing runs
+    /// ahead, so the cursor's may already count later pages.
+    filtered: usize,
+    duplicates: usize,
+    final_available_count: i32,
+    /// Codes first listed on the page, filtered ones included.
+    newly_seen: Vec<String>,
+}
+
+/// A city's listing position between pages.
+pub struct CityCursor {
+    pub extraction: CityExtraction,
+    query: ListingQuery,
+    offset: i32,
+    total_pages: i32,
+    /// Page already fetched but not yet processed (the initial listing).
+    next: Option<VendorListResponse>,
+    /// Codes already processed; the listing re-sorts between requests, so a
+    /// page can repeat vendors of the previous one.
+    seen: HashSet<String>,
+    /// Codes and their page, held back for sampling or shuffling until the
+    /// listing is done.
+    listed: Vec<(String, i32)>,
+    done: bool,
+}
+
+impl CityCursor {
+    pub fn is_done(&self) -> bool {
+        self.done
+    }
+
+    /// Continues the listing after the checkpoint's last page, with the
+    /// counts of the batches completed before it and the codes listed so far.
+    /// A change in the available count shows on the next page fetched.
+    fn resume_from(&mut self, checkpoint: &CityCheckpoint) {
+        self.extraction = checkpoint.extraction.clone();
+        self.total_pages = pages_for(self.extraction.final_available_count, self.extraction.page_size);
+        self.offset = checkpoint.offset;
+        self.seen = checkpoint.seen.iter().cloned().collect();
+        // The fresh first page was only needed to open the listing
+        self.next = None;
+    }
+}
+
+impl CityExtraction {
+    /// Whether the listing covered the whole catalog.
+    pub fn is_complete(&self) -> bool {
+        !self.truncated && !self.partial_listing && !self.sampled
+    }
+
+    /// Failed vendors as a percentage of all vendors attempted.
+    pub fn failure_pct(&self) -> f64 {
+        let attempted = self.succeeded + self.skipped + self.content_unchanged + self.failed.len();
+        if attempted == 0 {
+            return 0.0;
+        }
+        self.failed.len() as f64 * 100.0 / attempted as f64
+    }
+
+    /// Adds a processed batch's outcomes to the city's counts.
+    pub fn add_batch(&mut self, batch: BatchResult) {
+        self.succeeded += batch.succeeded;
+        self.skipped += batch.skipped;
+        for (status, count) in batch.skipped_by_status {
+            *self.skipped_by_status.entry(status).or_default() += count;
+        }
+        self.unchanged += batch.unchanged;
+        self.content_unchanged += batch.content_unchanged;
+        self.failed.extend(batch.failed);
+        self.unwritten.extend(batch.unwritten);
+    }
+
+    /// Vendor counts per terminal status, for the city summary.
+    pub fn status_counts(&self) -> BTreeMap<String, usize> {
+        let mut counts = self.skipped_by_status.clone();
+        for (status, count) in [
+            ("ok", self.succeeded),
+            ("failed", self.failed.len()),
+            ("unchanged", self.unchanged),
+            ("content_unchanged", self.content_unchanged),
+            ("filtered", self.filtered),
+        ] {
+            if count > 0 {
+                counts.insert(status.to_string(), count);
+            }
+        }
+        counts
+    }
+}
+
+/// Vendor status of a vendor written without details.
+fn skipped_status(unavailable_status: Option<u16>) -> String {
+    match unavailable_status {
+        Some(status) => format!("skipped_{}", status),
+        None => "skipped".to_string(),
+    }
+}
+
+/// Detail sections that carry discounts, depending on `include=`.
+const DISCOUNT_SECTIONS: &[&str] = &["discounts", "multiple_discounts"];
+
+/// Parses the discounts out of a vendor's details. A missing section or an
+/// entry that doesn't parse is logged and left out.
+fn discounts_from(code: &str, details: &VendorDetails) -> Vec<Discount> {
+    let Some(section) = DISCOUNT_SECTIONS.iter().find_map(|key| details.extra.get(*key)) else {
+        debug!(vendor_code = code, "Vendor details have no discounts section");
+        return Vec::new();
+    };
+    let Some(entries) = section.as_array() else {
+        debug!(vendor_code = code, "Discounts section is not an array, ignoring");
+        return Vec::new();
+    };
+    entries.iter()
+        .filter_map(|entry| match serde_json::from_value(entry.clone()) {
+            Ok(discount) => Some(discount),
+            Err(e) => {
+                debug!(error = %e, vendor_code = code, "Skipping unparseable discount");
+                None
+            }
+        })
+        .collect()
 }
 
 impl VendorService {
-    pub fn new(api_service: ApiService) -> Self {
-        Self { api_service }
+    pub fn new(api_service: Arc<dyn VendorApi>, settings: &Settings, throttling: SharedThrottling) -> Self {
+        Self {
+            api_service,
+            throttling,
+            extraction: settings.extraction.clone(),
+            max_bytes_downloaded: settings.limits.max_bytes_downloaded,
+            languages: settings.country.detail_languages(),
+            run_id: None,
+            listing_prefetch_pages: settings.concurrency.listing_prefetch_pages.max(1),
+            incremental: settings.incremental.clone(),
+            manifest: None,
+            vendor_filter: settings.vendor_filter.clone(),
+            // Checked when the settings were loaded
+            name_pattern: settings.vendor_filter.name_regex().ok().flatten(),
+            progress: None,
+            pacer: None,
+            checkpoints: None,
+            skipped_vendor_output: settings.output.skipped_vendor_output,
+        }
+    }
+
+    /// Stores a [`CityCheckpoint`] under `output_dir` after each batch of a
+    /// city extracted with [`extract_city`](Self::extract_city).
+    pub fn with_checkpoints(mut self, output_dir: &Path) -> Self {
+        self.checkpoints = Some(output_dir.to_path_buf());
+        self
+    }
+
+    /// Whether cities can be resumed from their checkpoint: not when the
+    /// listing is held back for sampling or shuffling.
+    pub fn resumable(&self) -> bool {
+        self.checkpoints.is_some() && !self.holds_listing()
+    }
+
+    /// Takes the base delay between vendors from the pacer instead of
+    /// `throttling.inter_vendor_delay_ms`.
+    pub fn with_pacer(mut self, pacer: Arc<AdaptivePacer>) -> Self {
+        self.pacer = Some(pacer);
+        self
+    }
+
+    /// Reports pages, vendors and batches as they finish. Events the receiver
+    /// isn't keeping up with are dropped.
+    pub fn with_progress(mut self, progress: ProgressSender) -> Self {
+        self.progress = Some(progress);
+        self
+    }
+
+    fn report(&self, event: ProgressEvent) {
+        if let Some(progress) = &self.progress {
+            progress.send(event);
+        }
+    }
+
+    /// Whether a listed vendor meets the configured `vendor_filter` criteria.
+    fn accepts(&self, item: &VendorItem) -> bool {
+        let filter = &self.vendor_filter;
+        let mut checks = Vec::new();
+        if let Some(min_rating) = filter.min_rating {
+            checks.push(item.rating.is_some_and(|rating| rating >= min_rating));
+        }
+        if !filter.cuisines.is_empty() {
+            checks.push(item.cuisines.iter()
+                .any(|cuisine| cuisine.id.is_some_and(|id| filter.cuisines.contains(&id))));
+        }
+        if let Some(pattern) = &self.name_pattern {
+            checks.push(pattern.is_match(&item.name));
+        }
+        if filter.match_any {
+            checks.is_empty() || checks.contains(&true)
+        } else {
+            !checks.contains(&false)
+        }
+    }
+
+    /// Skips vendors the manifest says were extracted within
+    /// `incremental.max_age_hours`, and records the ones fetched this run.
+    pub fn with_incremental(mut self, manifest: IncrementalManifest) -> Self {
+        self.manifest = Some(Arc::new(Mutex::new(manifest)));
+        self
+    }
+
+    /// The manifest to hand to the next run: the previous one plus every
+    /// vendor extracted by this one.
+    pub fn incremental_manifest(&self) -> Option<IncrementalManifest> {
+        self.manifest.as_ref()
+            .map(|manifest| manifest.lock().unwrap_or_else(|e| e.into_inner()).clone())
+    }
+
+    /// Writes listing-only records for vendors the filter rejected.
+    async fn write_filtered_stubs(
+        &self,
+        items: &[VendorItem],
+        writers: &CityWriters,
+        batch_number: i32,
+    ) -> Result<()> {
+        for item in items {
+            let mut vendor = Vendor::stub(&item.code, batch_number, self.run_id, "filtered");
+            vendor.filtered = true;
+            if !item.name.is_empty() {
+                vendor.name = item.name.clone();
+            }
+            writers.write_vendor(vendor).await.inspect_err(|e| error!(
+                error = %e,
+                vendor_code = item.code,
+                "Error writing vendor to file"
+            ))?;
+        }
+        Ok(())
+    }
+
+    /// Fetches a vendor's details and, when it has them, everything else the
+    /// extraction asks for. Only a details failure is an error; the other
+    /// fetches' results are handed back as they are.
+    async fn fetch_vendor(&self, code: &str) -> Result<FetchedVendor> {
+        let (details, attempts) = match self.api_service.fetch_vendor_details(code).await? {
+            DetailsFetch { details: Some(details), attempts, .. } => (details, attempts),
+            DetailsFetch { details: None, unavailable_status, attempts } => {
+                return Ok(FetchedVendor::Unavailable { status: unavailable_status, attempts });
+            }
+        };
+        let localized_details = self.fetch_localized_details(code, &details).await;
+
+        // Add delay before fetching reviews and ratings
+        if self.extraction.fetch_reviews || self.extraction.fetch_ratings {
+            let throttling = self.throttling.get();
+            sleep_with_jitter(throttling.sub_fetch_delay_ms, throttling.sub_fetch_jitter_ms).await;
+        }
+
+        let reviews_request = async {
+            if self.extraction.fetch_reviews {
+                self.api_service.fetch_vendor_reviews(code).await.map(Some)
+            } else {
+                Ok(None)
+            }
+        };
+        let ratings_request = async {
+            if self.extraction.fetch_ratings {
+                self.api_service.fetch_vendor_ratings(code).await
+            } else {
+                Ok(None)
+            }
+        };
+
+        // Quoted to the vendor's own location; listings carry no customer address
+        let delivery_request = async {
+            match (self.extraction.fetch_delivery_info, details.latitude, details.longitude) {
+                (true, Some(lat), Some(lng)) => {
+                    self.api_service.fetch_vendor_delivery_info(code, lat, lng).await
+                }
+                _ => Ok(None),
+            }
+        };
+
+        let (reviews, ratings, delivery) = tokio::join!(
+            reviews_request,
+            ratings_request,
+            delivery_request
+        );
+
+        Ok(FetchedVendor::Found {
+            details,
+            attempts,
+            localized_details,
+            reviews,
+            ratings,
+            delivery,
+        })
+    }
+
+    fn record_extracted(&self, code: &str, extracted_at: DateTime<Utc>, content_hash: Option<String>) {
+        if let Some(manifest) = &self.manifest {
+            manifest.lock().unwrap_or_else(|e| e.into_inner()).record(code, extracted_at, content_hash);
+        }
+    }
+
+    fn is_unchanged(&self, code: &str) -> bool {
+        let max_age = chrono::Duration::hours(self.incremental.max_age_hours as i64);
+        self.manifest.as_ref().is_some_and(|manifest| {
+            manifest.lock().unwrap_or_else(|e| e.into_inner())
+                .extracted_within(code, Utc::now(), max_age)
+        })
+    }
+
+    /// Stamps every extracted vendor with the run's correlation id.
+    pub fn with_run_id(mut self, run_id: Uuid) -> Self {
+        self.run_id = Some(run_id);
+        self
+    }
+
+    /// Fetches the vendor's details in every further configured language,
+    /// keyed by language id alongside the default-language `details`. Empty
+    /// when only one language is configured; a failed language is left out.
+    async fn fetch_localized_details(
+        &self,
+        code: &str,
+        details: &VendorDetails,
+    ) -> BTreeMap<i32, VendorDetails> {
+        let mut localized = BTreeMap::new();
+        let Some((default_language, others)) = self.languages.split_first() else {
+            return localized;
+        };
+        if others.is_empty() {
+            return localized;
+        }
+        localized.insert(*default_language, details.clone());
+
+        for language_id in others {
+            let throttling = self.throttling.get();
+            sleep_with_jitter(throttling.sub_fetch_delay_ms, throttling.sub_fetch_jitter_ms).await;
+            match self.api_service.fetch_vendor_details_in(code, *language_id).await {
+                Ok(Some(details)) => {
+                    localized.insert(*language_id, details);
+                }
+                Ok(None) => warn!(
+                    vendor_code = code,
+                    language_id = language_id,
+                    "Vendor details unavailable in language"
+                ),
+                Err(e) => warn!(
+                    error = %e,
+                    vendor_code = code,
+                    language_id = language_id,
+                    "Failed to fetch localized vendor details"
+                ),
+            }
+        }
+        localized
+    }
+
+    /// Pages through a city's listing and processes each page as a batch. The
+    /// initial listing response (fetched here if not supplied) is processed as
+    /// batch 1, so offset 0 is only ever requested once.
+    ///
+    /// Listing and processing run as a pipeline: the listing side pages ahead
+    /// into a channel of `concurrency.listing_prefetch_pages` pages while the
+    /// vendor workers process what it has sent, and records go to the writer
+    /// task through `writers`. A full channel holds the side feeding it back.
+    /// When either side fails the other is dropped with it and the caller
+    /// suspends `writers`, leaving the partial files for repair.
+    ///
+    /// With checkpoints on, a [`CityCheckpoint`] is stored after each batch;
+    /// given one as `resume`, the listing continues after its last page and
+    /// `writers` are expected to have been reopened at it.
+    pub async fn extract_city(
+        &self,
+        query: &ListingQuery,
+        initial_response: Option<VendorListResponse>,
+        writers: &CityWriters,
+        resume: Option<&CityCheckpoint>,
+    ) -> Result<CityExtraction> {
+        let mut cursor = self.start_city(query, initial_response).await?;
+        let city_id = cursor.extraction.city_id.clone();
+        let mut vendors_processed = 0;
+        if let Some(checkpoint) = resume.filter(|_| self.resumable() && !cursor.done) {
+            info!(
+                city_id = city_id,
+                last_page = checkpoint.last_page,
+                offset = checkpoint.offset,
+                vendors_processed = checkpoint.vendors_processed,
+                "Resuming city from checkpoint"
+            );
+            cursor.resume_from(checkpoint);
+            vendors_processed = checkpoint.vendors_processed;
+        }
+        let checkpoints = self.checkpoints.as_deref().filter(|_| self.resumable());
+        let mut checkpointed = cursor.extraction.clone();
+        let mut checkpointed_seen: BTreeSet<String> = cursor.seen.iter().cloned().collect();
+        let (pages, mut listed_pages) = mpsc::channel(self.listing_prefetch_pages);
+
+        let listing = async {
+            while !cursor.is_done() {
+                if let Some(listed) = self.list_page(&mut cursor, writers).await? {
+                    if pages.send(listed).await.is_err() {
+                        break;
+                    }
+                }
+            }
+            // Closing the channel lets the processing side finish
+            drop(pages);
+            Ok::<_, Error>(())
+        };
+        let processing = async {
+            let mut batches = Vec::new();
+            while let Some(listed) = listed_pages.recv().await {
+                vendors_processed += listed.vendor_codes.len();
+                let batch = self
+                    .run_batch(&city_id, listed.vendor_codes, writers, listed.page, listed.total_pages)
+                    .await?;
+                if let Some(output_dir) = checkpoints {
+                    checkpointed.add_batch(batch.clone());
+                    checkpointed.pages = listed.page;
+                    checkpointed.filtered = listed.filtered;
+                    checkpointed.duplicates = listed.duplicates;
+                    checkpointed.final_available_count = listed.final_available_count;
+                    checkpointed_seen.extend(listed.newly_seen);
+                    CityCheckpoint {
+                        city_id: city_id.clone(),
+                        run_id: self.run_id.unwrap_or_default(),
+                        last_page: listed.page,
+                        offset: listed.next_offset,
+                        vendors_processed,
+                        extraction: checkpointed.clone(),
+                        seen: checkpointed_seen.clone(),
+                        files: writers.checkpoint().await?,
+                        updated_at: Utc::now(),
+                    }.store(output_dir)?;
+                }
+                batches.push(batch);
+            }
+            Ok::<_, Error>(batches)
+        };
+        let ((), batches) = tokio::try_join!(listing, processing)?;
+
+        for batch in batches {
+            cursor.extraction.add_batch(batch);
+        }
+        self.finish_city(&mut cursor, writers).await?;
+        Ok(cursor.extraction)
+    }
+
+    /// Completes a city whose listing is done: processes the held-back codes
+    /// when sampling or shuffling, then retries the failed vendors.
+    pub async fn finish_city(
+        &self,
+        cursor: &mut CityCursor,
+        writers: &CityWriters,
+    ) -> Result<()> {
+        if self.holds_listing() {
+            let mut listed = std::mem::take(&mut cursor.listed);
+            if let Some(sample_size) = self.extraction.sample_size {
+                listed = self.sample(&mut cursor.extraction, listed, sample_size);
+            }
+            if self.extraction.shuffle_vendors {
+                self.process_shuffled(&mut cursor.extraction, listed, writers).await?;
+            } else {
+                self.process_by_page(&mut cursor.extraction, listed, writers).await?;
+            }
+        }
+        self.retry_failed_vendors(&mut cursor.extraction, writers).await?;
+        cursor.extraction.finished_at = Some(Utc::now());
+        Ok(())
+    }
+
+    /// Whether codes are held back until the city's listing is done instead of
+    /// being processed page by page.
+    fn holds_listing(&self) -> bool {
+        self.extraction.sample_size.is_some() || self.extraction.shuffle_vendors
+    }
+
+    /// Picks `sample_size` of the listed codes uniformly (reproducibly with
+    /// `sample_seed`), keeping listing order.
+    fn sample(
+        &self,
+        extraction: &mut CityExtraction,
+        listed: Vec<(String, i32)>,
+        sample_size: usize,
+    ) -> Vec<(String, i32)> {
+        let amount = sample_size.min(listed.len());
+        let mut rng = match self.extraction.sample_seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_os_rng(),
+        };
+        let mut chosen = rand::seq::index::sample(&mut rng, listed.len(), amount).into_vec();
+        chosen.sort_unstable();
+
+        extraction.sampled = true;
+        info!(
+            city_id = extraction.city_id,
+            listed_vendors = listed.len(),
+            sample_size = amount,
+            sample_seed = ?self.extraction.sample_seed,
+            "Processing vendor sample"
+        );
+
+        chosen.into_iter().map(|index| listed[index].clone()).collect()
+    }
+
+    /// Processes held-back codes grouped by their listing page.
+    async fn process_by_page(
+        &self,
+        extraction: &mut CityExtraction,
+        listed: Vec<(String, i32)>,
+        writers: &CityWriters,
+    ) -> Result<()> {
+        let mut batches: BTreeMap<i32, Vec<String>> = BTreeMap::new();
+        for (code, page) in listed {
+            batches.entry(page).or_default().push(code);
+        }
+        let total_batches = extraction.pages;
+        for (page, vendor_codes) in batches {
+            let batch = self.run_batch(&extraction.city_id, vendor_codes, writers, page, total_batches).await?;
+            extraction.add_batch(batch);
+        }
+        Ok(())
+    }
+
+    /// Shuffles the held-back codes, seeded by the run id so a run's order can
+    /// be reproduced, and processes them in batches of a listing page's size
+    /// numbered in the new order.
+    async fn process_shuffled(
+        &self,
+        extraction: &mut CityExtraction,
+        listed: Vec<(String, i32)>,
+        writers: &CityWriters,
+    ) -> Result<()> {
+        let mut rng = match self.run_id {
+            Some(run_id) => {
+                let (high, low) = run_id.as_u64_pair();
+                StdRng::seed_from_u64(high ^ low)
+            }
+            None => StdRng::from_os_rng(),
+        };
+        let mut codes: Vec<String> = listed.into_iter().map(|(code, _)| code).collect();
+        codes.shuffle(&mut rng);
+
+        let batch_size = (extraction.page_size as usize).max(1);
+        let total_batches = codes.len().div_ceil(batch_size) as i32;
+        info!(
+            city_id = extraction.city_id,
+            vendors_count = codes.len(),
+            total_batches = total_batches,
+            "Processing shuffled vendors"
+        );
+        for (index, vendor_codes) in codes.chunks(batch_size).enumerate() {
+            let batch = self
+                .run_batch(&extraction.city_id, vendor_codes.to_vec(), writers, index as i32 + 1, total_batches)
+                .await?;
+            extraction.add_batch(batch);
+        }
+        Ok(())
+    }
+
+    /// Gives the city's failed vendors another go after its listing is done,
+    /// one at a time with the longer `throttling.dead_letter_*` delays and a
+    /// single attempt each, its details request retried as usual. The client
+    /// rotation has moved on since the original failure, so the retries go
+    /// out through other clients. Vendors that fail again, or are left when
+    /// the download budget runs out, stay in `extraction.failed`.
+    pub async fn retry_failed_vendors(
+        &self,
+        extraction: &mut CityExtraction,
+        writers: &CityWriters,
+    ) -> Result<()> {
+        if extraction.failed.is_empty() {
+            return Ok(());
+        }
+        let failed = std::mem::take(&mut extraction.failed);
+        let vendors_count = failed.len();
+        info!(
+            city_id = extraction.city_id,
+            failed_vendors = vendors_count,
+            "Retrying failed vendors"
+        );
+
+        // A vendor over the download budget means none of the rest fit either
+        let over_budget = CancellationToken::new();
+        let pages = extraction.pages;
+        let BulkResult { mut successes, failures, skipped } = fetch_all_with_retries(
+            (0..vendors_count).collect(),
+            1,
+            0,
+            &over_budget,
+            |index| {
+                let (vendor, over_budget) = (&failed[index], &over_budget);
+                async move {
+                    let throttling = self.throttling.get();
+                    sleep_with_jitter(throttling.dead_letter_delay_ms, throttling.dead_letter_jitter_ms).await;
+                    let outcome = self
+                        .process_vendor(&vendor.code, writers, vendor.batch_number, pages, index, vendors_count)
+                        .await;
+                    if matches!(outcome, Ok(VendorOutcome::OverBudget)) {
+                        over_budget.cancel();
+                    }
+                    outcome
+                }
+            },
+        )
+        .await;
+        if !skipped.is_empty() {
+            warn!(
+                city_id = extraction.city_id,
+                vendors_left = skipped.len(),
+                "Download budget exhausted, leaving the remaining vendors failed"
+            );
+        }
+
+        let mut errors: HashMap<usize, Error> = failures.into_iter().collect();
+        for (index, mut vendor) in failed.into_iter().enumerate() {
+            if let Some(e) = errors.remove(&index) {
+                vendor.error = e.to_string();
+                vendor.attempts += attempts_of(&e);
+                vendor.last_failed_at = Utc::now();
+                extraction.failed.push(vendor);
+                continue;
+            }
+            match successes.remove(&index) {
+                Some(VendorOutcome::Written) => extraction.succeeded += 1,
+                Some(VendorOutcome::Skipped { status, written }) => {
+                    extraction.skipped += 1;
+                    *extraction.skipped_by_status.entry(status).or_default() += 1;
+                    if !written {
+                        extraction.unwritten.insert(vendor.code);
+                    }
+                }
+                Some(VendorOutcome::Unchanged { written }) => {
+                    extraction.unchanged += 1;
+                    if !written {
+                        extraction.unwritten.insert(vendor.code);
+                    }
+                }
+                Some(VendorOutcome::ContentUnchanged) => {
+                    extraction.content_unchanged += 1;
+                    extraction.unwritten.insert(vendor.code);
+                }
+                Some(VendorOutcome::OverBudget) | None => extraction.failed.push(vendor),
+            }
+        }
+
+        info!(
+            city_id = extraction.city_id,
+            recovered = vendors_count - extraction.failed.len(),
+            still_failed = extraction.failed.len(),
+            "Retry pass finished"
+        );
+        Ok(())
+    }
+
+    /// Opens a city's listing for page-at-a-time extraction with
+    /// [`extract_page`](Self::extract_page).
+    pub async fn start_city(
+        &self,
+        query: &ListingQuery,
+        initial_response: Option<VendorListResponse>,
+    ) -> Result<CityCursor> {
+        let response = match initial_response {
+            Some(response) => response,
+            None => self.api_service.fetch_vendor_page(query, 0, LISTING_PAGE_SIZE).await?,
+        };
+        let city_id = query.key();
+
+        let page_size = response.data.returned_count;
+        let extraction = CityExtraction {
+            city_id: city_id.clone(),
+            page_size,
+            pages: 0,
+            initial_available_count: response.data.available_count,
+            final_available_count: response.data.available_count,
+            truncated: false,
+            partial_listing: false,
+            sampled: false,
+            succeeded: 0,
+            skipped: 0,
+            skipped_by_status: BTreeMap::new(),
+            failed: Vec::new(),
+            duplicates: 0,
+            unchanged: 0,
+            content_unchanged: 0,
+            unwritten: BTreeSet::new(),
+            filtered: 0,
+            started_at: Utc::now(),
+            finished_at: None,
+        };
+        let total_pages = response.data.total_pages();
+
+        if response.data.is_empty_listing() {
+            info!(
+                city_id = city_id,
+                returned_count = response.data.returned_count,
+                available_count = response.data.available_count,
+                "Listing has no vendors, skipping city"
+            );
+            return Ok(CityCursor {
+                extraction,
+                query: query.clone(),
+                offset: 0,
+                total_pages,
+                next: None,
+                seen: HashSet::new(),
+                listed: Vec::new(),
+                done: true,
+            });
+        }
+
+        info!(
+            city_id = city_id,
+            total_vendors = extraction.initial_available_count,
+            total_pages = total_pages,
+            page_size = page_size,
+            max_pages = ?self.extraction.max_pages,
+            "Vendor pagination details"
+        );
+
+        Ok(CityCursor {
+            extraction,
+            query: query.clone(),
+            offset: 0,
+            total_pages,
+            next: Some(response),
+            seen: HashSet::new(),
+            listed: Vec::new(),
+            done: false,
+        })
+    }
+
+    /// Processes the city's next listing page. Keeps paging until the catalog
+    /// is exhausted; the planned page count is only an estimate.
+    pub async fn extract_page(
+        &self,
+        cursor: &mut CityCursor,
+        writers: &CityWriters,
+    ) -> Result<()> {
+        if let Some(listed) = self.list_page(cursor, writers).await? {
+            let batch = self
+                .run_batch(&cursor.extraction.city_id, listed.vendor_codes, writers, listed.page, listed.total_pages)
+                .await?;
+            cursor.extraction.add_batch(batch);
+        }
+        Ok(())
+    }
+
+    /// Fetches the city's next listing page and returns the codes to process,
+    /// if any: duplicates and filtered vendors are dropped, and codes are held
+    /// back in the cursor when sampling or shuffling.
+    async fn list_page(
+        &self,
+        cursor: &mut CityCursor,
+        writers: &CityWriters,
+    ) -> Result<Option<ListedPage>> {
+        if cursor.done {
+            return Ok(None);
+        }
+        let city_id = cursor.extraction.city_id.clone();
+        let page_size = cursor.extraction.page_size;
+
+        let response = match cursor.next.take() {
+            Some(response) => response,
+            None => {
+                let throttling = self.throttling.get();
+                sleep_with_jitter(throttling.page_delay_ms, throttling.page_jitter_ms).await;
+                self.api_service.fetch_vendor_page(&cursor.query, cursor.offset, page_size).await?
+            }
+        };
+
+        let extraction = &mut cursor.extraction;
+        if response.data.available_count != extraction.final_available_count {
+            let total_pages = pages_for(response.data.available_count, page_size);
+            info!(
+                city_id = city_id,
+                initial_available_count = extraction.initial_available_count,
+                previous_available_count = extraction.final_available_count,
+                available_count = response.data.available_count,
+                drift = response.data.available_count - extraction.initial_available_count,
+                previous_total_pages = cursor.total_pages,
+                total_pages = total_pages,
+                remaining_pages = (total_pages - extraction.pages).max(0),
+                "Available vendor count changed during pagination"
+            );
+            extraction.final_available_count = response.data.available_count;
+            cursor.total_pages = total_pages;
+
+            // Shrunk below the current offset: the page only holds vendors already seen
+            if cursor.offset > 0 && cursor.offset >= extraction.final_available_count {
+                info!(
+                    city_id = city_id,
+                    offset = cursor.offset,
+                    available_count = extraction.final_available_count,
+                    "Catalog shrank below the current offset, stopping pagination"
+                );
+                cursor.done = true;
+                return Ok(None);
+            }
+        }
+        let total_pages = cursor.total_pages;
+
+        let page = extraction.pages + 1;
+        if response.data.items.is_empty() {
+            info!(
+                city_id = city_id,
+                page = page,
+                offset = cursor.offset,
+                "Listing returned no vendors, stopping pagination"
+            );
+            extraction.partial_listing = cursor.offset < extraction.final_available_count;
+            cursor.done = true;
+            return Ok(None);
+        }
+
+        let listed = response.data.items.len();
+        let items: Vec<VendorItem> = response.data.items
+            .into_iter()
+            .filter(|item| cursor.seen.insert(item.code.clone()))
+            .collect();
+        let duplicates = listed - items.len();
+        if duplicates > 0 {
+            info!(
+                city_id = city_id,
+                page = page,
+                duplicates = duplicates,
+                "Dropped vendors already listed on an earlier page"
+            );
+        }
+        extraction.duplicates += duplicates;
+        let newly_seen: Vec<String> = items.iter().map(|item| item.code.clone()).collect();
+
+        let (accepted, rejected): (Vec<VendorItem>, Vec<VendorItem>) = items
+            .into_iter()
+            .partition(|item| self.accepts(item));
+        if !rejected.is_empty() {
+            info!(
+                city_id = city_id,
+                page = page,
+                filtered = rejected.len(),
+                "Vendors filtered out before fetching details"
+            );
+            extraction.filtered += rejected.len();
+            if self.vendor_filter.write_filtered_stub {
+                self.write_filtered_stubs(&rejected, writers, page).await?;
+            }
+        }
+        let vendor_codes: Vec<String> = accepted.into_iter().map(|item| item.code).collect();
+
+        self.report(ProgressEvent::PageFetched {
+            city_id: city_id.clone(),
+            page,
+            of: total_pages,
+        });
+        let listed = if self.holds_listing() {
+            cursor.listed.extend(vendor_codes.into_iter().map(|code| (code, page)));
+            None
+        } else {
+            Some(ListedPage {
+                vendor_codes,
+                page,
+                total_pages,
+                next_offset: cursor.offset + page_size,
+                filtered: cursor.extraction.filtered,
+                duplicates: cursor.extraction.duplicates,
+                final_available_count: cursor.extraction.final_available_count,
+                newly_seen,
+            })
+        };
+
+        let extraction = &mut cursor.extraction;
+        extraction.pages = page;
+        cursor.offset += page_size;
+
+        if self.budget_exhausted() {
+            warn!(
+                city_id = city_id,
+                page = page,
+                total_pages = total_pages,
+                "Download budget exhausted, stopping pagination"
+            );
+            extraction.truncated = true;
+            cursor.done = true;
+        } else if cursor.offset >= extraction.final_available_count {
+            cursor.done = true;
+        } else if self.extraction.max_pages.is_some_and(|max_pages| extraction.pages >= max_pages) {
+            info!(
+                city_id = city_id,
+                max_pages = ?self.extraction.max_pages,
+                "Reached configured max_pages, stopping pagination"
+            );
+            extraction.truncated = true;
+            cursor.done = true;
+        }
+
+        Ok(listed)
     }
 
+    /// Processes one batch of a city's vendors and reports it.
+    async fn run_batch(
+        &self,
+        city_id: &str,
+        vendor_codes: Vec<String>,
+        writers: &CityWriters,
+        page: i32,
+        total_pages: i32,
+    ) -> Result<BatchResult> {
+        info!(
+            city_id = city_id,
+            page = page,
+            total_pages = total_pages,
+            vendors_count = vendor_codes.len(),
+            "Processing vendor batch"
+        );
+
+        let batch = self.process_vendor_batch(vendor_codes, writers, page, total_pages).await?;
+        info!(
+            page = page,
+            total_pages = total_pages,
+            failed = batch.failed.len(),
+            "Batch processed"
+        );
+        self.report(ProgressEvent::BatchDone {
+            city_id: city_id.to_string(),
+            batch: page,
+            failed: batch.failed.len(),
+        });
+        Ok(batch)
+    }
+
+    /// Whether cities should be extracted round-robin, a page at a time.
+    pub fn interleave_cities(&self) -> bool {
+        self.extraction.interleave_cities
+    }
+
+    /// Whether the run has used up its download allowance; extraction stops
+    /// at the next vendor boundary once this is true.
+    pub fn budget_exhausted(&self) -> bool {
+        self.max_bytes_downloaded
+            .is_some_and(|max| self.api_service.stats().total_bytes() >= max)
+    }
+
+    /// Processes a page of vendors, up to the current vendor worker cap at a
+    /// time; a reload that changes the cap applies to vendors not yet started.
+    /// Each vendor waits its own jittered delay first; writes go through
+    /// the writers' mutexes. A vendor whose details or writes fail is recorded
+    /// in the result and the batch carries on.
     pub async fn process_vendor_batch(
         &self,
         vendor_codes: Vec<String>,
-        json_writer: &Arc<Mutex<JsonWriter>>,
+        writers: &CityWriters,
         batch_number: i32,
         total_batches: i32,
-    ) -> Result<()> {
+    ) -> Result<BatchResult> {
         info!(
             batch_number = batch_number,
             total_batches = total_batches,
+            vendor_workers = self.throttling.vendor_workers(),
             "Processing vendor batch"
         );
 
-        for (index, code) in vendor_codes.iter().enumerate() {
-            info!(
+        let vendors_count = vendor_codes.len();
+        let mut outcomes = stream::iter(vendor_codes.iter().enumerate())
+            .map(|(index, code)| async move {
+                let _worker = self.throttling.vendor_worker().await;
+                let outcome = self
+                    .process_vendor(code, writers, batch_number, total_batches, index, vendors_count)
+                    .await;
+                (code, outcome)
+            })
+            .buffer_unordered(self.throttling.max_vendor_workers());
+
+        let mut result = BatchResult::default();
+        let mut completed = 0;
+        while let Some((code, outcome)) = outcomes.next().await {
+            let status = match outcome {
+                Ok(VendorOutcome::Written) => {
+                    result.succeeded += 1;
+                    VendorStatus::Ok
+                }
+                Ok(VendorOutcome::Skipped { status: vendor_status, written }) => {
+                    result.skipped += 1;
+                    *result.skipped_by_status.entry(vendor_status).or_default() += 1;
+                    if !written {
+                        result.unwritten.push(code.clone());
+                    }
+                    VendorStatus::Skipped
+                }
+                Ok(VendorOutcome::OverBudget) => {
+                    result.over_budget += 1;
+                    VendorStatus::OverBudget
+                }
+                Ok(VendorOutcome::Unchanged { written }) => {
+                    result.unchanged += 1;
+                    if !written {
+                        result.unwritten.push(code.clone());
+                    }
+                    VendorStatus::Unchanged
+                }
+                Ok(VendorOutcome::ContentUnchanged) => {
+                    result.content_unchanged += 1;
+                    result.unwritten.push(code.clone());
+                    VendorStatus::ContentUnchanged
+                }
+                Err(e) => {
+                    result.failed.push(FailedVendor::new(code, batch_number, &e));
+                    VendorStatus::Failed
+                }
+            };
+            self.report(ProgressEvent::VendorDone { code: code.clone(), status });
+            completed += 1;
+            debug!(
                 batch_number = batch_number,
-                total_batches = total_batches,
-                vendor_index = index + 1,
-                vendors_count = vendor_codes.len(),
                 vendor_code = code,
-                "Processing vendor"
+                completed = completed,
+                vendors_count = vendors_count,
+                "Vendor finished"
             );
+        }
+
+        if result.over_budget > 0 {
+            warn!(
+                batch_number = batch_number,
+                remaining_vendors = result.over_budget,
+                bytes_downloaded = self.api_service.stats().total_bytes(),
+                "Download budget exhausted, truncating batch"
+            );
+        }
+        info!(
+            batch_number = batch_number,
+            total_batches = total_batches,
+            succeeded = result.succeeded,
+            skipped = result.skipped,
+            failed = result.failed.len(),
+            over_budget = result.over_budget,
+            unchanged = result.unchanged,
+            content_unchanged = result.content_unchanged,
+            "Vendor batch finished"
+        );
 
-            // Add random delay between vendors
-            sleep_with_jitter(1500, 1000).await;
+        Ok(result)
+    }
 
-            // Get vendor details first
-            match self.api_service.fetch_vendor_details(code).await {
-                Ok(Some(details)) => {
-                    // Add delay before fetching reviews and ratings
-                    sleep_with_jitter(800, 400).await;
-                    
-                    let (reviews_result, ratings_result) = tokio::join!(
-                        self.api_service.fetch_vendor_reviews(code),
-                        self.api_service.fetch_vendor_ratings(code)
-                    );
-                    
-                    let extraction_completed_at = chrono::Utc::now();
-                    
-                    let vendor = Vendor {
-                        code: code.clone(),
-                        name: details.get("name")
-                            .and_then(|n| n.as_str())
-                            .unwrap_or("Unknown")
-                            .to_string(),
-                        details: Some(details),
-                        batch_number,
-                        reviews: reviews_result.ok(),
-                        ratings: ratings_result.ok(),
-                        extraction_started_at: chrono::Utc::now(),
-                        extraction_completed_at,
-                    };
-                    
-                    let mut writer = json_writer.lock().await;
-                    if let Err(e) = writer.write_vendor(&vendor).await {
-                        error!(
-                            error = %e,
-                            vendor_code = code,
-                            "Error writing vendor to file"
-                        );
-                    }
-                },
-                Ok(None) => {
-                    // Vendor details returned 400, skip reviews and ratings
-                    info!(
+    async fn process_vendor(
+        &self,
+        code: &str,
+        writers: &CityWriters,
+        batch_number: i32,
+        total_batches: i32,
+        index: usize,
+        vendors_count: usize,
+    ) -> Result<VendorOutcome> {
+        if self.budget_exhausted() {
+            return Ok(VendorOutcome::OverBudget);
+        }
+
+        if self.is_unchanged(code) {
+            debug!(vendor_code = code, "Vendor extracted recently, not fetching again");
+            let written = self.incremental.unchanged == UnchangedVendors::Stub;
+            if written {
+                let mut vendor = Vendor::stub(code, batch_number, self.run_id, "unchanged");
+                vendor.unchanged = true;
+                writers.write_vendor(vendor).await.inspect_err(|e| error!(
+                    error = %e,
+                    vendor_code = code,
+                    "Error writing vendor to file"
+                ))?;
+            }
+            return Ok(VendorOutcome::Unchanged { written });
+        }
+
+        info!(
+            batch_number = batch_number,
+            total_batches = total_batches,
+            vendor_index = index + 1,
+            vendors_count = vendors_count,
+            vendor_code = code,
+            "Processing vendor"
+        );
+
+        // Add random delay between vendors
+        let throttling = self.throttling.get();
+        let inter_vendor_delay_ms = self.pacer.as_ref()
+            .map_or(throttling.inter_vendor_delay_ms, |pacer| pacer.delay_ms());
+        sleep_with_jitter(inter_vendor_delay_ms, throttling.inter_vendor_jitter_ms).await;
+
+        let extraction_started_at = Utc::now();
+
+        let fetched = match self.extraction.vendor_deadline_secs {
+            0 => self.fetch_vendor(code).await,
+            deadline_secs => timeout(Duration::from_secs(deadline_secs), self.fetch_vendor(code))
+                .await
+                .unwrap_or_else(|_| {
+                    warn!(
                         vendor_code = code,
-                        batch_number = batch_number,
-                        total_batches = total_batches,
-                        vendor_index = index + 1,
-                        vendors_count = vendor_codes.len(),
-                        "Skipping vendor due to 400 response"
+                        deadline_secs = deadline_secs,
+                        "Vendor exceeded its deadline, giving up on it"
                     );
-                    
-                    let extraction_completed_at = chrono::Utc::now();
-                    
-                    // Still write the vendor with minimal information
-                    let vendor = Vendor {
-                        code: code.clone(),
-                        name: "Unknown".to_string(),
-                        details: None,
-                        batch_number,
-                        reviews: None,
-                        ratings: None,
-                        extraction_started_at: chrono::Utc::now(),
-                        extraction_completed_at,
-                    };
-                    
-                    let mut writer = json_writer.lock().await;
-                    if let Err(e) = writer.write_vendor(&vendor).await {
+                    Err(Error::Deadline(deadline_secs))
+                }),
+        };
+
+        match fetched {
+            Ok(FetchedVendor::Found {
+                details,
+                attempts,
+                localized_details,
+                reviews: reviews_result,
+                ratings: ratings_result,
+                delivery: delivery_result,
+            }) => {
+                let extraction_completed_at = chrono::Utc::now();
+
+                let ratings = match ratings_result {
+                    Ok(Some(ratings)) => Some(ratings),
+                    Ok(None) => {
+                        if self.extraction.fetch_ratings {
+                            debug!(vendor_code = code, "Vendor has no ratings yet");
+                        }
+                        None
+                    }
+                    Err(e) => {
                         error!(
                             error = %e,
                             vendor_code = code,
-                            "Error writing vendor to file"
+                            "Failed to fetch vendor ratings"
                         );
+                        None
                     }
-                },
-                Err(e) => {
+                };
+
+                let delivery = delivery_result.unwrap_or_else(|e| {
                     error!(
                         error = %e,
                         vendor_code = code,
-                        batch_number = batch_number,
-                        total_batches = total_batches,
-                        vendor_index = index + 1,
-                        vendors_count = vendor_codes.len(),
-                        "Failed to fetch vendor details"
+                        "Failed to fetch vendor delivery info"
                     );
-                    return Err(e);
+                    None
+                });
+
+                let content_hash = content_hash(&(&details, &localized_details), &self.incremental.volatile_fields).ok();
+                let previous_hash = self.manifest.as_ref().and_then(|manifest| {
+                    manifest.lock().unwrap_or_else(|e| e.into_inner())
+                        .content_hash(code)
+                        .map(str::to_string)
+                });
+                let changed = previous_hash.is_none() || previous_hash != content_hash;
+                if self.incremental.delta && !changed {
+                    debug!(vendor_code = code, "Vendor content unchanged since the last run, not writing");
+                    self.record_extracted(code, extraction_completed_at, content_hash);
+                    return Ok(VendorOutcome::ContentUnchanged);
                 }
+
+                let menu_items = MenuItem::from_details(code, &details);
+                let raw_reviews = reviews_result.ok().flatten();
+                let reviews: Vec<Review> = raw_reviews.iter()
+                    .flatten()
+                    .map(|review| Review::from_value(code, review))
+                    .collect();
+                let mut vendor = Vendor::stub(code, batch_number, self.run_id, "ok");
+                if !details.name.is_empty() {
+                    vendor.name = details.name.clone();
+                }
+                vendor.discounts = discounts_from(code, &details);
+                vendor.details = Some(details);
+                vendor.localized_details = localized_details;
+                vendor.reviews = raw_reviews.filter(|_| self.extraction.nest_reviews);
+                vendor.ratings = ratings;
+                vendor.delivery_fee = delivery.as_ref().and_then(|d| d.fee);
+                vendor.delivery_eta_minutes = delivery.as_ref().and_then(|d| d.eta_minutes);
+                vendor.attempts = attempts as i32;
+                vendor.content_hash = content_hash.clone();
+                vendor.changed = changed;
+                vendor.extraction_started_at = extraction_started_at;
+                vendor.extraction_completed_at = extraction_completed_at;
+                vendor.extraction_duration_ms = (extraction_completed_at - extraction_started_at).num_milliseconds();
+
+                // A failed write fails the vendor, so it is retried and reported;
+                // the retry only adds the menu items and reviews not yet written
+                writers.write_menu_items(menu_items).await.inspect_err(|e| error!(
+                    error = %e,
+                    vendor_code = code,
+                    "Error writing menu items to file"
+                ))?;
+                writers.write_reviews(reviews).await.inspect_err(|e| error!(
+                    error = %e,
+                    vendor_code = code,
+                    "Error writing reviews to file"
+                ))?;
+                writers.write_vendor(vendor).await.inspect_err(|e| error!(
+                    error = %e,
+                    vendor_code = code,
+                    "Error writing vendor to file"
+                ))?;
+                self.record_extracted(code, extraction_completed_at, content_hash);
+                Ok(VendorOutcome::Written)
+            },
+            Ok(FetchedVendor::Unavailable { status: unavailable_status, attempts }) => {
+                // Vendor details returned 400/404/410, skip reviews and ratings
+                info!(
+                    vendor_code = code,
+                    batch_number = batch_number,
+                    total_batches = total_batches,
+                    vendor_index = index + 1,
+                    vendors_count = vendors_count,
+                    "Skipping vendor without details"
+                );
+                
+                let extraction_completed_at = chrono::Utc::now();
+                let vendor_status = skipped_status(unavailable_status);
+                
+                // Still write the vendor with minimal information, unless configured not to
+                let mut vendor = Vendor::stub(code, batch_number, self.run_id, &vendor_status);
+                vendor.attempts = attempts as i32;
+                vendor.extraction_started_at = extraction_started_at;
+                vendor.extraction_completed_at = extraction_completed_at;
+                vendor.extraction_duration_ms = (extraction_completed_at - extraction_started_at).num_milliseconds();
+                
+                let write = match self.skipped_vendor_output {
+                    SkippedVendorOutput::Inline => Some(writers.write_vendor(vendor).await),
+                    SkippedVendorOutput::Separate => Some(writers.write_skipped_vendor(vendor).await),
+                    SkippedVendorOutput::Drop => None,
+                };
+                let written = write.is_some();
+                write.transpose().inspect_err(|e| error!(
+                    error = %e,
+                    vendor_code = code,
+                    "Error writing vendor to file"
+                ))?;
+                Ok(VendorOutcome::Skipped { status: vendor_status, written })
+            },
+            Err(e) => {
+                error!(
+                    error = %e,
+                    vendor_code = code,
+                    batch_number = batch_number,
+                    total_batches = total_batches,
+                    vendor_index = index + 1,
+                    vendors_count = vendors_count,
+                    "Failed to fetch vendor details, leaving vendor out"
+                );
+                Err(e)
             }
         }
+    }
+}
 
-        Ok(())
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use crate::services::testing::{self, FakeDetails, FakeVendorApi, FAILED_ATTEMPTS};
+    use crate::storage::RecordStream;
+
+    fn service(api: Arc<FakeVendorApi>) -> VendorService {
+        let settings = testing::settings();
+        VendorService::new(api, &settings, SharedThrottling::new(settings.throttling.clone(), &settings.concurrency))
+    }
+
+    fn codes(codes: &[&str]) -> Vec<String> {
+        codes.iter().map(|code| code.to_string()).collect()
+    }
+
+    #[tokio::test]
+    async fn batch_counts_found_unavailable_and_failed_vendors() {
+        let api = Arc::new(FakeVendorApi::new(&[])
+            .with_details("gone", FakeDetails::Unavailable(404))
+            .with_details("broken", FakeDetails::Fails));
+        let dir = tempfile::tempdir().unwrap();
+        let writers = testing::city_writers(dir.path()).await;
+
+        let batch = service(api)
+            .process_vendor_batch(codes(&["found", "gone", "broken"]), &writers, 1, 1)
+            .await
+            .unwrap();
+
+        assert_eq!(batch.succeeded, 1);
+        assert_eq!(batch.skipped, 1);
+        assert_eq!(batch.skipped_by_status.get("skipped_404"), Some(&1));
+        assert_eq!(batch.failed.len(), 1);
+        assert_eq!(batch.failed[0].code, "broken");
+        assert_eq!(batch.failed[0].attempts, FAILED_ATTEMPTS);
+
+        let output = writers.finish().await.unwrap();
+        let mut written = testing::vendor_codes(&output.vendors_path);
+        written.sort();
+        assert_eq!(written, ["found", "gone"]);
+    }
+
+    #[tokio::test]
+    async fn failed_write_fails_the_vendor() {
+        let menu = json!({
+            "menus": [{
+                "id": 1,
+                "menu_categories": [{
+                    "name": "Mains",
+                    "products": [{ "id": 10, "name": "Biryani" }, { "id": 11, "name": "Karahi" }],
+                }],
+            }],
+        });
+        let details = VendorDetails {
+            name: "Found".to_string(),
+            extra: menu.as_object().unwrap().clone(),
+            ..VendorDetails::default()
+        };
+        let api = Arc::new(FakeVendorApi::new(&[])
+            .with_details("found", FakeDetails::Found(details))
+            .with_details("gone", FakeDetails::Unavailable(410)));
+        let dir = tempfile::tempdir().unwrap();
+        // Both vendor records are refused once; the menu items go through
+        let writers = testing::city_writers_failing_vendors(dir.path(), 2).await;
+        let service = service(api);
+
+        let batch = service
+            .process_vendor_batch(codes(&["found", "gone"]), &writers, 1, 1)
+            .await
+            .unwrap();
+
+        assert_eq!(batch.succeeded, 0);
+        assert_eq!(batch.skipped, 0);
+        let mut failed: Vec<&str> = batch.failed.iter().map(|vendor| vendor.code.as_str()).collect();
+        failed.sort();
+        assert_eq!(failed, ["found", "gone"]);
+
+        let mut extraction = service
+            .start_city(&ListingQuery::City("1".to_string()), None)
+            .await
+            .unwrap()
+            .extraction;
+        extraction.add_batch(batch);
+        service.retry_failed_vendors(&mut extraction, &writers).await.unwrap();
+
+        assert!(extraction.failed.is_empty());
+        assert_eq!(extraction.succeeded, 1);
+        assert_eq!(extraction.skipped, 1);
+        let output = writers.finish().await.unwrap();
+        let mut written = testing::vendor_codes(&output.vendors_path);
+        written.sort();
+        assert_eq!(written, ["found", "gone"]);
+        let menu_items: Vec<String> = RecordStream::<MenuItem>::open(&output.menu_items_path)
+            .unwrap()
+            .map(|item| item.unwrap().name)
+            .collect();
+        assert_eq!(menu_items, ["Biryani", "Karahi"]);
+    }
+
+    #[tokio::test]
+    async fn retry_pass_makes_one_attempt_per_failed_vendor() {
+        let api = Arc::new(FakeVendorApi::new(&[]).with_details("broken", FakeDetails::Fails));
+        let dir = tempfile::tempdir().unwrap();
+        let writers = testing::city_writers(dir.path()).await;
+        let service = service(api.clone());
+
+        let batch = service
+            .process_vendor_batch(codes(&["found", "broken"]), &writers, 1, 1)
+            .await
+            .unwrap();
+        let mut extraction = service
+            .start_city(&ListingQuery::City("1".to_string()), None)
+            .await
+            .unwrap()
+            .extraction;
+        extraction.add_batch(batch);
+        service.retry_failed_vendors(&mut extraction, &writers).await.unwrap();
+
+        assert_eq!(extraction.succeeded, 1);
+        assert_eq!(extraction.failed.len(), 1);
+        assert_eq!(extraction.failed[0].code, "broken");
+        // The batch's details request and the retry pass's one
+        assert_eq!(api.details_requests("broken"), 2);
+        assert_eq!(extraction.failed[0].attempts, 2 * FAILED_ATTEMPTS);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn vendor_past_its_deadline_fails() {
+        let api = Arc::new(FakeVendorApi::new(&[])
+            .with_details("stuck", FakeDetails::Hangs)
+            .with_reviews_delay("slow", Duration::from_secs(600)));
+        let dir = tempfile::tempdir().unwrap();
+        let writers = testing::city_writers(dir.path()).await;
+        let service = service(api.clone());
+        let deadline = Error::Deadline(120).to_string();
+
+        let batch = service
+            .process_vendor_batch(codes(&["found", "stuck", "slow"]), &writers, 1, 1)
+            .await
+            .unwrap();
+
+        assert_eq!(batch.succeeded, 1);
+        let mut failed: Vec<(&str, &str)> = batch.failed.iter()
+            .map(|vendor| (vendor.code.as_str(), vendor.error.as_str()))
+            .collect();
+        failed.sort();
+        assert_eq!(failed, [("slow", deadline.as_str()), ("stuck", deadline.as_str())]);
+
+        // Both go through the retry pass and are left for the dead-letter file
+        let mut extraction = service
+            .start_city(&ListingQuery::City("1".to_string()), None)
+            .await
+            .unwrap()
+            .extraction;
+        extraction.add_batch(batch);
+        service.retry_failed_vendors(&mut extraction, &writers).await.unwrap();
+
+        assert_eq!(extraction.failed.len(), 2);
+        assert!(extraction.failed.iter().all(|vendor| vendor.error == deadline));
+        assert_eq!(api.details_requests("stuck"), 2);
+        let output = writers.finish().await.unwrap();
+        assert_eq!(testing::vendor_codes(&output.vendors_path), ["found"]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn zero_vendor_deadline_waits_for_slow_vendors() {
+        let api = Arc::new(FakeVendorApi::new(&[]).with_reviews_delay("slow", Duration::from_secs(600)));
+        let dir = tempfile::tempdir().unwrap();
+        let writers = testing::city_writers(dir.path()).await;
+        let mut settings = testing::settings();
+        settings.extraction.vendor_deadline_secs = 0;
+        let service = VendorService::new(api, &settings, SharedThrottling::new(settings.throttling.clone(), &settings.concurrency));
+
+        let batch = service
+            .process_vendor_batch(codes(&["slow"]), &writers, 1, 1)
+            .await
+            .unwrap();
+
+        assert_eq!(batch.succeeded, 1);
+        assert!(batch.failed.is_empty());
+    }
+
+    #[tokio::test]
+    async fn listing_requests_each_offset_once() {
+        let api = Arc::new(FakeVendorApi::with_listing_of(100));
+        let dir = tempfile::tempdir().unwrap();
+        let writers = testing::city_writers(dir.path()).await;
+
+        let extraction = service(api.clone())
+            .extract_city(&ListingQuery::City("1".to_string()), None, &writers, None)
+            .await
+            .unwrap();
+
+        assert_eq!(api.listing_offsets(), [0, 48, 96]);
+        assert_eq!(extraction.pages, 3);
+        assert_eq!(extraction.succeeded, 100);
+        assert_eq!(extraction.duplicates, 0);
+        let output = writers.finish().await.unwrap();
+        assert_eq!(testing::vendor_codes(&output.vendors_path).len(), 100);
+    }
+
+    #[tokio::test]
+    async fn resumed_city_continues_from_its_checkpoint() {
+        let output_dir = tempfile::tempdir().unwrap();
+        let run_id = Uuid::new_v4();
+        // Page 3 of the first run comes back empty, ending it after batch 2
+        let killed = Arc::new(FakeVendorApi::with_listing_of(96).with_available_count(144));
+        let dir = tempfile::tempdir().unwrap();
+        let writers = testing::city_writers(dir.path()).await;
+        service(killed)
+            .with_checkpoints(output_dir.path())
+            .with_run_id(run_id)
+            .extract_city(&ListingQuery::City("1".to_string()), None, &writers, None)
+            .await
+            .unwrap();
+        writers.finish().await.unwrap();
+
+        let checkpoint = CityCheckpoint::latest(output_dir.path(), "1").unwrap().unwrap();
+        assert_eq!(checkpoint.run_id, run_id);
+        assert_eq!(checkpoint.last_page, 2);
+        assert_eq!(checkpoint.offset, 96);
+        assert_eq!(checkpoint.seen.len(), 96);
+
+        // The resumed listing repeats v5 on the page after the checkpoint
+        let mut listing: Vec<String> = (0..96).map(|index| format!("v{}", index)).collect();
+        listing.push("v5".to_string());
+        listing.extend((96..143).map(|index| format!("v{}", index)));
+        let listing: Vec<&str> = listing.iter().map(String::as_str).collect();
+        let api = Arc::new(FakeVendorApi::new(&listing));
+        let dir = tempfile::tempdir().unwrap();
+        let writers = testing::city_writers(dir.path()).await;
+        let extraction = service(api.clone())
+            .with_checkpoints(output_dir.path())
+            .with_run_id(Uuid::new_v4())
+            .extract_city(&ListingQuery::City("1".to_string()), None, &writers, Some(&checkpoint))
+            .await
+            .unwrap();
+
+        assert_eq!(api.listing_offsets(), [0, checkpoint.offset]);
+        assert_eq!(api.details_requests("v0"), 0);
+        assert_eq!(api.details_requests("v5"), 0);
+        assert_eq!(api.details_requests("v96"), 1);
+        assert_eq!(extraction.pages, 3);
+        assert_eq!(extraction.succeeded, 143);
+        assert_eq!(extraction.duplicates, 1);
+        assert!(extraction.failed.is_empty());
+        assert!(!extraction.partial_listing);
+        let output = writers.finish().await.unwrap();
+        assert_eq!(testing::vendor_codes(&output.vendors_path).len(), 47);
     }
 }
@@ -0,0 +1,114 @@
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use serde::Serialize;
+
+/// API endpoints the extractor talks to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Endpoint {
+    Listing,
+    Details,
+    Ratings,
+    Reviews,
+    Delivery,
+}
+
+impl Endpoint {
+    pub const ALL: [Endpoint; 5] = [
+        Endpoint::Listing,
+        Endpoint::Details,
+        Endpoint::Ratings,
+        Endpoint::Reviews,
+        Endpoint::Delivery,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Endpoint::Listing => "listing",
+            Endpoint::Details => "details",
+            Endpoint::Ratings => "ratings",
+            Endpoint::Reviews => "reviews",
+            Endpoint::Delivery => "delivery",
+        }
+    }
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// Process-wide counters shared by every client in the pool.
+#[derive(Debug, Default)]
+pub struct StatsRegistry {
+    bytes: [AtomicU64; Endpoint::ALL.len()],
+    requests: [AtomicU64; Endpoint::ALL.len()],
+}
+
+impl StatsRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Counts response body bytes, including those of retried and failed requests.
+    pub fn record_bytes(&self, endpoint: Endpoint, bytes: u64) {
+        self.bytes[endpoint.index()].fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    pub fn bytes(&self, endpoint: Endpoint) -> u64 {
+        self.bytes[endpoint.index()].load(Ordering::Relaxed)
+    }
+
+    pub fn total_bytes(&self) -> u64 {
+        Endpoint::ALL.iter().map(|e| self.bytes(*e)).sum()
+    }
+
+    /// Counts a request attempt (or, in a dry run, a request that was skipped).
+    pub fn record_request(&self, endpoint: Endpoint) {
+        self.requests[endpoint.index()].fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn requests(&self, endpoint: Endpoint) -> u64 {
+        self.requests[endpoint.index()].load(Ordering::Relaxed)
+    }
+
+    pub fn snapshot(&self) -> TrafficSnapshot {
+        TrafficSnapshot {
+            bytes: Endpoint::ALL.iter().map(|e| (*e, self.bytes(*e))).collect(),
+        }
+    }
+}
+
+/// Point-in-time copy of the byte counters; per-city figures are the
+/// difference between snapshots taken around the city.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct TrafficSnapshot {
+    pub bytes: BTreeMap<Endpoint, u64>,
+}
+
+impl TrafficSnapshot {
+    pub fn since(&self, earlier: &TrafficSnapshot) -> TrafficSnapshot {
+        TrafficSnapshot {
+            bytes: self.bytes.iter()
+                .map(|(endpoint, bytes)| {
+                    let before = earlier.bytes.get(endpoint).copied().unwrap_or(0);
+                    (*endpoint, bytes.saturating_sub(before))
+                })
+                .collect(),
+        }
+    }
+
+    pub fn total_bytes(&self) -> u64 {
+        self.bytes.values().sum()
+    }
+
+    pub fn bytes_per_vendor(&self, vendors: usize) -> u64 {
+        if vendors == 0 {
+            return 0;
+        }
+        self.total_bytes() / vendors as u64
+    }
+
+    pub fn by_endpoint(&self) -> BTreeMap<&'static str, u64> {
+        self.bytes.iter().map(|(e, b)| (e.as_str(), *b)).collect()
+    }
+}
@@ -0,0 +1,24 @@
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use serde::Serialize;
+use crate::error::Result;
+
+/// Writes `value` as pretty JSON to a sibling temp file and renames it over
+/// `path`, so readers never observe a half-written file.
+pub fn write_json_atomic<T: Serialize>(path: &Path, value: &T) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let mut tmp_name = path.as_os_str().to_owned();
+    tmp_name.push(".tmp");
+    let tmp_path = Path::new(&tmp_name);
+
+    let mut file = fs::File::create(tmp_path)?;
+    file.write_all(&serde_json::to_vec_pretty(value)?)?;
+    file.sync_all()?;
+    fs::rename(tmp_path, path)?;
+
+    Ok(())
+}
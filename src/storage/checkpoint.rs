@@ -0,0 +1,153 @@
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use crate::error::Result;
+use crate::services::vendor::CityExtraction;
+use crate::storage::atomic::write_json_atomic;
+use crate::storage::json::CityCheckpointFiles;
+use crate::utils::file_safe_name;
+
+/// Where a city's extraction stood after its last completed batch, persisted
+/// so a killed run can continue the city instead of starting from page zero.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CityCheckpoint {
+    pub city_id: String,
+    pub run_id: Uuid,
+    /// Last listing page whose batch was written.
+    pub last_page: i32,
+    /// Listing offset of the page after it.
+    pub offset: i32,
+    pub vendors_processed: usize,
+    /// Counts and failures of the completed batches, and the listing's
+    /// counters up to `last_page`.
+    pub extraction: CityExtraction,
+    /// Codes listed up to `last_page`, so the resumed listing still drops
+    /// vendors repeated from them.
+    #[serde(default)]
+    pub seen: BTreeSet<String>,
+    pub files: CityCheckpointFiles,
+    #[serde(with = "chrono::serde::ts_seconds")]
+    pub updated_at: DateTime<Utc>,
+}
+
+impl CityCheckpoint {
+    pub fn dir(output_dir: &Path) -> PathBuf {
+        output_dir.join("checkpoints")
+    }
+
+    pub fn path(output_dir: &Path, city_id: &str, run_id: Uuid) -> PathBuf {
+        Self::dir(output_dir).join(format!("{}_{}.json", file_safe_name(city_id), run_id))
+    }
+
+    pub fn store(&mut self, output_dir: &Path) -> Result<()> {
+        self.updated_at = Utc::now();
+        write_json_atomic(&Self::path(output_dir, &self.city_id, self.run_id), self)
+    }
+
+    /// The city's most recent checkpoint, whichever run wrote it.
+    pub fn latest(output_dir: &Path, city_id: &str) -> Result<Option<Self>> {
+        let mut latest: Option<Self> = None;
+        for path in Self::paths(output_dir, city_id)? {
+            let checkpoint: Self = serde_json::from_slice(&fs::read(&path)?)?;
+            if checkpoint.city_id == city_id
+                && latest.as_ref().is_none_or(|latest| checkpoint.updated_at > latest.updated_at)
+            {
+                latest = Some(checkpoint);
+            }
+        }
+        Ok(latest)
+    }
+
+    /// Removes every checkpoint of the city, once its upload has succeeded.
+    pub fn remove_all(output_dir: &Path, city_id: &str) -> Result<()> {
+        for path in Self::paths(output_dir, city_id)? {
+            fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+
+    /// Checkpoint files named after the city; run ids have no underscores, so
+    /// `{city}_{run_id}.json` can't be mistaken for another city's.
+    fn paths(output_dir: &Path, city_id: &str) -> Result<Vec<PathBuf>> {
+        let dir = Self::dir(output_dir);
+        if !dir.exists() {
+            return Ok(Vec::new());
+        }
+        let prefix = format!("{}_", file_safe_name(city_id));
+        let mut paths = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            let matches = path.file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| name.strip_prefix(&prefix))
+                .and_then(|rest| rest.strip_suffix(".json"))
+                .is_some_and(|run_id| Uuid::parse_str(run_id).is_ok());
+            if matches {
+                paths.push(path);
+            }
+        }
+        Ok(paths)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use crate::config::ListingQuery;
+    use crate::services::testing::{self, FakeVendorApi};
+    use crate::services::vendor::VendorService;
+    use crate::utils::throttle::SharedThrottling;
+
+    async fn checkpoint(city_id: &str, run_id: Uuid) -> CityCheckpoint {
+        let settings = testing::settings();
+        let service = VendorService::new(
+            Arc::new(FakeVendorApi::with_listing_of(3)),
+            &settings,
+            SharedThrottling::new(settings.throttling.clone(), &settings.concurrency),
+        );
+        let cursor = service.start_city(&ListingQuery::City(city_id.to_string()), None).await.unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let writers = testing::city_writers(dir.path()).await;
+        CityCheckpoint {
+            city_id: city_id.to_string(),
+            run_id,
+            last_page: 1,
+            offset: 48,
+            vendors_processed: 3,
+            extraction: cursor.extraction,
+            seen: BTreeSet::new(),
+            files: writers.checkpoint().await.unwrap(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    #[tokio::test]
+    async fn latest_picks_the_newest_run_and_remove_all_keeps_other_cities() {
+        let output_dir = tempfile::tempdir().unwrap();
+        let (older_run, newer_run) = (Uuid::new_v4(), Uuid::new_v4());
+
+        // Written directly, as `store` would stamp it with the current time
+        let mut older = checkpoint("1", older_run).await;
+        older.updated_at = Utc::now() - chrono::Duration::hours(1);
+        write_json_atomic(&CityCheckpoint::path(output_dir.path(), "1", older_run), &older).unwrap();
+        checkpoint("1", newer_run).await.store(output_dir.path()).unwrap();
+        checkpoint("10", older_run).await.store(output_dir.path()).unwrap();
+        fs::write(CityCheckpoint::dir(output_dir.path()).join("1_notes.json"), "{}").unwrap();
+
+        let latest = CityCheckpoint::latest(output_dir.path(), "1").unwrap().unwrap();
+        assert_eq!(latest.run_id, newer_run);
+        assert_eq!(latest.city_id, "1");
+
+        CityCheckpoint::remove_all(output_dir.path(), "1").unwrap();
+        assert!(CityCheckpoint::latest(output_dir.path(), "1").unwrap().is_none());
+        assert!(!CityCheckpoint::path(output_dir.path(), "1", older_run).exists());
+        assert!(!CityCheckpoint::path(output_dir.path(), "1", newer_run).exists());
+        let other = CityCheckpoint::latest(output_dir.path(), "10").unwrap().unwrap();
+        assert_eq!(other.run_id, older_run);
+        assert!(CityCheckpoint::dir(output_dir.path()).join("1_notes.json").exists());
+    }
+}
@@ -0,0 +1,51 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use crate::error::Result;
+use crate::storage::atomic::write_json_atomic;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExtractedVendor {
+    #[serde(with = "chrono::serde::ts_seconds")]
+    pub extracted_at: DateTime<Utc>,
+    /// Hash of the vendor's details when they were extracted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_hash: Option<String>,
+}
+
+/// Vendor codes and when their details were last fetched, carried from run to
+/// run so incremental runs can skip vendors extracted recently.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct IncrementalManifest {
+    pub vendors: BTreeMap<String, ExtractedVendor>,
+}
+
+impl IncrementalManifest {
+    pub fn load(path: &Path) -> Result<Option<Self>> {
+        if !path.exists() {
+            return Ok(None);
+        }
+        let bytes = fs::read(path)?;
+        Ok(Some(serde_json::from_slice(&bytes)?))
+    }
+
+    pub fn store(&self, path: &Path) -> Result<()> {
+        write_json_atomic(path, self)
+    }
+
+    /// Whether the vendor's details were fetched less than `max_age` ago.
+    pub fn extracted_within(&self, code: &str, now: DateTime<Utc>, max_age: Duration) -> bool {
+        self.vendors.get(code)
+            .is_some_and(|vendor| now - vendor.extracted_at < max_age)
+    }
+
+    pub fn content_hash(&self, code: &str) -> Option<&str> {
+        self.vendors.get(code)?.content_hash.as_deref()
+    }
+
+    pub fn record(&mut self, code: &str, extracted_at: DateTime<Utc>, content_hash: Option<String>) {
+        self.vendors.insert(code.to_string(), ExtractedVendor { extracted_at, content_hash });
+    }
+}
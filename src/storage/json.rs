@@ -1,59 +1,1020 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::marker::PhantomData;
 use std::sync::atomic::{AtomicUsize, Ordering};
-use std::path::Path;
-use tokio::fs::File as TokioFile;
-use tokio::io::{AsyncWriteExt, BufWriter as TokioBufWriter};
-use crate::error::Result;
-use crate::models::Vendor;
+use std::time::Instant;
+use std::path::{Path, PathBuf};
+use async_compression::tokio::write::GzipEncoder;
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use tokio::sync::{mpsc, oneshot};
+use tokio::fs::{File as TokioFile, OpenOptions as TokioOpenOptions};
+use tokio::io::{AsyncWrite, AsyncWriteExt, BufWriter as TokioBufWriter};
+use tracing::error;
+use crate::config::FlushPolicy;
+use crate::error::{Error, Result};
+use crate::models::{MenuItem, Review, Vendor};
+use crate::utils::serde_helpers::to_canonical_writer;
 
+/// Size of the pieces a record is serialized into before being written out,
+/// so a multi-MB vendor is never grown (and copied) into one contiguous buffer.
+const RECORD_CHUNK_SIZE: usize = 64 * 1024;
+
+#[derive(Debug, Clone, Default)]
+pub struct JsonWriterOptions {
+    /// Write records with sorted object keys (see `utils::serde_helpers`).
+    pub canonical_json: bool,
+    /// Gzip the file and add `.gz` to its name.
+    pub compress: bool,
+    /// Start a new part file once the current one holds this many records.
+    pub max_records_per_file: Option<usize>,
+    /// Start a new part file once the current one holds this many
+    /// (uncompressed) bytes.
+    pub max_bytes_per_file: Option<u64>,
+    /// Ignored when compressing: the gzip stream is flushed when finished.
+    pub flush_policy: FlushPolicy,
+    /// `get_count()` only counts records flushed to disk.
+    pub durable_count: bool,
+}
+
+impl JsonWriterOptions {
+    /// Name the writer gives a file asked for as `filename`.
+    pub fn file_name(&self, filename: &str) -> String {
+        if self.compress {
+            format!("{}.gz", filename)
+        } else {
+            filename.to_string()
+        }
+    }
+}
+
+/// Where a writer stood at a checkpoint: enough to reopen the part it was on
+/// with [`JsonWriter::open_append`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WriterCheckpoint {
+    /// Path of the first part.
+    pub base_path: PathBuf,
+    /// Final path of the part being written.
+    pub path: PathBuf,
+    pub part: u32,
+    pub part_records: usize,
+    /// Uncompressed bytes of the part so far.
+    pub part_bytes: u64,
+    pub records: usize,
+    pub bytes_written: u64,
+    /// Parts finished before this one.
+    pub finished: Vec<PathBuf>,
+}
+
+/// Streams records into a JSON array. The file is written as `<name>.tmp`
+/// next to its final path and only renamed into place by `finish()`, so a
+/// file under its final name is always complete.
+///
+/// With `max_records_per_file` or `max_bytes_per_file` set, a full file is
+/// finished and the records continue in `<name>_part0002.json`,
+/// `<name>_part0003.json` and so on.
 pub struct JsonWriter {
-    writer: TokioBufWriter<TokioFile>,
+    writer: Box<dyn AsyncWrite + Send + Unpin>,
+    /// Path of the first part; later parts are named after it.
+    base_path: PathBuf,
+    path: PathBuf,
+    /// Sibling of `path`, so the rename stays on one filesystem.
+    temp_path: PathBuf,
+    part: u32,
+    part_records: usize,
+    part_bytes: u64,
+    finished: Vec<PathBuf>,
     count: AtomicUsize,
+    /// Records known to be on disk.
+    flushed_count: usize,
+    last_flush: Instant,
+    /// Uncompressed bytes.
+    bytes_written: u64,
     is_first: bool,
+    options: JsonWriterOptions,
 }
 
 impl JsonWriter {
-    pub async fn new(filename: &str) -> Result<Self> {
-        // Get the output directory from environment variable or use a default
-        let output_dir = std::env::var("OUTPUT_DIR").unwrap_or_else(|_| "data".to_string());
-        
+    /// Creates `filename` in `dir`, which is created if needed.
+    pub async fn new(dir: &Path, filename: &str) -> Result<Self> {
+        Self::with_options(dir, filename, JsonWriterOptions::default()).await
+    }
+
+    pub async fn with_options(dir: &Path, filename: &str, options: JsonWriterOptions) -> Result<Self> {
         // Create the output directory if it doesn't exist
-        tokio::fs::create_dir_all(&output_dir).await?;
+        tokio::fs::create_dir_all(dir).await?;
         
         // Combine the directory and filename
-        let path = Path::new(&output_dir).join(filename);
-        let file_path = path.to_str().unwrap();
-        let file = TokioFile::create(file_path).await?;
-        let mut writer = TokioBufWriter::new(file);
-        writer.write_all(b"[\n").await?;
+        let path = dir.join(options.file_name(filename));
+        let temp_path = temp_path_of(&path);
+        let writer = open_array(&temp_path, options.compress).await?;
         
         Ok(Self {
             writer,
+            base_path: path.clone(),
+            path,
+            temp_path,
+            part: 1,
+            part_records: 0,
+            part_bytes: 2,
+            finished: Vec::new(),
             count: AtomicUsize::new(0),
+            flushed_count: 0,
+            last_flush: Instant::now(),
+            bytes_written: 2,
             is_first: true,
+            options,
+        })
+    }
+
+    /// Reopens the part a writer was on at `checkpoint` to append to it. The
+    /// part is cut back to what the checkpoint counted, so records written
+    /// after it aren't kept twice; parts started after it are removed.
+    pub async fn open_append(checkpoint: &WriterCheckpoint, options: JsonWriterOptions) -> Result<Self> {
+        let temp_path = temp_path_of(&checkpoint.path);
+        // Rotated past the checkpoint: the part was already finished
+        if !tokio::fs::try_exists(&temp_path).await? {
+            tokio::fs::rename(&checkpoint.path, &temp_path).await?;
+        }
+        let mut part = checkpoint.part + 1;
+        loop {
+            let later = part_path(&checkpoint.base_path, part);
+            let mut found = false;
+            for path in [temp_path_of(&later), later] {
+                if tokio::fs::try_exists(&path).await? {
+                    tokio::fs::remove_file(&path).await?;
+                    found = true;
+                }
+            }
+            if !found {
+                break;
+            }
+            part += 1;
+        }
+
+        let too_short = || Error::Storage(format!(
+            "{} is shorter than its checkpoint",
+            temp_path.display()
+        ));
+        let writer: Box<dyn AsyncWrite + Send + Unpin> = if options.compress {
+            // A gzip stream can't be cut, so the kept records are compressed again
+            let compressed = tokio::fs::read(&temp_path).await?;
+            let mut content = Vec::new();
+            // A killed run leaves the stream without its trailer; any other
+            // error means the part is corrupt
+            if let Err(e) = GzDecoder::new(compressed.as_slice()).read_to_end(&mut content) {
+                if e.kind() != io::ErrorKind::UnexpectedEof {
+                    return Err(e.into());
+                }
+            }
+            if (content.len() as u64) < checkpoint.part_bytes {
+                return Err(too_short());
+            }
+            content.truncate(checkpoint.part_bytes as usize);
+            let file = TokioBufWriter::new(TokioFile::create(&temp_path).await?);
+            let mut writer = GzipEncoder::new(file);
+            writer.write_all(&content).await?;
+            Box::new(writer)
+        } else {
+            let file = TokioOpenOptions::new().append(true).open(&temp_path).await?;
+            if file.metadata().await?.len() < checkpoint.part_bytes {
+                return Err(too_short());
+            }
+            file.set_len(checkpoint.part_bytes).await?;
+            Box::new(TokioBufWriter::new(file))
+        };
+
+        Ok(Self {
+            writer,
+            base_path: checkpoint.base_path.clone(),
+            path: checkpoint.path.clone(),
+            temp_path,
+            part: checkpoint.part,
+            part_records: checkpoint.part_records,
+            part_bytes: checkpoint.part_bytes,
+            finished: checkpoint.finished.clone(),
+            count: AtomicUsize::new(checkpoint.records),
+            flushed_count: checkpoint.records,
+            last_flush: Instant::now(),
+            bytes_written: checkpoint.bytes_written,
+            is_first: checkpoint.part_records == 0,
+            options,
         })
     }
 
     pub async fn write_vendor(&mut self, vendor: &Vendor) -> Result<()> {
+        self.write_record(vendor).await
+    }
+
+    /// Appends any serializable record to the array.
+    pub async fn write_record<T: Serialize>(&mut self, record: &T) -> Result<()> {
+        // Rotating before the write rather than after it never leaves an empty last part
+        if self.part_full() {
+            self.rotate().await?;
+        }
+        // The separator leads the first chunk, so a write refused outright
+        // leaves the array as it was
+        let mut chunks = RecordChunks::default();
         if !self.is_first {
-            self.writer.write_all(b",\n").await?;
+            chunks.write_all(b",\n")?;
+        }
+        if self.options.canonical_json {
+            to_canonical_writer(&mut chunks, record)?;
+        } else {
+            serde_json::to_writer(&mut chunks, record)?;
+        }
+        let len = chunks.len;
+        // Each chunk is freed once it is written
+        for chunk in chunks.chunks {
+            self.writer.write_all(&chunk).await?;
         }
         self.is_first = false;
-
-        let json = serde_json::to_vec(&vendor)?;
-        self.writer.write_all(&json).await?;
+        self.bytes_written += len;
+        self.part_bytes += len;
+        self.part_records += 1;
         self.count.fetch_add(1, Ordering::SeqCst);
-        self.writer.flush().await?;
+        if self.flush_due() {
+            self.flush().await?;
+        }
         
         Ok(())
     }
 
+    fn flush_due(&self) -> bool {
+        // Flushing a gzip stream often would cost most of its compression
+        if self.options.compress {
+            return false;
+        }
+        match self.options.flush_policy {
+            FlushPolicy::EveryRecord => true,
+            FlushPolicy::EveryNRecords(n) => self.get_written() - self.flushed_count >= n.max(1),
+            FlushPolicy::EveryDuration(secs) => self.last_flush.elapsed().as_secs() >= secs,
+        }
+    }
+
+    async fn flush(&mut self) -> Result<()> {
+        self.writer.flush().await?;
+        self.flushed_count = self.get_written();
+        self.last_flush = Instant::now();
+        Ok(())
+    }
+
+    /// Closes the array and renames the current part into place.
     pub async fn finish(&mut self) -> Result<()> {
         self.writer.write_all(b"\n]").await?;
-        self.writer.flush().await?;
+        self.bytes_written += 2;
+        // Also writes the gzip trailer
+        self.writer.shutdown().await?;
+        self.flushed_count = self.get_written();
+        TokioFile::open(&self.temp_path).await?.sync_all().await?;
+        tokio::fs::rename(&self.temp_path, &self.path).await?;
+        self.finished.push(self.path.clone());
         Ok(())
     }
 
+    /// Flushes what was written and returns where the writer stands.
+    pub async fn checkpoint(&mut self) -> Result<WriterCheckpoint> {
+        self.flush().await?;
+        Ok(WriterCheckpoint {
+            base_path: self.base_path.clone(),
+            path: self.path.clone(),
+            part: self.part,
+            part_records: self.part_records,
+            part_bytes: self.part_bytes,
+            records: self.get_written(),
+            bytes_written: self.bytes_written,
+            finished: self.finished.clone(),
+        })
+    }
+
+    fn part_full(&self) -> bool {
+        self.options.max_records_per_file.is_some_and(|max| self.part_records >= max)
+            || self.options.max_bytes_per_file.is_some_and(|max| self.part_bytes >= max)
+    }
+
+    /// Finishes the current part and continues in the next one.
+    async fn rotate(&mut self) -> Result<()> {
+        self.finish().await?;
+        self.part += 1;
+        self.path = part_path(&self.base_path, self.part);
+        self.temp_path = temp_path_of(&self.path);
+        self.writer = open_array(&self.temp_path, self.options.compress).await?;
+        self.bytes_written += 2;
+        self.part_records = 0;
+        self.part_bytes = 2;
+        self.is_first = true;
+        Ok(())
+    }
+
+    /// Gives up on the file: the temp file is removed and nothing appears
+    /// under the final name. Parts already finished are kept.
+    pub async fn abort(self) -> Result<()> {
+        drop(self.writer);
+        match tokio::fs::remove_file(&self.temp_path).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Flushes what was written and closes the temp file without finishing
+    /// the array, leaving it for [`repair`](Self::repair).
+    pub async fn suspend(mut self) -> Result<()> {
+        // Flushes whatever the policy held back
+        self.writer.shutdown().await?;
+        Ok(())
+    }
+
+    /// Recovers the temp file of a writer that never finished: records are
+    /// kept up to the first one that doesn't parse (cut short by the crash),
+    /// the array is closed and the file is renamed to its final name, which
+    /// is returned with the number of records kept. Compressed files are
+    /// decompressed as far as they go.
+    pub fn repair(temp_path: &Path) -> Result<(PathBuf, usize)> {
+        let path = temp_path.with_extension("");
+        let compressed = is_gzip(&path);
+
+        let mut content = Vec::new();
+        if compressed {
+            // A truncated gzip stream still yields everything before the cut
+            let _ = GzDecoder::new(File::open(temp_path)?).read_to_end(&mut content);
+        } else {
+            File::open(temp_path)?.read_to_end(&mut content)?;
+        }
+
+        // One record per line after the opening `[`, each but the last followed by `,`
+        let mut records: Vec<&[u8]> = Vec::new();
+        for line in content.split(|&byte| byte == b'\n').skip(1) {
+            let record = line.strip_suffix(b",").unwrap_or(line);
+            if record == b"]" || serde_json::from_slice::<serde::de::IgnoredAny>(record).is_err() {
+                break;
+            }
+            records.push(record);
+        }
+
+        let mut repaired = b"[\n".to_vec();
+        repaired.extend(records.join(&b",\n"[..]));
+        repaired.extend_from_slice(b"\n]");
+
+        let mut file = File::create(temp_path)?;
+        if compressed {
+            let mut encoder = GzEncoder::new(&mut file, Compression::default());
+            encoder.write_all(&repaired)?;
+            encoder.finish()?;
+        } else {
+            file.write_all(&repaired)?;
+        }
+        file.sync_all()?;
+        fs::rename(temp_path, &path)?;
+
+        Ok((path, records.len()))
+    }
+
+    /// Final path of the current part, which only exists once it is finished.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Parts finished so far, in order; the last part is added by `finish()`.
+    pub fn finished_files(&self) -> &[PathBuf] {
+        &self.finished
+    }
+
+    /// Records written, or only those flushed to disk with `durable_count`.
     pub fn get_count(&self) -> usize {
+        if self.options.durable_count {
+            self.flushed_count
+        } else {
+            self.get_written()
+        }
+    }
+
+    fn get_written(&self) -> usize {
         self.count.load(Ordering::SeqCst)
     }
-}
\ No newline at end of file
+
+    pub fn bytes_written(&self) -> u64 {
+        self.bytes_written
+    }
+}
+
+/// A serialized record, held in chunks of `RECORD_CHUNK_SIZE` bytes.
+#[derive(Default)]
+struct RecordChunks {
+    chunks: Vec<Vec<u8>>,
+    len: u64,
+}
+
+impl Write for RecordChunks {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut rest = buf;
+        while !rest.is_empty() {
+            match self.chunks.last_mut() {
+                Some(chunk) if chunk.len() < RECORD_CHUNK_SIZE => {
+                    let taken = rest.len().min(RECORD_CHUNK_SIZE - chunk.len());
+                    chunk.extend_from_slice(&rest[..taken]);
+                    rest = &rest[taken..];
+                }
+                _ => self.chunks.push(Vec::with_capacity(RECORD_CHUNK_SIZE)),
+            }
+        }
+        self.len += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+impl JsonWriter {
+    /// Makes the next `count` records fail to write, as on a full disk.
+    pub fn fail_next_writes(&mut self, count: usize) {
+        let writer = std::mem::replace(&mut self.writer, Box::new(tokio::io::sink()));
+        self.writer = Box::new(FailingWrites { writer, remaining: count });
+    }
+}
+
+/// Fails the first `remaining` writes before passing the rest through.
+#[cfg(test)]
+struct FailingWrites {
+    writer: Box<dyn AsyncWrite + Send + Unpin>,
+    remaining: usize,
+}
+
+#[cfg(test)]
+impl AsyncWrite for FailingWrites {
+    fn poll_write(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        buf: &[u8],
+    ) -> std::task::Poll<io::Result<usize>> {
+        if self.remaining > 0 {
+            self.remaining -= 1;
+            return std::task::Poll::Ready(Err(io::Error::other("injected write failure")));
+        }
+        std::pin::Pin::new(&mut self.writer).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: std::pin::Pin<&mut Self>, cx: &mut std::task::Context<'_>) -> std::task::Poll<io::Result<()>> {
+        std::pin::Pin::new(&mut self.writer).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: std::pin::Pin<&mut Self>, cx: &mut std::task::Context<'_>) -> std::task::Poll<io::Result<()>> {
+        std::pin::Pin::new(&mut self.writer).poll_shutdown(cx)
+    }
+}
+
+fn temp_path_of(path: &Path) -> PathBuf {
+    let mut temp_name = path.as_os_str().to_owned();
+    temp_name.push(".tmp");
+    PathBuf::from(temp_name)
+}
+
+/// Path of part `part` of the file whose first part is `path`.
+fn part_path(path: &Path, part: u32) -> PathBuf {
+    let mut name = replace_json_extension(path, "").into_os_string();
+    name.push(format!("_part{:04}.json", part));
+    if is_gzip(path) {
+        name.push(".gz");
+    }
+    PathBuf::from(name)
+}
+
+/// Creates `temp_path` and opens the array in it.
+async fn open_array(temp_path: &Path, compress: bool) -> Result<Box<dyn AsyncWrite + Send + Unpin>> {
+    let file = TokioBufWriter::new(TokioFile::create(temp_path).await?);
+    let mut writer: Box<dyn AsyncWrite + Send + Unpin> = if compress {
+        Box::new(GzipEncoder::new(file))
+    } else {
+        Box::new(file)
+    };
+    writer.write_all(b"[\n").await?;
+    Ok(writer)
+}
+
+fn is_gzip(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "gz")
+}
+
+/// Opens a JSON output file for reading, decompressing `.json.gz` files.
+pub fn open_json(path: &Path) -> Result<Box<dyn Read>> {
+    let file = BufReader::new(File::open(path)?);
+    if is_gzip(path) {
+        Ok(Box::new(BufReader::new(GzDecoder::new(file))))
+    } else {
+        Ok(Box::new(file))
+    }
+}
+
+/// Reads the records of a JSON output file one at a time instead of
+/// deserializing the whole file. Takes the array layout [`JsonWriter`] writes
+/// (one record per line) or NDJSON. A record that doesn't parse, or an array
+/// cut off before its `]`, is an error giving its (uncompressed) byte offset.
+pub struct RecordStream<T> {
+    reader: Box<dyn BufRead>,
+    line: Vec<u8>,
+    /// Bytes read so far.
+    offset: u64,
+    /// `None` until the first line tells the format apart.
+    array: Option<bool>,
+    closed: bool,
+    done: bool,
+    record: PhantomData<T>,
+}
+
+pub type VendorStream = RecordStream<Vendor>;
+
+impl<T: DeserializeOwned> RecordStream<T> {
+    pub fn open(path: &Path) -> Result<Self> {
+        Ok(Self::new(Box::new(BufReader::new(open_json(path)?))))
+    }
+
+    pub fn new(reader: Box<dyn BufRead>) -> Self {
+        Self {
+            reader,
+            line: Vec::new(),
+            offset: 0,
+            array: None,
+            closed: false,
+            done: false,
+            record: PhantomData,
+        }
+    }
+}
+
+fn malformed<T>(offset: u64, message: &str) -> Option<Result<T>> {
+    Some(Err(Error::MalformedRecord { offset, message: message.to_string() }))
+}
+
+impl<T: DeserializeOwned> Iterator for RecordStream<T> {
+    type Item = Result<T>;
+
+    fn next(&mut self) -> Option<Result<T>> {
+        while !self.done {
+            self.line.clear();
+            let read = match self.reader.read_until(b'\n', &mut self.line) {
+                Ok(read) => read,
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(e.into()));
+                }
+            };
+            let start = self.offset;
+            self.offset += read as u64;
+            if read == 0 {
+                self.done = true;
+                if self.array == Some(true) && !self.closed {
+                    return malformed(start, "array ends without its closing `]`");
+                }
+                return None;
+            }
+
+            let record = self.line.trim_ascii();
+            if record.is_empty() {
+                continue;
+            }
+            let start = start + (self.line.len() - self.line.trim_ascii_start().len()) as u64;
+            if self.closed {
+                self.done = true;
+                return malformed(start, "data after the end of the array");
+            }
+            let record = match self.array {
+                None if record == b"[" => {
+                    self.array = Some(true);
+                    continue;
+                }
+                None if record == b"[]" => {
+                    self.array = Some(true);
+                    self.closed = true;
+                    continue;
+                }
+                Some(true) if record == b"]" => {
+                    self.closed = true;
+                    continue;
+                }
+                Some(true) => record.strip_suffix(b",").unwrap_or(record),
+                _ => {
+                    self.array = Some(false);
+                    record
+                }
+            };
+            return match serde_json::from_slice(record) {
+                Ok(record) => Some(Ok(record)),
+                Err(e) => {
+                    self.done = true;
+                    malformed(start, &e.to_string())
+                }
+            };
+        }
+        None
+    }
+}
+
+/// `path` with its `.json` or `.json.gz` extension replaced by `extension`.
+pub fn replace_json_extension(path: &Path, extension: &str) -> PathBuf {
+    let path = if is_gzip(path) {
+        path.with_extension("")
+    } else {
+        path.to_path_buf()
+    };
+    path.with_extension(extension)
+}
+
+/// Where the city writer task sends the outcome of a command.
+type Reply<T> = oneshot::Sender<Result<T>>;
+
+/// What the city writer task is asked to do.
+enum WriteCommand {
+    Vendor(Box<Vendor>, Reply<()>),
+    SkippedVendor(Box<Vendor>, Reply<()>),
+    MenuItems(String, Vec<MenuItem>, Reply<()>),
+    Reviews(String, Vec<Review>, Reply<()>),
+    Finish(Reply<CityOutput>),
+    Abort(Reply<()>),
+    Suspend(Reply<()>),
+    Checkpoint(Reply<CityCheckpointFiles>),
+}
+
+/// What a city's writer task wrote, once it has finished.
+#[derive(Debug, Clone)]
+pub struct CityOutput {
+    pub vendors: usize,
+    /// Across the vendor, menu item and review files.
+    pub bytes_written: u64,
+    /// Last part of each file; earlier parts were reported as [`CompletedPart`]s.
+    pub vendors_path: PathBuf,
+    pub menu_items_path: PathBuf,
+    pub reviews_path: PathBuf,
+    /// Stubs of skipped vendors, when they have their own file.
+    pub skipped_vendors_path: Option<PathBuf>,
+}
+
+/// Where each of a city's files stood at a checkpoint.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CityCheckpointFiles {
+    pub vendors: WriterCheckpoint,
+    pub menu_items: WriterCheckpoint,
+    pub reviews: WriterCheckpoint,
+    #[serde(default)]
+    pub skipped_vendors: Option<WriterCheckpoint>,
+}
+
+/// A part of one of a city's files, finished by rotation while the city is
+/// still being extracted.
+#[derive(Debug, Clone)]
+pub struct CompletedPart {
+    pub city_id: String,
+    /// `vendors`, `menu_items` or `reviews`.
+    pub dataset: &'static str,
+    pub path: PathBuf,
+}
+
+/// The JSON files a city's records are streamed into. A single writer task
+/// owns the files and the vendor workers hand it records over a bounded
+/// channel, so a slow disk holds them back rather than queueing records
+/// without limit. Each write waits for the task to apply it and returns its
+/// error; a failed write doesn't stop the task.
+///
+/// The task remembers how many of each vendor's menu items and reviews it has
+/// written, so a vendor retried after a failed write only adds the rest.
+#[derive(Clone)]
+pub struct CityWriters {
+    commands: mpsc::Sender<WriteCommand>,
+}
+
+impl CityWriters {
+    /// Starts the writer task; `capacity` is the number of pending writes
+    /// the channel holds. Parts finished by rotation before the city is are
+    /// sent to `parts`, so they can be handled while extraction goes on;
+    /// those a reopened writer had finished before are not sent again.
+    /// Stubs of skipped vendors go to `skipped` when given; it isn't rotated.
+    pub fn new(
+        city_id: &str,
+        vendors: JsonWriter,
+        menu_items: JsonWriter,
+        reviews: JsonWriter,
+        skipped: Option<JsonWriter>,
+        capacity: usize,
+        parts: mpsc::UnboundedSender<CompletedPart>,
+    ) -> Self {
+        let (commands, receiver) = mpsc::channel(capacity.max(1));
+        let reported = [files_len(&vendors), files_len(&menu_items), files_len(&reviews)];
+        let files = CityFiles {
+            city_id: city_id.to_string(),
+            vendors,
+            menu_items,
+            reviews,
+            skipped,
+            menu_items_written: HashMap::new(),
+            reviews_written: HashMap::new(),
+            reported,
+            parts,
+        };
+        tokio::spawn(run_city_writer(files, receiver));
+        Self { commands }
+    }
+
+    pub async fn write_vendor(&self, vendor: Vendor) -> Result<()> {
+        self.request(|reply| WriteCommand::Vendor(Box::new(vendor), reply)).await
+    }
+
+    /// Queues the stub of a vendor without details for the skipped vendors
+    /// file, or the vendors file when the city has none.
+    pub async fn write_skipped_vendor(&self, vendor: Vendor) -> Result<()> {
+        self.request(|reply| WriteCommand::SkippedVendor(Box::new(vendor), reply)).await
+    }
+
+    /// Queues a vendor's menu items as one write, so they stay together.
+    /// Items already written for the vendor are skipped.
+    pub async fn write_menu_items(&self, items: Vec<MenuItem>) -> Result<()> {
+        let Some(code) = items.first().map(|item| item.vendor_code.clone()) else {
+            return Ok(());
+        };
+        self.request(|reply| WriteCommand::MenuItems(code, items, reply)).await
+    }
+
+    /// Queues a vendor's reviews as one write, so they stay together.
+    /// Reviews already written for the vendor are skipped.
+    pub async fn write_reviews(&self, reviews: Vec<Review>) -> Result<()> {
+        let Some(code) = reviews.first().map(|review| review.vendor_code.clone()) else {
+            return Ok(());
+        };
+        self.request(|reply| WriteCommand::Reviews(code, reviews, reply)).await
+    }
+
+    /// Writes out everything queued so far, closes all files and returns what
+    /// was written. Later writes fail.
+    pub async fn finish(&self) -> Result<CityOutput> {
+        self.request(WriteCommand::Finish).await
+    }
+
+    /// Drops everything written so far; none of the city's files appear.
+    pub async fn abort(&self) -> Result<()> {
+        self.request(WriteCommand::Abort).await
+    }
+
+    /// Writes out what is queued and stops without finishing the files,
+    /// leaving their temp files for [`JsonWriter::repair`].
+    pub async fn suspend(&self) -> Result<()> {
+        self.request(WriteCommand::Suspend).await
+    }
+
+    /// Flushes everything queued so far and returns where the files stand,
+    /// for reopening them with [`JsonWriter::open_append`].
+    pub async fn checkpoint(&self) -> Result<CityCheckpointFiles> {
+        self.request(WriteCommand::Checkpoint).await
+    }
+
+    /// Sends a command and waits for the task's reply.
+    async fn request<T>(&self, command: impl FnOnce(Reply<T>) -> WriteCommand) -> Result<T> {
+        let (reply, response) = oneshot::channel();
+        self.commands.send(command(reply)).await.map_err(|_| writer_stopped())?;
+        response.await.map_err(|_| writer_stopped())?
+    }
+}
+
+fn files_len(writer: &JsonWriter) -> usize {
+    writer.finished_files().len()
+}
+
+fn writer_stopped() -> Error {
+    Error::Storage("City writer has already finished".to_string())
+}
+
+struct CityFiles {
+    city_id: String,
+    vendors: JsonWriter,
+    menu_items: JsonWriter,
+    reviews: JsonWriter,
+    skipped: Option<JsonWriter>,
+    /// Menu items and reviews written so far, per vendor code.
+    menu_items_written: HashMap<String, usize>,
+    reviews_written: HashMap<String, usize>,
+    /// Finished parts already sent, per writer.
+    reported: [usize; 3],
+    parts: mpsc::UnboundedSender<CompletedPart>,
+}
+
+impl CityFiles {
+    /// Sends the parts finished since the last call.
+    fn report_parts(&mut self) {
+        let writers = [
+            ("vendors", &self.vendors),
+            ("menu_items", &self.menu_items),
+            ("reviews", &self.reviews),
+        ];
+        for ((dataset, writer), reported) in writers.into_iter().zip(self.reported.iter_mut()) {
+            for path in &writer.finished_files()[*reported..] {
+                // Nobody listening only means the parts are handled with the city
+                let _ = self.parts.send(CompletedPart {
+                    city_id: self.city_id.clone(),
+                    dataset,
+                    path: path.clone(),
+                });
+            }
+            *reported = writer.finished_files().len();
+        }
+    }
+
+    async fn finish(&mut self) -> Result<CityOutput> {
+        self.menu_items.finish().await?;
+        self.reviews.finish().await?;
+        if let Some(skipped) = &mut self.skipped {
+            skipped.finish().await?;
+        }
+        self.vendors.finish().await?;
+        Ok(CityOutput {
+            vendors: self.vendors.get_count(),
+            bytes_written: self.vendors.bytes_written()
+                + self.menu_items.bytes_written()
+                + self.reviews.bytes_written()
+                + self.skipped.as_ref().map_or(0, JsonWriter::bytes_written),
+            vendors_path: self.vendors.path().to_path_buf(),
+            menu_items_path: self.menu_items.path().to_path_buf(),
+            reviews_path: self.reviews.path().to_path_buf(),
+            skipped_vendors_path: self.skipped.as_ref().map(|skipped| skipped.path().to_path_buf()),
+        })
+    }
+
+    async fn checkpoint(&mut self) -> Result<CityCheckpointFiles> {
+        let skipped_vendors = match &mut self.skipped {
+            Some(skipped) => Some(skipped.checkpoint().await?),
+            None => None,
+        };
+        Ok(CityCheckpointFiles {
+            vendors: self.vendors.checkpoint().await?,
+            menu_items: self.menu_items.checkpoint().await?,
+            reviews: self.reviews.checkpoint().await?,
+            skipped_vendors,
+        })
+    }
+
+    async fn abort(self) -> Result<()> {
+        self.menu_items.abort().await?;
+        self.reviews.abort().await?;
+        if let Some(skipped) = self.skipped {
+            skipped.abort().await?;
+        }
+        self.vendors.abort().await
+    }
+
+    async fn suspend(self) -> Result<()> {
+        self.menu_items.suspend().await?;
+        self.reviews.suspend().await?;
+        if let Some(skipped) = self.skipped {
+            skipped.suspend().await?;
+        }
+        self.vendors.suspend().await
+    }
+}
+
+/// Writes a vendor's records, stopping at the first that fails. The first
+/// `written` are skipped as already in the file, and `written` counts each
+/// record that gets there.
+async fn write_records<T: Serialize>(writer: &mut JsonWriter, records: &[T], written: &mut usize) -> Result<()> {
+    for record in records.iter().skip(*written) {
+        writer.write_record(record).await?;
+        *written += 1;
+    }
+    Ok(())
+}
+
+/// Applies queued writes in order until told to finish, abort or suspend. If
+/// every handle is dropped first, the files are suspended.
+async fn run_city_writer(mut files: CityFiles, mut commands: mpsc::Receiver<WriteCommand>) {
+    while let Some(command) = commands.recv().await {
+        match command {
+            // The caller reports a failed write against its vendor
+            WriteCommand::Vendor(vendor, reply) => {
+                let _ = reply.send(files.vendors.write_vendor(&vendor).await);
+            }
+            WriteCommand::SkippedVendor(vendor, reply) => {
+                let writer = files.skipped.as_mut().unwrap_or(&mut files.vendors);
+                let _ = reply.send(writer.write_vendor(&vendor).await);
+            }
+            WriteCommand::MenuItems(code, items, reply) => {
+                let written = files.menu_items_written.entry(code).or_default();
+                let _ = reply.send(write_records(&mut files.menu_items, &items, written).await);
+            }
+            WriteCommand::Reviews(code, reviews, reply) => {
+                let written = files.reviews_written.entry(code).or_default();
+                let _ = reply.send(write_records(&mut files.reviews, &reviews, written).await);
+            }
+            WriteCommand::Finish(reply) => {
+                let _ = reply.send(files.finish().await);
+                return;
+            }
+            WriteCommand::Abort(reply) => {
+                let _ = reply.send(files.abort().await);
+                return;
+            }
+            WriteCommand::Suspend(reply) => {
+                let _ = reply.send(files.suspend().await);
+                return;
+            }
+            WriteCommand::Checkpoint(reply) => {
+                let _ = reply.send(files.checkpoint().await);
+            }
+        }
+        files.report_parts();
+    }
+    if let Err(e) = files.suspend().await {
+        error!(error = %e, "Error closing unfinished city files");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    fn record(id: usize) -> Value {
+        json!({ "id": id, "name": format!("Vendor {}", id) })
+    }
+
+    fn read_array(path: &Path) -> Vec<Value> {
+        serde_json::from_slice(&fs::read(path).unwrap()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn file_is_written_as_tmp_and_renamed_when_finished() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut writer = JsonWriter::new(dir.path(), "vendors.json").await.unwrap();
+        writer.write_record(&record(1)).await.unwrap();
+        writer.write_record(&record(2)).await.unwrap();
+
+        let path = dir.path().join("vendors.json");
+        let temp_path = dir.path().join("vendors.json.tmp");
+        assert!(temp_path.exists());
+        assert!(!path.exists());
+
+        writer.finish().await.unwrap();
+
+        assert!(!temp_path.exists());
+        assert_eq!(writer.path(), path);
+        assert_eq!(read_array(&path), [record(1), record(2)]);
+    }
+
+    #[tokio::test]
+    async fn full_parts_rotate_into_numbered_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let options = JsonWriterOptions { max_records_per_file: Some(2), ..JsonWriterOptions::default() };
+        let mut writer = JsonWriter::with_options(dir.path(), "vendors.json", options).await.unwrap();
+        for id in 0..5 {
+            writer.write_record(&record(id)).await.unwrap();
+        }
+
+        let parts = [
+            dir.path().join("vendors.json"),
+            dir.path().join("vendors_part0002.json"),
+            dir.path().join("vendors_part0003.json"),
+        ];
+        assert_eq!(writer.finished_files(), &parts[..2]);
+        assert!(dir.path().join("vendors_part0003.json.tmp").exists());
+
+        writer.finish().await.unwrap();
+
+        assert_eq!(writer.finished_files(), parts);
+        assert_eq!(writer.get_count(), 5);
+        assert_eq!(read_array(&parts[0]), [record(0), record(1)]);
+        assert_eq!(read_array(&parts[1]), [record(2), record(3)]);
+        assert_eq!(read_array(&parts[2]), [record(4)]);
+    }
+
+    #[tokio::test]
+    async fn repair_drops_a_truncated_last_record() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut writer = JsonWriter::new(dir.path(), "vendors.json").await.unwrap();
+        for id in 0..3 {
+            writer.write_record(&record(id)).await.unwrap();
+        }
+        writer.suspend().await.unwrap();
+        // A crash in the middle of the fourth record
+        let temp_path = dir.path().join("vendors.json.tmp");
+        let mut file = fs::OpenOptions::new().append(true).open(&temp_path).unwrap();
+        file.write_all(b",\n{\"id\": 3, \"na").unwrap();
+
+        let (path, records) = JsonWriter::repair(&temp_path).unwrap();
+
+        assert_eq!(path, dir.path().join("vendors.json"));
+        assert_eq!(records, 3);
+        assert!(!temp_path.exists());
+        assert_eq!(read_array(&path), [record(0), record(1), record(2)]);
+    }
+
+    #[tokio::test]
+    async fn repair_closes_an_empty_array() {
+        let dir = tempfile::tempdir().unwrap();
+        JsonWriter::new(dir.path(), "vendors.json").await.unwrap().suspend().await.unwrap();
+
+        let (path, records) = JsonWriter::repair(&dir.path().join("vendors.json.tmp")).unwrap();
+
+        assert_eq!(records, 0);
+        assert!(read_array(&path).is_empty());
+    }
+}
@@ -0,0 +1,450 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use crate::config::ThrottlingConfig;
+use tracing::warn;
+use crate::error::Result;
+use crate::models::City;
+use crate::storage::minio::MinioStorage;
+use crate::services::vendor::ExtractionReport;
+use crate::storage::atomic::write_json_atomic;
+use crate::storage::parquet::{schema_change_note, SCHEMA_VERSION};
+use crate::storage::phase::PhaseMarker;
+use crate::storage::quality::NullRates;
+use crate::utils::time::file_safe_timestamp;
+
+/// A file a city produced and the object key it was uploaded to.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ManifestFile {
+    pub dataset: String,
+    pub file_name: String,
+    pub s3_key: String,
+    pub rows: usize,
+    pub bytes: u64,
+    pub sha256: Option<String>,
+    /// Part files compacted into this one, when there were any.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub compacted_from: Vec<String>,
+}
+
+/// What became of a city in a run.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CityStatus {
+    #[default]
+    Uploaded,
+    Failed,
+    /// Skipped because it was uploaded within `extraction.skip_if_fresh_hours`.
+    Fresh,
+}
+
+/// When a city was last uploaded, and the record that says so.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LastUpload {
+    /// Key of the run manifest, or path of the phase marker, it was read from.
+    pub source: String,
+    pub uploaded_at: DateTime<Utc>,
+}
+
+/// Why a city was skipped as fresh.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Freshness {
+    #[serde(flatten)]
+    pub last_upload: LastUpload,
+    /// Minutes between the last upload and the freshness check.
+    pub age_minutes: i64,
+}
+
+/// What one city contributed to the run.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CityManifest {
+    pub city_id: String,
+    #[serde(default)]
+    pub status: CityStatus,
+    /// Rows uploaded per dataset.
+    pub records: BTreeMap<String, usize>,
+    pub listing_incomplete: bool,
+    pub files: Vec<ManifestFile>,
+    /// Counts, timing and failed vendors of the extraction; missing when the
+    /// city was resumed after its report had already been uploaded.
+    pub report: Option<ExtractionReport>,
+    /// Null rate per vendor column over the city's vendor files, whether or
+    /// not they passed `quality.max_null_rate`.
+    #[serde(default)]
+    pub null_rates: BTreeMap<String, f64>,
+    /// Why the city was given up on without uploading, when it was.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub failed: Option<String>,
+    /// The upload that made the city fresh, when it was skipped as fresh.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fresh: Option<Freshness>,
+}
+
+/// Machine-readable description of what a run produced. It is stored as
+/// `manifest_{run_id}.json` after every city and uploaded last, so its
+/// presence in the bucket means the run completed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RunManifest {
+    pub run_id: Uuid,
+    pub version: String,
+    /// `GIT_SHA` at build time, when it was set.
+    pub git_sha: Option<String>,
+    /// Layout version of the run's Parquet files and what it changed.
+    pub schema_version: i32,
+    pub schema_change: String,
+    pub dry_run: bool,
+    pub partition_date: NaiveDate,
+    pub started_at: DateTime<Utc>,
+    /// Run start as `YYYYMMDDTHHMMSSZ`, as it appears in the run's file names
+    /// and object keys.
+    #[serde(default)]
+    pub timestamp: String,
+    pub finished_at: Option<DateTime<Utc>>,
+    /// The loaded configuration without credentials (see `Settings::snapshot`).
+    pub settings: serde_json::Value,
+    /// What `cities: auto` discovered at startup, before the allow and deny
+    /// lists; absent when the cities were listed in the configuration.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub discovered_cities: Option<Vec<City>>,
+    /// Listing keys the run set out to extract, after `city_allowlist` and
+    /// `city_denylist`.
+    #[serde(default)]
+    pub selected_cities: Vec<String>,
+    pub cities: Vec<CityManifest>,
+    /// Every key the run uploaded, recovered files and the incremental
+    /// manifest included.
+    pub uploaded_keys: Vec<String>,
+    /// `SIGHUP` reloads that changed the run's throttling or worker cap.
+    #[serde(default)]
+    pub reloads: Vec<SettingsReload>,
+}
+
+/// The settings a `SIGHUP` reload can change.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReloadableSettings {
+    pub throttling: ThrottlingConfig,
+    pub vendor_workers: usize,
+}
+
+/// One `SIGHUP` reload and the values it replaced.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SettingsReload {
+    pub reloaded_at: DateTime<Utc>,
+    pub old: ReloadableSettings,
+    pub new: ReloadableSettings,
+}
+
+impl RunManifest {
+    pub fn new(
+        run_id: Uuid,
+        partition_date: NaiveDate,
+        dry_run: bool,
+        settings: serde_json::Value,
+        started_at: DateTime<Utc>,
+    ) -> Self {
+        Self {
+            run_id,
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            git_sha: option_env!("GIT_SHA").map(String::from),
+            schema_version: SCHEMA_VERSION,
+            schema_change: schema_change_note().to_string(),
+            dry_run,
+            partition_date,
+            started_at,
+            timestamp: file_safe_timestamp(started_at),
+            finished_at: None,
+            settings,
+            discovered_cities: None,
+            selected_cities: Vec::new(),
+            cities: Vec::new(),
+            uploaded_keys: Vec::new(),
+            reloads: Vec::new(),
+        }
+    }
+
+    pub fn path(output_dir: &Path, run_id: Uuid) -> PathBuf {
+        output_dir.join(format!("manifest_{}.json", run_id))
+    }
+
+    /// `runs/{date}/{run_id}/manifest.json`, keyed by the run's partition date.
+    pub fn s3_key(&self) -> String {
+        format!("runs/{}/{}/manifest.json", self.partition_date, self.run_id)
+    }
+
+    /// Adds a city whose upload finished, from its marker's uploads.
+    pub fn record_city(&mut self, marker: &PhaseMarker, report: Option<ExtractionReport>) {
+        let mut records = BTreeMap::new();
+        let mut null_rates = NullRates::new();
+        for upload in &marker.uploads {
+            *records.entry(upload.dataset.clone()).or_insert(0) += upload.rows;
+            if let Some(rates) = &upload.null_rates {
+                null_rates.merge(rates);
+            }
+        }
+        let files = marker.uploads.iter()
+            .map(|upload| ManifestFile {
+                dataset: upload.dataset.clone(),
+                file_name: upload.path.file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+                    .unwrap_or_default(),
+                s3_key: upload.s3_key.clone(),
+                rows: upload.rows,
+                bytes: upload.bytes,
+                sha256: upload.sha256.clone(),
+                compacted_from: upload.compacted_from.clone(),
+            })
+            .collect();
+        self.cities.push(CityManifest {
+            city_id: marker.city_id.clone(),
+            status: CityStatus::Uploaded,
+            records,
+            listing_incomplete: marker.listing_incomplete,
+            files,
+            report,
+            null_rates: null_rates.rates(),
+            failed: None,
+            fresh: None,
+        });
+    }
+
+    /// Adds a city that failed without uploading, with why and the null rates
+    /// computed before it failed.
+    pub fn record_failed_city(
+        &mut self,
+        marker: &PhaseMarker,
+        report: Option<ExtractionReport>,
+        reason: &str,
+        null_rates: BTreeMap<String, f64>,
+    ) {
+        self.cities.push(CityManifest {
+            city_id: marker.city_id.clone(),
+            status: CityStatus::Failed,
+            records: BTreeMap::new(),
+            listing_incomplete: marker.listing_incomplete,
+            files: Vec::new(),
+            report,
+            null_rates,
+            failed: Some(reason.to_string()),
+            fresh: None,
+        });
+    }
+
+    /// Adds a city skipped because it was uploaded recently.
+    pub fn record_fresh_city(&mut self, city_id: &str, freshness: Freshness) {
+        self.cities.push(CityManifest {
+            city_id: city_id.to_string(),
+            status: CityStatus::Fresh,
+            records: BTreeMap::new(),
+            listing_incomplete: false,
+            files: Vec::new(),
+            report: None,
+            null_rates: BTreeMap::new(),
+            failed: None,
+            fresh: Some(freshness),
+        });
+    }
+
+    /// The newest upload of each city among `manifests` (key and manifest),
+    /// counting only the cities a finished, real run uploaded.
+    pub fn last_uploads<'a>(
+        manifests: impl IntoIterator<Item = (&'a str, &'a RunManifest)>,
+    ) -> BTreeMap<String, LastUpload> {
+        let mut uploads: BTreeMap<String, LastUpload> = BTreeMap::new();
+        for (key, manifest) in manifests {
+            let Some(finished_at) = manifest.finished_at.filter(|_| !manifest.dry_run) else {
+                continue;
+            };
+            let uploaded = manifest.cities.iter()
+                .filter(|city| city.status == CityStatus::Uploaded && city.failed.is_none());
+            for city in uploaded {
+                let newer = uploads.get(&city.city_id)
+                    .is_none_or(|upload| upload.uploaded_at < finished_at);
+                if newer {
+                    uploads.insert(city.city_id.clone(), LastUpload {
+                        source: key.to_string(),
+                        uploaded_at: finished_at,
+                    });
+                }
+            }
+        }
+        uploads
+    }
+
+    /// [`last_uploads`](Self::last_uploads) of the run manifests uploaded
+    /// under `runs/` since `since`. A manifest that can't be read is logged
+    /// and left out.
+    pub async fn recent_uploads(storage: &MinioStorage, since: DateTime<Utc>) -> Result<BTreeMap<String, LastUpload>> {
+        let mut manifests = Vec::new();
+        for object in storage.list_objects("runs/").await? {
+            let recent = object.last_modified.is_none_or(|modified| modified >= since);
+            if !object.key.ends_with("/manifest.json") || !recent {
+                continue;
+            }
+            let manifest = storage.get_object_bytes(&object.key)
+                .await
+                .and_then(|bytes| Ok(serde_json::from_slice::<RunManifest>(&bytes)?));
+            match manifest {
+                Ok(manifest) => manifests.push((object.key, manifest)),
+                Err(e) => warn!(s3_key = object.key, error = %e, "Failed to read run manifest, ignoring it"),
+            }
+        }
+        Ok(Self::last_uploads(manifests.iter().map(|(key, manifest)| (key.as_str(), manifest))))
+    }
+
+    pub fn store(&self, output_dir: &Path) -> Result<()> {
+        write_json_atomic(&Self::path(output_dir, self.run_id), self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::phase::PendingUpload;
+    use crate::storage::testing::MockS3;
+
+    fn manifest() -> RunManifest {
+        let date = NaiveDate::from_ymd_opt(2024, 5, 1).unwrap();
+        RunManifest::new(Uuid::nil(), date, false, serde_json::Value::Null, Utc::now())
+    }
+
+    fn marker() -> PhaseMarker {
+        let date = NaiveDate::from_ymd_opt(2024, 5, 1).unwrap();
+        PhaseMarker::extracted("1", PathBuf::from("vendors.json"), date, false)
+    }
+
+    fn null_rates(rows: usize, details_nulls: usize) -> NullRates {
+        serde_json::from_value(serde_json::json!({
+            "rows": rows,
+            "null_counts": { "code": 0, "details": details_nulls },
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn uploaded_city_records_null_rates_over_its_parts() {
+        let mut marker = marker();
+        for (part, rates) in [null_rates(2, 2), null_rates(6, 0)].into_iter().enumerate() {
+            let mut upload = PendingUpload::new("vendors", PathBuf::from(format!("part{}.parquet", part)), format!("key{}", part), 0);
+            upload.null_rates = Some(rates);
+            marker.uploads.push(upload);
+        }
+        let mut manifest = manifest();
+
+        manifest.record_city(&marker, None);
+
+        let city = &manifest.cities[0];
+        assert_eq!(city.null_rates, BTreeMap::from([("code".to_string(), 0.0), ("details".to_string(), 0.25)]));
+        assert_eq!(city.failed, None);
+        assert_eq!(city.files.len(), 2);
+    }
+
+    #[test]
+    fn discovered_and_selected_cities_are_recorded() {
+        let mut manifest = manifest();
+        let stored = serde_json::to_value(&manifest).unwrap();
+        assert!(stored.get("discovered_cities").is_none());
+
+        manifest.discovered_cities = Some(vec![
+            City { id: 17, name: "Karachi".to_string(), url_key: Some("karachi".to_string()) },
+            City { id: 18, name: "Lahore".to_string(), url_key: None },
+        ]);
+        manifest.selected_cities = vec!["17".to_string()];
+
+        let stored = serde_json::to_value(&manifest).unwrap();
+        assert_eq!(stored["discovered_cities"][1]["name"], "Lahore");
+        assert_eq!(stored["selected_cities"], serde_json::json!(["17"]));
+        let read: RunManifest = serde_json::from_value(stored).unwrap();
+        assert_eq!(read.discovered_cities.unwrap().len(), 2);
+    }
+
+    #[test]
+    fn run_timestamp_matches_file_names() {
+        let started_at = DateTime::parse_from_rfc3339("2025-03-14T09:30:00Z").unwrap().with_timezone(&Utc);
+        let date = NaiveDate::from_ymd_opt(2025, 3, 14).unwrap();
+        let manifest = RunManifest::new(Uuid::nil(), date, false, serde_json::Value::Null, started_at);
+
+        let stored = serde_json::to_value(&manifest).unwrap();
+        assert_eq!(stored["timestamp"], "20250314T093000Z");
+    }
+
+    /// A finished run manifest recording `uploaded` and `failed` cities, and
+    /// city `3` as fresh.
+    fn finished(hours_ago: i64, uploaded: &[&str], failed: &[&str]) -> RunManifest {
+        let mut manifest = manifest();
+        manifest.run_id = Uuid::new_v4();
+        for city_id in uploaded {
+            manifest.record_city(&PhaseMarker::extracted(city_id, PathBuf::from("vendors.json"), manifest.partition_date, false), None);
+        }
+        for city_id in failed {
+            let marker = PhaseMarker::extracted(city_id, PathBuf::from("vendors.json"), manifest.partition_date, false);
+            manifest.record_failed_city(&marker, None, "failed", BTreeMap::new());
+        }
+        manifest.record_fresh_city("3", Freshness {
+            last_upload: LastUpload { source: "runs/old/manifest.json".to_string(), uploaded_at: Utc::now() },
+            age_minutes: 0,
+        });
+        manifest.finished_at = Some(Utc::now() - chrono::Duration::hours(hours_ago));
+        manifest
+    }
+
+    #[tokio::test]
+    async fn recent_uploads_keep_each_citys_newest_successful_upload() {
+        let s3 = MockS3::default();
+        let older = finished(5, &["1", "2"], &[]);
+        let newer = finished(1, &["2"], &["1"]);
+        let mut dry_run = finished(0, &["1"], &[]);
+        dry_run.dry_run = true;
+        let mut unfinished = finished(0, &["1"], &[]);
+        unfinished.finished_at = None;
+        for (key, manifest) in [("runs/a/manifest.json", &older), ("runs/b/manifest.json", &newer), ("runs/c/manifest.json", &dry_run), ("runs/d/manifest.json", &unfinished)] {
+            s3.put(key, &serde_json::to_vec(manifest).unwrap());
+        }
+        s3.put("runs/e/manifest.json", b"not json");
+        s3.put("runs/e/notes.txt", b"not a manifest");
+
+        let uploads = RunManifest::recent_uploads(&s3.storage(), Utc::now() - chrono::Duration::hours(12)).await.unwrap();
+
+        // City 1 failed in the newer run, so its upload is the older one's
+        assert_eq!(uploads.keys().collect::<Vec<_>>(), ["1", "2"]);
+        assert_eq!(uploads["1"].source, "runs/a/manifest.json");
+        assert_eq!(uploads["1"].uploaded_at, older.finished_at.unwrap());
+        assert_eq!(uploads["2"].source, "runs/b/manifest.json");
+        // Manifests modified before `since` aren't read
+        let none = RunManifest::recent_uploads(&s3.storage(), Utc::now() + chrono::Duration::hours(1)).await.unwrap();
+        assert!(none.is_empty());
+    }
+
+    #[test]
+    fn fresh_city_records_its_source_and_age() {
+        let mut manifest = manifest();
+        let uploaded_at = Utc::now();
+
+        manifest.record_fresh_city("1", Freshness {
+            last_upload: LastUpload { source: "runs/2024-05-01/run/manifest.json".to_string(), uploaded_at },
+            age_minutes: 90,
+        });
+
+        let stored = serde_json::to_value(&manifest).unwrap();
+        assert_eq!(stored["cities"][0]["status"], "fresh");
+        assert_eq!(stored["cities"][0]["fresh"]["source"], "runs/2024-05-01/run/manifest.json");
+        assert_eq!(stored["cities"][0]["fresh"]["age_minutes"], 90);
+        let read: RunManifest = serde_json::from_value(stored).unwrap();
+        assert_eq!(read.cities[0].status, CityStatus::Fresh);
+    }
+
+    #[test]
+    fn failed_city_keeps_its_null_rates_and_reason() {
+        let mut manifest = manifest();
+
+        manifest.record_failed_city(&marker(), None, "details too often null", null_rates(4, 4).rates());
+
+        let city = &manifest.cities[0];
+        assert_eq!(city.failed.as_deref(), Some("details too often null"));
+        assert_eq!(city.null_rates.get("details"), Some(&1.0));
+        assert!(city.files.is_empty());
+        let stored = serde_json::to_value(&manifest).unwrap();
+        assert_eq!(stored["cities"][0]["null_rates"]["details"], 1.0);
+    }
+}
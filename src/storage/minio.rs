@@ -1,83 +1,316 @@
 use aws_sdk_s3::Client as S3Client;
 use aws_sdk_s3::config::{Credentials, Region, BehaviorVersion};
 use aws_sdk_s3::primitives::ByteStream;
-use aws_sdk_s3::types::{CompletedMultipartUpload, CompletedPart, ObjectCannedAcl};
-use aws_smithy_runtime_api::client::result::SdkError;
+use aws_sdk_s3::types::{BucketLocationConstraint, ChecksumAlgorithm, ChecksumMode, CompletedMultipartUpload, CompletedPart, CreateBucketConfiguration, ObjectCannedAcl, ServerSideEncryption};
+use aws_smithy_runtime_api::client::result::{CreateUnhandledError, SdkError};
+use aws_smithy_runtime_api::http::Response as HttpResponse;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use futures::{stream, StreamExt, TryStreamExt};
+use md5::{Digest as _, Md5};
+use sha2::{Digest, Sha256};
+use std::future::Future;
 use std::path::Path;
 use std::fs::File;
-use std::io::Read;
-use tracing::{debug, error, info};
+use std::io::{Read, Seek, SeekFrom};
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tracing::{debug, error, info, warn};
 use crate::error::{Result, Error};
+use crate::utils::retry_with_backoff_if;
 
-pub struct MinioUploader {
+const VERIFY_RETRIES: u32 = 4;
+const VERIFY_BASE_DELAY_MS: u64 = 500;
+
+/// Retries of a failed upload request, unless set with
+/// [`MinioStorage::with_upload_retries`].
+pub const DEFAULT_UPLOAD_RETRIES: u32 = 4;
+const UPLOAD_BASE_DELAY_MS: u64 = 1000;
+
+/// Multipart parts in flight at once, unless set with
+/// [`MinioStorage::with_multipart_concurrency`].
+pub const DEFAULT_MULTIPART_CONCURRENCY: usize = 4;
+
+/// Size of a multipart upload's parts, unless set with
+/// [`MinioStorage::with_multipart_chunk_mb`].
+pub const DEFAULT_MULTIPART_CHUNK_MB: usize = 8;
+/// S3 rejects smaller parts, except for the last one.
+pub const MIN_MULTIPART_CHUNK_MB: usize = 5;
+const MIB: usize = 1024 * 1024;
+
+/// Reads and writes the run's objects in one MinIO bucket.
+pub struct MinioStorage {
     pub client: S3Client,
     bucket: String,
+    /// Log the keys that would be written instead of uploading.
+    dry_run: bool,
+    upload_retries: u32,
+    multipart_concurrency: usize,
+    multipart_chunk_size: usize,
+    /// Files larger than this many bytes are uploaded in parts.
+    multipart_threshold: usize,
+}
+
+#[deprecated(note = "renamed to `MinioStorage`")]
+pub type MinioUploader = MinioStorage;
+
+/// An object in the bucket, as listed by [`MinioStorage::list_objects`].
+#[derive(Debug, Clone)]
+pub struct ObjectInfo {
+    pub key: String,
+    pub size: u64,
+    pub last_modified: Option<DateTime<Utc>>,
+}
+
+/// An object as it was sent, to compare with what the bucket reports.
+struct SentObject {
+    size: u64,
+    /// Base64 SHA-256 of the body; for a multipart upload, of the parts'
+    /// digests followed by `-<parts>`, as S3 reports it.
+    checksum_sha256: String,
+    /// Hex MD5 of the body, which S3 reports as a single-part upload's ETag;
+    /// `None` for multipart uploads, whose ETag isn't the object's MD5.
+    md5_hex: Option<String>,
 }
 
-impl MinioUploader {
+/// Whether repeating an S3 request may help: timeouts, dropped connections,
+/// unreadable responses, throttling and 5xx. Client errors such as 403 or
+/// NoSuchBucket are final.
+fn is_transient<E>(err: &SdkError<E, HttpResponse>) -> bool {
+    match err {
+        SdkError::TimeoutError(_) | SdkError::ResponseError(_) => true,
+        SdkError::DispatchFailure(failure) => !failure.is_user(),
+        SdkError::ServiceError(service) => {
+            let status = service.raw().status().as_u16();
+            status >= 500 || status == 408 || status == 429
+        }
+        _ => false,
+    }
+}
+
+/// MIME type of a file to upload, from its extension; unknown extensions are
+/// sent as `application/octet-stream`.
+pub fn content_type_for(path: &Path) -> &'static str {
+    let name = path.file_name()
+        .map(|name| name.to_string_lossy().to_ascii_lowercase())
+        .unwrap_or_default();
+    if name.ends_with(".json.gz") {
+        return "application/gzip";
+    }
+    match name.rsplit_once('.').map(|(_, ext)| ext) {
+        Some("json") => "application/json",
+        Some("parquet") => "application/x-parquet",
+        Some("csv") => "text/csv",
+        Some("txt") => "text/plain",
+        Some("gz") => "application/gzip",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Converts an upload request's error, marking the ones worth retrying.
+fn upload_error<E: std::fmt::Debug + CreateUnhandledError>(err: SdkError<E, HttpResponse>) -> Error {
+    if is_transient(&err) {
+        Error::TransientStorage(format!("{:?}", err))
+    } else {
+        err.into()
+    }
+}
+
+fn build_client(endpoint: &str, access_key: &str, secret_key: &str, region: &str) -> S3Client {
+    let credentials = Credentials::new(
+        access_key,
+        secret_key,
+        None,
+        None,
+        "static-credentials",
+    );
+
+    let region = Region::new(region.to_string());
+    
+    let s3_config = aws_sdk_s3::Config::builder()
+        .behavior_version(BehaviorVersion::latest())
+        .endpoint_url(endpoint)
+        .region(region)
+        .credentials_provider(credentials)
+        .force_path_style(true)
+        .build();
+
+    S3Client::from_conf(s3_config)
+}
+
+async fn create_bucket(client: &S3Client, bucket: &str, region: &str) -> Result<()> {
+    // us-east-1 is the default location and rejects being named explicitly
+    let configuration = (region != "us-east-1").then(|| {
+        CreateBucketConfiguration::builder()
+            .location_constraint(BucketLocationConstraint::from(region))
+            .build()
+    });
+    match client
+        .create_bucket()
+        .bucket(bucket)
+        .set_create_bucket_configuration(configuration)
+        .send()
+        .await
+    {
+        Ok(_) => {
+            info!(bucket = bucket, region = region, "Created missing bucket");
+            Ok(())
+        }
+        // Another run created it first
+        Err(e) if e.as_service_error().is_some_and(|se| se.is_bucket_already_owned_by_you()) => {
+            info!(bucket = bucket, "Bucket was created concurrently");
+            Ok(())
+        }
+        Err(e) => {
+            error!(error = ?e, bucket = bucket, "Failed to create bucket");
+            Err(Error::Storage(format!("Cannot create bucket '{}': {}", bucket, e)))
+        }
+    }
+}
+
+impl MinioStorage {
+    /// Uploader for dry runs: no bucket verification, and uploads are only logged.
+    pub fn dry_run(
+        endpoint: &str,
+        access_key: &str,
+        secret_key: &str,
+        bucket: &str,
+        region: &str,
+    ) -> Self {
+        Self {
+            dry_run: true,
+            ..Self::with_client(build_client(endpoint, access_key, secret_key, region), bucket)
+        }
+    }
+
+    /// Storage over an already configured client, without verifying the bucket.
+    pub fn with_client(client: S3Client, bucket: &str) -> Self {
+        Self {
+            client,
+            bucket: bucket.to_string(),
+            dry_run: false,
+            upload_retries: DEFAULT_UPLOAD_RETRIES,
+            multipart_concurrency: DEFAULT_MULTIPART_CONCURRENCY,
+            multipart_chunk_size: DEFAULT_MULTIPART_CHUNK_MB * MIB,
+            multipart_threshold: DEFAULT_MULTIPART_CHUNK_MB * MIB,
+        }
+    }
+
     pub async fn new(
         endpoint: &str,
         access_key: &str,
         secret_key: &str,
         bucket: &str,
         region: &str,
+        create_bucket_if_missing: bool,
     ) -> Result<Self> {
         debug!(
             endpoint = endpoint,
             bucket = bucket,
             region = region,
-            "Initializing MinIO uploader"
+            "Initializing MinIO storage"
         );
 
-        let credentials = Credentials::new(
-            access_key,
-            secret_key,
-            None,
-            None,
-            "static-credentials",
-        );
+        let client = build_client(endpoint, access_key, secret_key, region);
+        Self::connect(client, bucket, region, create_bucket_if_missing).await
+    }
 
-        let region = Region::new(region.to_string());
-        
-        let s3_config = aws_sdk_s3::Config::builder()
-            .behavior_version(BehaviorVersion::latest())
-            .endpoint_url(endpoint)
-            .region(region)
-            .credentials_provider(credentials)
-            .force_path_style(true)
-            .build();
+    /// Storage over `client` once the bucket is verified (or created, with
+    /// `create_bucket_if_missing`).
+    async fn connect(client: S3Client, bucket: &str, region: &str, create_bucket_if_missing: bool) -> Result<Self> {
+        // Verify bucket exists and is accessible. A missing bucket won't appear
+        // by retrying; anything else is treated as a transient connectivity blip.
+        debug!("Verifying bucket access");
+        let verified = retry_with_backoff_if(
+            VERIFY_RETRIES,
+            VERIFY_BASE_DELAY_MS,
+            || async {
+                match client.head_bucket().bucket(bucket).send().await {
+                    Ok(_) => Ok(()),
+                    Err(e) if e.as_service_error().is_some_and(|se| se.is_not_found()) => {
+                        error!(bucket = bucket, "Bucket does not exist");
+                        Err(Error::BucketNotFound(bucket.to_string()))
+                    }
+                    Err(e) => {
+                        warn!(
+                            error = ?e,
+                            bucket = bucket,
+                            "Failed to access bucket"
+                        );
+                        Err(Error::Storage(format!("Cannot access bucket '{}': {}", bucket, e)))
+                    }
+                }
+            },
+            |e| !matches!(e, Error::BucketNotFound(_)),
+        ).await;
+        match verified {
+            Err(Error::BucketNotFound(_)) if create_bucket_if_missing => {
+                create_bucket(&client, bucket, region).await?;
+            }
+            verified => verified?,
+        }
 
-        let client = S3Client::from_conf(s3_config);
+        Ok(Self::with_client(client, bucket))
+    }
 
-        // Verify bucket exists and is accessible
-        debug!("Verifying bucket access");
-        let bucket_exists = client
-            .head_bucket()
-            .bucket(bucket)
-            .send()
-            .await;
+    /// Sets how many times a transient upload failure is retried.
+    pub fn with_upload_retries(mut self, retries: u32) -> Self {
+        self.upload_retries = retries;
+        self
+    }
 
-        if let Err(e) = bucket_exists {
-            error!(
-                error = ?e,
-                bucket = bucket,
-                "Failed to access bucket"
-            );
-            return Err(crate::error::Error::Storage(
-                format!("Cannot access bucket '{}': {}", bucket, e)
-            ));
-        }
+    /// Sets how many parts of a multipart upload are sent at once; each holds
+    /// a chunk in memory.
+    pub fn with_multipart_concurrency(mut self, concurrency: usize) -> Self {
+        self.multipart_concurrency = concurrency.max(1);
+        self
+    }
 
-        Ok(Self {
-            client,
-            bucket: bucket.to_string(),
-        })
+    /// Sets the size of a multipart upload's parts, raised to S3's 5 MiB
+    /// minimum.
+    pub fn with_multipart_chunk_mb(mut self, chunk_mb: usize) -> Self {
+        self.multipart_chunk_size = chunk_mb.max(MIN_MULTIPART_CHUNK_MB) * MIB;
+        self
+    }
+
+    /// Sets the size above which a file is uploaded in parts; a file of
+    /// exactly this size still goes in a single request.
+    pub fn with_multipart_threshold_mb(mut self, threshold_mb: usize) -> Self {
+        self.multipart_threshold = threshold_mb * MIB;
+        self
+    }
+
+    /// Runs an upload request, retrying it with backoff while it fails with
+    /// `Error::TransientStorage`.
+    async fn with_retries<T, F, Fut>(&self, s3_key: &str, operation: F) -> Result<T>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        retry_with_backoff_if(
+            self.upload_retries,
+            UPLOAD_BASE_DELAY_MS,
+            || async {
+                let result = operation().await;
+                if let Err(Error::TransientStorage(e)) = &result {
+                    warn!(s3_key = s3_key, error = %e, "Upload request failed, retrying");
+                }
+                result
+            },
+            |e| matches!(e, Error::TransientStorage(_)),
+        ).await
     }
 
-    pub async fn upload_file(&self, local_path: &Path, s3_key: &str) -> Result<()> {
+    /// Uploads a file as is, typed by `content_type` or else by its
+    /// extension (see [`content_type_for`]).
+    pub async fn upload_file(&self, local_path: &Path, s3_key: &str, content_type: Option<&str>) -> Result<()> {
+        let content_type = content_type.unwrap_or_else(|| content_type_for(local_path));
         debug!(
             local_path = ?local_path,
             s3_key = s3_key,
+            content_type = content_type,
             "Starting file upload"
         );
 
@@ -85,31 +318,45 @@ impl MinioUploader {
         let metadata = std::fs::metadata(local_path)?;
         let file_size = metadata.len();
 
-        let body = ByteStream::from_path(local_path).await?;
-        
+        if self.dry_run {
+            info!(
+                bucket = self.bucket,
+                s3_key = s3_key,
+                file_size = file_size,
+                "Dry run, skipping upload"
+            );
+            return Ok(());
+        }
+
         debug!(
             file_size = file_size,
             "Uploading file to MinIO"
         );
 
+        self.with_retries(s3_key, || self.put_file(local_path, s3_key, content_type)).await?;
+        debug!(
+            s3_key = s3_key,
+            "File uploaded successfully"
+        );
+        Ok(())
+    }
+
+    async fn put_file(&self, local_path: &Path, s3_key: &str, content_type: &str) -> Result<()> {
+        // Each attempt consumes its body
+        let body = ByteStream::from_path(local_path).await?;
         let result = self.client
             .put_object()
             .bucket(&self.bucket)
             .key(s3_key)
             .body(body)
-            .content_type("application/json")
+            .content_type(content_type)
             .acl(ObjectCannedAcl::BucketOwnerFullControl)
             .send()
             .await;
 
         match result {
-            Ok(_) => {
-                debug!(
-                    s3_key = s3_key,
-                    "File uploaded successfully"
-                );
-                Ok(())
-            }
+            Ok(_) => Ok(()),
+            Err(e) if is_transient(&e) => Err(Error::TransientStorage(format!("{:?}", e))),
             Err(e) => {
                 let error_msg = match &e {
                     SdkError::ServiceError(service_error) => {
@@ -136,31 +383,228 @@ impl MinioUploader {
         }
     }
 
-    pub async fn upload_parquet_file(&self, file_path: &Path, s3_key: &str) -> Result<()> {
-        const CHUNK_SIZE: usize = 8 * 1024 * 1024; // 8MB chunks
-        let file_size = std::fs::metadata(file_path)?.len() as usize;
-
-        if file_size > CHUNK_SIZE {
-            self.upload_multipart(file_path, s3_key, file_size, CHUNK_SIZE).await
-        } else {
-            self.upload_single_part(file_path, s3_key).await
+    /// Lists the objects under `prefix`, following continuation tokens past
+    /// the 1000 keys S3 returns per page.
+    pub async fn list_objects(&self, prefix: &str) -> Result<Vec<ObjectInfo>> {
+        let mut objects = Vec::new();
+        let mut continuation_token = None;
+        loop {
+            let page = self.client
+                .list_objects_v2()
+                .bucket(&self.bucket)
+                .prefix(prefix)
+                .set_continuation_token(continuation_token)
+                .send()
+                .await?;
+            for object in page.contents() {
+                let Some(key) = object.key() else { continue };
+                objects.push(ObjectInfo {
+                    key: key.to_string(),
+                    size: object.size().unwrap_or(0).max(0) as u64,
+                    last_modified: object.last_modified()
+                        .and_then(|modified| DateTime::from_timestamp(modified.secs(), modified.subsec_nanos())),
+                });
+            }
+            if !page.is_truncated().unwrap_or(false) {
+                break;
+            }
+            continuation_token = page.next_continuation_token().map(str::to_string);
         }
+        debug!(prefix = prefix, objects = objects.len(), "Listed objects");
+        Ok(objects)
     }
 
-    async fn upload_single_part(&self, file_path: &Path, s3_key: &str) -> Result<()> {
-        info!("Using single-part upload");
-        let body = ByteStream::from_path(file_path).await?;
-        
-        self.client
-            .put_object()
+    /// Starts reading an object; `Ok(None)` when the key doesn't exist.
+    async fn get_object(&self, s3_key: &str) -> Result<Option<ByteStream>> {
+        match self.client
+            .get_object()
             .bucket(&self.bucket)
             .key(s3_key)
-            .body(body)
-            .content_type("application/x-parquet")
             .send()
-            .await?;
+            .await
+        {
+            Ok(output) => Ok(Some(output.body)),
+            Err(SdkError::ServiceError(e)) if e.err().is_no_such_key() => Ok(None),
+            Err(e) => Err(Error::Storage(format!("MinIO error: {}", e))),
+        }
+    }
 
-        Ok(())
+    /// Reads a whole object into memory; a missing key is an `Error::Storage`.
+    pub async fn get_object_bytes(&self, s3_key: &str) -> Result<Bytes> {
+        let body = self.get_object(s3_key)
+            .await?
+            .ok_or_else(|| Error::Storage(format!("No object at '{}'", s3_key)))?;
+        Ok(body
+            .collect()
+            .await
+            .map_err(|e| Error::Storage(format!("MinIO download error: {}", e)))?
+            .into_bytes())
+    }
+
+    /// Streams an object to `local_path`; `Ok(false)` when the key doesn't exist.
+    pub async fn download_to_file(&self, s3_key: &str, local_path: &Path) -> Result<bool> {
+        let Some(body) = self.get_object(s3_key).await? else {
+            return Ok(false);
+        };
+
+        if let Some(parent) = local_path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        let mut file = tokio::fs::File::create(local_path).await?;
+        tokio::io::copy(&mut body.into_async_read(), &mut file).await?;
+        file.flush().await?;
+        debug!(s3_key = s3_key, local_path = ?local_path, "File downloaded successfully");
+        Ok(true)
+    }
+
+    /// Uploads a Parquet file, optionally attaching object tags
+    /// (`key=value&key2=value2`). Every request carries the body's SHA-256,
+    /// single-part puts its MD5 as `Content-MD5` too, and the stored object's
+    /// size, checksum and (single-part) ETag are compared with what was sent
+    /// afterwards; a mismatch uploads the file again, up to
+    /// `upload_retries` times, then fails with `Error::Storage`.
+    pub async fn upload_parquet_file(&self, file_path: &Path, s3_key: &str, tagging: Option<&str>) -> Result<()> {
+        let file_size = std::fs::metadata(file_path)?.len() as usize;
+
+        if self.dry_run {
+            info!(
+                bucket = self.bucket,
+                s3_key = s3_key,
+                file_size = file_size,
+                "Dry run, skipping upload"
+            );
+            return Ok(());
+        }
+
+        self.upload_verified(s3_key, || async {
+            if file_size > self.multipart_threshold {
+                self.upload_multipart(file_path, s3_key, file_size, self.multipart_chunk_size, tagging).await
+            } else {
+                info!(
+                    file_size = file_size,
+                    threshold_mb = self.multipart_threshold / MIB,
+                    "Using single-part upload"
+                );
+                let body = Bytes::from(std::fs::read(file_path)?);
+                self.put_verified_bytes(body, s3_key, content_type_for(file_path), tagging).await
+            }
+        }).await
+    }
+
+    /// Runs `upload` until the stored object matches what it sent, repeating
+    /// it at most `upload_retries` times before failing with `Error::Storage`.
+    async fn upload_verified<F, Fut>(&self, s3_key: &str, upload: F) -> Result<()>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = Result<SentObject>>,
+    {
+        let mut attempts = 0;
+        loop {
+            let sent = upload().await?;
+            match self.verify_upload(s3_key, &sent).await? {
+                None => return Ok(()),
+                Some(mismatch) if attempts < self.upload_retries => {
+                    attempts += 1;
+                    warn!(s3_key = s3_key, mismatch = %mismatch, "Uploaded object differs from what was sent, uploading again");
+                }
+                Some(mismatch) => return Err(Error::Storage(mismatch)),
+            }
+        }
+    }
+
+    /// Compares the stored object with what was sent. Servers that don't
+    /// report SHA-256 checksums are only checked for size and ETag; the ETag
+    /// isn't compared for KMS or customer-key encrypted objects, whose ETag
+    /// isn't their MD5.
+    async fn verify_upload(&self, s3_key: &str, sent: &SentObject) -> Result<Option<String>> {
+        let head = self.with_retries(s3_key, || async {
+            self.client
+                .head_object()
+                .bucket(&self.bucket)
+                .key(s3_key)
+                .checksum_mode(ChecksumMode::Enabled)
+                .send()
+                .await
+                .map_err(upload_error)
+        }).await?;
+
+        let size = head.content_length().unwrap_or_default() as u64;
+        if size != sent.size {
+            return Ok(Some(format!("{} holds {} bytes, {} were sent", s3_key, size, sent.size)));
+        }
+        if let Some(stored) = head.checksum_sha256() {
+            if stored != sent.checksum_sha256 {
+                return Ok(Some(format!(
+                    "{} has SHA-256 {}, {} was sent",
+                    s3_key, stored, sent.checksum_sha256
+                )));
+            }
+        }
+
+        let encrypted = head.sse_customer_algorithm().is_some()
+            || head.server_side_encryption().is_some_and(|sse| *sse != ServerSideEncryption::Aes256);
+        match (head.e_tag(), &sent.md5_hex) {
+            (Some(e_tag), Some(md5_hex)) if !encrypted && e_tag.trim_matches('"') != md5_hex => Ok(Some(format!(
+                "{} has ETag {}, MD5 {} was sent",
+                s3_key, e_tag, md5_hex
+            ))),
+            _ => Ok(None),
+        }
+    }
+
+    /// Uploads a file built in memory in a single request, optionally tagged
+    /// and verified like [`upload_parquet_file`](Self::upload_parquet_file).
+    pub async fn upload_bytes(
+        &self,
+        bytes: Bytes,
+        s3_key: &str,
+        content_type: &str,
+        tagging: Option<&str>,
+    ) -> Result<()> {
+        if self.dry_run {
+            info!(
+                bucket = self.bucket,
+                s3_key = s3_key,
+                file_size = bytes.len(),
+                "Dry run, skipping upload"
+            );
+            return Ok(());
+        }
+
+        self.upload_verified(s3_key, || self.put_verified_bytes(bytes.clone(), s3_key, content_type, tagging)).await
+    }
+
+    /// Puts a body with its SHA-256 and MD5, which the server checks on receipt.
+    async fn put_verified_bytes(
+        &self,
+        body: Bytes,
+        s3_key: &str,
+        content_type: &str,
+        tagging: Option<&str>,
+    ) -> Result<SentObject> {
+        let checksum_sha256 = BASE64.encode(Sha256::digest(&body));
+        let md5 = Md5::digest(&body);
+        let content_md5 = BASE64.encode(md5.as_slice());
+        self.with_retries(s3_key, || async {
+            self.client
+                .put_object()
+                .bucket(&self.bucket)
+                .key(s3_key)
+                .body(ByteStream::from(body.clone()))
+                .content_type(content_type)
+                .content_md5(&content_md5)
+                .checksum_sha256(&checksum_sha256)
+                .set_tagging(tagging.map(str::to_string))
+                .send()
+                .await
+                .map_err(upload_error)?;
+            Ok(())
+        }).await?;
+        Ok(SentObject {
+            size: body.len() as u64,
+            checksum_sha256,
+            md5_hex: Some(md5.iter().map(|byte| format!("{:02x}", byte)).collect()),
+        })
     }
 
     async fn upload_multipart(
@@ -169,75 +613,420 @@ impl MinioUploader {
         s3_key: &str,
         file_size: usize,
         chunk_size: usize,
-    ) -> Result<()> {
+        tagging: Option<&str>,
+    ) -> Result<SentObject> {
         info!(
-            file_size_mb = file_size / 1024 / 1024,
+            file_size_mb = file_size / MIB,
+            threshold_mb = self.multipart_threshold / MIB,
+            chunk_size_mb = chunk_size / MIB,
+            parts = file_size.div_ceil(chunk_size),
             "Large file detected, using multipart upload"
         );
 
         // Initialize multipart upload
-        let create_multipart_res = self.client
-            .create_multipart_upload()
-            .bucket(&self.bucket)
-            .key(s3_key)
-            .content_type("application/x-parquet")
-            .send()
+        let upload_id = self.with_retries(s3_key, || async {
+            self.client
+                .create_multipart_upload()
+                .bucket(&self.bucket)
+                .key(s3_key)
+                .content_type(content_type_for(file_path))
+                .checksum_algorithm(ChecksumAlgorithm::Sha256)
+                .set_tagging(tagging.map(str::to_string))
+                .send()
+                .await
+                .map_err(upload_error)?
+                .upload_id()
+                .map(str::to_string)
+                .ok_or_else(|| Error::Storage("Failed to get upload ID".to_string()))
+        }).await?;
+        let upload_id = upload_id.as_str();
+
+        // Parts left behind by a failed upload would count against quota until
+        // cleaned up
+        match self.send_parts(file_path, s3_key, upload_id, file_size, chunk_size).await {
+            Ok(sent) => Ok(sent),
+            Err(e) => {
+                self.abort_multipart(s3_key, upload_id).await;
+                Err(e)
+            }
+        }
+    }
+
+    /// Uploads the parts of a created multipart upload and completes it.
+    async fn send_parts(
+        &self,
+        file_path: &Path,
+        s3_key: &str,
+        upload_id: &str,
+        file_size: usize,
+        chunk_size: usize,
+    ) -> Result<SentObject> {
+        // Parts are read at their offsets as they are started, so at most
+        // `multipart_concurrency` chunks are in memory. The first failure drops
+        // the parts still in flight.
+        let total_parts = file_size.div_ceil(chunk_size);
+        let mut parts = stream::iter(1..=total_parts as i32)
+            .map(|part_number| self.upload_part(file_path, s3_key, upload_id, part_number, chunk_size, total_parts))
+            .buffer_unordered(self.multipart_concurrency)
+            .try_collect::<Vec<_>>()
             .await?;
-            
-            let upload_id = create_multipart_res.upload_id()
-            .ok_or_else(|| Error::Storage("Failed to get upload ID".to_string()))?;
-            
-        let mut part_number = 1;
-        let mut completed_parts = Vec::new();
+        parts.sort_by_key(|(part, _)| part.part_number());
+
+        let mut part_digests = Sha256::new();
+        for (_, digest) in &parts {
+            part_digests.update(digest);
+        }
+        let completed_parts: Vec<CompletedPart> = parts.into_iter().map(|(part, _)| part).collect();
+
+        // Complete multipart upload
+        self.with_retries(s3_key, || async {
+            let completed_upload = CompletedMultipartUpload::builder()
+                .set_parts(Some(completed_parts.clone()))
+                .build();
+            self.client
+                .complete_multipart_upload()
+                .bucket(&self.bucket)
+                .key(s3_key)
+                .upload_id(upload_id)
+                .multipart_upload(completed_upload)
+                .send()
+                .await
+                .map_err(upload_error)?;
+            Ok(())
+        }).await?;
+
+        Ok(SentObject {
+            size: file_size as u64,
+            checksum_sha256: format!("{}-{}", BASE64.encode(part_digests.finalize()), completed_parts.len()),
+            md5_hex: None,
+        })
+    }
+
+    /// Reads and uploads one part, retrying it within the upload. Returns the
+    /// completed part with its SHA-256 digest.
+    async fn upload_part(
+        &self,
+        file_path: &Path,
+        s3_key: &str,
+        upload_id: &str,
+        part_number: i32,
+        chunk_size: usize,
+        total_parts: usize,
+    ) -> Result<(CompletedPart, Vec<u8>)> {
         let mut file = File::open(file_path)?;
-        
-        // Upload parts
-        loop {
-            let mut buffer = vec![0; chunk_size];
-            let n = file.read(&mut buffer)?;
-            if n == 0 { break; }
-            buffer.truncate(n);
-            
+        file.seek(SeekFrom::Start((part_number as u64 - 1) * chunk_size as u64))?;
+        let mut buffer = Vec::with_capacity(chunk_size);
+        file.take(chunk_size as u64).read_to_end(&mut buffer)?;
+        let body = Bytes::from(buffer);
+        let digest = Sha256::digest(&body);
+        let checksum_sha256 = BASE64.encode(&digest);
+
+        let e_tag = self.with_retries(s3_key, || async {
             let part_res = self.client
                 .upload_part()
                 .bucket(&self.bucket)
                 .key(s3_key)
                 .upload_id(upload_id)
-                .body(ByteStream::from(buffer))
+                .body(ByteStream::from(body.clone()))
                 .part_number(part_number)
+                .checksum_sha256(&checksum_sha256)
                 .send()
-                .await?;
-                
-            completed_parts.push(
-                CompletedPart::builder()
-                    .e_tag(part_res.e_tag.unwrap_or_default())
-                    .part_number(part_number)
-                    .build()
-            );
-            
-            info!(
-                part_number = part_number,
-                total_parts = (file_size + chunk_size - 1) / chunk_size,
-                "Uploaded part"
-            );
-            
-            part_number += 1;
-        }
-        
-        // Complete multipart upload
-        let completed_upload = CompletedMultipartUpload::builder()
-            .set_parts(Some(completed_parts))
+                .await
+                .map_err(upload_error)?;
+            Ok(part_res.e_tag.unwrap_or_default())
+        }).await?;
+
+        info!(
+            part_number = part_number,
+            total_parts = total_parts,
+            "Uploaded part"
+        );
+
+        let part = CompletedPart::builder()
+            .e_tag(e_tag)
+            .part_number(part_number)
+            .checksum_sha256(checksum_sha256)
             .build();
-            
-        self.client
-            .complete_multipart_upload()
+        Ok((part, digest.to_vec()))
+    }
+
+    /// Abandons a multipart upload so its parts stop taking up storage. A
+    /// failure is only logged; the error that ended the upload is reported.
+    async fn abort_multipart(&self, s3_key: &str, upload_id: &str) {
+        let aborted = self.client
+            .abort_multipart_upload()
             .bucket(&self.bucket)
             .key(s3_key)
             .upload_id(upload_id)
-            .multipart_upload(completed_upload)
             .send()
-            .await?;
+            .await;
+        match aborted {
+            Ok(_) => info!(s3_key = s3_key, upload_id = upload_id, "Aborted multipart upload"),
+            Err(e) => warn!(s3_key = s3_key, upload_id = upload_id, error = ?e, "Failed to abort multipart upload"),
+        }
+    }
 
-        Ok(())
+    /// Aborts the multipart uploads under `prefix` started more than
+    /// `older_than` ago, which a killed run can leave behind. Returns how many
+    /// were aborted.
+    pub async fn cleanup_stale_uploads(&self, prefix: &str, older_than: Duration) -> Result<usize> {
+        if self.dry_run {
+            info!(bucket = self.bucket, prefix = prefix, "Dry run, skipping stale multipart upload cleanup");
+            return Ok(0);
+        }
+
+        let cutoff = Utc::now().timestamp() - older_than.as_secs() as i64;
+        let mut stale = Vec::new();
+        let mut key_marker = None;
+        let mut upload_id_marker = None;
+        loop {
+            let page = self.client
+                .list_multipart_uploads()
+                .bucket(&self.bucket)
+                .prefix(prefix)
+                .set_key_marker(key_marker)
+                .set_upload_id_marker(upload_id_marker)
+                .send()
+                .await?;
+            for upload in page.uploads() {
+                let started = upload.initiated().map(|initiated| initiated.secs());
+                if let (Some(key), Some(upload_id), true) = (
+                    upload.key(),
+                    upload.upload_id(),
+                    started.is_some_and(|started| started < cutoff),
+                ) {
+                    stale.push((key.to_string(), upload_id.to_string()));
+                }
+            }
+            if !page.is_truncated().unwrap_or(false) {
+                break;
+            }
+            key_marker = page.next_key_marker().map(str::to_string);
+            upload_id_marker = page.next_upload_id_marker().map(str::to_string);
+        }
+
+        for (key, upload_id) in &stale {
+            self.abort_multipart(key, upload_id).await;
+        }
+        Ok(stale.len())
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::testing::{MockS3, BUCKET};
+
+    /// A file of `size` bytes that differ from part to part.
+    fn file_of(dir: &Path, name: &str, size: usize) -> (std::path::PathBuf, Vec<u8>) {
+        let contents: Vec<u8> = (0..size).map(|index| (index % 251) as u8).collect();
+        let path = dir.join(name);
+        std::fs::write(&path, &contents).unwrap();
+        (path, contents)
+    }
+
+    fn multipart(mock: &MockS3) -> MinioStorage {
+        mock.storage()
+            .with_multipart_threshold_mb(MIN_MULTIPART_CHUNK_MB)
+            .with_multipart_chunk_mb(MIN_MULTIPART_CHUNK_MB)
+    }
+
+    #[tokio::test]
+    async fn missing_bucket_fails_without_retrying() {
+        let mock = MockS3::default().without_bucket();
+
+        let connected = MinioStorage::connect(mock.client(), BUCKET, "us-east-1", false).await;
+
+        assert!(matches!(connected, Err(Error::BucketNotFound(bucket)) if bucket == BUCKET));
+        assert_eq!(mock.count("HeadBucket"), 1);
+        assert_eq!(mock.count("CreateBucket"), 0);
+    }
+
+    #[tokio::test]
+    async fn missing_bucket_is_created_when_allowed() {
+        let mock = MockS3::default().without_bucket();
+
+        MinioStorage::connect(mock.client(), BUCKET, "us-east-1", true).await.unwrap();
+
+        assert_eq!(mock.count("CreateBucket"), 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn flaky_bucket_check_is_retried_until_it_succeeds() {
+        let mock = MockS3::default();
+        mock.fail_head_bucket(2);
+
+        MinioStorage::connect(mock.client(), BUCKET, "us-east-1", false).await.unwrap();
+
+        assert_eq!(mock.count("HeadBucket"), 3);
+        assert_eq!(mock.count("CreateBucket"), 0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn unreachable_bucket_fails_once_retries_are_spent() {
+        let mock = MockS3::default();
+        mock.fail_head_bucket(usize::MAX);
+
+        let connected = MinioStorage::connect(mock.client(), BUCKET, "us-east-1", true).await;
+
+        assert!(matches!(connected, Err(Error::Storage(_))));
+        assert_eq!(mock.count("HeadBucket"), VERIFY_RETRIES as usize + 1);
+        assert_eq!(mock.count("CreateBucket"), 0);
+    }
+
+    #[tokio::test]
+    async fn large_file_is_uploaded_in_parts_and_verified() {
+        let mock = MockS3::default();
+        let dir = tempfile::tempdir().unwrap();
+        let (path, contents) = file_of(dir.path(), "vendors.parquet", 11 * MIB);
+
+        multipart(&mock).upload_parquet_file(&path, "city_id=1/vendors.parquet", Some("run_id=1")).await.unwrap();
+
+        assert_eq!(mock.count("CreateMultipartUpload"), 1);
+        assert_eq!(mock.count("UploadPart"), 3);
+        assert_eq!(mock.count("CompleteMultipartUpload"), 1);
+        assert_eq!(mock.count("HeadObject"), 1);
+        assert_eq!(mock.object("city_id=1/vendors.parquet").unwrap(), contents);
+        assert_eq!(mock.tagging("city_id=1/vendors.parquet").as_deref(), Some("run_id=1"));
+        assert!(mock.open_uploads().is_empty());
+    }
+
+    #[tokio::test]
+    async fn file_at_the_threshold_is_uploaded_in_one_request() {
+        let threshold = MIN_MULTIPART_CHUNK_MB * MIB;
+        let dir = tempfile::tempdir().unwrap();
+
+        for (size, multipart_uploads, parts, puts) in [
+            (threshold - 1, 0, 0, 1),
+            (threshold, 0, 0, 1),
+            (threshold + 1, 1, 2, 0),
+        ] {
+            let mock = MockS3::default();
+            let (path, contents) = file_of(dir.path(), "vendors.parquet", size);
+
+            multipart(&mock).upload_parquet_file(&path, "vendors.parquet", None).await.unwrap();
+
+            assert_eq!(mock.count("PutObject"), puts, "size {}", size);
+            assert_eq!(mock.count("CreateMultipartUpload"), multipart_uploads, "size {}", size);
+            assert_eq!(mock.count("UploadPart"), parts, "size {}", size);
+            assert_eq!(mock.object("vendors.parquet").unwrap(), contents, "size {}", size);
+        }
+    }
+
+    #[tokio::test]
+    async fn failed_part_aborts_the_upload() {
+        let mock = MockS3::default();
+        mock.fail_part(2);
+        let dir = tempfile::tempdir().unwrap();
+        let (path, _) = file_of(dir.path(), "vendors.parquet", 11 * MIB);
+
+        let uploaded = multipart(&mock).upload_parquet_file(&path, "vendors.parquet", None).await;
+
+        assert!(uploaded.is_err());
+        assert_eq!(mock.count("CompleteMultipartUpload"), 0);
+        assert_eq!(mock.aborted().len(), 1);
+        assert!(mock.open_uploads().is_empty());
+        assert!(mock.object("vendors.parquet").is_none());
+    }
+
+    #[tokio::test]
+    async fn mismatched_object_is_uploaded_again() {
+        let mock = MockS3::default();
+        mock.corrupt_writes(1);
+        let body = Bytes::from_static(b"PAR1 vendors PAR1");
+
+        mock.storage().upload_bytes(body.clone(), "vendors.parquet", "application/x-parquet", None).await.unwrap();
+
+        assert_eq!(mock.count("PutObject"), 2);
+        assert_eq!(mock.count("HeadObject"), 2);
+        assert_eq!(mock.object("vendors.parquet").unwrap(), body);
+    }
+
+    #[tokio::test]
+    async fn single_part_put_carries_content_md5() {
+        let mock = MockS3::default();
+        let body = Bytes::from_static(b"PAR1 vendors PAR1");
+
+        mock.storage().upload_bytes(body.clone(), "vendors.parquet", "application/x-parquet", None).await.unwrap();
+
+        assert_eq!(mock.content_md5("vendors.parquet"), Some(BASE64.encode(Md5::digest(&body).as_slice())));
+        assert_eq!(mock.count("PutObject"), 1);
+    }
+
+    #[tokio::test]
+    async fn mismatched_etag_is_uploaded_again() {
+        let mock = MockS3::default();
+        mock.wrong_e_tags(1);
+        let body = Bytes::from_static(b"PAR1 vendors PAR1");
+
+        mock.storage().upload_bytes(body.clone(), "vendors.parquet", "application/x-parquet", None).await.unwrap();
+
+        assert_eq!(mock.count("PutObject"), 2);
+        assert_eq!(mock.count("HeadObject"), 2);
+
+        mock.wrong_e_tags(usize::MAX);
+        let uploaded = mock.storage().with_upload_retries(1)
+            .upload_bytes(body, "vendors.parquet", "application/x-parquet", None)
+            .await;
+        assert!(matches!(uploaded, Err(Error::Storage(message)) if message.contains("ETag")));
+    }
+
+    #[tokio::test]
+    async fn upload_fails_once_retries_are_spent_on_mismatches() {
+        let mock = MockS3::default();
+        mock.corrupt_writes(usize::MAX);
+        let storage = mock.storage().with_upload_retries(2);
+
+        let uploaded = storage
+            .upload_bytes(Bytes::from_static(b"PAR1 vendors PAR1"), "vendors.parquet", "application/x-parquet", None)
+            .await;
+
+        assert!(matches!(uploaded, Err(Error::Storage(_))));
+        assert_eq!(mock.count("PutObject"), 3);
+    }
+
+    #[tokio::test]
+    async fn listing_follows_continuation_tokens() {
+        let mock = MockS3::default().with_list_page_size(2);
+        for key in ["a/1", "a/2", "a/3", "a/4", "a/5", "b/1"] {
+            mock.put(key, key.as_bytes());
+        }
+
+        let objects = mock.storage().list_objects("a/").await.unwrap();
+
+        let keys: Vec<&str> = objects.iter().map(|object| object.key.as_str()).collect();
+        assert_eq!(keys, ["a/1", "a/2", "a/3", "a/4", "a/5"]);
+        assert!(objects.iter().all(|object| object.size == 3 && object.last_modified.is_some()));
+        assert_eq!(mock.count("ListObjectsV2"), 3);
+    }
+
+    #[tokio::test]
+    async fn stale_uploads_of_a_killed_run_are_aborted() {
+        let mock = MockS3::default();
+        let two_days_ago = Utc::now() - chrono::Duration::days(2);
+        let stale = mock.start_upload("a/old.parquet", two_days_ago);
+        mock.start_upload("a/new.parquet", Utc::now());
+        mock.start_upload("b/old.parquet", two_days_ago);
+
+        let aborted = mock.storage().cleanup_stale_uploads("a/", Duration::from_secs(24 * 3600)).await.unwrap();
+
+        assert_eq!(aborted, 1);
+        assert_eq!(mock.aborted(), [stale]);
+        assert_eq!(mock.open_uploads(), ["a/new.parquet", "b/old.parquet"]);
+    }
+
+    #[tokio::test]
+    async fn uploaded_file_downloads_back_and_missing_keys_are_reported() {
+        let mock = MockS3::default();
+        let dir = tempfile::tempdir().unwrap();
+        let (path, contents) = file_of(dir.path(), "manifest.json", 1024);
+        let storage = mock.storage();
+
+        storage.upload_file(&path, "state/manifest.json", None).await.unwrap();
+        let downloaded = dir.path().join("restored/manifest.json");
+
+        assert!(storage.download_to_file("state/manifest.json", &downloaded).await.unwrap());
+        assert_eq!(std::fs::read(&downloaded).unwrap(), contents);
+        assert!(!storage.download_to_file("state/missing.json", &downloaded).await.unwrap());
+        assert!(storage.get_object_bytes("state/missing.json").await.is_err());
+    }
+}
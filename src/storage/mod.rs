@@ -1,7 +1,26 @@
+pub mod atomic;
+pub mod checkpoint;
+pub mod incremental;
 pub mod json;
+pub mod manifest;
 pub mod minio;
 pub mod parquet;
+pub mod phase;
+pub mod quality;
+pub mod state;
+#[cfg(test)]
+pub mod testing;
+pub mod validate;
 
-pub use json::JsonWriter;
+pub use checkpoint::CityCheckpoint;
+pub use incremental::IncrementalManifest;
+pub use json::{open_json, replace_json_extension, CityCheckpointFiles, CityOutput, CityWriters, CompletedPart, JsonWriter, JsonWriterOptions, RecordStream, VendorStream, WriterCheckpoint};
+pub use manifest::{CityManifest, CityStatus, Freshness, LastUpload, ManifestFile, ReloadableSettings, RunManifest, SettingsReload};
+pub use minio::{MinioStorage, ObjectInfo};
+#[allow(deprecated)]
 pub use minio::MinioUploader;
-pub use parquet::ParquetConverter;
+pub use parquet::{ConversionStats, ParquetConverter, ParquetMeta, SkippedLine};
+pub use phase::{CityPhase, PendingUpload, PhaseMarker};
+pub use quality::{NullRates, QualityBreach, QualityVerdict};
+pub use state::{MissingVendor, ObservedVendor, VendorState};
+pub use validate::{ValidationReport, VendorSample};
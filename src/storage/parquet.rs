@@ -1,98 +1,1369 @@
 use std::fs::File;
-use arrow::array::{StringArray, Int32Array, Int64Array};
-use arrow::datatypes::{DataType, Field, Schema};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use arrow::array::{ArrayRef, BooleanArray, BooleanBuilder, StringArray, Int32Array, Int32Builder, Int64Array, Int64Builder, Float64Array, Float64Builder, Date32Array, ListBuilder, StringBuilder, StructBuilder, TimestampMillisecondArray, TimestampMillisecondBuilder};
+use chrono::{DateTime, Datelike, NaiveDate, NaiveDateTime, Utc};
+use arrow::datatypes::{DataType, Field, Fields, Schema, SchemaRef, TimeUnit};
 use arrow::record_batch::RecordBatch;
+use bytes::Bytes;
 use parquet::arrow::ArrowWriter;
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use parquet::basic::{Compression, GzipLevel, ZstdLevel};
+use parquet::file::properties::{EnabledStatistics, WriterProperties};
+use parquet::file::metadata::KeyValue;
+use parquet::file::reader::{ChunkReader, FileReader};
+use parquet::file::serialized_reader::SerializedFileReader;
+use parquet::schema::types::ColumnPath;
+use serde::Serialize;
 use std::sync::Arc;
-use crate::error::Result;
-use crate::models::Vendor;
+use crate::config::{ParquetCompression, ParquetConfig};
+use crate::error::{Error, Result};
+use crate::models::{MenuItem, RatingsDistribution, Review, Vendor};
+use crate::storage::json::open_json;
+use crate::storage::quality::NullRates;
+use crate::storage::state::MissingVendor;
+
+/// Bytes a vendor row group's string column may hold before the group is
+/// written early. Utf8 offsets are i32, so a column past 2 GiB (big cities'
+/// `details` with menus) would overflow; this leaves room for one more vendor.
+const GROUP_STRING_BYTES: usize = 1 << 30;
+
+/// Days between 0001-01-01 and the Unix epoch, for Arrow's Date32.
+const EPOCH_DAYS_FROM_CE: i32 = 719_163;
+
+/// Version of the Parquet file layout, written as the `schema_version` column
+/// of vendor, menu item and review files and in every file's footer. Any
+/// change to [`vendor_schema`], [`menu_item_schema`] or [`review_schema`] (a
+/// column added, removed, renamed or retyped) bumps it, with a note in
+/// [`SCHEMA_CHANGES`].
+pub const SCHEMA_VERSION: i32 = 2;
+
+/// What each schema version changed, oldest first; the run manifest records
+/// the current version's note.
+pub const SCHEMA_CHANGES: &[(i32, &str)] = &[
+    (1, "First versioned layout: vendor files gain a schema_version column and every file records schema_version in its footer"),
+    (2, "Menu item and review files gain a schema_version column"),
+];
+
+// A version bump without a note doesn't build
+const _: () = assert!(SCHEMA_CHANGES[SCHEMA_CHANGES.len() - 1].0 == SCHEMA_VERSION);
+
+/// The note [`SCHEMA_CHANGES`] has for [`SCHEMA_VERSION`].
+pub fn schema_change_note() -> &'static str {
+    SCHEMA_CHANGES[SCHEMA_CHANGES.len() - 1].1
+}
+
+/// Provenance written into the footer of every file the converter writes, so
+/// a file can be traced back to its run without its object key.
+#[derive(Debug, Clone)]
+pub struct ParquetMeta {
+    pub run_id: String,
+    pub city_id: String,
+    pub country: String,
+    /// Logical date of the partition the file belongs to.
+    pub extraction_date: NaiveDate,
+}
+
+impl ParquetMeta {
+    /// The footer's key-value entries; `record_count` is added by
+    /// [`close_writer`] once the rows are written.
+    fn key_value_metadata(&self) -> Vec<KeyValue> {
+        [
+            ("run_id", self.run_id.clone()),
+            ("city_id", self.city_id.clone()),
+            ("country", self.country.clone()),
+            ("extraction_date", self.extraction_date.to_string()),
+            ("crate_version", env!("CARGO_PKG_VERSION").to_string()),
+            ("schema_version", SCHEMA_VERSION.to_string()),
+        ]
+            .into_iter()
+            .map(|(key, value)| KeyValue::new(key.to_string(), value))
+            .collect()
+    }
+}
+
+/// Records the file's row count in its footer and closes it.
+fn close_writer<W: Write + Send>(mut writer: ArrowWriter<W>, rows: usize) -> Result<W> {
+    writer.append_key_value_metadata(KeyValue::new("record_count".to_string(), rows.to_string()));
+    Ok(writer.into_inner()?)
+}
+
+/// Writer properties from the `parquet` settings, with the file's key-value
+/// metadata. Settings naming a column the file doesn't have are ignored.
+fn writer_properties(config: &ParquetConfig, key_value_metadata: Option<Vec<KeyValue>>) -> Result<WriterProperties> {
+    let compression = match config.compression {
+        ParquetCompression::Zstd => Compression::ZSTD(ZstdLevel::try_new(config.zstd_level)?),
+        ParquetCompression::Snappy => Compression::SNAPPY,
+        ParquetCompression::Gzip => Compression::GZIP(GzipLevel::default()),
+        ParquetCompression::None => Compression::UNCOMPRESSED,
+    };
+    let statistics = if config.statistics_enabled {
+        EnabledStatistics::Page
+    } else {
+        EnabledStatistics::None
+    };
+    let mut builder = WriterProperties::builder()
+        .set_compression(compression)
+        .set_max_row_group_size(config.rows_per_group.max(1))
+        .set_dictionary_enabled(config.dictionary_enabled)
+        .set_statistics_enabled(statistics)
+        .set_key_value_metadata(key_value_metadata);
+    for column in &config.dictionary_columns {
+        builder = builder.set_column_dictionary_enabled(ColumnPath::from(column.as_str()), true);
+    }
+    if config.code_bloom_filter {
+        // Sized for a row group of distinct codes rather than the 1M default
+        builder = builder
+            .set_column_bloom_filter_enabled(ColumnPath::from("code"), true)
+            .set_column_bloom_filter_fpp(ColumnPath::from("code"), config.bloom_filter_fpp)
+            .set_column_bloom_filter_ndv(ColumnPath::from("code"), config.rows_per_group.max(1) as u64);
+    }
+    Ok(builder.build())
+}
+
+/// Bytes a Parquet file spends on bloom filters, from its footer.
+pub fn bloom_filter_bytes<R: ChunkReader + 'static>(parquet: R) -> Result<u64> {
+    let reader = SerializedFileReader::new(parquet)?;
+    Ok(reader.metadata().row_groups().iter()
+        .flat_map(|row_group| row_group.columns())
+        .filter_map(|column| column.bloom_filter_length())
+        .map(|length| length as u64)
+        .sum())
+}
+
+/// Fields of one entry of the vendors' `ratings` list.
+fn rating_fields() -> Fields {
+    Fields::from(vec![
+        Field::new("score", DataType::Int32, false),
+        Field::new("count", DataType::Int32, false),
+        Field::new("percentage", DataType::Int32, false),
+    ])
+}
+
+fn utc_millis() -> DataType {
+    DataType::Timestamp(TimeUnit::Millisecond, Some("UTC".into()))
+}
+
+/// A ratings distribution timestamp in epoch milliseconds; the API sends
+/// RFC 3339 or `YYYY-MM-DD HH:MM:SS` (UTC). `None` for anything else.
+fn ratings_time(value: &str) -> Option<i64> {
+    DateTime::parse_from_rfc3339(value)
+        .map(|time| time.timestamp_millis())
+        .or_else(|_| NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S").map(|time| time.and_utc().timestamp_millis()))
+        .ok()
+}
+
+/// A vendor timestamp column: UTC milliseconds, or epoch seconds in the
+/// legacy Int64 layout.
+enum TimestampColumn {
+    Millis(TimestampMillisecondBuilder),
+    Seconds(Int64Builder),
+}
+
+impl TimestampColumn {
+    fn data_type(legacy: bool) -> DataType {
+        if legacy {
+            DataType::Int64
+        } else {
+            utc_millis()
+        }
+    }
+
+    fn with_capacity(legacy: bool, rows: usize) -> Self {
+        if legacy {
+            Self::Seconds(Int64Builder::with_capacity(rows))
+        } else {
+            Self::Millis(TimestampMillisecondBuilder::with_capacity(rows).with_timezone("UTC"))
+        }
+    }
+
+    fn append(&mut self, time: DateTime<Utc>) {
+        match self {
+            Self::Millis(builder) => builder.append_value(time.timestamp_millis()),
+            Self::Seconds(builder) => builder.append_value(time.timestamp()),
+        }
+    }
+
+    fn finish(&mut self) -> ArrayRef {
+        match self {
+            Self::Millis(builder) => Arc::new(builder.finish()),
+            Self::Seconds(builder) => Arc::new(builder.finish()),
+        }
+    }
+}
+
+/// Column builders for the vendors table, filled one vendor at a time.
+struct VendorColumns {
+    rows: usize,
+    codes: StringBuilder,
+    names: StringBuilder,
+    details: StringBuilder,
+    batch_numbers: Int32Builder,
+    run_ids: StringBuilder,
+    reviews: StringBuilder,
+    /// Per-score counts; the distribution's other fields have their own columns.
+    ratings: ListBuilder<StructBuilder>,
+    discounts: StringBuilder,
+    delivery_fees: Float64Builder,
+    delivery_etas: Int32Builder,
+    unchanged: BooleanBuilder,
+    filtered: BooleanBuilder,
+    statuses: StringBuilder,
+    attempts: Int32Builder,
+    content_hashes: StringBuilder,
+    changed: BooleanBuilder,
+    extraction_started_at: TimestampColumn,
+    extraction_completed_at: TimestampColumn,
+    extraction_durations: Int64Builder,
+    // Typed copies of the most queried details fields, null without details
+    rating: Float64Builder,
+    review_count: Int64Builder,
+    latitude: Float64Builder,
+    longitude: Float64Builder,
+    address_city: StringBuilder,
+    cuisines: ListBuilder<StringBuilder>,
+    minimum_order_amount: Float64Builder,
+    is_active: BooleanBuilder,
+    chain_code: StringBuilder,
+    ratings_total_count: Int32Builder,
+    ratings_created_at: TimestampMillisecondBuilder,
+    ratings_updated_at: TimestampMillisecondBuilder,
+}
+
+impl VendorColumns {
+    fn with_capacity(rows: usize, legacy_timestamps: bool) -> Self {
+        // Byte capacities are left to grow; the JSON columns vary too much to guess
+        let strings = || StringBuilder::with_capacity(rows, 0);
+        Self {
+            rows: 0,
+            codes: strings(),
+            names: strings(),
+            details: strings(),
+            batch_numbers: Int32Builder::with_capacity(rows),
+            run_ids: strings(),
+            reviews: strings(),
+            ratings: ListBuilder::with_capacity(StructBuilder::from_fields(rating_fields(), 0), rows),
+            discounts: strings(),
+            delivery_fees: Float64Builder::with_capacity(rows),
+            delivery_etas: Int32Builder::with_capacity(rows),
+            unchanged: BooleanBuilder::with_capacity(rows),
+            filtered: BooleanBuilder::with_capacity(rows),
+            statuses: strings(),
+            attempts: Int32Builder::with_capacity(rows),
+            content_hashes: strings(),
+            changed: BooleanBuilder::with_capacity(rows),
+            extraction_started_at: TimestampColumn::with_capacity(legacy_timestamps, rows),
+            extraction_completed_at: TimestampColumn::with_capacity(legacy_timestamps, rows),
+            extraction_durations: Int64Builder::with_capacity(rows),
+            rating: Float64Builder::with_capacity(rows),
+            review_count: Int64Builder::with_capacity(rows),
+            latitude: Float64Builder::with_capacity(rows),
+            longitude: Float64Builder::with_capacity(rows),
+            address_city: strings(),
+            cuisines: ListBuilder::with_capacity(StringBuilder::new(), rows),
+            minimum_order_amount: Float64Builder::with_capacity(rows),
+            is_active: BooleanBuilder::with_capacity(rows),
+            chain_code: strings(),
+            ratings_total_count: Int32Builder::with_capacity(rows),
+            ratings_created_at: TimestampMillisecondBuilder::with_capacity(rows).with_timezone("UTC"),
+            ratings_updated_at: TimestampMillisecondBuilder::with_capacity(rows).with_timezone("UTC"),
+        }
+    }
+
+    fn append(&mut self, v: &Vendor) {
+        self.codes.append_value(&v.code);
+        self.names.append_value(&v.name);
+        // Multi-language runs store the details keyed by language id
+        self.details.append_option(if v.localized_details.is_empty() {
+            v.details.as_ref().map(|d| serde_json::to_string(d).unwrap_or_default())
+        } else {
+            serde_json::to_string(&v.localized_details).ok()
+        });
+        self.batch_numbers.append_value(v.batch_number);
+        self.run_ids.append_option(v.run_id.map(|id| id.to_string()));
+        self.reviews.append_option(v.reviews.as_ref().map(|r| serde_json::to_string(r).unwrap_or_default()));
+        self.append_ratings(v.ratings.as_ref());
+        self.discounts.append_option(
+            (!v.discounts.is_empty()).then(|| serde_json::to_string(&v.discounts).unwrap_or_default()),
+        );
+        self.delivery_fees.append_option(v.delivery_fee);
+        self.delivery_etas.append_option(v.delivery_eta_minutes);
+        self.unchanged.append_value(v.unchanged);
+        self.filtered.append_value(v.filtered);
+        self.statuses.append_value(&v.status);
+        self.attempts.append_value(v.attempts);
+        self.content_hashes.append_option(v.content_hash.as_deref());
+        self.changed.append_value(v.changed);
+        self.extraction_started_at.append(v.extraction_started_at);
+        self.extraction_completed_at.append(v.extraction_completed_at);
+        self.extraction_durations.append_value(v.extraction_duration_ms);
+
+        let details = v.details.as_ref();
+        self.rating.append_option(details.and_then(|d| d.rating));
+        self.review_count.append_option(details.and_then(|d| d.review_count));
+        self.latitude.append_option(details.and_then(|d| d.latitude));
+        self.longitude.append_option(details.and_then(|d| d.longitude));
+        self.address_city.append_option(details.and_then(|d| d.address_city()));
+        match details {
+            Some(d) => {
+                for cuisine in &d.cuisines {
+                    self.cuisines.values().append_value(&cuisine.name);
+                }
+                self.cuisines.append(true);
+            }
+            None => self.cuisines.append(false),
+        }
+        self.minimum_order_amount.append_option(details.and_then(|d| d.minimum_order));
+        self.is_active.append_option(details.and_then(|d| d.is_active));
+        self.chain_code.append_option(details.and_then(|d| d.chain_code()));
+        self.rows += 1;
+    }
+
+    /// Vendors without ratings get nulls in every ratings column.
+    fn append_ratings(&mut self, ratings: Option<&RatingsDistribution>) {
+        let Some(ratings) = ratings else {
+            self.ratings.append(false);
+            self.ratings_total_count.append_null();
+            self.ratings_created_at.append_null();
+            self.ratings_updated_at.append_null();
+            return;
+        };
+        let entries = self.ratings.values();
+        for rating in &ratings.ratings {
+            // The builders are created from `rating_fields`, in that order
+            for (index, value) in [rating.score, rating.count, rating.percentage].into_iter().enumerate() {
+                if let Some(builder) = entries.field_builder::<Int32Builder>(index) {
+                    builder.append_value(value);
+                }
+            }
+            entries.append(true);
+        }
+        self.ratings.append(true);
+        self.ratings_total_count.append_value(ratings.total_count);
+        self.ratings_created_at.append_option(ratings_time(&ratings.created_at));
+        self.ratings_updated_at.append_option(ratings_time(&ratings.updated_at));
+    }
+
+    /// Bytes held by the fullest string column.
+    fn string_bytes(&self) -> usize {
+        [
+            &self.codes,
+            &self.names,
+            &self.details,
+            &self.run_ids,
+            &self.reviews,
+            &self.discounts,
+            &self.statuses,
+            &self.content_hashes,
+            &self.address_city,
+            &self.chain_code,
+        ]
+        .into_iter()
+        .map(|column| column.values_slice().len())
+        .chain([self.cuisines.values_ref().values_slice().len()])
+        .max()
+        .unwrap_or(0)
+    }
+
+    /// Whether the group should be written: `rows_per_group` rows, or a string
+    /// column holding `byte_budget` bytes.
+    fn group_full(&self, rows_per_group: usize, byte_budget: usize) -> bool {
+        self.rows >= rows_per_group || self.string_bytes() >= byte_budget
+    }
+
+    /// The appended rows as a batch; the builders are left empty for the next group.
+    fn finish(&mut self, schema: &SchemaRef) -> Result<RecordBatch> {
+        let rows = std::mem::take(&mut self.rows);
+        Ok(RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(self.codes.finish()),
+                Arc::new(self.names.finish()),
+                Arc::new(self.details.finish()),
+                Arc::new(self.batch_numbers.finish()),
+                Arc::new(self.run_ids.finish()),
+                Arc::new(self.reviews.finish()),
+                Arc::new(self.ratings.finish()),
+                Arc::new(self.discounts.finish()),
+                Arc::new(self.delivery_fees.finish()),
+                Arc::new(self.delivery_etas.finish()),
+                Arc::new(self.unchanged.finish()),
+                Arc::new(self.filtered.finish()),
+                Arc::new(self.statuses.finish()),
+                Arc::new(self.attempts.finish()),
+                Arc::new(self.content_hashes.finish()),
+                Arc::new(self.changed.finish()),
+                self.extraction_started_at.finish(),
+                self.extraction_completed_at.finish(),
+                Arc::new(self.extraction_durations.finish()),
+                Arc::new(self.rating.finish()),
+                Arc::new(self.review_count.finish()),
+                Arc::new(self.latitude.finish()),
+                Arc::new(self.longitude.finish()),
+                Arc::new(self.address_city.finish()),
+                Arc::new(self.cuisines.finish()),
+                Arc::new(self.minimum_order_amount.finish()),
+                Arc::new(self.is_active.finish()),
+                Arc::new(self.chain_code.finish()),
+                Arc::new(self.ratings_total_count.finish()),
+                Arc::new(self.ratings_created_at.finish()),
+                Arc::new(self.ratings_updated_at.finish()),
+                Arc::new(Int32Array::from(vec![SCHEMA_VERSION; rows])),
+            ],
+        )?)
+    }
+}
+
+/// Columns of the vendors file, in order, for [`SCHEMA_VERSION`]. New columns
+/// go at the end so readers matching by position keep working.
+pub fn vendor_schema(legacy_int64_timestamps: bool) -> SchemaRef {
+    let timestamp_type = TimestampColumn::data_type(legacy_int64_timestamps);
+    Arc::new(Schema::new(vec![
+        Field::new("code", DataType::Utf8, false),
+        Field::new("name", DataType::Utf8, false),
+        Field::new("details", DataType::Utf8, true),
+        Field::new("batch_number", DataType::Int32, false),
+        Field::new("run_id", DataType::Utf8, true),
+        Field::new("reviews", DataType::Utf8, true),
+        Field::new("ratings", DataType::List(Arc::new(Field::new("item", DataType::Struct(rating_fields()), true))), true),
+        Field::new("discounts", DataType::Utf8, true),
+        Field::new("delivery_fee", DataType::Float64, true),
+        Field::new("delivery_eta_minutes", DataType::Int32, true),
+        Field::new("unchanged", DataType::Boolean, false),
+        Field::new("filtered", DataType::Boolean, false),
+        Field::new("status", DataType::Utf8, false),
+        Field::new("attempts", DataType::Int32, false),
+        Field::new("content_hash", DataType::Utf8, true),
+        Field::new("changed", DataType::Boolean, false),
+        Field::new("extraction_started_at", timestamp_type.clone(), false),
+        Field::new("extraction_completed_at", timestamp_type, false),
+        Field::new("extraction_duration_ms", DataType::Int64, false),
+        // Extracted from `details` so queries needn't parse it
+        Field::new("rating", DataType::Float64, true),
+        Field::new("review_count", DataType::Int64, true),
+        Field::new("latitude", DataType::Float64, true),
+        Field::new("longitude", DataType::Float64, true),
+        Field::new("address_city", DataType::Utf8, true),
+        Field::new("cuisines", DataType::List(Arc::new(Field::new("item", DataType::Utf8, true))), true),
+        Field::new("minimum_order_amount", DataType::Float64, true),
+        Field::new("is_active", DataType::Boolean, true),
+        Field::new("chain_code", DataType::Utf8, true),
+        Field::new("ratings_total_count", DataType::Int32, true),
+        Field::new("ratings_created_at", utc_millis(), true),
+        Field::new("ratings_updated_at", utc_millis(), true),
+        Field::new("schema_version", DataType::Int32, false),
+    ]))
+}
+
+/// Columns of the menu item Parquet files, one row per menu product, for
+/// [`SCHEMA_VERSION`]. New columns go at the end.
+pub fn menu_item_schema() -> SchemaRef {
+    Arc::new(Schema::new(vec![
+        Field::new("vendor_code", DataType::Utf8, false),
+        Field::new("menu_id", DataType::Int64, true),
+        Field::new("category", DataType::Utf8, true),
+        Field::new("product_id", DataType::Int64, true),
+        Field::new("name", DataType::Utf8, false),
+        Field::new("description", DataType::Utf8, true),
+        Field::new("price", DataType::Float64, true),
+        Field::new("is_available", DataType::Boolean, false),
+        Field::new("schema_version", DataType::Int32, false),
+    ]))
+}
+
+/// Columns of the review Parquet files, one row per review, for
+/// [`SCHEMA_VERSION`]. New columns go at the end.
+pub fn review_schema() -> SchemaRef {
+    Arc::new(Schema::new(vec![
+        Field::new("uuid", DataType::Utf8, false),
+        Field::new("vendor_code", DataType::Utf8, false),
+        Field::new("overall_rating", DataType::Float64, true),
+        Field::new("text", DataType::Utf8, true),
+        Field::new("created_at", utc_millis(), true),
+        Field::new("dish_names", DataType::List(Arc::new(Field::new("item", DataType::Utf8, true))), false),
+        Field::new("reviewer_id_hash", DataType::Utf8, true),
+        Field::new("schema_version", DataType::Int32, false),
+    ]))
+}
+
+/// A line [`ParquetConverter::convert_ndjson_file`] couldn't parse as a vendor.
+#[derive(Debug, Clone, Serialize)]
+pub struct SkippedLine {
+    /// 1-based line number in the input.
+    pub line: usize,
+    pub error: String,
+}
+
+/// What [`ParquetConverter::convert_ndjson_file`] converted.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ConversionStats {
+    pub rows_converted: usize,
+    pub rows_skipped: usize,
+    pub skipped: Vec<SkippedLine>,
+    pub output_bytes: u64,
+}
 
 pub struct ParquetConverter;
 
 impl ParquetConverter {
-    pub fn convert_vendors_to_parquet(
-        vendors: &[Vendor],
-        output_path: &str,
+    /// Writes the vendors to a Parquet file one row group of
+    /// `config.rows_per_group` rows (fewer when their strings near Arrow's
+    /// 2 GiB offset limit) at a time, so they can come straight from a
+    /// [`VendorStream`](crate::storage::json::VendorStream) and memory stays
+    /// bounded by the group size rather than the city's. `meta` is recorded
+    /// in the file's footer. The first vendor that fails to read fails the
+    /// conversion.
+    pub fn convert_vendors_to_parquet<I>(
+        vendors: I,
+        output_path: &Path,
+        meta: &ParquetMeta,
+        config: &ParquetConfig,
+    ) -> Result<NullRates>
+    where
+        I: IntoIterator<Item = Result<Vendor>>,
+    {
+        let (_, null_rates) = Self::write_vendors(vendors, File::create(output_path)?, meta, config, GROUP_STRING_BYTES)?;
+        Ok(null_rates)
+    }
+
+    /// Like [`convert_vendors_to_parquet`](Self::convert_vendors_to_parquet),
+    /// but builds the file in memory for uploading without a temp file. The
+    /// whole file is held at once, so callers keep this to small outputs.
+    pub fn convert_vendors_to_bytes<I>(
+        vendors: I,
+        meta: &ParquetMeta,
+        config: &ParquetConfig,
+    ) -> Result<(Bytes, NullRates)>
+    where
+        I: IntoIterator<Item = Result<Vendor>>,
+    {
+        let (buffer, null_rates) = Self::write_vendors(vendors, Vec::new(), meta, config, GROUP_STRING_BYTES)?;
+        Ok((Bytes::from(buffer), null_rates))
+    }
+
+    /// Streams an NDJSON vendor file (plain or `.gz`) into Parquet a row group
+    /// at a time, for backfilling historical files without loading them
+    /// whole. Lines that don't parse as a vendor are skipped and reported
+    /// with their line numbers rather than failing the file; blank lines are
+    /// ignored.
+    pub fn convert_ndjson_file(
+        input: &Path,
+        output: &Path,
+        meta: &ParquetMeta,
+        config: &ParquetConfig,
+    ) -> Result<ConversionStats> {
+        let mut stats = ConversionStats::default();
+        let skipped = &mut stats.skipped;
+        let vendors = BufReader::new(open_json(input)?)
+            .lines()
+            .enumerate()
+            .filter_map(|(index, line)| {
+                let line = match line {
+                    Ok(line) => line,
+                    Err(e) => return Some(Err(Error::from(e))),
+                };
+                if line.trim().is_empty() {
+                    return None;
+                }
+                match serde_json::from_str::<Vendor>(&line) {
+                    Ok(vendor) => Some(Ok(vendor)),
+                    Err(e) => {
+                        skipped.push(SkippedLine { line: index + 1, error: e.to_string() });
+                        None
+                    }
+                }
+            });
+        let (_, null_rates) = Self::write_vendors(vendors, File::create(output)?, meta, config, GROUP_STRING_BYTES)?;
+
+        stats.rows_converted = null_rates.rows();
+        stats.rows_skipped = stats.skipped.len();
+        stats.output_bytes = std::fs::metadata(output)?.len();
+        Ok(stats)
+    }
+
+    /// Writes the vendors into `sink` and returns it once the file is closed.
+    /// A group is also written once a string column holds `byte_budget` bytes.
+    fn write_vendors<I, W>(
+        vendors: I,
+        sink: W,
+        meta: &ParquetMeta,
+        config: &ParquetConfig,
+        byte_budget: usize,
+    ) -> Result<(W, NullRates)>
+    where
+        I: IntoIterator<Item = Result<Vendor>>,
+        W: Write + Send,
+    {
+        let rows_per_group = config.rows_per_group.max(1);
+        let schema = vendor_schema(config.legacy_int64_timestamps);
+
+        let props = writer_properties(config, Some(meta.key_value_metadata()))?;
+        let mut writer = ArrowWriter::try_new(sink, schema.clone(), Some(props))?;
+        let mut null_rates = NullRates::new();
+
+        // The builders are reused for every group; finishing one empties them
+        let mut columns = VendorColumns::with_capacity(rows_per_group, config.legacy_int64_timestamps);
+        for vendor in vendors {
+            columns.append(&vendor?);
+            if columns.group_full(rows_per_group, byte_budget) {
+                Self::write_vendor_group(&mut writer, &schema, &mut columns, &mut null_rates)?;
+            }
+        }
+        if columns.rows > 0 {
+            Self::write_vendor_group(&mut writer, &schema, &mut columns, &mut null_rates)?;
+        }
+
+        let rows = null_rates.rows();
+        Ok((close_writer(writer, rows)?, null_rates))
+    }
+
+    /// Writes the appended vendors as one row group.
+    fn write_vendor_group<W: Write + Send>(
+        writer: &mut ArrowWriter<W>,
+        schema: &SchemaRef,
+        columns: &mut VendorColumns,
+        null_rates: &mut NullRates,
+    ) -> Result<()> {
+        let batch = columns.finish(schema)?;
+        null_rates.observe(&batch);
+        writer.write(&batch)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    pub fn convert_missing_vendors_to_parquet(
+        missing: &[MissingVendor],
+        output_path: &Path,
+        meta: &ParquetMeta,
+        config: &ParquetConfig,
     ) -> Result<()> {
         let schema = Arc::new(Schema::new(vec![
             Field::new("code", DataType::Utf8, false),
-            Field::new("name", DataType::Utf8, false),
-            Field::new("details", DataType::Utf8, true),
-            Field::new("batch_number", DataType::Int32, false),
-            Field::new("reviews", DataType::Utf8, true),
-            Field::new("ratings", DataType::Utf8, true),
-            Field::new("extraction_started_at", DataType::Int64, false),
-            Field::new("extraction_completed_at", DataType::Int64, false),
+            Field::new("last_seen_date", DataType::Date32, false),
+            Field::new("last_known_name", DataType::Utf8, false),
+            Field::new("city_id", DataType::Utf8, false),
         ]));
 
-        // Create owned String vectors first
-        let details_strings: Vec<Option<String>> = vendors.iter()
-            .map(|v| v.details.as_ref()
-                .map(|d| serde_json::to_string(d).unwrap_or_default()))
+        let codes: StringArray = missing.iter()
+            .map(|m| Some(m.code.as_str()))
             .collect();
 
-        let reviews_strings: Vec<Option<String>> = vendors.iter()
-            .map(|v| v.reviews.as_ref()
-                .map(|r| serde_json::to_string(r).unwrap_or_default()))
+        let last_seen: Date32Array = missing.iter()
+            .map(|m| Some(m.last_seen_date.num_days_from_ce() - EPOCH_DAYS_FROM_CE))
             .collect();
 
-        let ratings_strings: Vec<Option<String>> = vendors.iter()
-            .map(|v| v.ratings.as_ref()
-                .map(|r| serde_json::to_string(r).unwrap_or_default()))
+        let names: StringArray = missing.iter()
+            .map(|m| Some(m.last_known_name.as_str()))
             .collect();
 
-        // Now create the arrays using references to the owned strings
-        let codes: StringArray = vendors.iter()
-            .map(|v| Some(v.code.as_str()))
+        let city_ids: StringArray = missing.iter()
+            .map(|m| Some(m.city_id.as_str()))
             .collect();
 
-        let names: StringArray = vendors.iter()
-            .map(|v| Some(v.name.as_str()))
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(codes),
+                Arc::new(last_seen),
+                Arc::new(names),
+                Arc::new(city_ids),
+            ],
+        )?;
+
+        let file = File::create(output_path)?;
+        let props = writer_properties(config, Some(meta.key_value_metadata()))?;
+        let mut writer = ArrowWriter::try_new(file, schema, Some(props))?;
+        writer.write(&batch)?;
+        close_writer(writer, batch.num_rows())?;
+
+        Ok(())
+    }
+
+    /// Streams menu items into a Parquet file a row group of
+    /// `config.rows_per_group` items at a time, so they can come straight
+    /// from a [`RecordStream`](crate::storage::json::RecordStream) and memory
+    /// stays bounded by the group size. Returns the rows written.
+    pub fn convert_menu_items_to_parquet<I>(
+        items: I,
+        output_path: &Path,
+        meta: &ParquetMeta,
+        config: &ParquetConfig,
+    ) -> Result<usize>
+    where
+        I: IntoIterator<Item = Result<MenuItem>>,
+    {
+        Self::write_groups(items, output_path, menu_item_schema(), meta, config, Self::menu_item_batch)
+    }
+
+    /// Streams reviews into a Parquet file like
+    /// [`convert_menu_items_to_parquet`](Self::convert_menu_items_to_parquet).
+    pub fn convert_reviews_to_parquet<I>(
+        reviews: I,
+        output_path: &Path,
+        meta: &ParquetMeta,
+        config: &ParquetConfig,
+    ) -> Result<usize>
+    where
+        I: IntoIterator<Item = Result<Review>>,
+    {
+        Self::write_groups(reviews, output_path, review_schema(), meta, config, Self::review_batch)
+    }
+
+    /// Writes the records a group of `config.rows_per_group` at a time, each
+    /// group built into a batch by `batch`, and returns the rows written. The
+    /// first record that fails to read fails the conversion.
+    fn write_groups<T, I>(
+        records: I,
+        output_path: &Path,
+        schema: SchemaRef,
+        meta: &ParquetMeta,
+        config: &ParquetConfig,
+        batch: fn(&SchemaRef, &[T]) -> Result<RecordBatch>,
+    ) -> Result<usize>
+    where
+        I: IntoIterator<Item = Result<T>>,
+    {
+        let rows_per_group = config.rows_per_group.max(1);
+        let props = writer_properties(config, Some(meta.key_value_metadata()))?;
+        let mut writer = ArrowWriter::try_new(File::create(output_path)?, schema.clone(), Some(props))?;
+
+        let mut group = Vec::with_capacity(rows_per_group);
+        let mut rows = 0;
+        let mut records = records.into_iter().peekable();
+        while let Some(record) = records.next() {
+            group.push(record?);
+            if group.len() >= rows_per_group || records.peek().is_none() {
+                writer.write(&batch(&schema, &group)?)?;
+                writer.flush()?;
+                rows += group.len();
+                group.clear();
+            }
+        }
+        close_writer(writer, rows)?;
+
+        Ok(rows)
+    }
+
+    fn menu_item_batch(schema: &SchemaRef, items: &[MenuItem]) -> Result<RecordBatch> {
+        let vendor_codes: StringArray = items.iter()
+            .map(|i| Some(i.vendor_code.as_str()))
             .collect();
 
-        let details: StringArray = details_strings.iter()
-            .map(|s| s.as_deref())
+        let menu_ids: Int64Array = items.iter()
+            .map(|i| i.menu_id)
             .collect();
 
-        let batch_numbers: Int32Array = vendors.iter()
-            .map(|v| Some(v.batch_number))
+        let categories: StringArray = items.iter()
+            .map(|i| i.category.as_deref())
             .collect();
 
-        let reviews: StringArray = reviews_strings.iter()
-            .map(|s| s.as_deref())
+        let product_ids: Int64Array = items.iter()
+            .map(|i| i.product_id)
             .collect();
 
-        let ratings: StringArray = ratings_strings.iter()
-            .map(|s| s.as_deref())
+        let names: StringArray = items.iter()
+            .map(|i| Some(i.name.as_str()))
             .collect();
 
-        let extraction_started_at: Int64Array = vendors.iter()
-            .map(|v| Some(v.extraction_started_at.timestamp()))
+        let descriptions: StringArray = items.iter()
+            .map(|i| i.description.as_deref())
             .collect();
 
-        let extraction_completed_at: Int64Array = vendors.iter()
-            .map(|v| Some(v.extraction_completed_at.timestamp()))
+        let prices: Float64Array = items.iter()
+            .map(|i| i.price)
             .collect();
 
-        let batch = RecordBatch::try_new(
+        let available: BooleanArray = items.iter()
+            .map(|i| Some(i.is_available))
+            .collect();
+
+        Ok(RecordBatch::try_new(
             schema.clone(),
             vec![
-                Arc::new(codes),
+                Arc::new(vendor_codes),
+                Arc::new(menu_ids),
+                Arc::new(categories),
+                Arc::new(product_ids),
                 Arc::new(names),
-                Arc::new(details),
-                Arc::new(batch_numbers),
-                Arc::new(reviews),
-                Arc::new(ratings),
-                Arc::new(extraction_started_at),
-                Arc::new(extraction_completed_at),
+                Arc::new(descriptions),
+                Arc::new(prices),
+                Arc::new(available),
+                Arc::new(Int32Array::from(vec![SCHEMA_VERSION; items.len()])),
             ],
-        )?;
+        )?)
+    }
 
-        let file = File::create(output_path)?;
-        let mut writer = ArrowWriter::try_new(file, schema, None)?;
-        writer.write(&batch)?;
-        writer.close()?;
+    fn review_batch(schema: &SchemaRef, reviews: &[Review]) -> Result<RecordBatch> {
+        let uuids: StringArray = reviews.iter()
+            .map(|r| Some(r.uuid.as_str()))
+            .collect();
+
+        let vendor_codes: StringArray = reviews.iter()
+            .map(|r| Some(r.vendor_code.as_str()))
+            .collect();
+
+        let overall_ratings: Float64Array = reviews.iter()
+            .map(|r| r.overall_rating)
+            .collect();
+
+        let texts: StringArray = reviews.iter()
+            .map(|r| r.text.as_deref())
+            .collect();
+
+        let created_at = TimestampMillisecondArray::from(
+            reviews.iter()
+                .map(|r| r.created_at.map(|t| t.timestamp_millis()))
+                .collect::<Vec<_>>()
+        ).with_timezone("UTC");
 
+        let mut dish_names = ListBuilder::new(StringBuilder::new());
+        for review in reviews {
+            for dish in &review.dish_names {
+                dish_names.values().append_value(dish);
+            }
+            dish_names.append(true);
+        }
+
+        let reviewer_id_hashes: StringArray = reviews.iter()
+            .map(|r| r.reviewer_id_hash.as_deref())
+            .collect();
+
+        Ok(RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(uuids),
+                Arc::new(vendor_codes),
+                Arc::new(overall_ratings),
+                Arc::new(texts),
+                Arc::new(created_at),
+                Arc::new(dish_names.finish()),
+                Arc::new(reviewer_id_hashes),
+                Arc::new(Int32Array::from(vec![SCHEMA_VERSION; reviews.len()])),
+            ],
+        )?)
+    }
+
+    /// Whether `parts` should be merged before upload: more than one part and a
+    /// combined size no larger than `max_bytes`.
+    pub fn should_compact(parts: &[PathBuf], max_bytes: u64) -> Result<bool> {
+        if parts.len() < 2 {
+            return Ok(false);
+        }
+        let mut total = 0u64;
+        for part in parts {
+            total += std::fs::metadata(part)?.len();
+        }
+        Ok(total <= max_bytes)
+    }
+
+    /// Streams the batches of every part into a single Parquet file, keeping the
+    /// first part's schema and key-value metadata with the merged
+    /// `record_count`. Returns the merged row count.
+    pub fn compact_files(parts: &[PathBuf], output_path: &Path, config: &ParquetConfig) -> Result<usize> {
+        let Some(first) = parts.first() else {
+            return Err(Error::Storage("No Parquet parts to compact".to_string()));
+        };
+
+        let (schema, key_value_metadata) = {
+            let builder = ParquetRecordBatchReaderBuilder::try_new(File::open(first)?)?;
+            let key_value_metadata = builder.metadata()
+                .file_metadata()
+                .key_value_metadata()
+                .map(|kv| kv.iter()
+                    // The writer embeds its own Arrow schema entry
+                    .filter(|entry| entry.key != "ARROW:schema" && entry.key != "record_count")
+                    .cloned()
+                    .collect::<Vec<_>>());
+            (builder.schema().clone(), key_value_metadata)
+        };
+
+        let props = writer_properties(config, key_value_metadata)?;
+        let mut writer = ArrowWriter::try_new(File::create(output_path)?, schema.clone(), Some(props))?;
+
+        let mut rows = 0;
+        for part in parts {
+            let builder = ParquetRecordBatchReaderBuilder::try_new(File::open(part)?)?;
+            Self::ensure_same_fields(&schema, builder.schema(), part)?;
+            for batch in builder.build()? {
+                let batch = batch?;
+                rows += batch.num_rows();
+                writer.write(&batch)?;
+            }
+        }
+        close_writer(writer, rows)?;
+
+        Ok(rows)
+    }
+
+    fn ensure_same_fields(expected: &SchemaRef, actual: &SchemaRef, part: &Path) -> Result<()> {
+        if expected.fields() != actual.fields() {
+            return Err(Error::Storage(format!(
+                "Cannot compact {}: schema differs from the first part",
+                part.display()
+            )));
+        }
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::{Array, ListArray, StructArray};
+    use chrono::{TimeZone, Timelike};
+    use crate::models::RatingScore;
+    use parquet::file::properties::ReaderProperties;
+    use parquet::file::serialized_reader::ReadOptionsBuilder;
+    use crate::storage::json::RecordStream;
+
+    fn meta() -> ParquetMeta {
+        ParquetMeta {
+            run_id: "test".to_string(),
+            city_id: "1".to_string(),
+            country: "pk".to_string(),
+            extraction_date: NaiveDate::from_ymd_opt(2024, 5, 1).unwrap(),
+        }
+    }
+
+    fn vendor(index: usize) -> Vendor {
+        let mut vendor = Vendor::stub(&format!("v{}", index), 1, None, "ok");
+        vendor.name = format!("Vendor {}", index);
+        vendor
+    }
+
+    /// Rows of each row group of a Parquet file, in order.
+    fn row_group_rows(path: &Path) -> Vec<i64> {
+        let reader = SerializedFileReader::new(File::open(path).unwrap()).unwrap();
+        reader.metadata().row_groups().iter().map(|group| group.num_rows()).collect()
+    }
+
+    #[test]
+    fn group_is_full_at_its_rows_or_byte_budget() {
+        let mut columns = VendorColumns::with_capacity(4, false);
+        // Each vendor adds 100 bytes of name, the fullest string column
+        let append = |columns: &mut VendorColumns| {
+            let mut vendor = vendor(0);
+            vendor.name = "n".repeat(100);
+            columns.append(&vendor);
+        };
+
+        append(&mut columns);
+        append(&mut columns);
+        assert_eq!(columns.string_bytes(), 200);
+        assert!(!columns.group_full(4, 250));
+        append(&mut columns);
+        assert!(columns.group_full(4, 250));
+        assert!(!columns.group_full(4, 301));
+        append(&mut columns);
+        assert!(columns.group_full(4, 1 << 20));
+
+        columns.finish(&vendor_schema(false)).unwrap();
+        assert_eq!(columns.string_bytes(), 0);
+        assert!(!columns.group_full(4, 250));
+    }
+
+    #[test]
+    fn row_groups_split_at_the_byte_budget() {
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("vendors.parquet");
+        // A group is written as soon as its names reach the 250 byte budget
+        let vendors = [200, 100, 50, 50, 300, 10].into_iter().enumerate().map(|(index, length)| {
+            let mut vendor = vendor(index);
+            vendor.name = "n".repeat(length);
+            Ok(vendor)
+        });
+        let config = ParquetConfig { rows_per_group: 1000, ..ParquetConfig::default() };
+
+        let (_, null_rates) =
+            ParquetConverter::write_vendors(vendors, File::create(&output).unwrap(), &meta(), &config, 250).unwrap();
+
+        assert_eq!(null_rates.rows(), 6);
+        assert_eq!(row_group_rows(&output), [2, 3, 1]);
+    }
+
+    #[test]
+    fn vendor_bytes_read_back() {
+        let config = ParquetConfig { rows_per_group: 2, ..ParquetConfig::default() };
+        let (bytes, null_rates) =
+            ParquetConverter::convert_vendors_to_bytes((0..5).map(|index| Ok(vendor(index))), &meta(), &config).unwrap();
+        assert_eq!(null_rates.rows(), 5);
+
+        let builder = ParquetRecordBatchReaderBuilder::try_new(bytes).unwrap();
+        assert_eq!(builder.schema().fields(), vendor_schema(false).fields());
+
+        let batches: Vec<RecordBatch> = builder.build().unwrap().collect::<std::result::Result<_, _>>().unwrap();
+        assert_eq!(batches.iter().map(RecordBatch::num_rows).sum::<usize>(), 5);
+        let codes: Vec<String> = batches
+            .iter()
+            .flat_map(|batch| {
+                let codes = batch.column_by_name("code").unwrap().as_any().downcast_ref::<StringArray>().unwrap();
+                codes.iter().map(|code| code.unwrap().to_string()).collect::<Vec<_>>()
+            })
+            .collect();
+        assert_eq!(codes, ["v0", "v1", "v2", "v3", "v4"]);
+    }
+
+    /// A vendor whose extraction timestamps have sub-second parts.
+    fn timed_vendor() -> Vendor {
+        let mut vendor = vendor(0);
+        vendor.extraction_started_at = Utc.timestamp_millis_opt(1_741_944_600_123).unwrap();
+        vendor.extraction_completed_at = Utc.timestamp_millis_opt(1_741_944_601_987).unwrap();
+        vendor
+    }
+
+    fn first_batch(bytes: Bytes) -> RecordBatch {
+        ParquetRecordBatchReaderBuilder::try_new(bytes).unwrap().build().unwrap().next().unwrap().unwrap()
+    }
+
+    #[test]
+    fn extraction_timestamps_read_back_as_utc_millis() {
+        let vendor = timed_vendor();
+        let (bytes, _) =
+            ParquetConverter::convert_vendors_to_bytes([Ok(vendor.clone())], &meta(), &ParquetConfig::default()).unwrap();
+
+        let batch = first_batch(bytes);
+        for (name, expected) in [
+            ("extraction_started_at", vendor.extraction_started_at),
+            ("extraction_completed_at", vendor.extraction_completed_at),
+        ] {
+            let column = batch.column_by_name(name).unwrap();
+            assert_eq!(column.data_type(), &utc_millis(), "{}", name);
+            let times = column.as_any().downcast_ref::<TimestampMillisecondArray>().unwrap();
+            let read = Utc.timestamp_millis_opt(times.value(0)).unwrap();
+            assert_eq!(read, expected, "{}", name);
+        }
+    }
+
+    #[test]
+    fn legacy_timestamps_are_int64_epoch_seconds() {
+        let vendor = timed_vendor();
+        let config = ParquetConfig { legacy_int64_timestamps: true, ..ParquetConfig::default() };
+        let (bytes, _) = ParquetConverter::convert_vendors_to_bytes([Ok(vendor.clone())], &meta(), &config).unwrap();
+
+        let batch = first_batch(bytes);
+        for (name, expected) in [
+            ("extraction_started_at", vendor.extraction_started_at),
+            ("extraction_completed_at", vendor.extraction_completed_at),
+        ] {
+            let column = batch.column_by_name(name).unwrap();
+            assert_eq!(column.data_type(), &DataType::Int64, "{}", name);
+            let seconds = column.as_any().downcast_ref::<Int64Array>().unwrap();
+            let read = Utc.timestamp_opt(seconds.value(0), 0).unwrap();
+            assert_eq!(read, expected.with_nanosecond(0).unwrap(), "{}", name);
+        }
+    }
+
+    fn ratings(scores: &[(i32, i32, i32)]) -> RatingsDistribution {
+        RatingsDistribution {
+            total_count: scores.iter().map(|(_, count, _)| count).sum(),
+            created_at: "2024-05-01T10:00:00Z".to_string(),
+            updated_at: "2024-05-02 11:30:00".to_string(),
+            ratings: scores.iter()
+                .map(|&(score, count, percentage)| RatingScore { score, count, percentage })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn nested_ratings_and_reviews_read_back() {
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("vendors.parquet");
+        let mut rated = vendor(0);
+        rated.ratings = Some(ratings(&[(5, 30, 75), (1, 10, 25)]));
+        rated.reviews = Some(vec![serde_json::json!({ "uuid": "r1", "text": "Great biryani" })]);
+        let mut unrated = vendor(1);
+        unrated.ratings = Some(ratings(&[]));
+
+        ParquetConverter::convert_vendors_to_parquet(
+            [Ok(rated), Ok(unrated), Ok(vendor(2))],
+            &output,
+            &meta(),
+            &ParquetConfig::default(),
+        )
+        .unwrap();
+
+        let mut reader = ParquetRecordBatchReaderBuilder::try_new(File::open(&output).unwrap()).unwrap().build().unwrap();
+        let batch = reader.next().unwrap().unwrap();
+        assert!(reader.next().is_none());
+        let list = batch.column_by_name("ratings").unwrap().as_any().downcast_ref::<ListArray>().unwrap();
+        assert_eq!(list.len(), 3);
+
+        let first = list.value(0);
+        let entries = first.as_any().downcast_ref::<StructArray>().unwrap();
+        let column = |name: &str| -> Vec<i32> {
+            entries.column_by_name(name).unwrap()
+                .as_any().downcast_ref::<Int32Array>().unwrap()
+                .values().to_vec()
+        };
+        assert_eq!(column("score"), [5, 1]);
+        assert_eq!(column("count"), [30, 10]);
+        assert_eq!(column("percentage"), [75, 25]);
+        // An empty distribution is an empty list, no ratings at all a null
+        assert!(list.is_valid(1));
+        assert_eq!(list.value(1).len(), 0);
+        assert!(list.is_null(2));
+
+        let totals = batch.column_by_name("ratings_total_count").unwrap().as_any().downcast_ref::<Int32Array>().unwrap();
+        assert_eq!(totals.value(0), 40);
+        assert!(totals.is_null(2));
+        let created_at = batch.column_by_name("ratings_created_at").unwrap()
+            .as_any().downcast_ref::<TimestampMillisecondArray>().unwrap();
+        assert_eq!(created_at.value(0), 1_714_557_600_000);
+        let updated_at = batch.column_by_name("ratings_updated_at").unwrap()
+            .as_any().downcast_ref::<TimestampMillisecondArray>().unwrap();
+        assert_eq!(updated_at.value(0), 1_714_649_400_000);
+
+        let reviews = batch.column_by_name("reviews").unwrap().as_any().downcast_ref::<StringArray>().unwrap();
+        let nested: serde_json::Value = serde_json::from_str(reviews.value(0)).unwrap();
+        assert_eq!(nested, serde_json::json!([{ "uuid": "r1", "text": "Great biryani" }]));
+        assert!(reviews.is_null(1));
+    }
+
+    #[test]
+    fn ndjson_conversion_reports_skipped_lines() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("vendors.json");
+        let output = dir.path().join("vendors.parquet");
+        let line = |index| serde_json::to_string(&vendor(index)).unwrap();
+        let lines = [line(0), line(1), "{not json".to_string(), String::new(), line(2), r#"{"code": "v3"}"#.to_string()];
+        std::fs::write(&input, lines.join("\n")).unwrap();
+
+        let stats = ParquetConverter::convert_ndjson_file(&input, &output, &meta(), &ParquetConfig::default()).unwrap();
+
+        assert_eq!(stats.rows_converted, 3);
+        assert_eq!(stats.rows_skipped, 2);
+        let skipped: Vec<usize> = stats.skipped.iter().map(|skipped| skipped.line).collect();
+        assert_eq!(skipped, [3, 6]);
+        assert_eq!(stats.output_bytes, std::fs::metadata(&output).unwrap().len());
+        assert_eq!(row_group_rows(&output), [3]);
+    }
+
+    #[test]
+    fn menu_items_stream_a_row_group_at_a_time() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("menu_items.json");
+        let output = dir.path().join("menu_items.parquet");
+        let items: Vec<MenuItem> = (0..5)
+            .map(|index| MenuItem {
+                vendor_code: format!("v{}", index),
+                menu_id: Some(1),
+                category: None,
+                product_id: Some(index),
+                name: format!("Dish {}", index),
+                description: None,
+                price: Some(2.5),
+                is_available: true,
+            })
+            .collect();
+        // The array layout the JSON writer produces, one record per line
+        let lines: Vec<String> = items.iter().map(|item| serde_json::to_string(item).unwrap()).collect();
+        std::fs::write(&input, format!("[\n{}\n]\n", lines.join(",\n"))).unwrap();
+        let config = ParquetConfig { rows_per_group: 2, ..ParquetConfig::default() };
+
+        let rows = ParquetConverter::convert_menu_items_to_parquet(
+            RecordStream::<MenuItem>::open(&input).unwrap(),
+            &output,
+            &meta(),
+            &config,
+        )
+        .unwrap();
+
+        assert_eq!(rows, 5);
+        assert_eq!(row_group_rows(&output), [2, 2, 1]);
+    }
+
+    #[test]
+    fn rotated_parts_compact_into_one_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let parts = [dir.path().join("vendors_part0001.parquet"), dir.path().join("vendors_part0002.parquet")];
+        for (part, range) in parts.iter().zip([0..3, 3..5]) {
+            ParquetConverter::convert_vendors_to_parquet(
+                range.map(|index| Ok(vendor(index))),
+                part,
+                &meta(),
+                &ParquetConfig::default(),
+            )
+            .unwrap();
+        }
+        let output = dir.path().join("vendors.parquet");
+
+        assert!(ParquetConverter::should_compact(&parts, 1 << 20).unwrap());
+        assert!(!ParquetConverter::should_compact(&parts[..1], 1 << 20).unwrap());
+        let rows = ParquetConverter::compact_files(&parts, &output, &ParquetConfig::default()).unwrap();
+
+        assert_eq!(rows, 5);
+        assert_eq!(row_group_rows(&output).iter().sum::<i64>(), 5);
+        let reader = SerializedFileReader::new(File::open(&output).unwrap()).unwrap();
+        let record_count = reader.metadata().file_metadata().key_value_metadata().unwrap()
+            .iter()
+            .find(|entry| entry.key == "record_count")
+            .and_then(|entry| entry.value.clone());
+        assert_eq!(record_count.as_deref(), Some("5"));
+    }
+
+    /// The footer's key-value metadata of a Parquet file.
+    fn footer(path: &Path) -> std::collections::BTreeMap<String, Option<String>> {
+        let reader = SerializedFileReader::new(File::open(path).unwrap()).unwrap();
+        reader.metadata().file_metadata().key_value_metadata().unwrap()
+            .iter()
+            .filter(|entry| entry.key != "ARROW:schema")
+            .map(|entry| (entry.key.clone(), entry.value.clone()))
+            .collect()
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn non_utf8_output_path_is_written() {
+        use std::os::unix::ffi::OsStrExt;
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join(std::ffi::OsStr::from_bytes(b"vendors_\xff.parquet"));
+
+        ParquetConverter::convert_vendors_to_parquet((0..3).map(|index| Ok(vendor(index))), &output, &meta(), &ParquetConfig::default())
+            .unwrap();
+
+        assert_eq!(row_group_rows(&output), [3]);
+    }
+
+    #[test]
+    fn footer_records_the_file_provenance() {
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("vendors.parquet");
+
+        ParquetConverter::convert_vendors_to_parquet((0..3).map(|index| Ok(vendor(index))), &output, &meta(), &ParquetConfig::default())
+            .unwrap();
+
+        let expected = [
+            ("run_id", "test".to_string()),
+            ("city_id", "1".to_string()),
+            ("country", "pk".to_string()),
+            ("extraction_date", "2024-05-01".to_string()),
+            ("crate_version", env!("CARGO_PKG_VERSION").to_string()),
+            ("schema_version", SCHEMA_VERSION.to_string()),
+            ("record_count", "3".to_string()),
+        ];
+        let expected: std::collections::BTreeMap<String, Option<String>> = expected.into_iter()
+            .map(|(key, value)| (key.to_string(), Some(value)))
+            .collect();
+        assert_eq!(footer(&output), expected);
+    }
+
+    /// Per row group, whether each column chunk in `columns` has a bloom filter.
+    fn bloom_filters(path: &Path, columns: &[&str]) -> Vec<Vec<bool>> {
+        let reader = SerializedFileReader::new(File::open(path).unwrap()).unwrap();
+        reader.metadata().row_groups().iter()
+            .map(|group| {
+                columns.iter()
+                    .map(|name| {
+                        group.columns().iter()
+                            .find(|column| column.column_path().string() == *name)
+                            .unwrap()
+                            .bloom_filter_offset()
+                            .is_some()
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn vendors_file(dir: &Path, config: &ParquetConfig) -> PathBuf {
+        let output = dir.join("vendors.parquet");
+        ParquetConverter::convert_vendors_to_parquet((0..5).map(|index| Ok(vendor(index))), &output, &meta(), config)
+            .unwrap();
+        output
+    }
+
+    #[test]
+    fn code_column_gets_a_bloom_filter_in_every_row_group() {
+        let dir = tempfile::tempdir().unwrap();
+        let output = vendors_file(dir.path(), &ParquetConfig { rows_per_group: 2, ..ParquetConfig::default() });
+
+        assert_eq!(bloom_filters(&output, &["code", "name", "status"]), vec![vec![true, false, false]; 3]);
+        assert!(bloom_filter_bytes(File::open(&output).unwrap()).unwrap() > 0);
+
+        let options = ReadOptionsBuilder::new()
+            .with_reader_properties(ReaderProperties::builder().set_read_bloom_filter(true).build())
+            .build();
+        let reader = SerializedFileReader::new_with_options(File::open(&output).unwrap(), options).unwrap();
+        let first_group = reader.get_row_group(0).unwrap();
+        let filter = first_group.get_column_bloom_filter(0).unwrap();
+        assert!(filter.check("v0"));
+        assert!(filter.check("v1"));
+    }
+
+    #[test]
+    fn bloom_filter_can_be_turned_off() {
+        let dir = tempfile::tempdir().unwrap();
+        let output = vendors_file(dir.path(), &ParquetConfig { code_bloom_filter: false, ..ParquetConfig::default() });
+
+        assert_eq!(bloom_filters(&output, &["code"]), [[false]]);
+        assert_eq!(bloom_filter_bytes(File::open(&output).unwrap()).unwrap(), 0);
+    }
+
+    fn field_names(schema: &SchemaRef) -> Vec<&str> {
+        schema.fields().iter().map(|field| field.name().as_str()).collect()
+    }
+
+    // These lock the column layout: a test that fails here needs a
+    // SCHEMA_VERSION bump and a SCHEMA_CHANGES note, not just a new list
+
+    #[test]
+    fn vendor_schema_fields_are_locked() {
+        let expected = [
+            "code", "name", "details", "batch_number", "run_id", "reviews", "ratings", "discounts",
+            "delivery_fee", "delivery_eta_minutes", "unchanged", "filtered", "status", "attempts",
+            "content_hash", "changed", "extraction_started_at", "extraction_completed_at",
+            "extraction_duration_ms", "rating", "review_count", "latitude", "longitude", "address_city",
+            "cuisines", "minimum_order_amount", "is_active", "chain_code", "ratings_total_count",
+            "ratings_created_at", "ratings_updated_at", "schema_version",
+        ];
+        assert_eq!(field_names(&vendor_schema(false)), expected);
+        assert_eq!(field_names(&vendor_schema(true)), expected);
+    }
+
+    #[test]
+    fn menu_item_schema_fields_are_locked() {
+        assert_eq!(
+            field_names(&menu_item_schema()),
+            ["vendor_code", "menu_id", "category", "product_id", "name", "description", "price", "is_available", "schema_version"],
+        );
+    }
+
+    #[test]
+    fn review_schema_fields_are_locked() {
+        assert_eq!(
+            field_names(&review_schema()),
+            ["uuid", "vendor_code", "overall_rating", "text", "created_at", "dish_names", "reviewer_id_hash", "schema_version"],
+        );
+    }
+
+    #[test]
+    fn review_files_carry_the_schema_version() {
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("reviews.parquet");
+        let review = Review {
+            uuid: "r1".to_string(),
+            vendor_code: "v1".to_string(),
+            overall_rating: Some(4.0),
+            text: None,
+            created_at: None,
+            dish_names: Vec::new(),
+            reviewer_id_hash: None,
+        };
+
+        ParquetConverter::convert_reviews_to_parquet([Ok(review)], &output, &meta(), &ParquetConfig::default())
+            .unwrap();
+
+        let reader = SerializedFileReader::new(File::open(&output).unwrap()).unwrap();
+        let footer_version = reader.metadata().file_metadata().key_value_metadata().unwrap()
+            .iter()
+            .find(|entry| entry.key == "schema_version")
+            .and_then(|entry| entry.value.clone());
+        assert_eq!(footer_version, Some(SCHEMA_VERSION.to_string()));
+        let batch = ParquetRecordBatchReaderBuilder::try_new(File::open(&output).unwrap()).unwrap()
+            .build().unwrap()
+            .next().unwrap().unwrap();
+        let versions = batch.column_by_name("schema_version").unwrap()
+            .as_any().downcast_ref::<Int32Array>().unwrap();
+        assert_eq!(versions.value(0), SCHEMA_VERSION);
+    }
+}
@@ -0,0 +1,187 @@
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use bytes::Bytes;
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use crate::error::Result;
+use crate::storage::atomic::write_json_atomic;
+use crate::storage::quality::NullRates;
+use crate::utils::file_safe_name;
+
+/// Last completed step of a city's extract → convert → upload pipeline.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CityPhase {
+    Extracted,
+    Converted,
+    Uploaded,
+    /// Given up on without uploading; the next run extracts the city again.
+    Failed,
+}
+
+/// A converted file and the object key it is destined for.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PendingUpload {
+    pub dataset: String,
+    pub path: PathBuf,
+    pub s3_key: String,
+    pub tagging: Option<String>,
+    pub rows: usize,
+    #[serde(default)]
+    pub uploaded: bool,
+    /// Size of the file, recorded before it is uploaded.
+    #[serde(default)]
+    pub bytes: u64,
+    /// Hex SHA-256 of the file, recorded before it is uploaded.
+    #[serde(default)]
+    pub sha256: Option<String>,
+    /// Parquet built in memory (`parquet.in_memory`), uploaded instead of a
+    /// file at `path`, which is never written. It isn't stored in the marker,
+    /// so a resumed upload converts `source` again.
+    #[serde(skip)]
+    pub contents: Option<Bytes>,
+    /// JSON the in-memory Parquet was converted from.
+    #[serde(default)]
+    pub source: Option<PathBuf>,
+    /// Null counts of a vendor Parquet file, for the run manifest.
+    #[serde(default)]
+    pub null_rates: Option<NullRates>,
+    /// File names of the parts merged into this file by
+    /// `parquet.compact_before_upload`.
+    #[serde(default)]
+    pub compacted_from: Vec<String>,
+}
+
+impl PendingUpload {
+    /// A file of `rows` records waiting to be uploaded to `s3_key`, untagged
+    /// and on disk.
+    pub fn new(dataset: &str, path: PathBuf, s3_key: String, rows: usize) -> Self {
+        Self {
+            dataset: dataset.to_string(),
+            path,
+            s3_key,
+            tagging: None,
+            rows,
+            uploaded: false,
+            bytes: 0,
+            sha256: None,
+            contents: None,
+            source: None,
+            null_rates: None,
+            compacted_from: Vec::new(),
+        }
+    }
+
+    /// Records the file's size and checksum for the run manifest; the file
+    /// itself is removed once uploaded.
+    pub fn record_checksum(&mut self) -> Result<()> {
+        let mut hasher = Sha256::new();
+        self.bytes = match &self.contents {
+            Some(contents) => {
+                hasher.update(contents);
+                contents.len() as u64
+            }
+            None => io::copy(&mut File::open(&self.path)?, &mut hasher)?,
+        };
+        self.sha256 = Some(format!("{:x}", hasher.finalize()));
+        Ok(())
+    }
+}
+
+/// Per-city marker persisted in the output directory so a killed run can resume
+/// from the last completed phase instead of re-extracting.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PhaseMarker {
+    pub city_id: String,
+    pub phase: CityPhase,
+    pub json_path: PathBuf,
+    /// Logical run date the city's partitions are keyed by.
+    #[serde(default)]
+    pub partition_date: Option<NaiveDate>,
+    /// The listing was cut short (budget, page cap or an early empty page).
+    #[serde(default)]
+    pub listing_incomplete: bool,
+    /// Vendors still failing after the retry pass, when there were any.
+    #[serde(default)]
+    pub dead_letter_path: Option<PathBuf>,
+    /// Vendor files finished by rotation before `json_path`. Their Parquet
+    /// was uploaded during extraction; the JSON is kept until the city is
+    /// uploaded, for missing vendor detection.
+    #[serde(default)]
+    pub vendor_parts: Vec<PathBuf>,
+    #[serde(default)]
+    pub menu_items_path: Option<PathBuf>,
+    #[serde(default)]
+    pub reviews_path: Option<PathBuf>,
+    /// Stubs of skipped vendors, with `output.skipped_vendor_output: separate`.
+    #[serde(default)]
+    pub skipped_vendors_path: Option<PathBuf>,
+    /// The city's extraction report.
+    #[serde(default)]
+    pub report_path: Option<PathBuf>,
+    #[serde(default)]
+    pub uploads: Vec<PendingUpload>,
+    #[serde(with = "chrono::serde::ts_seconds")]
+    pub updated_at: DateTime<Utc>,
+}
+
+impl PhaseMarker {
+    pub fn extracted(
+        city_id: &str,
+        json_path: PathBuf,
+        partition_date: NaiveDate,
+        listing_incomplete: bool,
+    ) -> Self {
+        Self {
+            city_id: city_id.to_string(),
+            phase: CityPhase::Extracted,
+            json_path,
+            partition_date: Some(partition_date),
+            listing_incomplete,
+            dead_letter_path: None,
+            vendor_parts: Vec::new(),
+            menu_items_path: None,
+            reviews_path: None,
+            skipped_vendors_path: None,
+            report_path: None,
+            uploads: Vec::new(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    pub fn path(output_dir: &Path, city_id: &str) -> PathBuf {
+        output_dir.join("phases").join(format!("city_{}.json", file_safe_name(city_id)))
+    }
+
+    pub fn load(output_dir: &Path, city_id: &str) -> Result<Option<Self>> {
+        let path = Self::path(output_dir, city_id);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let bytes = fs::read(&path)?;
+        Ok(Some(serde_json::from_slice(&bytes)?))
+    }
+
+    /// Whether the previous run stopped before the upload finished.
+    pub fn is_pending(&self) -> bool {
+        matches!(self.phase, CityPhase::Extracted | CityPhase::Converted)
+    }
+
+    /// When the city's upload finished, if its last run got that far.
+    pub fn uploaded_at(&self) -> Option<DateTime<Utc>> {
+        (self.phase == CityPhase::Uploaded).then_some(self.updated_at)
+    }
+
+    /// Advances the marker to `phase` and persists it (write temp + rename).
+    pub fn advance(&mut self, output_dir: &Path, phase: CityPhase) -> Result<()> {
+        self.phase = phase;
+        self.store(output_dir)
+    }
+
+    pub fn store(&mut self, output_dir: &Path) -> Result<()> {
+        self.updated_at = Utc::now();
+        write_json_atomic(&Self::path(output_dir, &self.city_id), self)
+    }
+}
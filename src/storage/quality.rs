@@ -0,0 +1,167 @@
+use std::collections::{BTreeMap, HashMap};
+use arrow::record_batch::RecordBatch;
+use serde::{Deserialize, Serialize};
+use crate::config::{BreachAction, QualityConfig};
+
+/// Null counts per column accumulated over every batch written to a Parquet file.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct NullRates {
+    rows: usize,
+    null_counts: BTreeMap<String, usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct QualityBreach {
+    pub column: String,
+    pub null_rate: f64,
+    pub max_null_rate: f64,
+}
+
+/// What `quality.on_breach` makes of a file's null rates.
+#[derive(Debug, Clone, PartialEq)]
+pub enum QualityVerdict {
+    Passed,
+    /// Uploaded anyway, tagged `quality=degraded`.
+    Tagged(Vec<QualityBreach>),
+    /// The city fails without uploading.
+    Failed(Vec<QualityBreach>),
+}
+
+impl NullRates {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn observe(&mut self, batch: &RecordBatch) {
+        self.rows += batch.num_rows();
+        for (field, column) in batch.schema().fields().iter().zip(batch.columns()) {
+            *self.null_counts.entry(field.name().clone()).or_insert(0) += column.null_count();
+        }
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    /// Adds another file's counts, for the rates of a city written in parts.
+    pub fn merge(&mut self, other: &NullRates) {
+        self.rows += other.rows;
+        for (column, nulls) in &other.null_counts {
+            *self.null_counts.entry(column.clone()).or_insert(0) += nulls;
+        }
+    }
+
+    pub fn rate(&self, column: &str) -> Option<f64> {
+        let nulls = *self.null_counts.get(column)?;
+        if self.rows == 0 {
+            return Some(0.0);
+        }
+        Some(nulls as f64 / self.rows as f64)
+    }
+
+    pub fn rates(&self) -> BTreeMap<String, f64> {
+        self.null_counts.keys()
+            .filter_map(|column| self.rate(column).map(|rate| (column.clone(), rate)))
+            .collect()
+    }
+
+    /// Columns whose null rate exceeds the configured threshold. Thresholds naming
+    /// columns that aren't in the schema are ignored.
+    pub fn breaches(&self, max_null_rate: &HashMap<String, f64>) -> Vec<QualityBreach> {
+        let mut breaches: Vec<QualityBreach> = max_null_rate.iter()
+            .filter_map(|(column, max)| {
+                let rate = self.rate(column)?;
+                (rate > *max).then(|| QualityBreach {
+                    column: column.clone(),
+                    null_rate: rate,
+                    max_null_rate: *max,
+                })
+            })
+            .collect();
+        breaches.sort_by(|a, b| a.column.cmp(&b.column));
+        breaches
+    }
+
+    /// The breaches of `config.max_null_rate` and what `config.on_breach`
+    /// does about them.
+    pub fn verdict(&self, config: &QualityConfig) -> QualityVerdict {
+        let breaches = self.breaches(&config.max_null_rate);
+        if breaches.is_empty() {
+            return QualityVerdict::Passed;
+        }
+        match config.on_breach {
+            BreachAction::Fail => QualityVerdict::Failed(breaches),
+            BreachAction::Tag => QualityVerdict::Tagged(breaches),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use arrow::array::StringArray;
+    use arrow::datatypes::{DataType, Field, Schema};
+
+    /// Rates of four rows, `details` null in three of them.
+    fn null_rates() -> NullRates {
+        let schema = Schema::new(vec![
+            Field::new("code", DataType::Utf8, false),
+            Field::new("details", DataType::Utf8, true),
+        ]);
+        let batch = RecordBatch::try_new(
+            Arc::new(schema),
+            vec![
+                Arc::new(StringArray::from(vec!["a", "b", "c", "d"])),
+                Arc::new(StringArray::from(vec![Some("{}"), None, None, None])),
+            ],
+        )
+        .unwrap();
+        let mut null_rates = NullRates::new();
+        null_rates.observe(&batch);
+        null_rates
+    }
+
+    fn config(max_details: f64, on_breach: BreachAction) -> QualityConfig {
+        QualityConfig {
+            max_null_rate: HashMap::from([("details".to_string(), max_details), ("missing".to_string(), 0.0)]),
+            on_breach,
+            ..QualityConfig::default()
+        }
+    }
+
+    fn details_breach() -> Vec<QualityBreach> {
+        vec![QualityBreach { column: "details".to_string(), null_rate: 0.75, max_null_rate: 0.5 }]
+    }
+
+    #[test]
+    fn breach_tags_the_upload_with_on_breach_tag() {
+        assert_eq!(null_rates().verdict(&config(0.5, BreachAction::Tag)), QualityVerdict::Tagged(details_breach()));
+    }
+
+    #[test]
+    fn breach_fails_the_city_with_on_breach_fail() {
+        assert_eq!(null_rates().verdict(&config(0.5, BreachAction::Fail)), QualityVerdict::Failed(details_breach()));
+    }
+
+    #[test]
+    fn rates_within_their_thresholds_pass() {
+        let null_rates = null_rates();
+
+        assert_eq!(null_rates.verdict(&config(0.75, BreachAction::Fail)), QualityVerdict::Passed);
+        assert_eq!(null_rates.rates(), BTreeMap::from([("code".to_string(), 0.0), ("details".to_string(), 0.75)]));
+    }
+
+    #[test]
+    fn merged_parts_give_the_rates_of_the_whole_city() {
+        let mut city = null_rates();
+        let mut full = NullRates::new();
+        full.rows = 4;
+        full.null_counts = BTreeMap::from([("code".to_string(), 0), ("details".to_string(), 0)]);
+
+        city.merge(&full);
+
+        assert_eq!(city.rows(), 8);
+        assert_eq!(city.rate("details"), Some(0.375));
+    }
+}
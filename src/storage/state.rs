@@ -0,0 +1,164 @@
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use crate::error::Result;
+use crate::models::Vendor;
+use crate::storage::atomic::write_json_atomic;
+use crate::utils::file_safe_name;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SeenVendor {
+    pub name: String,
+    pub last_seen: NaiveDate,
+}
+
+/// Vendor codes seen in a city's listing by previous runs, kept locally so the
+/// next run can tell which vendors disappeared.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct VendorState {
+    pub city_id: String,
+    pub vendors: BTreeMap<String, SeenVendor>,
+    /// Codes the latest extraction processed without writing them (unchanged
+    /// in a delta run, omitted or dropped stubs). They count as seen by the
+    /// next comparison, which then clears them.
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub unwritten: BTreeSet<String>,
+}
+
+/// What the state keeps of a vendor in today's output, so a city's vendors
+/// don't all have to be held in memory to compare them.
+#[derive(Debug, Clone)]
+pub struct ObservedVendor {
+    pub code: String,
+    pub name: String,
+    pub has_details: bool,
+}
+
+impl From<&Vendor> for ObservedVendor {
+    fn from(vendor: &Vendor) -> Self {
+        Self {
+            code: vendor.code.clone(),
+            name: vendor.name.clone(),
+            has_details: vendor.details.is_some(),
+        }
+    }
+}
+
+/// A vendor present in the previous listing but absent from today's.
+#[derive(Debug, Clone, Serialize)]
+pub struct MissingVendor {
+    pub code: String,
+    pub last_seen_date: NaiveDate,
+    pub last_known_name: String,
+    pub city_id: String,
+}
+
+impl VendorState {
+    pub fn new(city_id: &str) -> Self {
+        Self {
+            city_id: city_id.to_string(),
+            vendors: BTreeMap::new(),
+            unwritten: BTreeSet::new(),
+        }
+    }
+
+    pub fn path(output_dir: &Path, city_id: &str) -> PathBuf {
+        output_dir.join("state").join(format!("vendors_city_{}.json", file_safe_name(city_id)))
+    }
+
+    pub fn load(output_dir: &Path, city_id: &str) -> Result<Option<Self>> {
+        let path = Self::path(output_dir, city_id);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let bytes = fs::read(&path)?;
+        Ok(Some(serde_json::from_slice(&bytes)?))
+    }
+
+    pub fn store(&self, output_dir: &Path) -> Result<()> {
+        write_json_atomic(&Self::path(output_dir, &self.city_id), self)
+    }
+
+    pub fn missing_from(&self, vendors: &[ObservedVendor]) -> Vec<MissingVendor> {
+        let today: HashSet<&str> = vendors.iter().map(|v| v.code.as_str()).collect();
+        self.vendors.iter()
+            .filter(|(code, _)| !today.contains(code.as_str()) && !self.unwritten.contains(*code))
+            .map(|(code, seen)| MissingVendor {
+                code: code.clone(),
+                last_seen_date: seen.last_seen,
+                last_known_name: seen.name.clone(),
+                city_id: self.city_id.clone(),
+            })
+            .collect()
+    }
+
+    /// Adds codes of vendors extracted without being written, to be counted
+    /// as seen by the next [`observe`](Self::observe).
+    pub fn record_unwritten<'a>(&mut self, codes: impl IntoIterator<Item = &'a String>) {
+        self.unwritten.extend(codes.into_iter().cloned());
+    }
+
+    /// Records today's vendors, the unwritten ones included. A complete
+    /// listing replaces the previous set; an incomplete one only refreshes
+    /// the vendors it saw.
+    pub fn observe(&mut self, vendors: &[ObservedVendor], seen_on: NaiveDate, complete: bool) {
+        let unwritten = std::mem::take(&mut self.unwritten);
+        if complete {
+            self.vendors.retain(|code, _| unwritten.contains(code));
+        }
+        for code in unwritten {
+            self.vendors.entry(code)
+                .or_insert_with(|| SeenVendor {
+                    name: "Unknown".to_string(),
+                    last_seen: seen_on,
+                })
+                .last_seen = seen_on;
+        }
+        for vendor in vendors {
+            let entry = self.vendors.entry(vendor.code.clone()).or_insert_with(|| SeenVendor {
+                name: vendor.name.clone(),
+                last_seen: seen_on,
+            });
+            entry.last_seen = seen_on;
+            // Keep the last real name rather than the "Unknown" stub placeholder
+            if vendor.has_details {
+                entry.name = vendor.name.clone();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn observed(code: &str) -> ObservedVendor {
+        ObservedVendor {
+            code: code.to_string(),
+            name: format!("Vendor {}", code),
+            has_details: true,
+        }
+    }
+
+    #[test]
+    fn unwritten_vendors_are_not_missing() {
+        let day1 = NaiveDate::from_ymd_opt(2024, 5, 1).unwrap();
+        let day2 = NaiveDate::from_ymd_opt(2024, 5, 2).unwrap();
+        let mut state = VendorState::new("1");
+        state.observe(&[observed("a"), observed("b"), observed("c")], day1, true);
+
+        state.record_unwritten(&[String::from("b")]);
+        let today = [observed("a")];
+        let missing: Vec<String> = state.missing_from(&today).into_iter().map(|vendor| vendor.code).collect();
+        assert_eq!(missing, ["c"]);
+
+        state.observe(&today, day2, true);
+        assert!(state.unwritten.is_empty());
+        let kept: Vec<&str> = state.vendors.keys().map(String::as_str).collect();
+        assert_eq!(kept, ["a", "b"]);
+        assert_eq!(state.vendors["b"].name, "Vendor b");
+        assert_eq!(state.vendors["b"].last_seen, day2);
+    }
+}
@@ -0,0 +1,560 @@
+//! An in-process stand-in for the S3 API, for `MinioStorage` tests: the
+//! client's requests are answered from an in-memory bucket instead of going
+//! over the network.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+use aws_sdk_s3::Client as S3Client;
+use aws_sdk_s3::config::{BehaviorVersion, Credentials, Region, RequestChecksumCalculation, ResponseChecksumValidation};
+use aws_sdk_s3::config::retry::RetryConfig;
+use aws_sdk_s3::primitives::ByteStream;
+use aws_smithy_runtime_api::client::http::{HttpClient, HttpConnector, HttpConnectorFuture, HttpConnectorSettings, SharedHttpConnector};
+use aws_smithy_runtime_api::client::orchestrator::{HttpRequest, HttpResponse};
+use aws_smithy_runtime_api::client::result::ConnectorError;
+use aws_smithy_runtime_api::client::runtime_components::RuntimeComponents;
+use aws_smithy_runtime_api::http::StatusCode;
+use aws_smithy_types::body::SdkBody;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use bytes::Bytes;
+use chrono::{DateTime, SecondsFormat, Utc};
+use md5::{Digest as _, Md5};
+use regex::Regex;
+use sha2::{Digest, Sha256};
+use url::Url;
+use crate::storage::minio::MinioStorage;
+
+/// The only bucket the mock serves.
+pub const BUCKET: &str = "test";
+
+#[derive(Debug)]
+struct StoredObject {
+    body: Bytes,
+    /// As sent by the client; S3 only reports checksums it was given.
+    checksum_sha256: Option<String>,
+    /// As sent by the client with a single-part put.
+    content_md5: Option<String>,
+    /// Quoted; the hex MD5 of the body, or of the parts' MD5s followed by
+    /// `-<parts>` for a multipart upload.
+    e_tag: String,
+    tagging: Option<String>,
+    last_modified: DateTime<Utc>,
+}
+
+#[derive(Debug)]
+struct MultipartUpload {
+    key: String,
+    tagging: Option<String>,
+    initiated: DateTime<Utc>,
+    parts: BTreeMap<i32, (Bytes, Option<String>)>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    objects: BTreeMap<String, StoredObject>,
+    uploads: BTreeMap<String, MultipartUpload>,
+    uploads_started: usize,
+    /// Operations served, in order, e.g. `PutObject`.
+    operations: Vec<String>,
+    aborted: Vec<String>,
+    list_page_size: usize,
+    failing_part: Option<i32>,
+    corrupt_writes: usize,
+    /// `HeadObject` responses still to report an ETag that matches nothing.
+    wrong_e_tags: usize,
+    /// Whether the bucket exists; `CreateBucket` creates it.
+    exists: bool,
+    /// `HeadBucket` requests still to be answered with a 503.
+    head_bucket_failures: usize,
+}
+
+/// Serves `head_bucket`, `create_bucket`, `put_object`, the multipart calls, `head_object`,
+/// `get_object`, `list_objects_v2` and `list_multipart_uploads` from memory.
+/// Clones share the bucket.
+#[derive(Debug, Clone, Default)]
+pub struct MockS3 {
+    bucket: Arc<Mutex<Bucket>>,
+}
+
+impl Default for Bucket {
+    fn default() -> Self {
+        Self {
+            objects: BTreeMap::new(),
+            uploads: BTreeMap::new(),
+            uploads_started: 0,
+            operations: Vec::new(),
+            aborted: Vec::new(),
+            list_page_size: 1000,
+            failing_part: None,
+            corrupt_writes: 0,
+            wrong_e_tags: 0,
+            exists: true,
+            head_bucket_failures: 0,
+        }
+    }
+}
+
+impl MockS3 {
+    /// Keys returned per `list_objects_v2` page.
+    pub fn with_list_page_size(self, page_size: usize) -> Self {
+        self.bucket.lock().unwrap().list_page_size = page_size.max(1);
+        self
+    }
+
+    /// A mock whose bucket doesn't exist until it is created.
+    pub fn without_bucket(self) -> Self {
+        self.bucket.lock().unwrap().exists = false;
+        self
+    }
+
+    /// A client sending its requests to the mock. Neither it nor the mock
+    /// retries, so every failure reaches the storage layer.
+    pub fn client(&self) -> S3Client {
+        let config = aws_sdk_s3::Config::builder()
+            .behavior_version(BehaviorVersion::latest())
+            .endpoint_url("http://s3.mock")
+            .region(Region::new("us-east-1"))
+            .credentials_provider(Credentials::new("test", "test", None, None, "test"))
+            .force_path_style(true)
+            .retry_config(RetryConfig::disabled())
+            .request_checksum_calculation(RequestChecksumCalculation::WhenRequired)
+            .response_checksum_validation(ResponseChecksumValidation::WhenRequired)
+            .http_client(self.clone())
+            .build();
+        S3Client::from_conf(config)
+    }
+
+    /// Storage over [`BUCKET`], with its default settings.
+    pub fn storage(&self) -> MinioStorage {
+        MinioStorage::with_client(self.client(), BUCKET)
+    }
+
+    /// Answers uploads of part `part_number` with a 403.
+    pub fn fail_part(&self, part_number: i32) {
+        self.bucket.lock().unwrap().failing_part = Some(part_number);
+    }
+
+    /// Answers the next `count` `HeadBucket` requests with a 503.
+    pub fn fail_head_bucket(&self, count: usize) {
+        self.bucket.lock().unwrap().head_bucket_failures = count;
+    }
+
+    /// Stores the next `count` objects written without their last byte.
+    pub fn corrupt_writes(&self, count: usize) {
+        self.bucket.lock().unwrap().corrupt_writes = count;
+    }
+
+    /// Reports an ETag that matches nothing for the next `count` `HeadObject` requests.
+    pub fn wrong_e_tags(&self, count: usize) {
+        self.bucket.lock().unwrap().wrong_e_tags = count;
+    }
+
+    pub fn put(&self, key: &str, body: &[u8]) {
+        self.bucket.lock().unwrap().objects.insert(key.to_string(), StoredObject {
+            body: Bytes::copy_from_slice(body),
+            checksum_sha256: None,
+            content_md5: None,
+            e_tag: e_tag(body),
+            tagging: None,
+            last_modified: Utc::now(),
+        });
+    }
+
+    /// Starts a multipart upload as a run would have at `initiated`; returns
+    /// its upload id.
+    pub fn start_upload(&self, key: &str, initiated: DateTime<Utc>) -> String {
+        self.bucket.lock().unwrap().start_upload(key, None, initiated)
+    }
+
+    pub fn object(&self, key: &str) -> Option<Bytes> {
+        self.bucket.lock().unwrap().objects.get(key).map(|object| object.body.clone())
+    }
+
+    /// The `Content-MD5` the object was put with.
+    pub fn content_md5(&self, key: &str) -> Option<String> {
+        self.bucket.lock().unwrap().objects.get(key).and_then(|object| object.content_md5.clone())
+    }
+
+    pub fn tagging(&self, key: &str) -> Option<String> {
+        self.bucket.lock().unwrap().objects.get(key).and_then(|object| object.tagging.clone())
+    }
+
+    /// How many requests of `operation` (e.g. `UploadPart`) were served.
+    pub fn count(&self, operation: &str) -> usize {
+        self.bucket.lock().unwrap().operations.iter().filter(|served| *served == operation).count()
+    }
+
+    /// Keys of the multipart uploads neither completed nor aborted.
+    pub fn open_uploads(&self) -> Vec<String> {
+        let mut keys: Vec<String> = self.bucket.lock().unwrap().uploads.values().map(|upload| upload.key.clone()).collect();
+        keys.sort();
+        keys
+    }
+
+    /// Ids of the aborted multipart uploads, in order.
+    pub fn aborted(&self) -> Vec<String> {
+        self.bucket.lock().unwrap().aborted.clone()
+    }
+
+    async fn serve(&self, mut request: HttpRequest) -> Result<HttpResponse, ConnectorError> {
+        let method = request.method().to_string();
+        let url = Url::parse(request.uri()).map_err(|e| ConnectorError::other(e.into(), None))?;
+        let query: HashMap<String, String> = url.query_pairs().into_owned().collect();
+        let path = decode_path(url.path());
+        let key = path.trim_start_matches('/').split_once('/').map(|(_, key)| key.to_string()).unwrap_or_default();
+        let header = |name: &str| request.headers().get(name).map(str::to_string);
+        let checksum = header("x-amz-checksum-sha256");
+        let content_md5 = header("content-md5");
+        let tagging = header("x-amz-tagging");
+        let chunked = header("content-encoding").is_some_and(|encoding| encoding.contains("aws-chunked"));
+        let body = ByteStream::new(request.take_body())
+            .collect()
+            .await
+            .map_err(|e| ConnectorError::other(e.into(), None))?
+            .into_bytes();
+        let body = if chunked { decode_aws_chunked(&body) } else { body };
+
+        let mut bucket = self.bucket.lock().unwrap();
+        let response = match (method.as_str(), key.is_empty()) {
+            ("HEAD", true) => {
+                let response = if bucket.head_bucket_failures > 0 {
+                    bucket.head_bucket_failures -= 1;
+                    empty(503)
+                } else if bucket.exists {
+                    empty(200)
+                } else {
+                    empty(404)
+                };
+                bucket.served("HeadBucket", response)
+            }
+            ("PUT", true) => {
+                let response = if bucket.exists {
+                    error(409, "BucketAlreadyOwnedByYou")
+                } else {
+                    bucket.exists = true;
+                    empty(200)
+                };
+                bucket.served("CreateBucket", response)
+            }
+            ("HEAD", false) => {
+                let response = bucket.head_object(&key);
+                let response = if bucket.wrong_e_tags > 0 && response.status().as_u16() == 200 {
+                    bucket.wrong_e_tags -= 1;
+                    with_header(response, "etag", e_tag(b"something else"))
+                } else {
+                    response
+                };
+                bucket.served("HeadObject", response)
+            }
+            ("GET", true) if query.contains_key("uploads") => {
+                let response = bucket.list_uploads(query.get("prefix").map_or("", String::as_str));
+                bucket.served("ListMultipartUploads", response)
+            }
+            ("GET", true) => {
+                let prefix = query.get("prefix").map_or("", String::as_str);
+                let response = bucket.list_objects(prefix, query.get("continuation-token").map(String::as_str));
+                bucket.served("ListObjectsV2", response)
+            }
+            ("GET", false) => {
+                let response = bucket.get_object(&key);
+                bucket.served("GetObject", response)
+            }
+            ("PUT", false) if query.contains_key("uploadId") => {
+                let part_number = query.get("partNumber").and_then(|number| number.parse().ok()).unwrap_or(0);
+                let response = bucket.upload_part(&query["uploadId"], part_number, body, checksum);
+                bucket.served("UploadPart", response)
+            }
+            ("PUT", false) => {
+                let response = bucket.put_object(&key, body, content_md5, checksum, tagging);
+                bucket.served("PutObject", response)
+            }
+            ("POST", false) if query.contains_key("uploads") => {
+                let upload_id = bucket.start_upload(&key, tagging, Utc::now());
+                let response = xml(format!(
+                    "<InitiateMultipartUploadResult><Bucket>{}</Bucket><Key>{}</Key><UploadId>{}</UploadId></InitiateMultipartUploadResult>",
+                    BUCKET, escape(&key), upload_id
+                ));
+                bucket.served("CreateMultipartUpload", response)
+            }
+            ("POST", false) if query.contains_key("uploadId") => {
+                let response = bucket.complete_upload(&query["uploadId"], &body);
+                bucket.served("CompleteMultipartUpload", response)
+            }
+            ("DELETE", false) if query.contains_key("uploadId") => {
+                let upload_id = &query["uploadId"];
+                let response = match bucket.uploads.remove(upload_id) {
+                    Some(_) => {
+                        bucket.aborted.push(upload_id.clone());
+                        empty(204)
+                    }
+                    None => error(404, "NoSuchUpload"),
+                };
+                bucket.served("AbortMultipartUpload", response)
+            }
+            _ => error(501, "NotImplemented"),
+        };
+        Ok(response)
+    }
+}
+
+impl HttpClient for MockS3 {
+    fn http_connector(&self, _settings: &HttpConnectorSettings, _components: &RuntimeComponents) -> SharedHttpConnector {
+        SharedHttpConnector::new(self.clone())
+    }
+}
+
+impl HttpConnector for MockS3 {
+    fn call(&self, request: HttpRequest) -> HttpConnectorFuture {
+        let mock = self.clone();
+        HttpConnectorFuture::new(async move { mock.serve(request).await })
+    }
+}
+
+impl Bucket {
+    fn served(&mut self, operation: &str, response: HttpResponse) -> HttpResponse {
+        self.operations.push(operation.to_string());
+        response
+    }
+
+    fn start_upload(&mut self, key: &str, tagging: Option<String>, initiated: DateTime<Utc>) -> String {
+        self.uploads_started += 1;
+        let upload_id = format!("upload-{}", self.uploads_started);
+        self.uploads.insert(upload_id.clone(), MultipartUpload {
+            key: key.to_string(),
+            tagging,
+            initiated,
+            parts: BTreeMap::new(),
+        });
+        upload_id
+    }
+
+    /// Stores an object, dropping its last byte while writes are to be corrupted.
+    fn store(&mut self, key: &str, mut body: Bytes, object: StoredObject) {
+        if self.corrupt_writes > 0 && !body.is_empty() {
+            self.corrupt_writes -= 1;
+            body.truncate(body.len() - 1);
+        }
+        self.objects.insert(key.to_string(), StoredObject { body, ..object });
+    }
+
+    /// Rejects a body that doesn't match its `Content-MD5` with S3's `BadDigest`.
+    fn put_object(
+        &mut self,
+        key: &str,
+        body: Bytes,
+        content_md5: Option<String>,
+        checksum: Option<String>,
+        tagging: Option<String>,
+    ) -> HttpResponse {
+        if content_md5.as_ref().is_some_and(|md5| *md5 != BASE64.encode(Md5::digest(&body).as_slice())) {
+            return error(400, "BadDigest");
+        }
+        let e_tag = e_tag(&body);
+        self.store(key, body, StoredObject {
+            body: Bytes::new(),
+            checksum_sha256: checksum,
+            content_md5,
+            e_tag: e_tag.clone(),
+            tagging,
+            last_modified: Utc::now(),
+        });
+        with_header(empty(200), "etag", e_tag)
+    }
+
+    fn head_object(&self, key: &str) -> HttpResponse {
+        let Some(object) = self.objects.get(key) else {
+            return empty(404);
+        };
+        let mut response = with_header(empty(200), "content-length", object.body.len().to_string());
+        response = with_header(response, "etag", object.e_tag.clone());
+        if let Some(checksum) = &object.checksum_sha256 {
+            response = with_header(response, "x-amz-checksum-sha256", checksum.clone());
+        }
+        response
+    }
+
+    fn get_object(&self, key: &str) -> HttpResponse {
+        match self.objects.get(key) {
+            Some(object) => with_header(
+                response(200, SdkBody::from(object.body.clone())),
+                "content-length",
+                object.body.len().to_string(),
+            ),
+            None => error(404, "NoSuchKey"),
+        }
+    }
+
+    fn list_objects(&self, prefix: &str, after: Option<&str>) -> HttpResponse {
+        let mut matching = self.objects.iter()
+            .filter(|(key, _)| key.starts_with(prefix) && after.is_none_or(|after| key.as_str() > after));
+        let page: Vec<(&String, &StoredObject)> = matching.by_ref().take(self.list_page_size).collect();
+        let truncated = matching.next().is_some();
+        let contents: String = page.iter()
+            .map(|(key, object)| format!(
+                "<Contents><Key>{}</Key><LastModified>{}</LastModified><Size>{}</Size></Contents>",
+                escape(key),
+                object.last_modified.to_rfc3339_opts(SecondsFormat::Millis, true),
+                object.body.len()
+            ))
+            .collect();
+        let next_token = match (truncated, page.last()) {
+            (true, Some((key, _))) => format!("<NextContinuationToken>{}</NextContinuationToken>", escape(key)),
+            _ => String::new(),
+        };
+        xml(format!(
+            "<ListBucketResult xmlns=\"http://s3.amazonaws.com/doc/2006-03-01/\"><Name>{}</Name><Prefix>{}</Prefix><KeyCount>{}</KeyCount><MaxKeys>{}</MaxKeys><IsTruncated>{}</IsTruncated>{}{}</ListBucketResult>",
+            BUCKET, escape(prefix), page.len(), self.list_page_size, truncated, next_token, contents
+        ))
+    }
+
+    fn list_uploads(&self, prefix: &str) -> HttpResponse {
+        let uploads: String = self.uploads.iter()
+            .filter(|(_, upload)| upload.key.starts_with(prefix))
+            .map(|(upload_id, upload)| format!(
+                "<Upload><Key>{}</Key><UploadId>{}</UploadId><Initiated>{}</Initiated></Upload>",
+                escape(&upload.key),
+                upload_id,
+                upload.initiated.to_rfc3339_opts(SecondsFormat::Millis, true)
+            ))
+            .collect();
+        xml(format!(
+            "<ListMultipartUploadsResult xmlns=\"http://s3.amazonaws.com/doc/2006-03-01/\"><Bucket>{}</Bucket><Prefix>{}</Prefix><IsTruncated>false</IsTruncated>{}</ListMultipartUploadsResult>",
+            BUCKET, escape(prefix), uploads
+        ))
+    }
+
+    fn upload_part(&mut self, upload_id: &str, part_number: i32, body: Bytes, checksum: Option<String>) -> HttpResponse {
+        if self.failing_part == Some(part_number) {
+            return error(403, "AccessDenied");
+        }
+        let Some(upload) = self.uploads.get_mut(upload_id) else {
+            return error(404, "NoSuchUpload");
+        };
+        let e_tag = e_tag(&body);
+        upload.parts.insert(part_number, (body, checksum.clone()));
+        let response = with_header(empty(200), "etag", e_tag);
+        match checksum {
+            Some(checksum) => with_header(response, "x-amz-checksum-sha256", checksum),
+            None => response,
+        }
+    }
+
+    /// Joins the parts the request lists, in order. The object's checksum is
+    /// S3's checksum of checksums: the SHA-256 of the parts' digests, with
+    /// `-<parts>` appended; its ETag is built the same way from the parts' MD5s.
+    fn complete_upload(&mut self, upload_id: &str, request: &[u8]) -> HttpResponse {
+        let Some(upload) = self.uploads.remove(upload_id) else {
+            return error(404, "NoSuchUpload");
+        };
+        let part_numbers: Vec<i32> = Regex::new(r"<PartNumber>(\d+)</PartNumber>")
+            .unwrap()
+            .captures_iter(&String::from_utf8_lossy(request))
+            .filter_map(|captures| captures[1].parse().ok())
+            .collect();
+
+        let mut body = Vec::new();
+        let mut digests = Sha256::new();
+        let mut md5s = Md5::new();
+        let mut checksummed = !part_numbers.is_empty();
+        for part_number in &part_numbers {
+            let Some((part, checksum)) = upload.parts.get(part_number) else {
+                self.uploads.insert(upload_id.to_string(), upload);
+                return error(400, "InvalidPart");
+            };
+            body.extend_from_slice(part);
+            md5s.update(Md5::digest(part).as_slice());
+            match checksum.as_ref().and_then(|checksum| BASE64.decode(checksum).ok()) {
+                Some(digest) => digests.update(digest),
+                None => checksummed = false,
+            }
+        }
+        let checksum = checksummed
+            .then(|| format!("{}-{}", BASE64.encode(digests.finalize()), part_numbers.len()));
+
+        let e_tag = format!("\"{}-{}\"", hex(md5s.finalize().as_slice()), part_numbers.len());
+        self.store(&upload.key, Bytes::from(body), StoredObject {
+            body: Bytes::new(),
+            checksum_sha256: checksum,
+            content_md5: None,
+            e_tag: e_tag.clone(),
+            tagging: upload.tagging,
+            last_modified: Utc::now(),
+        });
+        xml(format!(
+            "<CompleteMultipartUploadResult><Bucket>{}</Bucket><Key>{}</Key><ETag>{}</ETag></CompleteMultipartUploadResult>",
+            BUCKET, escape(&upload.key), escape(&e_tag)
+        ))
+    }
+}
+
+fn response(status: u16, body: SdkBody) -> HttpResponse {
+    HttpResponse::new(StatusCode::try_from(status).expect("valid status code"), body)
+}
+
+fn empty(status: u16) -> HttpResponse {
+    response(status, SdkBody::empty())
+}
+
+fn xml(body: String) -> HttpResponse {
+    response(200, SdkBody::from(format!("<?xml version=\"1.0\" encoding=\"UTF-8\"?>{}", body)))
+}
+
+fn error(status: u16, code: &str) -> HttpResponse {
+    response(status, SdkBody::from(format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?><Error><Code>{}</Code><Message>{}</Message></Error>",
+        code, code
+    )))
+}
+
+fn with_header(mut response: HttpResponse, name: &'static str, value: String) -> HttpResponse {
+    response.headers_mut().insert(name, value);
+    response
+}
+
+/// S3's ETag of a single-part object: its quoted hex MD5.
+fn e_tag(body: &[u8]) -> String {
+    format!("\"{}\"", hex(Md5::digest(body).as_slice()))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+/// Decodes the `%XX` escapes of a request path.
+fn decode_path(path: &str) -> String {
+    let mut decoded = Vec::with_capacity(path.len());
+    let mut bytes = path.bytes();
+    while let Some(byte) = bytes.next() {
+        if byte == b'%' {
+            let hex: String = bytes.by_ref().take(2).map(char::from).collect();
+            if let Ok(value) = u8::from_str_radix(&hex, 16) {
+                decoded.push(value);
+                continue;
+            }
+        }
+        decoded.push(byte);
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// The payload of an `aws-chunked` body: `<hex size>[;extensions]\r\n<data>\r\n`
+/// chunks up to a zero-sized one, followed by trailers.
+fn decode_aws_chunked(body: &[u8]) -> Bytes {
+    let mut payload = Vec::with_capacity(body.len());
+    let mut rest = body;
+    while let Some(line_end) = rest.windows(2).position(|window| window == b"\r\n") {
+        let size_field = String::from_utf8_lossy(&rest[..line_end]);
+        let size = usize::from_str_radix(size_field.split(';').next().unwrap_or("").trim(), 16).unwrap_or(0);
+        rest = &rest[line_end + 2..];
+        if size == 0 || rest.len() < size {
+            break;
+        }
+        payload.extend_from_slice(&rest[..size]);
+        rest = rest.get(size + 2..).unwrap_or_default();
+    }
+    Bytes::from(payload)
+}
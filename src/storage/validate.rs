@@ -0,0 +1,414 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::File;
+use std::path::Path;
+use arrow::array::{Array, BooleanArray, Float64Array, Int32Array, Int64Array, ListArray, StringArray, StructArray, TimestampMillisecondArray};
+use arrow::record_batch::RecordBatch;
+use chrono::{SecondsFormat, TimeZone, Utc};
+use parquet::arrow::ProjectionMask;
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use parquet::file::reader::ChunkReader;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use crate::error::{Error, Result};
+use crate::storage::json::open_json;
+use crate::models::{Discount, RatingsDistribution, Vendor, VendorDetails};
+
+/// Vendor columns stored as JSON strings in the Parquet output.
+const JSON_COLUMNS: &[&str] = &["details", "reviews", "discounts"];
+
+/// Optional vendor fields checked on their own in lenient mode.
+const OPTIONAL_FIELDS: &[&str] = &["details", "reviews", "ratings", "discounts"];
+
+/// Distinct error messages listed in a report.
+const TOP_ERRORS: usize = 10;
+
+/// Vendor codes kept per error message.
+const SAMPLES_PER_ERROR: usize = 3;
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ErrorSummary {
+    pub count: usize,
+    pub vendor_codes: Vec<String>,
+}
+
+/// Outcome of running stored records through the current typed models.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ValidationReport {
+    pub total: usize,
+    pub ok: usize,
+    /// Records that only parse after dropping an unparseable optional field.
+    pub coerced: usize,
+    pub failed: usize,
+    pub errors: BTreeMap<String, ErrorSummary>,
+}
+
+enum Outcome {
+    Ok,
+    Coerced(Vec<String>),
+    Failed(String),
+}
+
+impl ValidationReport {
+    /// Validates a vendors JSON file (as written by `JsonWriter`) or a vendors
+    /// Parquet file, chosen by extension. In strict mode nothing is coerced.
+    pub fn for_file(path: &Path, strict: bool) -> Result<Self> {
+        let records = read_records(path)?;
+        let mut report = Self::default();
+        for record in &records {
+            report.observe(record, strict);
+        }
+        Ok(report)
+    }
+
+    fn observe(&mut self, record: &Value, strict: bool) {
+        self.total += 1;
+        let code = record.get("code").and_then(Value::as_str).unwrap_or("<unknown>");
+        match check(record, strict) {
+            Outcome::Ok => self.ok += 1,
+            Outcome::Coerced(messages) => {
+                self.coerced += 1;
+                for message in messages {
+                    self.record_error(message, code);
+                }
+            }
+            Outcome::Failed(message) => {
+                self.failed += 1;
+                self.record_error(message, code);
+            }
+        }
+    }
+
+    fn record_error(&mut self, message: String, code: &str) {
+        let summary = self.errors.entry(message).or_default();
+        summary.count += 1;
+        if summary.vendor_codes.len() < SAMPLES_PER_ERROR {
+            summary.vendor_codes.push(code.to_string());
+        }
+    }
+
+    /// Failed records as a percentage of all records.
+    pub fn failure_pct(&self) -> f64 {
+        if self.total == 0 {
+            return 0.0;
+        }
+        self.failed as f64 * 100.0 / self.total as f64
+    }
+
+    /// Most frequent error messages first.
+    pub fn top_errors(&self) -> Vec<(&String, &ErrorSummary)> {
+        let mut errors: Vec<_> = self.errors.iter().collect();
+        errors.sort_by(|a, b| b.1.count.cmp(&a.1.count));
+        errors.truncate(TOP_ERRORS);
+        errors
+    }
+}
+
+impl fmt::Display for ValidationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "records: {}, ok: {}, coerced: {}, failed: {} ({:.2}%)",
+            self.total, self.ok, self.coerced, self.failed, self.failure_pct()
+        )?;
+        for (message, summary) in self.top_errors() {
+            writeln!(
+                f,
+                "  {:>6}  {}  (e.g. {})",
+                summary.count,
+                message,
+                summary.vendor_codes.join(", ")
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Parses one optional field with the model it is stored as.
+fn check_field(name: &str, value: &Value) -> std::result::Result<(), String> {
+    if value.is_null() {
+        return Ok(());
+    }
+    let parsed = match name {
+        "details" => serde_json::from_value::<VendorDetails>(value.clone()).map(|_| ()),
+        "ratings" => serde_json::from_value::<RatingsDistribution>(value.clone()).map(|_| ()),
+        "reviews" => serde_json::from_value::<Vec<Value>>(value.clone()).map(|_| ()),
+        "discounts" => serde_json::from_value::<Vec<Discount>>(value.clone()).map(|_| ()),
+        _ => Ok(()),
+    };
+    parsed.map_err(|e| format!("{}: {}", name, e))
+}
+
+fn check(record: &Value, strict: bool) -> Outcome {
+    let error = match serde_json::from_value::<Vendor>(record.clone()) {
+        Ok(_) => return Outcome::Ok,
+        Err(e) => e.to_string(),
+    };
+    if strict {
+        return Outcome::Failed(error);
+    }
+
+    // Lenient: drop optional fields that don't parse and try again
+    let mut lenient = record.clone();
+    let mut messages = Vec::new();
+    if let Some(object) = lenient.as_object_mut() {
+        for name in OPTIONAL_FIELDS {
+            let Some(value) = object.get(*name) else {
+                continue;
+            };
+            if let Err(message) = check_field(name, value) {
+                messages.push(message);
+                object.remove(*name);
+            }
+        }
+    }
+    if !messages.is_empty() && serde_json::from_value::<Vendor>(lenient).is_ok() {
+        return Outcome::Coerced(messages);
+    }
+    Outcome::Failed(error)
+}
+
+/// Reads stored vendor records as raw JSON, so each can fail on its own.
+pub fn read_records(path: &Path) -> Result<Vec<Value>> {
+    let is_parquet = path.extension().is_some_and(|ext| ext == "parquet");
+    if !is_parquet {
+        return Ok(serde_json::from_reader(open_json(path)?)?);
+    }
+
+    let reader = ParquetRecordBatchReaderBuilder::try_new(File::open(path)?)?.build()?;
+    let mut records = Vec::new();
+    for batch in reader {
+        records.extend(batch_records(&batch?));
+    }
+    Ok(records)
+}
+
+fn batch_records(batch: &RecordBatch) -> Vec<Value> {
+    let mut records = vec![Map::new(); batch.num_rows()];
+    for (field, column) in batch.schema().fields().iter().zip(batch.columns()) {
+        let is_json = JSON_COLUMNS.contains(&field.name().as_str());
+        for (row, record) in records.iter_mut().enumerate() {
+            // Leave nulls out so they read as missing, which defaulted fields accept
+            if column.is_null(row) {
+                continue;
+            }
+            let value = match column.as_any().downcast_ref::<StringArray>() {
+                Some(array) if is_json => {
+                    let text = array.value(row);
+                    serde_json::from_str(text).unwrap_or_else(|_| Value::String(text.to_string()))
+                }
+                _ => cell_value(column.as_ref(), row),
+            };
+            record.insert(field.name().clone(), value);
+        }
+    }
+    for record in &mut records {
+        nest_ratings(record);
+    }
+    records.into_iter().map(Value::Object).collect()
+}
+
+/// One non-null cell as JSON; lists and structs are decoded element by element.
+fn cell_value(column: &dyn Array, row: usize) -> Value {
+    let column = column.as_any();
+    if let Some(array) = column.downcast_ref::<StringArray>() {
+        Value::String(array.value(row).to_string())
+    } else if let Some(array) = column.downcast_ref::<Int32Array>() {
+        Value::from(array.value(row))
+    } else if let Some(array) = column.downcast_ref::<Int64Array>() {
+        Value::from(array.value(row))
+    } else if let Some(array) = column.downcast_ref::<Float64Array>() {
+        Value::from(array.value(row))
+    } else if let Some(array) = column.downcast_ref::<BooleanArray>() {
+        Value::from(array.value(row))
+    } else if let Some(array) = column.downcast_ref::<TimestampMillisecondArray>() {
+        // The models read timestamps as epoch milliseconds
+        Value::from(array.value(row))
+    } else if let Some(array) = column.downcast_ref::<ListArray>() {
+        let items = array.value(row);
+        (0..items.len())
+            .map(|index| if items.is_null(index) { Value::Null } else { cell_value(items.as_ref(), index) })
+            .collect()
+    } else if let Some(array) = column.downcast_ref::<StructArray>() {
+        let fields = array.fields().iter().zip(array.columns())
+            .filter(|(_, child)| !child.is_null(row))
+            .map(|(field, child)| (field.name().clone(), cell_value(child.as_ref(), row)))
+            .collect();
+        Value::Object(fields)
+    } else {
+        Value::Null
+    }
+}
+
+/// Folds the `ratings` list and its `ratings_*` columns back into the
+/// distribution the API sends, so it is checked as a `RatingsDistribution`.
+/// Timestamps the converter couldn't parse were written as nulls and stay
+/// missing.
+fn nest_ratings(record: &mut Map<String, Value>) {
+    let total_count = record.remove("ratings_total_count");
+    let created_at = record.remove("ratings_created_at");
+    let updated_at = record.remove("ratings_updated_at");
+    let Some(ratings) = record.remove("ratings") else {
+        return;
+    };
+
+    let mut distribution = Map::new();
+    if let Some(total_count) = total_count {
+        distribution.insert("totalCount".to_string(), total_count);
+    }
+    for (key, millis) in [("createdAt", created_at), ("updatedAt", updated_at)] {
+        let time = millis
+            .and_then(|millis| millis.as_i64())
+            .and_then(|millis| Utc.timestamp_millis_opt(millis).single());
+        if let Some(time) = time {
+            distribution.insert(key.to_string(), Value::String(time.to_rfc3339_opts(SecondsFormat::Millis, true)));
+        }
+    }
+    distribution.insert("ratings".to_string(), ratings);
+    record.insert("ratings".to_string(), Value::Object(distribution));
+}
+
+/// Row count and the `code`s at rows 0, 1, 3, 7, 15, ... of the vendors fed
+/// to a conversion, to check the written file against before it is uploaded.
+#[derive(Debug, Default)]
+pub struct VendorSample {
+    rows: usize,
+    codes: Vec<(usize, String)>,
+}
+
+impl VendorSample {
+    pub fn observe(&mut self, vendor: &Vendor) {
+        if (self.rows + 1).is_power_of_two() {
+            self.codes.push((self.rows, vendor.code.clone()));
+        }
+        self.rows += 1;
+    }
+
+    /// Reads a vendor Parquet file back and fails with `Error::Validation`
+    /// unless it has every observed row, no nulls in its non-nullable columns
+    /// and the sampled codes in their rows.
+    pub fn verify<R: ChunkReader + 'static>(&self, parquet: R) -> Result<()> {
+        let builder = ParquetRecordBatchReaderBuilder::try_new(parquet)?;
+        let rows = builder.metadata().file_metadata().num_rows();
+        if rows != self.rows as i64 {
+            return Err(Error::Validation(format!(
+                "file has {} rows, {} vendors were converted",
+                rows, self.rows
+            )));
+        }
+
+        // Only the non-nullable columns are decoded; `code` is one of them
+        let required: Vec<usize> = builder.schema().fields().iter()
+            .enumerate()
+            .filter(|(_, field)| !field.is_nullable())
+            .map(|(index, _)| index)
+            .collect();
+        let mask = ProjectionMask::roots(builder.parquet_schema(), required);
+        let reader = builder.with_projection(mask).build()?;
+
+        let mut offset = 0;
+        let mut samples = self.codes.iter().peekable();
+        for batch in reader {
+            let batch = batch?;
+            let schema = batch.schema();
+            for (field, column) in schema.fields().iter().zip(batch.columns()) {
+                if column.null_count() > 0 {
+                    return Err(Error::Validation(format!(
+                        "required column {} has {} nulls",
+                        field.name(),
+                        column.null_count()
+                    )));
+                }
+            }
+            let codes = batch.column_by_name("code")
+                .and_then(|column| column.as_any().downcast_ref::<StringArray>())
+                .ok_or_else(|| Error::Validation("file has no code column".to_string()))?;
+            while let Some((row, code)) = samples.next_if(|(row, _)| *row < offset + batch.num_rows()) {
+                let written = codes.value(row - offset);
+                if written != code {
+                    return Err(Error::Validation(format!(
+                        "row {} has code {}, {} was converted",
+                        row, written, code
+                    )));
+                }
+            }
+            offset += batch.num_rows();
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+    use crate::config::ParquetConfig;
+    use crate::models::RatingScore;
+    use crate::storage::parquet::{ParquetConverter, ParquetMeta};
+
+    fn rated_vendor(code: &str, created_at: &str) -> Vendor {
+        let mut vendor = Vendor::stub(code, 1, None, "ok");
+        vendor.ratings = Some(RatingsDistribution {
+            total_count: 40,
+            created_at: created_at.to_string(),
+            updated_at: "2024-05-02 11:30:00".to_string(),
+            ratings: vec![
+                RatingScore { score: 5, count: 30, percentage: 75 },
+                RatingScore { score: 1, count: 10, percentage: 25 },
+            ],
+        });
+        vendor
+    }
+
+    fn vendors_parquet(dir: &Path, vendors: Vec<Vendor>) -> std::path::PathBuf {
+        let output = dir.join("vendors.parquet");
+        let meta = ParquetMeta {
+            run_id: "test".to_string(),
+            city_id: "1".to_string(),
+            country: "pk".to_string(),
+            extraction_date: NaiveDate::from_ymd_opt(2024, 5, 1).unwrap(),
+        };
+        ParquetConverter::convert_vendors_to_parquet(
+            vendors.into_iter().map(Ok),
+            &output,
+            &meta,
+            &ParquetConfig::default(),
+        )
+        .unwrap();
+        output
+    }
+
+    #[test]
+    fn parquet_ratings_are_checked_as_a_distribution() {
+        let dir = tempfile::tempdir().unwrap();
+        let output = vendors_parquet(dir.path(), vec![
+            rated_vendor("rated", "2024-05-01T10:00:00Z"),
+            // Written with a null `ratings_created_at`, which the model requires
+            rated_vendor("undated", "yesterday"),
+            Vendor::stub("unrated", 1, None, "ok"),
+        ]);
+
+        let records = read_records(&output).unwrap();
+        assert_eq!(
+            records[0]["ratings"],
+            serde_json::json!({
+                "totalCount": 40,
+                "createdAt": "2024-05-01T10:00:00.000Z",
+                "updatedAt": "2024-05-02T11:30:00.000Z",
+                "ratings": [
+                    { "score": 5, "count": 30, "percentage": 75 },
+                    { "score": 1, "count": 10, "percentage": 25 },
+                ],
+            })
+        );
+        assert!(records[2].get("ratings").is_none());
+
+        let strict = ValidationReport::for_file(&output, true).unwrap();
+        assert_eq!((strict.total, strict.ok, strict.failed), (3, 2, 1));
+        let lenient = ValidationReport::for_file(&output, false).unwrap();
+        assert_eq!((lenient.ok, lenient.coerced, lenient.failed), (2, 1, 0));
+        let (message, summary) = lenient.top_errors()[0];
+        assert!(message.starts_with("ratings: missing field `createdAt`"), "{}", message);
+        assert_eq!(summary.vendor_codes, ["undated"]);
+    }
+}
@@ -0,0 +1,330 @@
+//! Full-screen progress dashboard for interactive runs (`--tui`, built with
+//! the `tui` feature). It shows per-city page progress, vendor throughput,
+//! per-endpoint request and error counts, the adaptive pacer's delay and the
+//! latest warnings, fed by the same progress events as the periodic progress
+//! log line and the client pool's counters. The pipeline tracks neither
+//! client health nor quiet hours, so there are no panels for them.
+//!
+//! Console logging is paused while the dashboard is up; the log file keeps
+//! everything.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+use ratatui::DefaultTerminal;
+use ratatui::Frame;
+use ratatui::crossterm::event::{self, Event as TermEvent, KeyCode};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Gauge, List, Paragraph, Row, Table};
+use tokio::sync::mpsc;
+use tracing::field::{Field, Visit};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::filter::{filter_fn, FilterFn};
+use tracing_subscriber::layer::{Context, Layer};
+use crate::metrics::Metrics;
+use crate::services::{ProgressEvent, VendorStatus};
+use crate::stats::StatsRegistry;
+use crate::utils::AdaptivePacer;
+
+const REDRAW_INTERVAL: Duration = Duration::from_millis(500);
+/// Window of the live vendors/sec figure.
+const RATE_WINDOW: Duration = Duration::from_secs(10);
+const MAX_WARNINGS: usize = 8;
+const MAX_CITY_ROWS: usize = 10;
+
+/// Set while the dashboard owns the terminal.
+static CONSOLE_PAUSED: AtomicBool = AtomicBool::new(false);
+/// Latest warnings and errors, oldest first.
+static WARNINGS: Mutex<VecDeque<String>> = Mutex::new(VecDeque::new());
+
+/// Filter for the console log layer: lets nothing through while the
+/// dashboard is up, everything again once it is closed.
+pub fn console_filter() -> FilterFn<impl Fn(&tracing::Metadata<'_>) -> bool> {
+    filter_fn(|_| !CONSOLE_PAUSED.load(Ordering::Relaxed))
+}
+
+/// Layer keeping the last few warnings and errors for the dashboard.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RecentWarnings;
+
+fn recent_warnings() -> Vec<String> {
+    WARNINGS.lock().unwrap_or_else(|e| e.into_inner()).iter().cloned().collect()
+}
+
+impl<S: Subscriber> Layer<S> for RecentWarnings {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let level = *event.metadata().level();
+        if level > Level::WARN {
+            return;
+        }
+        let mut message = MessageVisitor::default();
+        event.record(&mut message);
+        let mut warnings = WARNINGS.lock().unwrap_or_else(|e| e.into_inner());
+        if warnings.len() == MAX_WARNINGS {
+            warnings.pop_front();
+        }
+        warnings.push_back(format!("{} {} {}", chrono::Utc::now().format("%H:%M:%S"), level, message.0));
+    }
+}
+
+#[derive(Default)]
+struct MessageVisitor(String);
+
+impl Visit for MessageVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            self.0 = format!("{:?}", value);
+        }
+    }
+}
+
+/// Where the dashboard reads what the progress events don't carry.
+pub struct DashboardSources {
+    pub stats: Arc<StatsRegistry>,
+    pub metrics: Arc<Metrics>,
+    pub pacer: Option<Arc<AdaptivePacer>>,
+}
+
+/// The running dashboard. Dropping it, on any exit from the run, restores
+/// the terminal and console logging; `q` does so early while the run goes on.
+pub struct Dashboard {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Dashboard {
+    /// Takes over the terminal and redraws it from its own thread.
+    pub fn start(events: mpsc::Receiver<ProgressEvent>, sources: DashboardSources) -> std::io::Result<Self> {
+        let terminal = ratatui::try_init()?;
+        CONSOLE_PAUSED.store(true, Ordering::Relaxed);
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread = std::thread::spawn({
+            let stop = stop.clone();
+            move || run(terminal, events, &sources, &stop)
+        });
+        Ok(Self { stop, thread: Some(thread) })
+    }
+}
+
+impl Drop for Dashboard {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn run(mut terminal: DefaultTerminal, mut events: mpsc::Receiver<ProgressEvent>, sources: &DashboardSources, stop: &AtomicBool) {
+    let mut progress = Progress::new(Instant::now());
+    while !stop.load(Ordering::Relaxed) {
+        while let Ok(event) = events.try_recv() {
+            progress.apply(event);
+        }
+        progress.sample(Instant::now());
+        if terminal.draw(|frame| render(frame, &progress, sources)).is_err() {
+            break;
+        }
+        let quit = event::poll(REDRAW_INTERVAL).unwrap_or(false)
+            && matches!(event::read(), Ok(TermEvent::Key(key)) if key.code == KeyCode::Char('q'));
+        if quit {
+            break;
+        }
+    }
+    ratatui::restore();
+    CONSOLE_PAUSED.store(false, Ordering::Relaxed);
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+struct CityProgress {
+    pages_done: i32,
+    pages_planned: i32,
+    failed: usize,
+}
+
+/// What the progress events have reported so far.
+struct Progress {
+    started: Instant,
+    cities: BTreeMap<String, CityProgress>,
+    vendors_done: usize,
+    by_status: BTreeMap<&'static str, usize>,
+    /// Vendors done at each redraw within the rate window, oldest first.
+    samples: VecDeque<(Instant, usize)>,
+}
+
+impl Progress {
+    fn new(started: Instant) -> Self {
+        Self {
+            started,
+            cities: BTreeMap::new(),
+            vendors_done: 0,
+            by_status: BTreeMap::new(),
+            samples: VecDeque::new(),
+        }
+    }
+
+    fn apply(&mut self, event: ProgressEvent) {
+        match event {
+            ProgressEvent::PageFetched { city_id, of, .. } => {
+                self.cities.entry(city_id).or_default().pages_planned = of;
+            }
+            ProgressEvent::VendorDone { status, .. } => {
+                self.vendors_done += 1;
+                *self.by_status.entry(status_name(status)).or_default() += 1;
+            }
+            ProgressEvent::BatchDone { city_id, batch, failed } => {
+                let city = self.cities.entry(city_id).or_default();
+                city.pages_done = batch;
+                city.failed += failed;
+            }
+        }
+    }
+
+    fn sample(&mut self, now: Instant) {
+        self.samples.push_back((now, self.vendors_done));
+        while self.samples.front().is_some_and(|(at, _)| now.duration_since(*at) > RATE_WINDOW) {
+            self.samples.pop_front();
+        }
+    }
+
+    /// Vendors per second over the rate window.
+    fn live_rate(&self) -> f64 {
+        match (self.samples.front(), self.samples.back()) {
+            (Some((first_at, first)), Some((last_at, last))) if last_at > first_at => {
+                (last - first) as f64 / last_at.duration_since(*first_at).as_secs_f64()
+            }
+            _ => 0.0,
+        }
+    }
+
+    fn overall_rate(&self) -> f64 {
+        let elapsed = self.started.elapsed().as_secs_f64();
+        if elapsed > 0.0 { self.vendors_done as f64 / elapsed } else { 0.0 }
+    }
+}
+
+fn status_name(status: VendorStatus) -> &'static str {
+    match status {
+        VendorStatus::Ok => "ok",
+        VendorStatus::Skipped => "skipped",
+        VendorStatus::Unchanged => "unchanged",
+        VendorStatus::ContentUnchanged => "content unchanged",
+        VendorStatus::OverBudget => "over budget",
+        VendorStatus::Failed => "failed",
+    }
+}
+
+fn render(frame: &mut Frame, progress: &Progress, sources: &DashboardSources) {
+    let city_rows = progress.cities.len().clamp(1, MAX_CITY_ROWS) as u16;
+    let [summary, cities, endpoints, warnings] = Layout::vertical([
+        Constraint::Length(4),
+        Constraint::Length(city_rows + 2),
+        Constraint::Length(crate::stats::Endpoint::ALL.len() as u16 + 3),
+        Constraint::Min(3),
+    ])
+    .areas(frame.area());
+
+    render_summary(frame, summary, progress, sources);
+    render_cities(frame, cities, progress);
+    render_endpoints(frame, endpoints, sources);
+    let warnings_list = List::new(recent_warnings())
+        .block(Block::bordered().title("Recent warnings"));
+    frame.render_widget(warnings_list, warnings);
+}
+
+fn render_summary(frame: &mut Frame, area: Rect, progress: &Progress, sources: &DashboardSources) {
+    let elapsed = progress.started.elapsed().as_secs();
+    let statuses: Vec<String> = progress.by_status.iter()
+        .map(|(status, count)| format!("{} {}", status, count))
+        .collect();
+    let pacing = match &sources.pacer {
+        Some(pacer) => format!("adaptive delay {} ms", pacer.delay_ms()),
+        None => "fixed throttling".to_string(),
+    };
+    let lines = vec![
+        Line::from(format!(
+            "Elapsed {}:{:02}:{:02}   Vendors {} ({:.2}/s live, {:.2}/s overall)   Pacing: {}",
+            elapsed / 3600,
+            elapsed / 60 % 60,
+            elapsed % 60,
+            progress.vendors_done,
+            progress.live_rate(),
+            progress.overall_rate(),
+            pacing
+        )),
+        Line::from(statuses.join("   ")),
+    ];
+    let block = Block::bordered().title("foodpanda_etl (q closes the dashboard)");
+    frame.render_widget(Paragraph::new(lines).block(block), area);
+}
+
+fn render_cities(frame: &mut Frame, area: Rect, progress: &Progress) {
+    let block = Block::bordered().title(format!("Cities ({})", progress.cities.len()));
+    let inner = block.inner(area);
+    frame.render_widget(block, area);
+
+    // The most recently started cities are the ones still moving
+    let shown: Vec<(&String, &CityProgress)> = progress.cities.iter().rev().take(MAX_CITY_ROWS).collect();
+    let rows = Layout::vertical(vec![Constraint::Length(1); shown.len()]).split(inner);
+    for ((city_id, city), row) in shown.into_iter().rev().zip(rows.iter()) {
+        let planned = city.pages_planned.max(city.pages_done).max(1);
+        let gauge = Gauge::default()
+            .ratio((city.pages_done as f64 / planned as f64).clamp(0.0, 1.0))
+            .label(format!("city {}: page {}/{}, {} failed", city_id, city.pages_done, planned, city.failed));
+        frame.render_widget(gauge, *row);
+    }
+}
+
+fn render_endpoints(frame: &mut Frame, area: Rect, sources: &DashboardSources) {
+    let summaries = sources.metrics.summary();
+    let rows: Vec<Row> = crate::stats::Endpoint::ALL.iter()
+        .map(|endpoint| {
+            let summary = summaries.iter().find(|summary| summary.endpoint == *endpoint);
+            let (count, errors, p95) = summary.map_or((0, 0, 0), |summary| {
+                (summary.count, (summary.error_rate * summary.count as f64).round() as usize, summary.p95_ms)
+            });
+            Row::new(vec![
+                endpoint.as_str().to_string(),
+                sources.stats.requests(*endpoint).to_string(),
+                count.to_string(),
+                errors.to_string(),
+                format!("{} ms", p95),
+                format!("{:.1} MB", sources.stats.bytes(*endpoint) as f64 / 1e6),
+            ])
+        })
+        .collect();
+    let table = Table::new(rows, [Constraint::Length(10); 6])
+        .header(Row::new(vec!["endpoint", "attempts", "calls", "errors", "p95", "received"]))
+        .block(Block::bordered().title("Requests"));
+    frame.render_widget(table, area);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn progress_follows_events() {
+        let started = Instant::now();
+        let mut progress = Progress::new(started);
+        progress.apply(ProgressEvent::PageFetched { city_id: "17".to_string(), page: 1, of: 4 });
+        for status in [VendorStatus::Ok, VendorStatus::Ok, VendorStatus::Failed] {
+            progress.apply(ProgressEvent::VendorDone { code: "v".to_string(), status });
+        }
+        progress.apply(ProgressEvent::BatchDone { city_id: "17".to_string(), batch: 1, failed: 1 });
+
+        assert_eq!(progress.cities["17"], CityProgress { pages_done: 1, pages_planned: 4, failed: 1 });
+        assert_eq!(progress.vendors_done, 3);
+        assert_eq!(progress.by_status["ok"], 2);
+        assert_eq!(progress.by_status["failed"], 1);
+
+        progress.sample(started);
+        progress.apply(ProgressEvent::VendorDone { code: "w".to_string(), status: VendorStatus::Ok });
+        progress.sample(started + Duration::from_secs(2));
+        assert_eq!(progress.live_rate(), 0.5);
+    }
+}
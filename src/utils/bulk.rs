@@ -0,0 +1,207 @@
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::hash::Hash;
+use futures::stream::{self, StreamExt};
+use tokio_util::sync::CancellationToken;
+use crate::error::{Error, Result};
+use crate::utils::retry::retry_with_backoff;
+
+const MAX_RETRIES: u32 = 3;
+const BASE_DELAY_MS: u64 = 1000;
+
+/// Outcome of [`fetch_all`]: every key ends up in exactly one of the fields.
+#[derive(Debug)]
+pub struct BulkResult<K, T> {
+    pub successes: HashMap<K, T>,
+    pub failures: Vec<(K, Error)>,
+    /// Keys not attempted because the cancellation token fired.
+    pub skipped: Vec<K>,
+}
+
+impl<K, T> Default for BulkResult<K, T> {
+    fn default() -> Self {
+        Self {
+            successes: HashMap::new(),
+            failures: Vec::new(),
+            skipped: Vec::new(),
+        }
+    }
+}
+
+impl<K, T> BulkResult<K, T> {
+    pub fn is_complete(&self) -> bool {
+        self.failures.is_empty() && self.skipped.is_empty()
+    }
+}
+
+/// Fetches every key with at most `concurrency` requests in flight, retrying
+/// each with the standard backoff. Individual failures are collected rather
+/// than aborting the rest; only `cancel` stops early.
+pub async fn fetch_all<K, T, F, Fut>(
+    keys: Vec<K>,
+    concurrency: usize,
+    cancel: &CancellationToken,
+    fetch: F,
+) -> BulkResult<K, T>
+where
+    K: Clone + Eq + Hash,
+    F: Fn(K) -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    fetch_all_with_retries(keys, concurrency, MAX_RETRIES, cancel, fetch).await
+}
+
+/// Like [`fetch_all`], retrying each key at most `retries` times; 0 makes a
+/// single attempt.
+pub async fn fetch_all_with_retries<K, T, F, Fut>(
+    keys: Vec<K>,
+    concurrency: usize,
+    retries: u32,
+    cancel: &CancellationToken,
+    fetch: F,
+) -> BulkResult<K, T>
+where
+    K: Clone + Eq + Hash,
+    F: Fn(K) -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    let fetch = &fetch;
+    let mut results = stream::iter(keys.clone())
+        .map(|key| async move {
+            let result = retry_with_backoff(retries, BASE_DELAY_MS, || fetch(key.clone())).await;
+            (key, result)
+        })
+        .buffer_unordered(concurrency.max(1));
+
+    let mut bulk = BulkResult::default();
+    loop {
+        tokio::select! {
+            biased;
+            _ = cancel.cancelled() => break,
+            next = results.next() => match next {
+                Some((key, Ok(value))) => {
+                    bulk.successes.insert(key, value);
+                }
+                Some((key, Err(e))) => bulk.failures.push((key, e)),
+                None => break,
+            },
+        }
+    }
+
+    if cancel.is_cancelled() {
+        let failed: HashSet<&K> = bulk.failures.iter().map(|(key, _)| key).collect();
+        let skipped: Vec<K> = keys
+            .into_iter()
+            .filter(|key| !bulk.successes.contains_key(key) && !failed.contains(key))
+            .collect();
+        bulk.skipped = skipped;
+    }
+    bulk
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+    use std::time::Duration;
+    use tokio::time::{sleep, Instant};
+
+    const FETCH_TIME: Duration = Duration::from_millis(100);
+
+    /// Fetches that take `FETCH_TIME` and fail a programmed number of times
+    /// per key before returning the key doubled; `None` fails every time.
+    #[derive(Default)]
+    struct MockFetch {
+        failures: HashMap<u32, Option<u32>>,
+        calls: Mutex<HashMap<u32, u32>>,
+    }
+
+    impl MockFetch {
+        fn failing(mut self, key: u32, times: Option<u32>) -> Self {
+            self.failures.insert(key, times);
+            self
+        }
+
+        async fn fetch(&self, key: u32) -> Result<u32> {
+            let call = {
+                let mut calls = self.calls.lock().unwrap();
+                let call = calls.entry(key).or_default();
+                *call += 1;
+                *call
+            };
+            sleep(FETCH_TIME).await;
+            match self.failures.get(&key) {
+                Some(None) => Err(Error::GatewayTimeout),
+                Some(Some(times)) if call <= *times => Err(Error::GatewayTimeout),
+                _ => Ok(key * 2),
+            }
+        }
+
+        fn calls(&self, key: u32) -> u32 {
+            self.calls.lock().unwrap().get(&key).copied().unwrap_or(0)
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn failures_are_collected_without_stopping_the_rest() {
+        let mock = MockFetch::default().failing(1, Some(2)).failing(2, None);
+
+        let bulk = fetch_all(vec![0, 1, 2, 3], 2, &CancellationToken::new(), |key| mock.fetch(key)).await;
+
+        assert_eq!(bulk.successes, HashMap::from([(0, 0), (1, 2), (3, 6)]));
+        let failed: Vec<u32> = bulk.failures.iter().map(|(key, _)| *key).collect();
+        assert_eq!(failed, [2]);
+        assert!(bulk.skipped.is_empty());
+        assert!(!bulk.is_complete());
+        assert_eq!(mock.calls(1), 3);
+        assert_eq!(mock.calls(2), MAX_RETRIES + 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn zero_retries_makes_a_single_attempt() {
+        let mock = MockFetch::default().failing(1, Some(1));
+
+        let bulk = fetch_all_with_retries(vec![0, 1], 1, 0, &CancellationToken::new(), |key| mock.fetch(key)).await;
+
+        assert_eq!(bulk.successes, HashMap::from([(0, 0)]));
+        let failed: Vec<u32> = bulk.failures.iter().map(|(key, _)| *key).collect();
+        assert_eq!(failed, [1]);
+        assert_eq!(mock.calls(1), 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn at_most_concurrency_fetches_run_at_once() {
+        let mock = MockFetch::default();
+        let started = Instant::now();
+
+        let bulk = fetch_all((0..6).collect(), 2, &CancellationToken::new(), |key| mock.fetch(key)).await;
+
+        assert!(bulk.is_complete());
+        assert_eq!(bulk.successes.len(), 6);
+        // Three rounds of two, where one at a time would take six
+        let elapsed = started.elapsed();
+        assert!(elapsed >= FETCH_TIME * 3 && elapsed < FETCH_TIME * 4, "took {:?}", elapsed);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn cancellation_skips_the_keys_not_fetched() {
+        let mock = MockFetch::default();
+        let cancel = CancellationToken::new();
+        tokio::spawn({
+            let cancel = cancel.clone();
+            async move {
+                sleep(FETCH_TIME * 5 / 2).await;
+                cancel.cancel();
+            }
+        });
+
+        let bulk = fetch_all((0..5).collect(), 1, &cancel, |key| mock.fetch(key)).await;
+
+        assert_eq!(bulk.successes, HashMap::from([(0, 0), (1, 2)]));
+        assert!(bulk.failures.is_empty());
+        // The fetch in flight when the token fired is dropped with the rest
+        assert_eq!(bulk.skipped, [2, 3, 4]);
+        assert_eq!(mock.calls(2), 1);
+        assert_eq!(mock.calls(3), 0);
+    }
+}
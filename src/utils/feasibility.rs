@@ -0,0 +1,295 @@
+use std::time::Duration;
+use crate::config::{ConcurrencyConfig, ExtractionConfig, LimitsConfig, ThrottlingConfig};
+
+/// Listing counts for one city, as reported by its first listing page.
+#[derive(Debug, Clone)]
+pub struct CityEstimate {
+    pub city_id: String,
+    pub vendors: u64,
+    pub page_size: u64,
+}
+
+/// Lower bound on run duration implied by the throttling settings.
+#[derive(Debug, Clone)]
+pub struct RunEstimate {
+    pub cities: usize,
+    pub vendors: u64,
+    pub pages: u64,
+    /// Vendors processed at once; each waits its own delays.
+    pub workers: usize,
+    /// Reviews and ratings requests made per vendor after the sub-fetch delay.
+    pub sub_fetches: usize,
+    /// One vendor's time on a single worker.
+    pub seconds_per_vendor: f64,
+    pub seconds_per_page: f64,
+    pub min_duration: Duration,
+}
+
+fn avg_delay_secs(base_ms: u64, jitter_ms: u64) -> f64 {
+    (base_ms as f64 + jitter_ms as f64 / 2.0) / 1000.0
+}
+
+fn format_hours(duration: Duration) -> String {
+    format!("{:.1}h", duration.as_secs_f64() / 3600.0)
+}
+
+impl RunEstimate {
+    pub fn new(
+        throttling: &ThrottlingConfig,
+        limits: &LimitsConfig,
+        concurrency: &ConcurrencyConfig,
+        extraction: &ExtractionConfig,
+        cities: &[CityEstimate],
+    ) -> Self {
+        let request_secs = limits.assumed_request_ms as f64 / 1000.0;
+        let workers = concurrency.vendor_workers.max(1);
+        let sub_fetches = [extraction.fetch_reviews, extraction.fetch_ratings]
+            .into_iter()
+            .filter(|&enabled| enabled)
+            .count();
+
+        // details, then reviews and ratings after the sub-fetch delay when either is on
+        let mut seconds_per_vendor = avg_delay_secs(throttling.inter_vendor_delay_ms, throttling.inter_vendor_jitter_ms)
+            + request_secs;
+        if sub_fetches > 0 {
+            seconds_per_vendor += avg_delay_secs(throttling.sub_fetch_delay_ms, throttling.sub_fetch_jitter_ms)
+                + sub_fetches as f64 * request_secs;
+        }
+        let seconds_per_page = avg_delay_secs(throttling.page_delay_ms, throttling.page_jitter_ms) + request_secs;
+
+        let vendors: u64 = cities.iter().map(|c| c.vendors).sum();
+        let pages: u64 = cities.iter()
+            .filter(|c| c.page_size > 0)
+            .map(|c| c.vendors.div_ceil(c.page_size))
+            .sum();
+
+        let total_secs = vendors as f64 * seconds_per_vendor / workers as f64 + pages as f64 * seconds_per_page;
+
+        Self {
+            cities: cities.len(),
+            vendors,
+            pages,
+            workers,
+            sub_fetches,
+            seconds_per_vendor,
+            seconds_per_page,
+            min_duration: Duration::from_secs_f64(total_secs),
+        }
+    }
+
+    pub fn vendors_per_second(&self) -> f64 {
+        if self.seconds_per_vendor <= 0.0 {
+            return f64::INFINITY;
+        }
+        self.workers as f64 / self.seconds_per_vendor
+    }
+
+    pub fn fits(&self, budget: Duration) -> bool {
+        self.min_duration <= budget
+    }
+
+    pub fn describe(&self, budget: Duration) -> String {
+        format!(
+            "at {:.2} vendors/sec the {} configured cities ({} vendors) need >= {}; budget is {}",
+            self.vendors_per_second(),
+            self.cities,
+            self.vendors,
+            format_hours(self.min_duration),
+            format_hours(budget)
+        )
+    }
+
+    /// Settings changes that would bring the estimate within `budget`, cheapest first.
+    pub fn suggestions(&self, throttling: &ThrottlingConfig, budget: Duration) -> Vec<String> {
+        let mut suggestions = Vec::new();
+        if self.fits(budget) || self.vendors == 0 {
+            return suggestions;
+        }
+
+        // each worker's share of the vendors waits its delays one after another
+        let excess_secs = (self.min_duration - budget).as_secs_f64();
+        let mut needed_ms = (excess_secs * self.workers as f64 / self.vendors as f64 * 1000.0).ceil() as u64;
+
+        let sub_fetch_delay_ms = if self.sub_fetches > 0 { throttling.sub_fetch_delay_ms } else { 0 };
+        for (name, base_ms) in [
+            ("throttling.inter_vendor_delay_ms", throttling.inter_vendor_delay_ms),
+            ("throttling.sub_fetch_delay_ms", sub_fetch_delay_ms),
+        ] {
+            if needed_ms == 0 || base_ms == 0 {
+                continue;
+            }
+            let cut = needed_ms.min(base_ms);
+            suggestions.push(format!(
+                "lower {} by {}ms (from {} to {})",
+                name, cut, base_ms, base_ms - cut
+            ));
+            needed_ms -= cut;
+        }
+
+        suggestions.push(format!(
+            "or raise limits.run_budget_minutes to at least {}",
+            (self.min_duration.as_secs_f64() / 60.0).ceil() as u64
+        ));
+        suggestions
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// One second per vendor and per listing page, nothing else.
+    fn throttling() -> ThrottlingConfig {
+        ThrottlingConfig {
+            inter_vendor_delay_ms: 1000,
+            inter_vendor_jitter_ms: 0,
+            sub_fetch_delay_ms: 0,
+            sub_fetch_jitter_ms: 0,
+            page_delay_ms: 1000,
+            page_jitter_ms: 0,
+            ..ThrottlingConfig::default()
+        }
+    }
+
+    fn limits() -> LimitsConfig {
+        LimitsConfig { assumed_request_ms: 0, ..LimitsConfig::default() }
+    }
+
+    fn city(vendors: u64, page_size: u64) -> CityEstimate {
+        CityEstimate { city_id: "1".to_string(), vendors, page_size }
+    }
+
+    fn concurrency(vendor_workers: usize) -> ConcurrencyConfig {
+        ConcurrencyConfig { vendor_workers, ..ConcurrencyConfig::default() }
+    }
+
+    fn extraction(fetch_reviews: bool, fetch_ratings: bool) -> ExtractionConfig {
+        ExtractionConfig { fetch_reviews, fetch_ratings, ..ExtractionConfig::default() }
+    }
+
+    #[test]
+    fn budget_table() {
+        // (case, city, workers, budget secs, pages, min duration secs, fits, suggestions)
+        let cases = [
+            ("under budget", city(100, 50), 1, 200, 2, 102, true, vec![]),
+            ("exactly the budget", city(100, 50), 1, 102, 2, 102, true, vec![]),
+            (
+                "over budget",
+                city(1000, 50),
+                1,
+                600,
+                20,
+                1020,
+                false,
+                vec![
+                    "lower throttling.inter_vendor_delay_ms by 420ms (from 1000 to 580)",
+                    "or raise limits.run_budget_minutes to at least 17",
+                ],
+            ),
+            ("two workers fit what one cannot", city(1000, 50), 2, 600, 20, 520, true, vec![]),
+            (
+                "four workers over budget",
+                city(4000, 50),
+                4,
+                580,
+                80,
+                1080,
+                false,
+                vec![
+                    "lower throttling.inter_vendor_delay_ms by 500ms (from 1000 to 500)",
+                    "or raise limits.run_budget_minutes to at least 18",
+                ],
+            ),
+            ("zero pages", city(0, 50), 1, 0, 0, 0, true, vec![]),
+            ("zero page size", city(10, 0), 1, 60, 0, 10, true, vec![]),
+        ];
+
+        for (case, city, workers, budget_secs, pages, min_secs, fits, suggestions) in cases {
+            let budget = Duration::from_secs(budget_secs);
+            let estimate = RunEstimate::new(
+                &throttling(),
+                &limits(),
+                &concurrency(workers),
+                &extraction(true, true),
+                &[city],
+            );
+
+            assert_eq!(estimate.pages, pages, "{}", case);
+            assert_eq!(estimate.min_duration, Duration::from_secs(min_secs), "{}", case);
+            assert_eq!(estimate.fits(budget), fits, "{}", case);
+            assert_eq!(estimate.suggestions(&throttling(), budget), suggestions, "{}", case);
+        }
+    }
+
+    #[test]
+    fn sub_fetch_toggle_table() {
+        // 1s inter-vendor delay, 0.5s sub-fetch delay and 0.25s per request
+        let throttling = ThrottlingConfig { sub_fetch_delay_ms: 500, ..throttling() };
+        let limits = LimitsConfig { assumed_request_ms: 250, ..limits() };
+
+        // (case, fetch_reviews, fetch_ratings, budget secs, min duration secs, suggestions)
+        let cases = [
+            (
+                "reviews and ratings",
+                true,
+                true,
+                6,
+                18,
+                vec![
+                    "lower throttling.inter_vendor_delay_ms by 1000ms (from 1000 to 0)",
+                    "lower throttling.sub_fetch_delay_ms by 500ms (from 500 to 0)",
+                    "or raise limits.run_budget_minutes to at least 1",
+                ],
+            ),
+            (
+                "reviews only",
+                true,
+                false,
+                6,
+                16,
+                vec![
+                    "lower throttling.inter_vendor_delay_ms by 1000ms (from 1000 to 0)",
+                    "lower throttling.sub_fetch_delay_ms by 250ms (from 500 to 250)",
+                    "or raise limits.run_budget_minutes to at least 1",
+                ],
+            ),
+            (
+                "ratings only",
+                false,
+                true,
+                6,
+                16,
+                vec![
+                    "lower throttling.inter_vendor_delay_ms by 1000ms (from 1000 to 0)",
+                    "lower throttling.sub_fetch_delay_ms by 250ms (from 500 to 250)",
+                    "or raise limits.run_budget_minutes to at least 1",
+                ],
+            ),
+            (
+                "neither skips the sub-fetch delay",
+                false,
+                false,
+                1,
+                10,
+                vec![
+                    "lower throttling.inter_vendor_delay_ms by 1000ms (from 1000 to 0)",
+                    "or raise limits.run_budget_minutes to at least 1",
+                ],
+            ),
+        ];
+
+        for (case, fetch_reviews, fetch_ratings, budget_secs, min_secs, suggestions) in cases {
+            let budget = Duration::from_secs(budget_secs);
+            let estimate = RunEstimate::new(
+                &throttling,
+                &limits,
+                &concurrency(1),
+                &extraction(fetch_reviews, fetch_ratings),
+                &[city(8, 0)],
+            );
+
+            assert_eq!(estimate.min_duration, Duration::from_secs(min_secs), "{}", case);
+            assert_eq!(estimate.suggestions(&throttling, budget), suggestions, "{}", case);
+        }
+    }
+}
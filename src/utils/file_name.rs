@@ -0,0 +1,61 @@
+/// `value` made safe to use inside a file name: every character outside
+/// `[A-Za-z0-9._=-]` becomes `_`, and a value that is empty or only dots
+/// (`.`, `..`) becomes `_`, so it can't name a directory. City ids go through
+/// this before they are put in a local file name.
+pub fn file_safe_name(value: &str) -> String {
+    let safe: String = value.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '=' | '-') { c } else { '_' })
+        .collect();
+    if safe.chars().all(|c| c == '.') {
+        return "_".to_string();
+    }
+    safe
+}
+
+/// Whether `name` only uses the characters [`file_safe_name`] keeps.
+pub fn is_file_safe(name: &str) -> bool {
+    !name.is_empty()
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '=' | '-'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+    use crate::utils::time::file_safe_timestamp;
+
+    #[test]
+    fn unsafe_characters_are_replaced() {
+        // (city id, file-safe name)
+        let cases = [
+            ("17", "17"),
+            ("24.861_-67.010", "24.861_-67.010"),
+            ("a/b", "a_b"),
+            ("../etc/passwd", ".._etc_passwd"),
+            ("..", "_"),
+            (".", "_"),
+            ("", "_"),
+            ("north side", "north_side"),
+            ("tab\there", "tab_here"),
+            ("Karāchi", "Kar_chi"),
+            ("لاہور", "_____"),
+            ("a:b\\c*?\"<>|", "a_b_c______"),
+        ];
+        for (city_id, expected) in cases {
+            let safe = file_safe_name(city_id);
+            assert_eq!(safe, expected, "{:?}", city_id);
+            assert!(is_file_safe(&safe), "{:?}", city_id);
+        }
+    }
+
+    #[test]
+    fn city_file_names_stay_in_the_charset() {
+        let timestamp = file_safe_timestamp(Utc.with_ymd_and_hms(2025, 3, 14, 9, 30, 0).unwrap());
+        assert_eq!(timestamp, "20250314T093000Z");
+        for city_id in ["17", "../up", "north side", "Lahore/لاہور", ""] {
+            let name = format!("vendors_city_{}_{}_.json", file_safe_name(city_id), timestamp);
+            assert!(is_file_safe(&name), "{}", name);
+            assert!(!name.contains('/'), "{}", name);
+        }
+    }
+}
@@ -1,5 +1,16 @@
+pub mod bulk;
+pub mod feasibility;
+pub mod file_name;
+pub mod pacer;
 pub mod retry;
+pub mod serde_helpers;
+pub mod throttle;
 pub mod time;
 
-pub use retry::retry_with_backoff;
+pub use bulk::{fetch_all, fetch_all_with_retries, BulkResult};
+pub use feasibility::{CityEstimate, RunEstimate};
+pub use file_name::file_safe_name;
+pub use pacer::AdaptivePacer;
+pub use retry::{retry_with_backoff, retry_with_backoff_if};
+pub use throttle::SharedThrottling;
 pub use time::sleep_with_jitter;
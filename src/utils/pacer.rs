@@ -0,0 +1,84 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use tracing::info;
+use crate::config::PacingConfig;
+
+/// Inter-vendor delay that follows the API's mood: the share of 403/429
+/// responses among the last `window` requests scales the delay linearly from
+/// `floor_ms` (none) to `ceiling_ms` (`saturation_rate` or more). Fed by the
+/// HTTP clients, read by `VendorService`.
+#[derive(Debug)]
+pub struct AdaptivePacer {
+    window: usize,
+    floor_ms: u64,
+    ceiling_ms: u64,
+    saturation_rate: f64,
+    state: Mutex<PacerState>,
+}
+
+#[derive(Debug)]
+struct PacerState {
+    /// Whether each recent response was a 403/429, oldest first.
+    recent: VecDeque<bool>,
+    blocked: usize,
+    delay_ms: u64,
+    /// Last delay logged; changes within 2x of it are not logged.
+    logged_delay_ms: u64,
+}
+
+impl AdaptivePacer {
+    pub fn new(config: &PacingConfig) -> Self {
+        let floor_ms = config.floor_ms;
+        Self {
+            window: config.window.max(1),
+            floor_ms,
+            ceiling_ms: config.ceiling_ms.max(floor_ms),
+            saturation_rate: config.saturation_rate,
+            state: Mutex::new(PacerState {
+                recent: VecDeque::new(),
+                blocked: 0,
+                delay_ms: floor_ms,
+                logged_delay_ms: floor_ms,
+            }),
+        }
+    }
+
+    /// Records a response status and recomputes the delay.
+    pub fn observe(&self, status: u16) {
+        let blocked = status == 403 || status == 429;
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+
+        state.recent.push_back(blocked);
+        state.blocked += blocked as usize;
+        if state.recent.len() > self.window && state.recent.pop_front() == Some(true) {
+            state.blocked -= 1;
+        }
+
+        let rate = state.blocked as f64 / state.recent.len() as f64;
+        let scale = if self.saturation_rate > 0.0 {
+            (rate / self.saturation_rate).min(1.0)
+        } else if rate > 0.0 {
+            1.0
+        } else {
+            0.0
+        };
+        state.delay_ms = self.floor_ms + ((self.ceiling_ms - self.floor_ms) as f64 * scale) as u64;
+
+        let (delay, logged) = (state.delay_ms.max(1), state.logged_delay_ms.max(1));
+        if delay > logged * 2 || logged > delay * 2 {
+            info!(
+                previous_delay_ms = state.logged_delay_ms,
+                delay_ms = state.delay_ms,
+                blocked_rate = rate,
+                window = state.recent.len(),
+                "Adaptive inter-vendor delay changed"
+            );
+            state.logged_delay_ms = state.delay_ms;
+        }
+    }
+
+    /// Current base delay between vendors.
+    pub fn delay_ms(&self) -> u64 {
+        self.state.lock().unwrap_or_else(|e| e.into_inner()).delay_ms
+    }
+}
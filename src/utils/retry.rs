@@ -2,13 +2,29 @@ use std::future::Future;
 use crate::utils::time::sleep_with_jitter;
 
 pub async fn retry_with_backoff<T, F, Fut>(
+    retries: u32,
+    base_delay_ms: u64,
+    operation: F,
+) -> crate::error::Result<T>
+where
+    F: Fn() -> Fut,
+    Fut: Future<Output = crate::error::Result<T>>,
+{
+    retry_with_backoff_if(retries, base_delay_ms, operation, |_| true).await
+}
+
+/// Like [`retry_with_backoff`], but gives up immediately on errors that
+/// `should_retry` rejects.
+pub async fn retry_with_backoff_if<T, F, Fut, P>(
     mut retries: u32,
     base_delay_ms: u64,
     operation: F,
+    should_retry: P,
 ) -> crate::error::Result<T>
 where
     F: Fn() -> Fut,
     Fut: Future<Output = crate::error::Result<T>>,
+    P: Fn(&crate::error::Error) -> bool,
 {
     let mut delay = base_delay_ms;
     
@@ -16,7 +32,7 @@ where
         match operation().await {
             Ok(value) => return Ok(value),
             Err(e) => {
-                if retries == 0 {
+                if retries == 0 || !should_retry(&e) {
                     return Err(e);
                 }
                 
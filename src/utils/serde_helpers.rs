@@ -0,0 +1,152 @@
+use serde::Serialize;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+/// Serializes `value` with object keys sorted at every nesting level, so the
+/// same record always produces the same bytes regardless of field insertion
+/// order. Numbers keep serde_json's shortest round-trip formatting.
+pub fn to_canonical_vec<T: Serialize + ?Sized>(value: &T) -> serde_json::Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    to_canonical_writer(&mut bytes, value)?;
+    Ok(bytes)
+}
+
+/// [`to_canonical_vec`] into `writer`.
+pub fn to_canonical_writer<W: std::io::Write, T: Serialize + ?Sized>(writer: W, value: &T) -> serde_json::Result<()> {
+    let value = canonicalize(serde_json::to_value(value)?);
+    serde_json::to_writer(writer, &value)
+}
+
+/// Hex SHA-256 of `value`'s canonical JSON, with object keys named in
+/// `ignored_keys` removed at every nesting level first.
+pub fn content_hash<T: Serialize + ?Sized>(value: &T, ignored_keys: &[String]) -> serde_json::Result<String> {
+    let value = canonicalize(strip_keys(serde_json::to_value(value)?, ignored_keys));
+    Ok(format!("{:x}", Sha256::digest(serde_json::to_vec(&value)?)))
+}
+
+fn strip_keys(value: Value, keys: &[String]) -> Value {
+    match value {
+        Value::Object(map) => Value::Object(
+            map.into_iter()
+                .filter(|(key, _)| !keys.contains(key))
+                .map(|(key, value)| (key, strip_keys(value, keys)))
+                .collect(),
+        ),
+        Value::Array(items) => Value::Array(items.into_iter().map(|item| strip_keys(item, keys)).collect()),
+        other => other,
+    }
+}
+
+/// Recursively rebuilds objects with their keys in lexicographic order.
+pub fn canonicalize(value: Value) -> Value {
+    match value {
+        Value::Object(map) => {
+            let mut entries: Vec<(String, Value)> = map.into_iter()
+                .map(|(key, value)| (key, canonicalize(value)))
+                .collect();
+            entries.sort_by(|a, b| a.0.cmp(&b.0));
+            Value::Object(entries.into_iter().collect())
+        }
+        Value::Array(items) => Value::Array(items.into_iter().map(canonicalize).collect()),
+        other => other,
+    }
+}
+
+/// `#[serde(with = "epoch_millis")]` for `DateTime<Utc>`: written as epoch
+/// milliseconds. Values below 10^11 are read as the epoch seconds files
+/// written before the switch hold (10^11 ms is in 1973, 10^11 s in 5138).
+pub mod epoch_millis {
+    use chrono::{DateTime, Utc};
+    use serde::{de, Deserialize, Deserializer, Serializer};
+
+    const SECONDS_BELOW: i64 = 100_000_000_000;
+
+    pub fn serialize<S: Serializer>(value: &DateTime<Utc>, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_i64(value.timestamp_millis())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<DateTime<Utc>, D::Error> {
+        let epoch = i64::deserialize(deserializer)?;
+        let parsed = if epoch.abs() < SECONDS_BELOW {
+            DateTime::from_timestamp(epoch, 0)
+        } else {
+            DateTime::from_timestamp_millis(epoch)
+        };
+        parsed.ok_or_else(|| de::Error::custom(format!("timestamp {} is out of range", epoch)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Serialize)]
+    struct Inner {
+        b: i32,
+        a: Vec<f64>,
+    }
+
+    #[derive(Serialize)]
+    struct Forward {
+        code: String,
+        name: String,
+        inner: Inner,
+    }
+
+    /// `Forward`'s content with the fields declared in another order.
+    #[derive(Serialize)]
+    struct Reordered {
+        inner: ReorderedInner,
+        name: String,
+        code: String,
+    }
+
+    #[derive(Serialize)]
+    struct ReorderedInner {
+        a: Vec<f64>,
+        b: i32,
+    }
+
+    fn forward() -> Forward {
+        Forward {
+            code: "v1".to_string(),
+            name: "Café".to_string(),
+            inner: Inner { b: 2, a: vec![0.1, 1.0] },
+        }
+    }
+
+    fn reordered() -> Reordered {
+        Reordered {
+            inner: ReorderedInner { a: vec![0.1, 1.0], b: 2 },
+            name: "Café".to_string(),
+            code: "v1".to_string(),
+        }
+    }
+
+    #[test]
+    fn field_order_does_not_change_the_bytes() {
+        let forward = to_canonical_vec(&forward()).unwrap();
+        let reordered = to_canonical_vec(&reordered()).unwrap();
+
+        assert_eq!(forward, reordered);
+        assert_eq!(forward, r#"{"code":"v1","inner":{"a":[0.1,1.0],"b":2},"name":"Café"}"#.as_bytes());
+        assert_ne!(serde_json::to_vec(&forward()).unwrap(), serde_json::to_vec(&reordered()).unwrap());
+    }
+
+    #[test]
+    fn writer_matches_vec() {
+        let mut written = Vec::new();
+        to_canonical_writer(&mut written, &reordered()).unwrap();
+
+        assert_eq!(written, to_canonical_vec(&forward()).unwrap());
+    }
+
+    #[test]
+    fn field_order_does_not_change_the_hash() {
+        let ignored = ["name".to_string()];
+
+        assert_eq!(content_hash(&forward(), &[]).unwrap(), content_hash(&reordered(), &[]).unwrap());
+        assert_eq!(content_hash(&forward(), &ignored).unwrap(), content_hash(&reordered(), &ignored).unwrap());
+        assert_ne!(content_hash(&forward(), &[]).unwrap(), content_hash(&forward(), &ignored).unwrap());
+    }
+}
@@ -0,0 +1,202 @@
+use std::sync::{Arc, Mutex, RwLock};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use crate::config::{ConcurrencyConfig, ThrottlingConfig};
+
+/// Throttling values shared between the orchestration and `VendorService`,
+/// and the cap on vendors processed at once, all of which can be changed
+/// while a run is in progress.
+#[derive(Debug, Clone)]
+pub struct SharedThrottling {
+    inner: Arc<RwLock<ThrottlingConfig>>,
+    workers: Arc<WorkerLimit>,
+}
+
+impl SharedThrottling {
+    pub fn new(config: ThrottlingConfig, concurrency: &ConcurrencyConfig) -> Self {
+        Self {
+            inner: Arc::new(RwLock::new(config)),
+            workers: Arc::new(WorkerLimit::new(concurrency.vendor_workers, concurrency.max_vendor_workers())),
+        }
+    }
+
+    pub fn get(&self) -> ThrottlingConfig {
+        self.inner.read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clone()
+    }
+
+    /// Installs `config` and returns the values it replaced.
+    pub fn replace(&self, config: ThrottlingConfig) -> ThrottlingConfig {
+        let mut guard = self.inner.write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        std::mem::replace(&mut *guard, config)
+    }
+
+    /// Vendors currently allowed to be processed at once.
+    pub fn vendor_workers(&self) -> usize {
+        self.workers.state().workers
+    }
+
+    /// The most `vendor_workers` can be raised to.
+    pub fn max_vendor_workers(&self) -> usize {
+        self.workers.max
+    }
+
+    /// Sets the vendors processed at once, clamped to `1..=max_vendor_workers`,
+    /// and returns the previous value. Raising it lets waiting vendors start
+    /// at once; lowering it stops new vendors from starting until enough of
+    /// the running ones have finished.
+    pub fn set_vendor_workers(&self, workers: usize) -> usize {
+        self.workers.resize(workers)
+    }
+
+    /// Waits for a vendor worker slot, held until the permit is dropped.
+    pub async fn vendor_worker(&self) -> WorkerPermit {
+        let permit = self.workers.semaphore.clone()
+            .acquire_owned()
+            .await
+            .expect("the vendor worker semaphore is never closed");
+        WorkerPermit {
+            permit: Some(permit),
+            limit: self.workers.clone(),
+        }
+    }
+}
+
+/// Semaphore sized to the vendor worker cap. Shrinking it retires permits
+/// that are free right away and the rest as they are returned.
+#[derive(Debug)]
+struct WorkerLimit {
+    semaphore: Arc<Semaphore>,
+    max: usize,
+    state: Mutex<WorkerState>,
+}
+
+#[derive(Debug)]
+struct WorkerState {
+    workers: usize,
+    /// Permits in use that are dropped instead of returned, after a shrink.
+    retiring: usize,
+}
+
+impl WorkerLimit {
+    fn new(workers: usize, max: usize) -> Self {
+        let max = max.max(workers).max(1);
+        let workers = workers.clamp(1, max);
+        Self {
+            semaphore: Arc::new(Semaphore::new(workers)),
+            max,
+            state: Mutex::new(WorkerState { workers, retiring: 0 }),
+        }
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, WorkerState> {
+        self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn resize(&self, workers: usize) -> usize {
+        let workers = workers.clamp(1, self.max);
+        let mut state = self.state();
+        let old = state.workers;
+        if workers > old {
+            // Permits still retiring are kept instead of being replaced
+            let grow = workers - old;
+            let kept = grow.min(state.retiring);
+            state.retiring -= kept;
+            self.semaphore.add_permits(grow - kept);
+        } else if workers < old {
+            let shrink = old - workers;
+            let forgotten = self.semaphore.forget_permits(shrink);
+            state.retiring += shrink - forgotten;
+        }
+        state.workers = workers;
+        old
+    }
+}
+
+/// A vendor worker slot from [`SharedThrottling::vendor_worker`].
+#[derive(Debug)]
+pub struct WorkerPermit {
+    permit: Option<OwnedSemaphorePermit>,
+    limit: Arc<WorkerLimit>,
+}
+
+impl Drop for WorkerPermit {
+    fn drop(&mut self) {
+        let mut state = self.limit.state();
+        if state.retiring > 0 {
+            state.retiring -= 1;
+            if let Some(permit) = self.permit.take() {
+                permit.forget();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tokio::time::timeout;
+
+    fn throttling(workers: usize, max: Option<usize>) -> SharedThrottling {
+        let concurrency = ConcurrencyConfig {
+            vendor_workers: workers,
+            max_vendor_workers: max,
+            ..ConcurrencyConfig::default()
+        };
+        SharedThrottling::new(ThrottlingConfig::default(), &concurrency)
+    }
+
+    /// Whether another worker could start now.
+    async fn slot_free(throttling: &SharedThrottling) -> bool {
+        timeout(Duration::from_millis(10), throttling.vendor_worker()).await.is_ok()
+    }
+
+    #[tokio::test]
+    async fn growing_lets_waiting_vendors_start_up_to_the_max() {
+        let throttling = throttling(1, Some(3));
+        let _running = throttling.vendor_worker().await;
+        assert!(!slot_free(&throttling).await);
+
+        assert_eq!(throttling.set_vendor_workers(10), 1);
+
+        assert_eq!(throttling.vendor_workers(), 3);
+        let _second = throttling.vendor_worker().await;
+        let _third = throttling.vendor_worker().await;
+        assert!(!slot_free(&throttling).await);
+    }
+
+    #[tokio::test]
+    async fn shrinking_waits_for_running_vendors_to_finish() {
+        let throttling = throttling(3, None);
+        let first = throttling.vendor_worker().await;
+        let second = throttling.vendor_worker().await;
+
+        // The free slot goes at once, one running vendor's when it finishes
+        assert_eq!(throttling.set_vendor_workers(1), 3);
+        assert!(!slot_free(&throttling).await);
+        drop(first);
+        assert!(!slot_free(&throttling).await);
+        drop(second);
+
+        let _only = throttling.vendor_worker().await;
+        assert!(!slot_free(&throttling).await);
+    }
+
+    #[tokio::test]
+    async fn growing_again_keeps_the_retiring_slots() {
+        let throttling = throttling(2, None);
+        let first = throttling.vendor_worker().await;
+        let second = throttling.vendor_worker().await;
+
+        throttling.set_vendor_workers(1);
+        throttling.set_vendor_workers(2);
+        drop(first);
+        drop(second);
+
+        let _first = throttling.vendor_worker().await;
+        let _second = throttling.vendor_worker().await;
+        assert!(!slot_free(&throttling).await);
+    }
+}
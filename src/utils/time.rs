@@ -1,7 +1,21 @@
 use rand::Rng;
 use std::time::Duration;
+use chrono::{DateTime, NaiveDate, Utc};
+use chrono_tz::Tz;
 
 pub async fn sleep_with_jitter(base_ms: u64, jitter_ms: u64) {
     let jitter = rand::rng().random_range(0..=jitter_ms);
     tokio::time::sleep(Duration::from_millis(base_ms + jitter)).await;
 }
+
+/// Calendar date of `now` in `tz`; a run's partitions all use the date
+/// computed once at run start.
+pub fn logical_date(now: DateTime<Utc>, tz: Tz) -> NaiveDate {
+    now.with_timezone(&tz).date_naive()
+}
+
+/// `now` as `YYYYMMDDTHHMMSSZ`, for file names and object keys: no colons or
+/// spaces, which Windows and some object-store gateways reject.
+pub fn file_safe_timestamp(now: DateTime<Utc>) -> String {
+    now.format("%Y%m%dT%H%M%SZ").to_string()
+}
@@ -0,0 +1,65 @@
+//! A counting global allocator for the memory tests, kept in their own test
+//! binaries so it doesn't sit under every other test.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::future::Future;
+
+/// Counts the bytes each thread has allocated, so a test can bound what its
+/// own work holds at once while other tests run.
+struct CountingAllocator;
+
+thread_local! {
+    static LIVE: Cell<isize> = const { Cell::new(0) };
+    static PEAK: Cell<isize> = const { Cell::new(0) };
+}
+
+fn track(delta: isize) {
+    let _ = LIVE.try_with(|live| {
+        live.set(live.get() + delta);
+        let _ = PEAK.try_with(|peak| peak.set(peak.get().max(live.get())));
+    });
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        track(layout.size() as isize);
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        track(-(layout.size() as isize));
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// Bytes this thread holds.
+#[allow(dead_code)]
+pub fn live() -> isize {
+    LIVE.with(Cell::get)
+}
+
+/// Runs `f` and returns the most bytes this thread held at once on top of
+/// what it held before.
+#[allow(dead_code)]
+pub fn peak_allocated<T>(f: impl FnOnce() -> T) -> (T, usize) {
+    let start = live();
+    PEAK.with(|peak| peak.set(start));
+    let result = f();
+    let peak = PEAK.with(Cell::get);
+    (result, (peak - start).max(0) as usize)
+}
+
+/// [`peak_allocated`] for a future polled on this thread, as on a
+/// current-thread runtime.
+#[allow(dead_code)]
+pub async fn peak_allocated_async<T>(f: impl Future<Output = T>) -> (T, usize) {
+    let start = live();
+    PEAK.with(|peak| peak.set(start));
+    let result = f.await;
+    let peak = PEAK.with(Cell::get);
+    (result, (peak - start).max(0) as usize)
+}
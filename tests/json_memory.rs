@@ -0,0 +1,42 @@
+//! Peak allocation of writing one large vendor, under a counting allocator.
+
+mod common;
+
+use foodpanda_etl::storage::JsonWriter;
+use foodpanda_etl::Vendor;
+use serde_json::json;
+
+/// A vendor of over 5 MB of JSON, most of it reviews.
+fn large_vendor() -> Vendor {
+    let mut vendor = Vendor::stub("big", 1, None, "ok");
+    vendor.reviews = Some((0..5_000).map(|index| json!({ "id": index, "text": "x".repeat(1024) })).collect());
+    vendor
+}
+
+#[tokio::test]
+async fn large_vendor_is_written_without_a_whole_record_buffer() {
+    let vendor = large_vendor();
+    let (expected, to_vec_peak) = common::peak_allocated(|| serde_json::to_vec(&vendor).unwrap());
+    assert!(expected.len() > 5_000_000);
+
+    let dir = tempfile::tempdir().unwrap();
+    let mut writer = JsonWriter::new(dir.path(), "vendors.json").await.unwrap();
+    let before = common::live();
+    let (written, streamed_peak) = common::peak_allocated_async(writer.write_vendor(&vendor)).await;
+    written.unwrap();
+    let retained = common::live() - before;
+
+    // to_vec grows one buffer by doubling, holding the old and new at once
+    assert!(
+        streamed_peak < expected.len() + (1 << 20),
+        "writing held {} bytes for a {} byte record",
+        streamed_peak,
+        expected.len()
+    );
+    assert!(streamed_peak * 3 < to_vec_peak * 2, "{} vs {} with to_vec", streamed_peak, to_vec_peak);
+    assert!(retained < 1 << 20, "writer kept {} bytes after the write", retained);
+
+    writer.finish().await.unwrap();
+    let file = std::fs::read(dir.path().join("vendors.json")).unwrap();
+    assert_eq!(file, [&b"[\n"[..], &expected, b"\n]"].concat());
+}
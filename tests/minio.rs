@@ -0,0 +1,59 @@
+//! Uploads against a live MinIO, such as the one in docker-compose.yml:
+//! `cargo test --features integration`. `MINIO_ENDPOINT`, `MINIO_ACCESS_KEY`,
+//! `MINIO_SECRET_KEY` and `MINIO_BUCKET` override the compose defaults; the
+//! bucket is created when missing.
+#![cfg(feature = "integration")]
+
+use std::env;
+use bytes::Bytes;
+use foodpanda_etl::storage::MinioStorage;
+use uuid::Uuid;
+
+async fn storage() -> MinioStorage {
+    let setting = |name: &str, default: &str| env::var(name).unwrap_or_else(|_| default.to_string());
+    MinioStorage::new(
+        &setting("MINIO_ENDPOINT", "http://127.0.0.1:9000"),
+        &setting("MINIO_ACCESS_KEY", "access_key"),
+        &setting("MINIO_SECRET_KEY", "secret_key"),
+        &setting("MINIO_BUCKET", "food-panda-vendors"),
+        "us-east-1",
+        true,
+    )
+    .await
+    .expect("MinIO is reachable")
+}
+
+/// A prefix no other run uses.
+fn prefix() -> String {
+    format!("integration-tests/{}/", Uuid::new_v4())
+}
+
+#[tokio::test]
+async fn multipart_upload_round_trips() {
+    let storage = storage().await.with_multipart_threshold_mb(5).with_multipart_chunk_mb(5);
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("vendors.parquet");
+    let contents: Vec<u8> = (0..11 * 1024 * 1024).map(|index| (index % 251) as u8).collect();
+    std::fs::write(&path, &contents).unwrap();
+    let key = format!("{}vendors.parquet", prefix());
+
+    storage.upload_parquet_file(&path, &key, Some("source=integration")).await.unwrap();
+
+    assert_eq!(storage.get_object_bytes(&key).await.unwrap(), contents);
+}
+
+#[tokio::test]
+async fn uploaded_bytes_are_listed() {
+    let storage = storage().await;
+    let prefix = prefix();
+    for name in ["a.json", "b.json"] {
+        let key = format!("{}{}", prefix, name);
+        storage.upload_bytes(Bytes::from_static(b"[]"), &key, "application/json", None).await.unwrap();
+    }
+
+    let objects = storage.list_objects(&prefix).await.unwrap();
+
+    let keys: Vec<String> = objects.into_iter().map(|object| object.key).collect();
+    assert_eq!(keys, [format!("{}a.json", prefix), format!("{}b.json", prefix)]);
+    assert!(!storage.download_to_file(&format!("{}missing.json", prefix), &std::env::temp_dir().join(Uuid::new_v4().to_string())).await.unwrap());
+}
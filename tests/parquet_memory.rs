@@ -0,0 +1,55 @@
+//! Peak allocation of converting a large city to Parquet, under a counting
+//! allocator.
+
+mod common;
+
+use std::fs::File;
+use std::path::Path;
+use chrono::NaiveDate;
+use parquet::file::reader::{FileReader, SerializedFileReader};
+use foodpanda_etl::config::ParquetConfig;
+use foodpanda_etl::storage::{ParquetConverter, ParquetMeta};
+use foodpanda_etl::Vendor;
+
+fn meta() -> ParquetMeta {
+    ParquetMeta {
+        run_id: "test".to_string(),
+        city_id: "1".to_string(),
+        country: "pk".to_string(),
+        extraction_date: NaiveDate::from_ymd_opt(2024, 5, 1).unwrap(),
+    }
+}
+
+/// Rows of each row group of a Parquet file, in order.
+fn row_group_rows(path: &Path) -> Vec<i64> {
+    let reader = SerializedFileReader::new(File::open(path).unwrap()).unwrap();
+    reader.metadata().row_groups().iter().map(|group| group.num_rows()).collect()
+}
+
+#[test]
+fn vendor_conversion_memory_is_bounded_by_the_row_group() {
+    const VENDORS: usize = 50_000;
+    // Holding the whole city would take over 50 MiB of reviews alone
+    const CEILING: usize = 32 << 20;
+    let dir = tempfile::tempdir().unwrap();
+    let output = dir.path().join("vendors.parquet");
+    let vendors = (0..VENDORS).map(|index| {
+        let mut vendor = Vendor::stub(&format!("v{}", index), 1, None, "ok");
+        vendor.name = format!("Vendor {}", index);
+        vendor.reviews = Some(vec![serde_json::json!({ "text": format!("{} {}", index, "x".repeat(1024)) })]);
+        Ok(vendor)
+    });
+
+    let (null_rates, peak) = common::peak_allocated(|| {
+        ParquetConverter::convert_vendors_to_parquet(
+            vendors,
+            &output,
+            &meta(),
+            &ParquetConfig::default(),
+        )
+    });
+
+    assert_eq!(null_rates.unwrap().rows(), VENDORS);
+    assert_eq!(row_group_rows(&output), vec![1000; VENDORS / 1000]);
+    assert!(peak < CEILING, "conversion held {} bytes at once", peak);
+}